@@ -0,0 +1,258 @@
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, TimeZone};
+use flate2::read::ZlibDecoder;
+
+use crate::media::{FileType, ImgInfo};
+use crate::sorting::PATHSTR_FB;
+
+const MAGIC: &[u8] = b"ANDROID BACKUP\n";
+
+/// An unencrypted `adb backup` archive. It consists of a text header followed by an optionally
+/// zlib-compressed tar stream with the app data (`apps/<package>/...`) and shared storage
+/// (`shared/0/...`) of the device.
+///
+/// Media files are extracted into a temporary workspace, which is removed again once the
+/// backup is dropped. The archive is only extracted once, scanning the backup again (e.g. after
+/// checking the free space) reuses the extracted files.
+pub struct AndroidBackup {
+    file: PathBuf,
+    workspace: Option<PathBuf>,
+    /// files extracted into the workspace, `None` until the archive has been read
+    extracted: Option<Vec<Extracted>>,
+    /// entries of the archive that could not be extracted
+    extract_errors: Vec<(PathBuf, String)>
+}
+
+/// a file extracted into the workspace
+struct Extracted {
+    /// path inside the archive
+    inner: PathBuf,
+    path: PathBuf,
+    modified: Option<DateTime<Local>>
+}
+
+impl AndroidBackup {
+    pub fn new(file: &Path) -> AndroidBackup {
+        AndroidBackup {
+            file: file.to_path_buf(),
+            workspace: None,
+            extracted: None,
+            extract_errors: Vec::new()
+        }
+    }
+
+    pub fn is_backup(path: &Path) -> bool {
+        let mut magic = [0u8; 15];
+        path.is_file() && match File::open(path) {
+            Ok(mut f) => f.read_exact(&mut magic).is_ok() && magic == MAGIC,
+            Err(_) => false
+        }
+    }
+
+    /// get all files of a supported type, extracting them from the archive into the workspace
+    /// on first use
+    pub fn files(&mut self, errors: &mut Vec<(PathBuf, String)>) -> Result<Vec<ImgInfo>, String> {
+        let extracted = match self.extracted.take() {
+            Some(e) => e,
+            None => {
+                let mut extract_errors = Vec::new();
+                let extracted = self.extract(&mut extract_errors)?;
+                self.extract_errors = extract_errors;
+                extracted
+            }
+        };
+        errors.extend(self.extract_errors.iter().cloned());
+        let mut files = Vec::with_capacity(extracted.len());
+        for f in &extracted {
+            let name = match f.inner.file_name() {
+                Some(n) => n,
+                None => continue
+            };
+            match ImgInfo::with_original_name(f.path.clone(), name, f.modified) {
+                Ok(info) => files.push(info),
+                Err(e) => errors.push((f.inner.clone(), format!("error processing backup file: {}", e)))
+            }
+        }
+        self.extracted = Some(extracted);
+        Ok(files)
+    }
+
+    fn extract(&mut self, errors: &mut Vec<(PathBuf, String)>) -> Result<Vec<Extracted>, String> {
+        let file = File::open(&self.file).map_err(|e| format!("failed to open backup: {}", e))?;
+        let mut reader = BufReader::new(file);
+        let header = Self::read_header(&mut reader)?;
+        if header.encryption != "none" {
+            return Err(format!("encrypted backups are not supported (encryption: {})", header.encryption));
+        }
+        let stream: Box<dyn Read> = match header.compressed {
+            true => Box::new(ZlibDecoder::new(reader)),
+            false => Box::new(reader)
+        };
+
+        let workspace = self.create_workspace()?;
+        let mut archive = tar::Archive::new(stream);
+        let entries = archive.entries().map_err(|e| format!("failed to read backup archive: {}", e))?;
+        let mut files = Vec::new();
+        for entry in entries {
+            let mut entry = match entry {
+                Ok(e) => e,
+                Err(e) => return Err(format!("failed to read backup archive: {}", e))
+            };
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let inner = match entry.path() {
+                Ok(p) => p.to_path_buf(),
+                Err(e) => {
+                    errors.push((self.file.clone(), format!("invalid entry path: {}", e)));
+                    continue;
+                }
+            };
+            let is_supported = inner.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| !matches!(FileType::from(e), FileType::Other));
+            if !is_supported {
+                continue;
+            }
+            let modified = entry.header().mtime().ok()
+                .and_then(|ts| Local.timestamp_opt(ts as i64, 0).single());
+            match entry.unpack_in(workspace.as_path()) {
+                // entries escaping the workspace (e.g. via "..") are not unpacked
+                Ok(false) => continue,
+                Ok(true) => (),
+                Err(e) => {
+                    errors.push((inner.clone(), format!("failed to extract file from backup: {}", e)));
+                    continue;
+                }
+            }
+            files.push(Extracted {
+                path: workspace.join(&inner),
+                inner,
+                modified
+            });
+        }
+        Ok(files)
+    }
+
+    fn create_workspace(&mut self) -> Result<PathBuf, String> {
+        if let Some(ws) = &self.workspace {
+            return Ok(ws.clone());
+        }
+        let ws = std::env::temp_dir().join(format!("dcim-sort-{}-{}",
+                                                   std::process::id(),
+                                                   Local::now().format("%Y%m%d%H%M%S%f")));
+        fs::create_dir_all(&ws).map_err(|e| format!("failed to create workspace \"{}\": {}",
+                                                     ws.to_str().unwrap_or(PATHSTR_FB), e))?;
+        self.workspace = Some(ws.clone());
+        Ok(ws)
+    }
+
+    fn read_header<R: BufRead>(reader: &mut R) -> Result<Header, String> {
+        let mut lines = Vec::with_capacity(4);
+        for _ in 0..4 {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(n) if n > 0 => lines.push(String::from(line.trim_end())),
+                _ => return Err(String::from("truncated backup header"))
+            }
+        }
+        if lines[0].as_bytes() != &MAGIC[..MAGIC.len() - 1] {
+            return Err(String::from("not an Android backup"));
+        }
+        Ok(Header {
+            compressed: lines[2] == "1",
+            encryption: lines[3].clone()
+        })
+    }
+}
+
+impl Drop for AndroidBackup {
+    fn drop(&mut self) {
+        if let Some(ws) = &self.workspace {
+            if let Err(e) = fs::remove_dir_all(ws) {
+                eprintln!("[ERROR] failed to remove workspace \"{}\": {}", ws.to_str().unwrap_or(PATHSTR_FB), e);
+            }
+        }
+    }
+}
+
+struct Header {
+    compressed: bool,
+    encryption: String
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod android_backup {
+        use std::io::Write;
+        use std::path::Path;
+
+        use chrono::{Local, TimeZone};
+        use flate2::Compression;
+        use flate2::write::ZlibEncoder;
+
+        use crate::backup::android::AndroidBackup;
+        use crate::test_util::TempDir;
+
+        /// create a compressed backup at `path` containing `(path, content)` entries
+        fn create_backup(path: &Path, encryption: &str, entries: &[(&str, &[u8])]) {
+            let mut archive = tar::Builder::new(Vec::new());
+            for (name, content) in entries {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mtime(1_600_000_000);
+                header.set_mode(0o644);
+                header.set_cksum();
+                archive.append_data(&mut header, name, *content).unwrap();
+            }
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&archive.into_inner().unwrap()).unwrap();
+            let mut backup = format!("ANDROID BACKUP\n5\n1\n{}\n", encryption).into_bytes();
+            backup.extend(encoder.finish().unwrap());
+            std::fs::write(path, backup).unwrap();
+        }
+
+        #[test]
+        fn extracts_media_files_once() {
+            let dir = TempDir::new("android-backup");
+            let path = dir.join("device.ab");
+            create_backup(path.as_path(), "none", &[
+                ("shared/0/DCIM/Camera/IMG_0001.jpg", b"jpeg"),
+                ("apps/com.example/f/notes.txt", b"text")
+            ]);
+            assert!(AndroidBackup::is_backup(path.as_path()));
+
+            let mut backup = AndroidBackup::new(path.as_path());
+            let mut errors = Vec::new();
+            let files = backup.files(&mut errors).unwrap();
+            assert!(errors.is_empty());
+            assert_eq!(files.len(), 1);
+            assert_eq!(files[0].file_name().unwrap(), "IMG_0001.jpg");
+            assert_eq!(files[0].changed_at(), &Local.timestamp_opt(1_600_000_000, 0).unwrap());
+            let extracted = files[0].path().to_path_buf();
+            assert!(extracted.is_file());
+
+            // scanning again reuses the extracted files instead of reading the archive
+            std::fs::remove_file(&path).unwrap();
+            let again = backup.files(&mut errors).unwrap();
+            assert_eq!(again.len(), 1);
+            assert_eq!(again[0].path(), extracted.as_path());
+
+            drop(backup);
+            assert!(!extracted.exists());
+        }
+
+        #[test]
+        fn rejects_encrypted_backups() {
+            let dir = TempDir::new("android-backup-encrypted");
+            let path = dir.join("device.ab");
+            create_backup(path.as_path(), "AES-256", &[("shared/0/DCIM/Camera/IMG_0001.jpg", b"jpeg")]);
+            assert!(AndroidBackup::new(path.as_path()).files(&mut Vec::new()).is_err());
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, TimeZone};
+use rusqlite::{Connection, OpenFlags};
+
+use crate::media::ImgInfo;
+
+const MANIFEST_DB: &str = "Manifest.db";
+/// camera roll files of the `CameraRollDomain`; thumbnails and other photo library data are
+/// stored outside of `Media/DCIM`
+const QUERY_CAMERA_ROLL: &str = "SELECT fileID, relativePath, file FROM Files \
+    WHERE domain = 'CameraRollDomain' AND flags = 1 AND relativePath LIKE 'Media/DCIM/%'";
+
+/// An unencrypted iTunes/Finder backup (iOS 10 or later). Files are stored as
+/// `<first 2 chars of id>/<id>` and mapped to their original path by the `Files` table of
+/// `Manifest.db`, which also holds their metadata as a serialized property list.
+pub struct IosBackup {
+    root: PathBuf
+}
+
+impl IosBackup {
+    pub fn new(root: &Path) -> IosBackup {
+        IosBackup {
+            root: root.to_path_buf()
+        }
+    }
+
+    pub fn is_backup(path: &Path) -> bool {
+        path.is_dir() && path.join(MANIFEST_DB).is_file()
+    }
+
+    pub fn files(&self, errors: &mut Vec<(PathBuf, String)>) -> Result<Vec<ImgInfo>, String> {
+        let manifest = self.root.join(MANIFEST_DB);
+        let conn = Connection::open_with_flags(&manifest, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("failed to open {}: {}", MANIFEST_DB, e))?;
+        // encrypted backups fail here, as the manifest is not a readable database
+        let mut stmt = conn.prepare(QUERY_CAMERA_ROLL)
+            .map_err(|e| format!("failed to read {} (encrypted backups are not supported): {}", MANIFEST_DB, e))?;
+        let rows = stmt.query_map([], |row| Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<Vec<u8>>>(2)?
+        ))).map_err(|e| format!("failed to query {}: {}", MANIFEST_DB, e))?;
+
+        let mut files = Vec::new();
+        for row in rows {
+            let (id, relative_path, blob) = match row {
+                Ok(r) => r,
+                Err(e) => {
+                    errors.push((manifest.clone(), format!("invalid manifest entry: {}", e)));
+                    continue;
+                }
+            };
+            if id.len() < 2 {
+                errors.push((manifest.clone(), format!("invalid file id for \"{}\"", relative_path)));
+                continue;
+            }
+            let path = self.root.join(&id[..2]).join(&id);
+            let name = match Path::new(&relative_path).file_name() {
+                Some(n) => n.to_os_string(),
+                None => OsStr::new(&id).to_os_string()
+            };
+            let modified = blob.and_then(|b| Self::last_modified(b.as_slice()))
+                .and_then(|ts| Local.timestamp_opt(ts, 0).single());
+            match ImgInfo::with_original_name(path.clone(), name.as_os_str(), modified) {
+                Ok(info) => files.push(info),
+                Err(e) => errors.push((path, format!("error processing backup file \"{}\": {}", relative_path, e)))
+            }
+        }
+        Ok(files)
+    }
+
+    /// read the modification timestamp from the `NSKeyedArchiver` property list of a file
+    fn last_modified(blob: &[u8]) -> Option<i64> {
+        let plist = plist::Value::from_reader(std::io::Cursor::new(blob)).ok()?;
+        let archive = plist.as_dictionary()?;
+        let root = match archive.get("$top")?.as_dictionary()?.get("root")? {
+            plist::Value::Uid(uid) => uid.get() as usize,
+            _ => return None
+        };
+        let object = archive.get("$objects")?.as_array()?.get(root)?.as_dictionary()?;
+        object.get("LastModified")?.as_signed_integer()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod ios_backup {
+        use std::path::Path;
+
+        use chrono::{Local, TimeZone};
+        use rusqlite::{Connection, params};
+
+        use crate::backup::ios::IosBackup;
+        use crate::test_util::TempDir;
+
+        /// `NSKeyedArchiver` property list of a file last modified at `ts`
+        fn file_blob(ts: i64) -> Vec<u8> {
+            let mut top = plist::Dictionary::new();
+            top.insert(String::from("root"), plist::Value::Uid(plist::Uid::new(1)));
+            let mut file = plist::Dictionary::new();
+            file.insert(String::from("LastModified"), plist::Value::Integer(ts.into()));
+            let mut archive = plist::Dictionary::new();
+            archive.insert(String::from("$top"), plist::Value::Dictionary(top));
+            archive.insert(String::from("$objects"), plist::Value::Array(vec![
+                plist::Value::String(String::from("$null")),
+                plist::Value::Dictionary(file)
+            ]));
+            let mut blob = Vec::new();
+            plist::Value::Dictionary(archive).to_writer_binary(&mut blob).unwrap();
+            blob
+        }
+
+        /// create a backup at `root` with the given `(fileID, domain, relativePath)` entries
+        fn create_backup(root: &Path, entries: &[(&str, &str, &str)]) {
+            let conn = Connection::open(root.join("Manifest.db")).unwrap();
+            conn.execute_batch("CREATE TABLE Files (fileID TEXT PRIMARY KEY, domain TEXT, \
+                relativePath TEXT, flags INTEGER, file BLOB);").unwrap();
+            for (id, domain, relative_path) in entries {
+                conn.execute("INSERT INTO Files VALUES (?1, ?2, ?3, 1, ?4)",
+                             params![id, domain, relative_path, file_blob(1_600_000_000)]).unwrap();
+                let dir = root.join(&id[..2]);
+                std::fs::create_dir_all(&dir).unwrap();
+                std::fs::write(dir.join(id), relative_path.as_bytes()).unwrap();
+            }
+        }
+
+        #[test]
+        fn maps_camera_roll_to_original_names() {
+            let dir = TempDir::new("ios-backup");
+            create_backup(dir.as_path(), &[
+                ("aa01", "CameraRollDomain", "Media/DCIM/100APPLE/IMG_0001.HEIC"),
+                ("bb02", "CameraRollDomain", "Media/PhotoData/Thumbnails/IMG_0001.JPG"),
+                ("cc03", "HomeDomain", "Media/DCIM/100APPLE/IMG_0002.JPG")
+            ]);
+            assert!(IosBackup::is_backup(dir.as_path()));
+
+            let mut errors = Vec::new();
+            let files = IosBackup::new(dir.as_path()).files(&mut errors).unwrap();
+            assert!(errors.is_empty());
+            assert_eq!(files.len(), 1);
+            assert_eq!(files[0].path(), dir.join("aa").join("aa01").as_path());
+            assert_eq!(files[0].file_name().unwrap(), "IMG_0001.HEIC");
+            assert_eq!(files[0].changed_at(), &Local.timestamp_opt(1_600_000_000, 0).unwrap());
+        }
+
+        #[test]
+        fn reports_missing_files() {
+            let dir = TempDir::new("ios-backup-missing");
+            create_backup(dir.as_path(), &[("aa01", "CameraRollDomain", "Media/DCIM/100APPLE/IMG_0001.JPG")]);
+            std::fs::remove_file(dir.join("aa").join("aa01")).unwrap();
+
+            let mut errors = Vec::new();
+            let files = IosBackup::new(dir.as_path()).files(&mut errors).unwrap();
+            assert!(files.is_empty());
+            assert_eq!(errors.len(), 1);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+
+use crate::media::ImgInfo;
+
+pub mod android;
+pub mod ios;
+
+use android::AndroidBackup;
+use ios::IosBackup;
+
+/// A device backup used as source instead of a plain directory. Backups store files under
+/// internal names, the adapters map them back to their real filenames and timestamps.
+///
+/// # Variants
+/// - [Backup::Ios] an iTunes/Finder backup folder, see [IosBackup]
+/// - [Backup::Android] an `adb backup` archive (`.ab`), see [AndroidBackup]
+pub enum Backup {
+    Ios(IosBackup),
+    Android(AndroidBackup)
+}
+
+impl Backup {
+    /// check if `path` is a supported backup
+    pub fn detect(path: &Path) -> Option<Backup> {
+        if IosBackup::is_backup(path) {
+            Some(Backup::Ios(IosBackup::new(path)))
+        }
+        else if AndroidBackup::is_backup(path) {
+            Some(Backup::Android(AndroidBackup::new(path)))
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backup::Ios(_) => "iOS backup",
+            Backup::Android(_) => "Android backup"
+        }
+    }
+
+    /// get all media files of the backup. Entries that could not be read are skipped and added
+    /// to `errors`.
+    pub fn files(&mut self, errors: &mut Vec<(PathBuf, String)>) -> Result<Vec<ImgInfo>, String> {
+        match self {
+            Backup::Ios(b) => b.files(errors),
+            Backup::Android(b) => b.files(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod backup {
+        use crate::backup::Backup;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn detects_supported_backups() {
+            let dir = TempDir::new("backup-detect");
+            let ios = dir.join("ios");
+            std::fs::create_dir(&ios).unwrap();
+            std::fs::write(ios.join("Manifest.db"), b"").unwrap();
+            let android = dir.join("device.ab");
+            std::fs::write(&android, b"ANDROID BACKUP\n5\n1\nnone\n").unwrap();
+            let plain = dir.join("IMG_0001.jpg");
+            std::fs::write(&plain, b"ANDROID").unwrap();
+
+            assert!(matches!(Backup::detect(ios.as_path()), Some(Backup::Ios(_))));
+            assert!(matches!(Backup::detect(android.as_path()), Some(Backup::Android(_))));
+            assert!(Backup::detect(plain.as_path()).is_none());
+            assert!(Backup::detect(dir.as_path()).is_none());
+        }
+    }
+}
@@ -7,6 +7,7 @@ use dcim_sort::index::Scanner;
 use dcim_sort::media::{FileType, ImgInfo, ImgMeta};
 use dcim_sort::media::kadamak_exif::KadamakExifProcessor;
 use dcim_sort::media::metadata_processor::{MetaProcessor, Priority};
+#[cfg(feature = "rexiv2")]
 use dcim_sort::media::rexiv_proc::Rexiv2Processor;
 use dcim_sort::pattern::device::{CaseNormalization, DevicePart, MakeModelPattern};
 use dcim_sort::pattern::fallback::DummyPattern;
@@ -92,10 +93,12 @@ fn read_file(inp_file: &Path) -> Result<ImgInfo, String> {
 }
 
 
-/// build a default MetaProcessor with Rexiv2 as default and Kadamak as fallback
+/// build a default MetaProcessor with Rexiv2 as default (if available) and Kadamak as fallback
 fn build_meta_proc() -> MetaProcessor {
-    MetaProcessor::new()
-        .processor(Rexiv2Processor::new(), Priority::Highest)
+    let builder = MetaProcessor::new();
+    #[cfg(feature = "rexiv2")]
+    let builder = builder.processor(Rexiv2Processor::new(), Priority::Highest);
+    builder
         .processor(KadamakExifProcessor::new(), Priority::Lowest)
         .build_clone()
 }
@@ -154,9 +157,16 @@ fn main() {
     for file in &cfg.files {
         let mut file_meta = read_file(file.as_path()).unwrap();
         processor.process(&mut file_meta);
-        let action = sorter.calc_simulation(&file_meta, &cfg.out_dir.as_path());
-
-        println!("file: {}", action.get_source().to_str().unwrap_or(PATHSTR_FB));
+        let preview = sorter.preview(&file_meta, cfg.out_dir.as_path());
+        let target = match preview.target() {
+            Some(t) => t,
+            None => {
+                eprintln!("{}", preview.warnings().join("\n"));
+                continue;
+            }
+        };
+
+        println!("file: {}", file.to_str().unwrap_or(PATHSTR_FB));
         if cfg.print_sorting {
             println!("\t==== sorting =====\n\ttype: {}\n\ttarget: {}",
                      match file_meta.file_type(){
@@ -167,8 +177,14 @@ fn main() {
                          FileType::ARW => "ARW",
                          FileType::Other => "other (unsupported metadata)"
                      },
-                     action.get_target().to_str().unwrap_or(PATHSTR_FB)
+                     target.to_str().unwrap_or(PATHSTR_FB)
             );
+            for (name, value) in &preview.segments {
+                println!("\tsegment: {} -> {}", name, value.as_deref().unwrap_or("<skipped>"));
+            }
+            for warning in preview.warnings() {
+                println!("\twarning: {}", warning);
+            }
         }
 
         if cfg.print_meta {
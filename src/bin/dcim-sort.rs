@@ -1,31 +1,204 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
 use std::time;
 use clap::{App, AppSettings, Arg};
-use dcim_sort::config::RootCfg;
-use dcim_sort::index::Scanner;
-use dcim_sort::media::kadamak_exif::KadamakExifProcessor;
-use dcim_sort::media::metadata_processor::{MetaProcessor, MetaProcessorBuilder, Priority};
-use dcim_sort::media::rexiv_proc::Rexiv2Processor;
-use dcim_sort::pattern::device::{CaseNormalization, DevicePart, MakeModelPattern};
-use dcim_sort::pattern::fallback::SimpleFileTypePattern;
-use dcim_sort::pattern::general::{DateTimePart, DateTimePattern, ScreenshotPattern};
-use dcim_sort::pipeline::{Pipeline, PipelineController};
-use dcim_sort::sorting::comparison::HashAlgorithm;
-use dcim_sort::sorting::{ActionResult, DuplicateResolution, Operation, PATHSTR_FB, Sorter, SorterBuilder};
+#[cfg(feature = "catalog")]
+use dcim_sort::catalog::Catalog;
+use dcim_sort::checkpoint;
+use dcim_sort::checkpoint::Checkpoint;
+use dcim_sort::config::{self, CfgError, ConfigFormat, parse_byte_size, RootCfg};
+use dcim_sort::config::default_config;
+use dcim_sort::config::logger_config::LoggerCfg;
+use dcim_sort::config::migration;
+use dcim_sort::config::overrides::{self, Layered, SettingSource};
+use dcim_sort::config::processor_config::ProcessorCfg;
+use dcim_sort::config::scanner_config::ScannerCfg;
+use dcim_sort::config::validation::{self, Severity};
+use dcim_sort::hook::PostActionHook;
+use dcim_sort::index;
+use dcim_sort::index::{DateRange, ScanOrder, Scanner, ScannerBuilder};
+use dcim_sort::logging::{LogBackend, LogHandle, Logger, LogReq};
+use dcim_sort::logging::console::{self, Event, OutputFormat, Verbosity};
+use dcim_sort::manifest::{self, EntryStatus, Manifest};
+use dcim_sort::media::ImgInfo;
+use dcim_sort::media::clock_drift::{ClockDrift, DEFAULT_MIN_OFFSET_SECS, DEFAULT_MIN_SAMPLES, DEFAULT_TOLERANCE_SECS, DriftEstimator};
+use dcim_sort::media::metadata_processor::MetaProcessorBuilder;
+use dcim_sort::observer::SortObserver;
+#[cfg(feature = "notifications")]
+use dcim_sort::notification::{Notifier, RunSummary};
+use dcim_sort::pipeline::{DEFAULT_TOP_N, format_byte_size, HistogramFormat, Pipeline, Report};
+use dcim_sort::runner::Runner;
+use dcim_sort::sorting::comparison::{FileComparer, HashAlgorithm, MtimeTolerance};
+use dcim_sort::sorting::dedupe;
+use dcim_sort::sorting::dedupe::DedupeAction;
+use dcim_sort::sorting::fs_support;
+use dcim_sort::sorting::hash_cache::HashCache;
+use dcim_sort::sorting::journal;
+use dcim_sort::sorting::journal::Journal;
+use dcim_sort::sorting::merge;
+use dcim_sort::sorting::preview::TargetPreview;
+use dcim_sort::sorting::simulation::{self, ScriptFormat, SimulationPrinter};
+use dcim_sort::sorting::throttle::Throttle;
+use dcim_sort::sorting::workspace::{WORKSPACE_DIR_NAME, Workspace};
+use dcim_sort::sorting::{ActionResult, DuplicateResolution, Operation, PATHSTR_FB, SorterBuilder};
+use dcim_sort::stats::LibraryStats;
+#[cfg(feature = "watch")]
+use dcim_sort::watch::DropWatcher;
+
+/// exit code if files failed to be sorted or the run failed at runtime
+const EXIT_ERRORS: i32 = 1;
+/// exit code for invalid arguments or configs, as for arguments rejected by clap
+const EXIT_CONFIG_ERROR: i32 = 2;
+/// exit code if the run was interrupted with Ctrl-C, as set by shells for SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
 
 /// helper struct to collect common options from command-line args
 struct MArgs {
     file: String,
+    /// read the input files from this list instead of scanning `file`, `-` for stdin
+    files_from: Option<String>,
     target_root: String,
-    max_recursion: u8,
-    debug: u64,
+    /// overrides the maximum recursion level of the config if given
+    max_recursion: Option<u8>,
     ignore_unknown_types: bool,
+    excludes: Vec<String>,
+    includes: Vec<String>,
+    no_ignore_files: bool,
+    /// print simulated files relative to the input and output directory
+    relative_paths: bool,
+    order: ScanOrder,
+    one_file_system: bool,
+    date_range: Option<DateRange>,
+    fallback_root: Option<PathBuf>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    preflight: bool,
+    temp_dir: Option<PathBuf>,
+    /// limit of copied bytes per second
+    bwlimit: Option<u64>,
     dry_run: bool,
     config_path: Option<PathBuf>,
+    /// sorter profile of the config to use
+    profile: Option<String>,
+    /// write the effective config to this file, `-` for stdout
+    dump_config: Option<PathBuf>,
+    /// log file given by --log-file, overriding the directory and filename of the config
+    log_file: Option<PathBuf>,
+    /// log directory given by --log-dir, overriding the directory of the config
+    log_dir: Option<PathBuf>,
+    no_log: bool,
+    space_check: SpaceCheck,
+    /// duplicate handling given by DCIM_SORT_DUPLICATE_RESOLUTION, overriding the config
+    env_dup_policy: Option<DuplicateResolution>,
+    /// duplicate handling given by --on-duplicate, overriding the environment and config
+    cli_dup_policy: Option<DuplicateResolution>,
     operation: Operation,
     thread_count: usize,
-    hash_operation: HashAlgorithm
+    meta_thread_count: usize,
+    /// hash algorithm given by -h or -H, overriding the config
+    hash_operation: Option<HashAlgorithm>,
+    partial_hash: Option<u64>,
+    hash_cache: Option<PathBuf>,
+    mtime_tolerance: Option<MtimeTolerance>,
+    session_id: Option<String>,
+    mode: RunMode,
+    clock_drift: DriftMode,
+    report_file: Option<PathBuf>,
+    top_n: usize,
+    histogram: Option<HistogramFormat>,
+    checkpoint: Option<PathBuf>,
+    manifest: Option<PathBuf>,
+    post_action: Option<String>,
+    /// print a script performing the operation instead of performing it
+    script: Option<(ScriptFormat, Operation)>,
+    resume: bool,
+    catalog: Option<PathBuf>,
+    notify: NotifyArgs
+}
+
+/// what to do with the files found by the scanner
+///
+/// # Variants
+/// - [RunMode::Sort] sort the input files into the output directory with the given operation
+/// - [RunMode::Recheck] print a migration plan for files in the output directory
+/// - [RunMode::Migrate] move files in the output directory to their computed location,
+///   recording all moves in the given journal
+/// - [RunMode::Undo] revert all moves recorded in the given journal
+/// - [RunMode::Verify] check the files recorded in the given manifest for missing or changed
+///   content
+/// - [RunMode::Merge] merge several sorted archives into the output directory, treating files
+///   with identical content as one file
+/// - [RunMode::Watch] sort the input files and then files arriving in the input once they have
+///   settled for the given time, until no file arrived for the optional idle timeout
+/// - [RunMode::Dedupe] find files with identical content in the output directory and apply the
+///   given action to them
+/// - [RunMode::Stats] read the metadata of the input files and print aggregate statistics
+///   without sorting anything
+/// - [RunMode::ValidateConfig] check the given config file and report all problems
+/// - [RunMode::InitConfig] write a commented config of the built-in defaults to the given file,
+///   overwriting it if the flag is set, or print it if no file is given
+/// - [RunMode::MigrateConfig] migrate the given config file to the current config version and
+///   write it to the optional file, overwriting it if the flag is set, or print it
+enum RunMode {
+    Sort,
+    Recheck,
+    Migrate(PathBuf),
+    Undo(PathBuf),
+    Verify(PathBuf),
+    Merge(Vec<PathBuf>),
+    Watch(time::Duration, Option<time::Duration>),
+    Dedupe(DedupeAction),
+    Stats,
+    ValidateConfig(PathBuf),
+    InitConfig(Option<PathBuf>, bool),
+    MigrateConfig(PathBuf, Option<PathBuf>, bool)
+}
+
+/// handling of camera clock drift
+///
+/// # Variants
+/// - [DriftMode::Off] timestamps are used as they are
+/// - [DriftMode::Detect] infer and print the drift per device before processing
+/// - [DriftMode::Correct] additionally correct timestamps by the inferred drift
+#[derive(PartialEq)]
+enum DriftMode {
+    Off,
+    Detect,
+    Correct
+}
+
+/// comparison of the size of the input with the free space of the file systems it is sorted to
+/// before copying or moving
+///
+/// # Variants
+/// - [SpaceCheck::Abort] exit before sorting anything if the input does not fit
+/// - [SpaceCheck::Warn] only print a warning if the input does not fit
+/// - [SpaceCheck::Off] do not scan the input in advance (default)
+#[derive(PartialEq)]
+enum SpaceCheck {
+    Abort,
+    Warn,
+    Off
+}
+
+/// helper struct for post-run notification targets
+#[cfg_attr(not(feature = "notifications"), allow(dead_code))]
+struct NotifyArgs {
+    webhook: Option<String>,
+    email: Option<String>,
+    smtp_server: String,
+    from: String
+}
+
+impl NotifyArgs {
+    fn is_enabled(&self) -> bool {
+        self.webhook.is_some() || self.email.is_some()
+    }
 }
 
 /// helper struct to collect pipeline configurations.
@@ -36,71 +209,289 @@ struct RuntimeCfg {
     output_dir: PathBuf,
     operation: Operation,
     dup_policy: DuplicateResolution,
-    thread_count: usize
+    /// effective hash algorithm of the config or command line
+    hash_algo: HashAlgorithm,
+    checkpoint: Option<Checkpoint>,
+    manifest: Option<Manifest>,
+    post_action: Option<PostActionHook>,
+    /// scanner settings from the config, overridden by command line arguments
+    scanner_cfg: ScannerCfg,
+    /// workspace for partial copies, removed at the end of the run if empty
+    workspace: Option<Workspace>,
+    /// saved when the last handle is dropped
+    hash_cache: Option<HashCache>,
+    #[cfg(feature = "catalog")]
+    catalog: Option<Catalog>,
+    /// log file settings from the config, no log is written if `None`
+    logger_cfg: Option<LoggerCfg>,
+    /// channel to the running logger, see [start_logger]
+    log: Option<Sender<LogReq>>,
+    /// set on Ctrl-C to stop scanning, see [handle_interrupts]
+    interrupt: Arc<AtomicBool>
 }
 
 /// parse command-line args
 fn parse_args() -> MArgs {
+    parse_args_from(std::env::args_os())
+}
+
+/// parse the command-line args `args`, starting with the binary name
+fn parse_args_from<I, T>(args: I) -> MArgs
+    where I: IntoIterator<Item = T>, T: Into<std::ffi::OsString> + Clone {
     let about_hash_algo = format!(
-        "hash algorithm used for comparing files in case the same file exist already in the target directory. Possible values are: {:?}",
-        HashAlgorithm::names());
+        "hash algorithm used for comparing files in case the same file exist already in the target directory, overrides the hashAlgorithm of the config. Possible values are: {:?} (default: {})",
+        HashAlgorithm::names(), SorterBuilder::default_hash_algorithm().name());
+    let default_top_n = DEFAULT_TOP_N.to_string();
 
     let name_outdir = "output-dir";
     let name_threads = "max-threads";
+    let name_meta_threads = "metadata-threads";
     let name_infile = "FILE";
+    let name_files_from = "files-from";
     let name_max_recursion = "max-recursion";
     let name_debug = "debug";
+    let name_quiet = "quiet";
+    let name_verbose = "verbose";
     let name_ignore_ftype = "ignore-other-types";
+    let name_exclude = "exclude";
+    let name_include = "include";
+    let name_no_ignore_files = "no-ignore-files";
+    let name_order = "order";
+    let name_one_fs = "one-file-system";
+    let name_since = "since";
+    let name_fallback_root = "fallback-root";
+    let name_min_size = "min-size";
+    let name_no_preflight = "no-preflight";
+    let name_relative_paths = "relative-paths";
+    let name_temp_dir = "temp-dir";
+    let name_bwlimit = "bwlimit";
+    let name_log_file = "log-file";
+    let name_log_dir = "log-dir";
+    let name_no_log = "no-log";
+    let name_max_size = "max-size";
+    let name_until = "until";
     let name_cfg_path = "config";
+    let name_profile = "profile";
+    let name_no_config = "no-config";
+    let name_dump_config = "dump-effective-config";
     let name_simulate = "dry-run";
     let name_operation = "OPERATION";
     let name_hash_algo = "hash-algorithm";
+    let name_on_duplicate = "on-duplicate";
+    let name_space_check = "space-check";
     let name_hash_algo_none = "hash-algorithm-none";
+    let name_quick_compare = "quick-compare";
+    let name_hash_cache = "hash-cache";
+    let name_mtime_tolerance = "mtime-tolerance";
+    let name_dst_shift = "dst-shift";
+    let name_report_file = "report-file";
+    let name_top = "top";
+    let name_checkpoint = "checkpoint";
+    let name_manifest = "manifest";
+    let name_post_action = "post-action";
+    let name_script = "script";
+    let name_histogram = "histogram";
+    let name_output_format = "output-format";
+    let name_resume = "resume";
+    let name_catalog = "catalog";
+    let name_recheck = "recheck";
+    let name_journal = "journal";
+    let name_verify_manifest = "MANIFEST";
+    let name_validate_file = "CONFIG";
+    let name_init_file = "FILE";
+    let name_init_force = "force";
+    let name_migrate_output = "output-file";
+    let name_merge_roots = "ROOT";
+    let name_merge_plan = "plan";
+    let name_merge_move = "move";
+    let name_watch_copy = "copy";
+    let name_watch_settle = "settle";
+    let name_watch_idle = "idle-timeout";
+    let name_dedupe_hardlink = "hardlink";
+    let name_dedupe_remove = "remove";
+    let name_detect_drift = "detect-clock-drift";
+    let name_correct_drift = "correct-clock-drift";
+    let name_provenance = "provenance";
+    let name_session_id = "session-id";
+    let name_notify_webhook = "notify-webhook";
+    let name_notify_email = "notify-email";
+    let name_smtp_server = "smtp-server";
+    let name_notify_from = "notify-from";
 
 
     let matches = App::new("dcim-sort - sort images from DCIM folders")
         .version("0.1.0")
         .author("MCL")
         .about("Sort images from (unintuitive) DCIM file structures")
+        .after_help("EXIT CODES:\n    0      success\n    1      completed with errors for some files, or failed at runtime\n    2      invalid arguments or config\n    130    interrupted with Ctrl-C")
         .setting(AppSettings::UnifiedHelpMessage)
         .arg(Arg::new(name_outdir)
             .required(false)
             .short('o')
             .long("output")
-            .default_value("sorted")
-            .help("Output directory"))
+            .takes_value(true)
+            .help("Output directory [default: sorted]. Overrides the environment variable DCIM_SORT_OUTPUT_DIR."))
         .arg(Arg::new(name_threads)
             .required(false)
             .short('p')
             .long("max-threads")
+            .takes_value(true)
+            .help("maximum count of threads [default: 0]. Setting to 0 will disable threading. Overrides the environment variable DCIM_SORT_THREADS."))
+        .arg(Arg::new(name_meta_threads)
+            .required(false)
+            .long("metadata-threads")
             .default_value("0")
-            .help("maximum count of threads. Setting to 0 will disable threading."))
+            .help("count of threads processing metadata in a separate stage, feeding the threads set by --max-threads which then only copy/move files. Setting to 0 will process metadata and files in the same threads."))
         .arg(Arg::new(name_max_recursion)
             .multiple_occurrences(false)
             .short('n')
             .long("max-recursion")
-            .help("maximum recursion level while scanning")
+            .help("maximum recursion level while scanning [default: 10]. Overrides the config.")
             .takes_value(true)
-            .default_value("10")
             .required(false))
         .arg(Arg::new(name_debug)
             .required(false)
             .multiple_occurrences(true)
             .long("debug")
             .short('d')
-            .help("show debug messages")
+            .help("show debug messages, same as --verbose")
+            .takes_value(false))
+        .arg(Arg::new(name_verbose)
+            .long("verbose")
+            .short('v')
+            .help("additionally print details like every scanned path and the outcome of every file")
+            .takes_value(false))
+        .arg(Arg::new(name_quiet)
+            .long("quiet")
+            .short('q')
+            .help("only print warnings and errors, e.g. no targets of a simulation and no report")
+            .conflicts_with_all(&[name_verbose, name_debug])
             .takes_value(false))
         .arg(Arg::new(name_ignore_ftype)
             .help("ignore unknown file types (based on file ending)")
             .short('i')
             .long("ignore-unknown")
             .required(false))
+        .arg(Arg::new(name_exclude)
+            .help("skip files and directories matching this pattern, e.g. '@eaDir' or '*.tmp'. '*' and '?' do not match '/', '**' does. Patterns containing a '/' are matched against the path relative to the input, others against the name only. Can be given multiple times.")
+            .long("exclude")
+            .value_name("GLOB")
+            .multiple_occurrences(true)
+            .takes_value(true)
+            .required(false))
+        .arg(Arg::new(name_order)
+            .help("order in which the entries of each directory are processed, for stable output of repeated simulations. The output of multiple sorting threads is still interleaved.")
+            .long("order")
+            .value_name("ORDER")
+            .possible_values(["none", "name", "mtime"])
+            .default_value("none")
+            .takes_value(true)
+            .required(false))
+        .arg(Arg::new(name_one_fs)
+            .help("do not descend into directories on other file systems than the input, e.g. mounted backups")
+            .short('x')
+            .long("one-file-system")
+            .required(false))
+        .arg(Arg::new(name_include)
+            .help("only process files matching this pattern, e.g. '*.jpg' (see --exclude). Can be given multiple times.")
+            .long("include")
+            .value_name("GLOB")
+            .multiple_occurrences(true)
+            .takes_value(true)
+            .required(false))
+        .arg(Arg::new(name_no_ignore_files)
+            .help("do not evaluate .dcimignore files (gitignore syntax) in scanned directories")
+            .long("no-ignore-files")
+            .required(false))
+        .arg(Arg::new(name_relative_paths)
+            .help("print the sources of simulated files relative to the input and the targets relative to the output directory")
+            .long("relative-paths")
+            .required(false))
+        .arg(Arg::new(name_no_preflight)
+            .help("do not create the output directory and test it for write access before processing any file, e.g. for targets which only become writable on demand")
+            .long("no-preflight")
+            .required(false))
+        .arg(Arg::new(name_temp_dir)
+            .help("directory for partial copies, which are renamed to their target once complete. Must be on the same file system as the output directory. [default: a hidden directory in the output directory]")
+            .long("temp-dir")
+            .value_name("DIR")
+            .takes_value(true)
+            .required(false))
+        .arg(Arg::new(name_bwlimit)
+            .help("limit the rate of copying files to RATE bytes per second for all threads together, suffixes K, M, G allowed (e.g. 10M). Moves are not limited.")
+            .long("bwlimit")
+            .value_name("RATE")
+            .takes_value(true)
+            .required(false))
+        .arg(Arg::new(name_log_file)
+            .help("write the log to FILE instead of the file configured in <logging>, '.log' is appended if missing. Enables the log without a <logging> element in the config")
+            .long("log-file")
+            .value_name("FILE")
+            .takes_value(true)
+            .conflicts_with(name_log_dir)
+            .required(false))
+        .arg(Arg::new(name_log_dir)
+            .help("write the log file to DIR instead of the directory configured in <logging> or the output directory. Enables the log without a <logging> element in the config")
+            .long("log-dir")
+            .value_name("DIR")
+            .takes_value(true)
+            .required(false))
+        .arg(Arg::new(name_no_log)
+            .help("do not write a log, even if <logging> is configured")
+            .long("no-log")
+            .conflicts_with_all(&[name_log_file, name_log_dir])
+            .required(false))
+        .arg(Arg::new(name_min_size)
+            .help("skip files smaller than this size in bytes, e.g. '10K' to skip thumbnails (suffixes K, M, G, T allowed)")
+            .long("min-size")
+            .value_name("SIZE")
+            .takes_value(true)
+            .required(false))
+        .arg(Arg::new(name_max_size)
+            .help("skip files larger than this size in bytes, e.g. '4G' to skip long videos (suffixes K, M, G, T allowed)")
+            .long("max-size")
+            .value_name("SIZE")
+            .takes_value(true)
+            .required(false))
+        .arg(Arg::new(name_fallback_root)
+            .help("place all files without supported metadata in this folder below the output directory, e.g. 'unsorted'. Overrides the root set in the config.")
+            .long("fallback-root")
+            .value_name("DIR")
+            .takes_value(true)
+            .required(false))
+        .arg(Arg::new(name_since)
+            .help("only process files with a metadata timestamp (or modification time if there is none) in or after this period, given as YYYY-mm, YYYY-mm-dd or YYYY-mm-ddTHH:MM:SS")
+            .long("since")
+            .value_name("DATE")
+            .takes_value(true)
+            .required(false))
+        .arg(Arg::new(name_until)
+            .help("only process files with a metadata timestamp (or modification time if there is none) in or before this period (see --since)")
+            .long("until")
+            .value_name("DATE")
+            .takes_value(true)
+            .required(false))
         .arg(Arg::new(name_cfg_path)
-            .help("configuration file input")
+            .help("configuration file input, read as TOML, JSON or YAML by its extension ('.toml', '.json', '.yaml'/'.yml') and as XML otherwise. Without it, './dcim-sort.xml' and '$XDG_CONFIG_HOME/dcim-sort/config.xml' are used if they exist.")
             .short('f')
             .long("config")
             .required(false)
             .takes_value(true))
+        .arg(Arg::new(name_no_config)
+            .help("do not look for a config in the standard locations and use the built-in defaults")
+            .long("no-config")
+            .conflicts_with(name_cfg_path)
+            .takes_value(false))
+        .arg(Arg::new(name_profile)
+            .help("use the sorter profile with this name, i.e. the <sorter> element with this 'name' attribute, instead of the one without a name")
+            .long("profile")
+            .conflicts_with(name_no_config)
+            .takes_value(true))
+        .arg(Arg::new(name_dump_config)
+            .help("write the config used by the run including all defaults and overrides to FILE, '-' for stdout")
+            .long("dump-effective-config")
+            .value_name("FILE")
+            .takes_value(true))
         .arg(Arg::new(name_simulate)
             .help("configure and exit without processing")
             .short('t')
@@ -109,15 +500,41 @@ fn parse_args() -> MArgs {
             .takes_value(false))
         .arg(Arg::new(name_infile)
             .multiple_occurrences(false)
-            .help("input file to process. In case of a folder, all children are processed recursively.")
-            .required(true))
+            .help("input file to process. In case of a folder, all children are processed recursively. '-' reads a list of files from stdin, see --files-from.")
+            .required(false))
+        .arg(Arg::new(name_files_from)
+            .help("process the files listed in this file (one path per line, '-' for stdin) instead of an input file, e.g. the output of find. Listed directories are skipped.")
+            .long("files-from")
+            .value_name("LIST")
+            .conflicts_with(name_infile)
+            .takes_value(true)
+            .required(false))
+        .arg(Arg::new(name_recheck)
+            .help("re-evaluate files in the output directory against the current config and print a migration plan for files that are not where they belong. Nothing is modified.")
+            .long("recheck")
+            .required(false)
+            .takes_value(false))
+        .arg(Arg::new(name_on_duplicate)
+            .help("handling of files already existing in the target: keep the existing file (ignore), overwrite it, or compare both and keep both if they differ (rename) or the existing/new one (favor-target/favor-source). Overrides the config and DCIM_SORT_DUPLICATE_RESOLUTION. [default: ignore]")
+            .long("on-duplicate")
+            .value_name("POLICY")
+            .possible_values(["ignore", "overwrite", "rename", "favor-target", "favor-source"])
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_space_check)
+            .help("before copying or moving, calculate the target of every file and compare the total size of the files going to each file system with its free space, abort or warn if they do not fit. Reads the metadata of all files in advance. Files skipped later, e.g. as duplicates, are included in the total.")
+            .long("space-check")
+            .value_name("MODE")
+            .possible_values(["abort", "warn", "off"])
+            .default_value("off")
+            .required(false)
+            .takes_value(true))
         .arg(Arg::new(name_hash_algo)
             .help(about_hash_algo.as_str())
             .multiple(false)
             .short('h')
             .long("hash-algorithm")
             .takes_value(true)
-            .default_value(HashAlgorithm::names()[0])
         )
         .arg(Arg::new(name_hash_algo_none)
             .help("disables file hashing for comparison (same as '-h none')")
@@ -126,113 +543,904 @@ fn parse_args() -> MArgs {
             .required(false)
             .takes_value(false)
         )
+        .arg(Arg::new(name_quick_compare)
+            .help("only hash the first and last SIZE bytes and the size of files when comparing them, e.g. '16M' for huge videos (suffixes K, M, G, T allowed). Files differing only in the middle are considered equal.")
+            .long("quick-compare")
+            .value_name("SIZE")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_hash_cache)
+            .help("keep file hashes in this cache file, so unchanged files are not hashed again in later runs. It is created if it does not exist.")
+            .long("hash-cache")
+            .value_name("FILE")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_mtime_tolerance)
+            .help("seconds modification times may differ when comparing files with '-h size-mtime' (default: 2, the granularity of FAT32/exFAT)")
+            .long("mtime-tolerance")
+            .value_name("SECS")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_dst_shift)
+            .help("with '-h size-mtime', also treat modification times differing by one hour as equal, as some tools shift them by daylight saving time")
+            .long("dst-shift")
+            .required(false)
+            .takes_value(false))
+        .arg(Arg::new(name_detect_drift)
+            .help("infer the camera clock drift per device from files with GPS timestamps and print it before processing")
+            .long("detect-clock-drift")
+            .required(false)
+            .takes_value(false))
+        .arg(Arg::new(name_correct_drift)
+            .help("like --detect-clock-drift, but also correct camera timestamps by the inferred drift")
+            .long("correct-clock-drift")
+            .required(false)
+            .takes_value(false))
+        .arg(Arg::new(name_top)
+            .help("list this many of the largest files and slowest operations in the final report, 0 to disable")
+            .long("top")
+            .value_name("N")
+            .default_value(default_top_n.as_str())
+            .takes_value(true)
+            .required(false))
+        .arg(Arg::new(name_report_file)
+            .help("write the final report as JSON to this file")
+            .long("report-file")
+            .value_name("FILE")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_histogram)
+            .help("print a histogram of files per month and per device after the run")
+            .long("histogram")
+            .value_name("FORMAT")
+            .possible_values(["text", "csv"])
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_output_format)
+            .help("'json' prints only progress events and the final report as one JSON object per line instead of human readable text, e.g. for GUIs or scripts. Errors are still printed to stderr.")
+            .long("output-format")
+            .value_name("FORMAT")
+            .possible_values(["text", "json"])
+            .default_value("text")
+            .conflicts_with_all(&[name_verbose, name_debug, name_histogram])
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_checkpoint)
+            .help("record processed files in this checkpoint file, so an interrupted run can be continued with --resume. Concurrent runs need distinct checkpoint files.")
+            .long("checkpoint")
+            .value_name("FILE")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_resume)
+            .help("skip files recorded in the checkpoint by a previous run. The input must be given as in that run.")
+            .long("resume")
+            .requires(name_checkpoint)
+            .takes_value(false))
+        .arg(Arg::new(name_manifest)
+            .help("record the SHA-256 hash of every moved or copied file in this manifest (in the format of sha256sum), appending to it if it exists, to check the archive with 'verify' later")
+            .long("manifest")
+            .value_name("FILE")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_post_action)
+            .help("run CMD after every moved or copied file, e.g. to generate thumbnails. {source}, {target} and {action} (\"moved\" or \"copied\") in CMD are replaced and passed as DCIM_SORT_SOURCE, DCIM_SORT_TARGET and DCIM_SORT_ACTION in the environment. CMD is run without a shell, use sh -c '...' for pipes. Within sh -c, refer to the files as \"$DCIM_SORT_SOURCE\" and \"$DCIM_SORT_TARGET\" instead of the placeholders, as quotes in file names would end the shell string.")
+            .long("post-action")
+            .value_name("CMD")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_script)
+            .help("do not move or copy files, but print a script of the commands doing so to STDOUT, so it can be reviewed and run by hand. Only for the operations \"move\" and \"copy\", files that would not be sorted are listed in comments.")
+            .long("script")
+            .value_name("FORMAT")
+            .possible_values(["sh", "powershell"])
+            .conflicts_with_all(&[name_verbose, name_debug, name_histogram, name_relative_paths])
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_catalog)
+            .help("skip files recorded in this catalog database as imported by a previous run and record sorted files in it, creating it if needed (requires the \"catalog\" feature)")
+            .long("catalog")
+            .value_name("DB")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_provenance)
+            .help("write the import session ID and original source path of copied files into XMP sidecars next to them (<file>.xmp), leaving the copies unchanged (requires the \"rexiv2\" feature)")
+            .long("provenance")
+            .required(false)
+            .takes_value(false))
+        .arg(Arg::new(name_session_id)
+            .help("session ID recorded with --provenance (default: generated from start time and PID)")
+            .long("session-id")
+            .value_name("ID")
+            .requires(name_provenance)
+            .takes_value(true))
+        .arg(Arg::new(name_notify_webhook)
+            .help("POST a JSON summary of the run to this URL when finished")
+            .long("notify-webhook")
+            .value_name("URL")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_notify_email)
+            .help("send a summary of the run to this mail address when finished")
+            .long("notify-email")
+            .value_name("ADDR")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::new(name_smtp_server)
+            .help("SMTP relay (HOST[:PORT]) used for --notify-email. Only plain SMTP without authentication is supported.")
+            .long("smtp-server")
+            .value_name("HOST")
+            .default_value("localhost")
+            .takes_value(true))
+        .arg(Arg::new(name_notify_from)
+            .help("sender address used for --notify-email")
+            .long("notify-from")
+            .value_name("ADDR")
+            .default_value("dcim-sort@localhost")
+            .takes_value(true))
         .subcommand(App::new("simulate")
             .help("only simulate processing with generated targets printed to STDOUT"))
         .subcommand(App::new("move")
             .help("move files"))
         .subcommand(App::new("copy")
             .help("copy files instead of moving"))
+        .subcommand(App::new("migrate")
+            .help("move files in the output directory whose location does not match the current config")
+            .arg(Arg::new(name_journal)
+                .help("journal of all moves for reverting with 'undo' (default: dcim-sort-migrate-<timestamp>.journal)")
+                .long("journal")
+                .value_name("FILE")
+                .takes_value(true)))
+        .subcommand(App::new("undo")
+            .help("revert a migration")
+            .arg(Arg::new(name_journal)
+                .help("journal written by 'migrate'")
+                .value_name("JOURNAL")
+                .required(true)))
+        .subcommand(App::new("merge")
+            .help("merge sorted archives into the output directory under the current config. Files with identical content are merged once, conflicting files are resolved with the configured duplicate handling.")
+            .arg(Arg::new(name_merge_roots)
+                .help("sorted archives to merge, files of earlier archives are preferred")
+                .value_name("ROOT")
+                .multiple_values(true)
+                .min_values(2)
+                .required(true))
+            .arg(Arg::new(name_merge_plan)
+                .help("only print the merge plan")
+                .long("plan")
+                .takes_value(false))
+            .arg(Arg::new(name_merge_move)
+                .help("move files instead of copying them")
+                .long("move")
+                .conflicts_with(name_merge_plan)
+                .takes_value(false)))
+        .subcommand(App::new("watch")
+            .help("sort the input and then keep watching it, moving files into the output directory as they arrive (requires the \"watch\" feature)")
+            .arg(Arg::new(name_watch_copy)
+                .help("copy files instead of moving them")
+                .long("copy")
+                .takes_value(false))
+            .arg(Arg::new(name_watch_settle)
+                .help("seconds a file has to stay unchanged before it is processed, so files still being written are skipped until complete")
+                .long("settle")
+                .value_name("SECS")
+                .default_value("2")
+                .takes_value(true))
+            .arg(Arg::new(name_watch_idle)
+                .help("stop once no file arrived for this many seconds (default: watch forever)")
+                .long("idle-timeout")
+                .value_name("SECS")
+                .takes_value(true)))
+        .subcommand(App::new("dedupe")
+            .help("find files with identical content in the output directory, e.g. in different folders after several device migrations, and list them. The first file in path order is kept.")
+            .arg(Arg::new(name_dedupe_hardlink)
+                .help("replace duplicates by hard links to the kept file, after comparing them byte by byte")
+                .long("hardlink")
+                .takes_value(false))
+            .arg(Arg::new(name_dedupe_remove)
+                .help("remove duplicates, after comparing them byte by byte to the kept file")
+                .long("remove")
+                .conflicts_with(name_dedupe_hardlink)
+                .takes_value(false)))
+        .subcommand(App::new("verify")
+            .help("re-hash all files recorded in a manifest written with --manifest and report missing or corrupted ones")
+            .arg(Arg::new(name_verify_manifest)
+                .help("manifest of one or more previous runs")
+                .required(true)))
+        .subcommand(App::new("stats")
+            .help("read the metadata of all input files and print the files per device, year, month and type, their total size and the files without metadata timestamp, e.g. to decide on a layout before sorting. Nothing is written."))
+        .subcommand(App::new("config")
+            .help("work with config files")
+            .subcommand_required(true)
+            .subcommand(App::new("validate")
+                .help("check a config file and report all errors with their element, line and a suggested fix")
+                .arg(Arg::new(name_validate_file)
+                    .help("config file to check (default: the file given by --config)")
+                    .value_name("CONFIG")))
+            .subcommand(App::new("init")
+                .help("write a commented TOML config equivalent to the built-in defaults as a starting point")
+                .arg(Arg::new(name_init_file)
+                    .help("file to write, e.g. 'dcim-sort.toml' (default: print to stdout)")
+                    .value_name("FILE"))
+                .arg(Arg::new(name_init_force)
+                    .help("overwrite an existing file")
+                    .long("force")
+                    .takes_value(false)))
+            .subcommand(App::new("migrate")
+                .help("update a config file of an older version to the current one, keeping the order of its segments")
+                .arg(Arg::new(name_validate_file)
+                    .help("config file to migrate (default: the file given by --config)")
+                    .value_name("CONFIG"))
+                .arg(Arg::new(name_migrate_output)
+                    .help("write the migrated config as XML to this file (default: print to stdout)")
+                    .long("output-file")
+                    .value_name("FILE")
+                    .takes_value(true))
+                .arg(Arg::new(name_init_force)
+                    .help("overwrite an existing output file")
+                    .long("force")
+                    .takes_value(false))))
         .subcommand_value_name("OPERATION")
         .subcommand_help_heading("OPERATIONS")
-        .get_matches();
+        .get_matches_from(args);
 
-    let file = matches.value_of(name_infile).unwrap();
-    let output_dir = matches.value_of(name_outdir).unwrap();
+    let verbosity = match (matches.is_present(name_quiet), matches.is_present(name_verbose) || matches.occurrences_of(name_debug) > 0) {
+        (true, _) => Verbosity::Quiet,
+        (false, true) => Verbosity::Verbose,
+        (false, false) => Verbosity::Normal
+    };
+    console::set_verbosity(verbosity);
+    if let Some(format) = matches.value_of(name_output_format).and_then(OutputFormat::parse) {
+        console::set_output_format(format);
+    }
+
+    let file = matches.value_of(name_infile).unwrap_or("");
+    let files_from = match (file, matches.value_of(name_files_from)) {
+        ("-", _) => Some(String::from("-")),
+        (_, list) => list.map(String::from)
+    };
+    // settings which can be overridden by environment variables, e.g. in containers
+    let output_dir = Layered::new(String::from("sorted"))
+        .layer(env_or_exit(overrides::env_value(overrides::ENV_OUTPUT_DIR, |s| Ok::<String, String>(String::from(s)))), SettingSource::Env)
+        .layer(matches.value_of(name_outdir).map(String::from), SettingSource::Cli);
+    print_env_override("output directory", overrides::ENV_OUTPUT_DIR, &output_dir);
+    let env_dup_policy = env_or_exit(overrides::env_value(overrides::ENV_DUPLICATE_RESOLUTION, |s| DuplicateResolution::parse(s)
+        .ok_or("expected one of ignore, overwrite, rename, favor_target, favor_source")));
 
 
-    let max_recursion: u8 = matches.value_of_t_or_exit(name_max_recursion);
-    let max_threads: usize = matches.value_of_t_or_exit(name_threads);
-    let debug = matches.occurrences_of(name_debug);
+    let max_recursion = match matches.is_present(name_max_recursion) {
+        true => Some(matches.value_of_t_or_exit(name_max_recursion)),
+        false => None
+    };
+    let max_threads = Layered::new(0usize)
+        .layer(env_or_exit(overrides::env_value(overrides::ENV_THREADS, usize::from_str)), SettingSource::Env)
+        .layer(match matches.is_present(name_threads) {
+            true => Some(matches.value_of_t_or_exit(name_threads)),
+            false => None
+        }, SettingSource::Cli);
+    print_env_override("maximum count of threads", overrides::ENV_THREADS, &max_threads);
+    let meta_threads: usize = matches.value_of_t_or_exit(name_meta_threads);
     let ignore_unknown = matches.is_present(name_ignore_ftype);
     let dry_run = matches.is_present(name_simulate);
 
-    let cfg_path = match matches.is_present(name_cfg_path) {
-        true => {
-            let s = matches.value_of(name_cfg_path).unwrap();
-            let p = PathBuf::from(s);
-            if !p.is_file() {
-                panic!("[ERROR] file does not exist: {}", s);
+    let cfg_path = match (matches.value_of(name_cfg_path), matches.is_present(name_no_config)) {
+        (Some(s), _) => Some(PathBuf::from(s)),
+        (None, true) => None,
+        (None, false) => {
+            let found = config::discover_config();
+            if let Some(path) = found.as_ref().filter(|_| console::shows(Verbosity::Normal)) {
+                println!("[INFO] using config \"{}\"", path.to_str().unwrap_or(PATHSTR_FB));
             }
-            Some(p)
+            found
         }
-        false => None
     };
 
     let override_no_hash = matches.is_present(name_hash_algo_none);
     let hash_algo = match override_no_hash {
-        true => HashAlgorithm::None,
-        false => HashAlgorithm::parse(matches.value_of(name_hash_algo).unwrap())
+        true => Some(HashAlgorithm::None),
+        false => matches.value_of(name_hash_algo).map(HashAlgorithm::parse)
     };
 
-    let operation = match matches.subcommand_name().expect("Missing operation!") {
-        "simulate" => Operation::Print,
-        "move" => Operation::Move,
-        "copy" => Operation::Copy,
-        o => panic!("Invalid operation: {}", o)
+    let session_id = match matches.is_present(name_provenance) {
+        true => Some(match matches.value_of(name_session_id) {
+            Some(id) => String::from(id),
+            None => format!("{}-{}", chrono::Local::now().format("%Y%m%dT%H%M%S"), std::process::id())
+        }),
+        false => None
+    };
+
+    let mut sizes = [None, None, None, None];
+    for (i, name) in [name_min_size, name_max_size, name_quick_compare, name_bwlimit].iter().enumerate() {
+        if let Some(s) = matches.value_of(name) {
+            match parse_byte_size(s) {
+                Some(size) => sizes[i] = Some(size),
+                None => {
+                    eprintln!("[ERROR] Invalid size for --{}: {}", name, s);
+                    std::process::exit(EXIT_CONFIG_ERROR);
+                }
+            }
+        }
+    }
+
+    let fallback_root = matches.value_of(name_fallback_root).map(PathBuf::from);
+    if fallback_root.as_ref().is_some_and(|r| r.is_absolute()) {
+        eprintln!("[ERROR] the fallback root must be a relative path");
+        std::process::exit(EXIT_CONFIG_ERROR);
+    }
+
+    let date_range = match (matches.value_of(name_since), matches.value_of(name_until)) {
+        (None, None) => None,
+        (since, until) => match DateRange::parse(since, until) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                eprintln!("[ERROR] {}", e);
+                std::process::exit(EXIT_CONFIG_ERROR);
+            }
+        }
+    };
+
+    let notify = NotifyArgs {
+        webhook: matches.value_of(name_notify_webhook).map(String::from),
+        email: matches.value_of(name_notify_email).map(String::from),
+        smtp_server: String::from(matches.value_of(name_smtp_server).unwrap()),
+        from: String::from(matches.value_of(name_notify_from).unwrap())
+    };
+
+    let (operation, mode) = match matches.subcommand() {
+        Some(("simulate", _)) => (Operation::Print, RunMode::Sort),
+        Some(("move", _)) => (Operation::Move, RunMode::Sort),
+        Some(("copy", _)) => (Operation::Copy, RunMode::Sort),
+        Some(("migrate", sub)) => {
+            let journal = match sub.value_of(name_journal) {
+                Some(j) => PathBuf::from(j),
+                // the process ID keeps journals of concurrent runs apart
+                None => PathBuf::from(format!("dcim-sort-migrate-{}-{}.journal",
+                                              chrono::Local::now().format("%Y%m%dT%H%M%S"),
+                                              std::process::id()))
+            };
+            (Operation::Move, RunMode::Migrate(journal))
+        },
+        Some(("merge", sub)) => {
+            let roots = sub.values_of(name_merge_roots).unwrap().map(PathBuf::from).collect();
+            let op = match (sub.is_present(name_merge_plan), sub.is_present(name_merge_move)) {
+                (true, _) => Operation::Print,
+                (false, true) => Operation::Move,
+                (false, false) => Operation::Copy
+            };
+            (op, RunMode::Merge(roots))
+        },
+        Some(("undo", sub)) => (Operation::Move, RunMode::Undo(PathBuf::from(sub.value_of(name_journal).unwrap()))),
+        Some(("watch", sub)) => {
+            let settle: u64 = sub.value_of_t_or_exit(name_watch_settle);
+            let idle_timeout = match sub.is_present(name_watch_idle) {
+                true => Some(time::Duration::from_secs(sub.value_of_t_or_exit(name_watch_idle))),
+                false => None
+            };
+            let op = match sub.is_present(name_watch_copy) {
+                true => Operation::Copy,
+                false => Operation::Move
+            };
+            (op, RunMode::Watch(time::Duration::from_secs(settle), idle_timeout))
+        },
+        Some(("dedupe", sub)) => {
+            let action = match (sub.is_present(name_dedupe_hardlink), sub.is_present(name_dedupe_remove)) {
+                (true, _) => DedupeAction::Hardlink,
+                (false, true) => DedupeAction::Remove,
+                (false, false) => DedupeAction::Report
+            };
+            // only listing duplicates never writes to the output directory
+            let op = match action {
+                DedupeAction::Report => Operation::Print,
+                _ => Operation::Move
+            };
+            (op, RunMode::Dedupe(action))
+        },
+        Some(("stats", _)) => (Operation::Print, RunMode::Stats),
+        Some(("verify", sub)) => (Operation::Print, RunMode::Verify(PathBuf::from(sub.value_of(name_verify_manifest).unwrap()))),
+        Some(("config", sub)) => match sub.subcommand() {
+            Some(("validate", v)) => match v.value_of(name_validate_file).map(PathBuf::from).or_else(|| cfg_path.clone()) {
+                Some(path) => (Operation::Print, RunMode::ValidateConfig(path)),
+                None => {
+                    eprintln!("[ERROR] Missing config file to validate!");
+                    std::process::exit(EXIT_CONFIG_ERROR);
+                }
+            },
+            Some(("init", i)) => (Operation::Print, RunMode::InitConfig(i.value_of(name_init_file).map(PathBuf::from), i.is_present(name_init_force))),
+            Some(("migrate", m)) => match m.value_of(name_validate_file).map(PathBuf::from).or_else(|| cfg_path.clone()) {
+                Some(path) => (Operation::Print, RunMode::MigrateConfig(path, m.value_of(name_migrate_output).map(PathBuf::from), m.is_present(name_init_force))),
+                None => {
+                    eprintln!("[ERROR] Missing config file to migrate!");
+                    std::process::exit(EXIT_CONFIG_ERROR);
+                }
+            },
+            _ => {
+                eprintln!("[ERROR] Missing config operation!");
+                std::process::exit(EXIT_CONFIG_ERROR);
+            }
+        },
+        Some((o, _)) => {
+            eprintln!("[ERROR] Invalid operation: {}", o);
+            std::process::exit(EXIT_CONFIG_ERROR);
+        }
+        // a recheck never modifies anything
+        None if matches.is_present(name_recheck) => (Operation::Print, RunMode::Recheck),
+        None => {
+            eprintln!("[ERROR] Missing operation!");
+            std::process::exit(EXIT_CONFIG_ERROR);
+        }
+    };
+
+    if matches!(mode, RunMode::Sort | RunMode::Stats) && file.is_empty() && files_from.is_none() {
+        eprintln!("[ERROR] Missing input file!");
+        std::process::exit(EXIT_CONFIG_ERROR);
+    }
+    if matches!(mode, RunMode::Watch(..)) && (file.is_empty() || files_from.is_some()) {
+        eprintln!("[ERROR] watching requires an input directory!");
+        std::process::exit(EXIT_CONFIG_ERROR);
+    }
+
+    // the operation of a script is only performed when running the script
+    let script = match (matches.value_of(name_script).and_then(ScriptFormat::parse), &mode, operation) {
+        (None, _, _) => None,
+        (Some(format), RunMode::Sort, Operation::Move | Operation::Copy) => Some((format, operation)),
+        (Some(_), _, _) => {
+            eprintln!("[ERROR] a script can only be printed for the operations \"move\" and \"copy\"!");
+            std::process::exit(EXIT_CONFIG_ERROR);
+        }
+    };
+    let operation = match script {
+        Some(_) => {
+            console::set_output_format(OutputFormat::Script);
+            Operation::Print
+        },
+        None => operation
     };
 
 
     MArgs {
         file: String::from(file),
-        target_root: String::from(output_dir),
+        files_from,
+        target_root: output_dir.into_value(),
         max_recursion,
-        debug,
         ignore_unknown_types: ignore_unknown,
+        excludes: matches.values_of(name_exclude).map(|v| v.map(String::from).collect()).unwrap_or_default(),
+        includes: matches.values_of(name_include).map(|v| v.map(String::from).collect()).unwrap_or_default(),
+        no_ignore_files: matches.is_present(name_no_ignore_files),
+        relative_paths: matches.is_present(name_relative_paths),
+        one_file_system: matches.is_present(name_one_fs),
+        order: ScanOrder::parse(matches.value_of(name_order).unwrap()).unwrap(),
+        date_range,
+        fallback_root,
+        preflight: !matches.is_present(name_no_preflight),
+        temp_dir: matches.value_of(name_temp_dir).map(PathBuf::from),
+        min_size: sizes[0],
+        max_size: sizes[1],
         dry_run,
         config_path: cfg_path,
+        profile: matches.value_of(name_profile).map(String::from),
+        dump_config: matches.value_of(name_dump_config).map(PathBuf::from),
+        log_file: matches.value_of(name_log_file).map(PathBuf::from),
+        log_dir: matches.value_of(name_log_dir).map(PathBuf::from),
+        no_log: matches.is_present(name_no_log),
+        env_dup_policy,
+        cli_dup_policy: matches.value_of(name_on_duplicate).and_then(DuplicateResolution::parse),
+        space_check: match matches.value_of(name_space_check) {
+            Some("abort") => SpaceCheck::Abort,
+            Some("warn") => SpaceCheck::Warn,
+            _ => SpaceCheck::Off
+        },
         operation,
-        thread_count: max_threads,
-        hash_operation: hash_algo
+        thread_count: max_threads.into_value(),
+        meta_thread_count: meta_threads,
+        hash_operation: hash_algo,
+        partial_hash: sizes[2],
+        bwlimit: sizes[3],
+        hash_cache: matches.value_of(name_hash_cache).map(PathBuf::from),
+        mtime_tolerance: match (matches.is_present(name_mtime_tolerance), matches.is_present(name_dst_shift)) {
+            (false, false) => None,
+            (has_window, dst_shift) => Some(MtimeTolerance::new(
+                match has_window {
+                    true => time::Duration::from_secs(matches.value_of_t_or_exit(name_mtime_tolerance)),
+                    false => MtimeTolerance::default().window
+                },
+                dst_shift
+            ))
+        },
+        session_id,
+        mode,
+        clock_drift: match (matches.is_present(name_correct_drift), matches.is_present(name_detect_drift)) {
+            (true, _) => DriftMode::Correct,
+            (false, true) => DriftMode::Detect,
+            (false, false) => DriftMode::Off
+        },
+        report_file: matches.value_of(name_report_file).map(PathBuf::from),
+        top_n: matches.value_of_t_or_exit(name_top),
+        histogram: match matches.value_of(name_histogram) {
+            Some("csv") => Some(HistogramFormat::Csv),
+            Some(_) => Some(HistogramFormat::Text),
+            None => None
+        },
+        checkpoint: matches.value_of(name_checkpoint).map(PathBuf::from),
+        manifest: matches.value_of(name_manifest).map(PathBuf::from),
+        post_action: matches.value_of(name_post_action).map(String::from),
+        script,
+        resume: matches.is_present(name_resume),
+        catalog: matches.value_of(name_catalog).map(PathBuf::from),
+        notify
     }
 }
 
 /// a default sorter configuration
- fn generate_default_sorter() -> SorterBuilder {
-    Sorter::builder()
-        .segment(MakeModelPattern::new()
-            .part(DevicePart::Make)
-            .part(DevicePart::Model)
-            .separator('_')
-            .replace_spaces(true)
-            .case_normalization(CaseNormalization::Lowercase)
-            .fallback(String::from("unknown_device"))
-            .build())
-        .segment(ScreenshotPattern::new(String::from("screenshots")))
-        .segment(DateTimePattern::new()
-            .part(DateTimePart::Year)
-            .part(DateTimePart::Month)
-            .build())
-        .fallback(SimpleFileTypePattern::new().build())
-}
-
-/// main procedure for multi-threading scenarios
-fn process_threaded(mut cfg: RuntimeCfg, args: &MArgs) {
-
-    let mut controller = PipelineController::new(
-        args.thread_count,
+ /// the value of an environment variable read by [overrides::env_value], exiting if it is invalid
+fn env_or_exit<T>(value: Result<Option<T>, String>) -> Option<T> {
+    match value {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            std::process::exit(EXIT_CONFIG_ERROR);
+        }
+    }
+}
+
+/// tell about settings taken from the environment, which are easily overlooked
+fn print_env_override<T: std::fmt::Display>(setting: &str, var: &str, value: &Layered<T>) {
+    if value.source() == SettingSource::Env && console::shows(Verbosity::Normal) {
+        println!("[INFO] using {} \"{}\" of environment variable {}", setting, value.value(), var);
+    }
+}
+
+/// main procedure for sorting the input, single-threaded if no threads are configured
+fn process_sort(cfg: RuntimeCfg, args: &MArgs) -> (Report, Vec<(PathBuf, String)>) {
+    if cfg.scanner.single_file().is_some() {
+        return process_single(cfg, args);
+    }
+    let mut runner = create_runner(cfg, args);
+
+    let time_start = time::Instant::now();
+
+    let report = runner.run();
+    print_resume_skipped(runner.scanner());
+    print_scan_errors(runner.scanner());
+
+    let elapsed = chrono::Duration::from_std(time_start.elapsed()).unwrap();
+    if console::shows(Verbosity::Normal) {
+        println!("finished in {:.4} seconds or {:03}:{:02}:{:02}", elapsed.num_milliseconds() as f64 / 1000.0,
+                 elapsed.num_hours(),
+                 elapsed.num_minutes() % 60,
+                 elapsed.num_seconds() % 3600
+        );
+        println!("{}", report);
+    }
+    (report, runner.scanner().errors().to_vec())
+}
+
+/// sort the input and then watch it for arriving files, see [RunMode::Watch]
+#[cfg(feature = "watch")]
+fn process_watch(cfg: RuntimeCfg, args: &MArgs, settle: time::Duration, idle_timeout: Option<time::Duration>) -> (Report, Vec<(PathBuf, String)>) {
+    let watcher = match DropWatcher::start(Path::new(&args.file), settle) {
+        Ok(w) => w,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+    if console::shows(Verbosity::Normal) {
+        println!("watching \"{}\"", args.file);
+    }
+    let mut runner = create_runner(cfg, args);
+    let report = match runner.watch(&watcher, idle_timeout) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+    if console::shows(Verbosity::Normal) {
+        println!("{}", report);
+    }
+    (report, runner.scanner().errors().to_vec())
+}
+
+#[cfg(not(feature = "watch"))]
+fn process_watch(_cfg: RuntimeCfg, _args: &MArgs, _settle: time::Duration, _idle_timeout: Option<time::Duration>) -> (Report, Vec<(PathBuf, String)>) {
+    eprintln!("[ERROR] watching requested, but dcim-sort was built without the \"watch\" feature");
+    std::process::exit(EXIT_ERRORS);
+}
+
+/// create a runner for the input with the pipeline settings given in `args`
+fn create_runner(cfg: RuntimeCfg, args: &MArgs) -> Runner {
+    let mut runner = Runner::new(
+        cfg.scanner,
         cfg.proc_builder,
         cfg.sorter_builder,
-        cfg.operation,
         cfg.output_dir.as_path(),
+        cfg.operation,
         cfg.dup_policy
     );
+    runner.set_threads(args.thread_count, args.meta_thread_count);
+    if let Some(cp) = cfg.checkpoint {
+        runner.set_checkpoint(cp);
+    }
+    if let Some(manifest) = cfg.manifest {
+        runner.set_manifest(manifest);
+    }
+    if let Some(hook) = cfg.post_action {
+        runner.set_post_action(hook);
+    }
+    #[cfg(feature = "catalog")]
+    if let Some(catalog) = cfg.catalog {
+        runner.set_catalog(catalog);
+    }
+    if let Some(range) = args.date_range {
+        runner.set_date_range(range);
+    }
+    runner.set_top_n(args.top_n);
+    if let Some(log) = cfg.log {
+        runner.set_log(log);
+    }
+    runner.set_interrupt(cfg.interrupt);
+    runner
+}
 
-    let time_start = time::Instant::now();
+/// fast path for a single input file: evaluate and execute it on the calling thread without
+/// setting up any threads, printing its metadata and target details
+fn process_single(cfg: RuntimeCfg, args: &MArgs) -> (Report, Vec<(PathBuf, String)>) {
+    let mut runner = create_runner(cfg, args);
+    runner.set_threads(0, 0);
+    if console::shows(Verbosity::Normal) {
+        runner.set_observer(Arc::new(FileDetails));
+        runner.set_preview(true);
+    }
 
-    cfg.scanner.scan_pipeline(&mut controller);
-    let report = controller.shutdown();
+    // the scanner still applies all filters, so the file may be skipped
+    let report = runner.run();
+    print_resume_skipped(runner.scanner());
+    print_scan_errors(runner.scanner());
 
-    let elapsed = chrono::Duration::from_std(time_start.elapsed()).unwrap();
-    println!("finished in {:.4} seconds or {:03}:{:02}:{:02}", elapsed.num_milliseconds() as f64 / 1000.0,
-             elapsed.num_hours(),
-             elapsed.num_minutes() % 60,
-             elapsed.num_seconds() % 3600
+    if report.count_error > 0 && console::shows(Verbosity::Normal) {
+        println!("{}", report);
+    }
+    (report, runner.scanner().errors().to_vec())
+}
+
+/// prints the details of a single file sorted by [process_single]
+struct FileDetails;
+
+impl SortObserver for FileDetails {
+    fn on_target_previewed(&self, file: &ImgInfo, preview: &TargetPreview) {
+        print_file_details(file, preview);
+    }
+
+    fn on_action_executed(&self, _source: &Path, _target: Option<&Path>, result: &ActionResult) {
+        match result {
+            ActionResult::Moved => println!("moved"),
+            ActionResult::Copied => println!("copied"),
+            ActionResult::Skipped => ()
+        }
+    }
+}
+
+/// print the metadata of `file` and details of its target
+fn print_file_details(file: &ImgInfo, preview: &TargetPreview) {
+    let meta = file.metadata();
+    println!("file: {}", file.path().to_str().unwrap_or(PATHSTR_FB));
+    println!("\t==== metadata ====\n\tmake: {}\n\tmodel: {}\n\ttimestamp: {}\n\tis_screenshot: {}",
+             meta.make(),
+             meta.model(),
+             match meta.created_at() {
+                 None => "<none>".to_string(),
+                 Some(t) => t.format("%F %T").to_string()
+             },
+             meta.is_screenshot()
+    );
+    println!("\t==== sorting =====\n\ttarget: {}",
+             preview.target().map_or(String::from("<none>"), |t| t.to_str().unwrap_or(PATHSTR_FB).to_string()));
+    for (name, value) in &preview.segments {
+        println!("\tsegment: {} -> {}", name, value.as_deref().unwrap_or("<skipped>"));
+    }
+    for warning in preview.warnings() {
+        println!("\twarning: {}", warning);
+    }
+}
+
+fn print_resume_skipped(scanner: &Scanner) {
+    if scanner.skipped() > 0 && console::shows(Verbosity::Normal) {
+        println!("skipped {} files already processed according to the checkpoint", scanner.skipped());
+    }
+}
+
+/// scan and read the metadata of all files once to infer the clock drift of each device
+fn detect_clock_drift(cfg: &mut RuntimeCfg) -> ClockDrift {
+    let processor = cfg.proc_builder.build_clone();
+    let mut estimator = DriftEstimator::new();
+    for mut file in cfg.scanner.scan() {
+        processor.process(&mut file);
+        estimator.add(file.metadata());
+    }
+    let drift = estimator.estimate(DEFAULT_MIN_SAMPLES, DEFAULT_TOLERANCE_SECS, DEFAULT_MIN_OFFSET_SECS);
+
+    if !console::shows(Verbosity::Normal) {
+        return drift;
+    }
+    if drift.is_empty() {
+        println!("no camera clock drift detected");
+    }
+    else {
+        println!("detected camera clock drift:");
+        for ((make, model), offset) in drift.offsets() {
+            println!("  {:<30} {:>+10}s", format!("{} {}", make, model), offset.num_seconds());
+        }
+    }
+    drift
+}
+
+/// re-evaluate all files in the output directory and print a migration plan for files whose
+/// computed location differs from their current one
+fn process_recheck(mut cfg: RuntimeCfg) {
+    let processor = cfg.proc_builder.build_clone();
+    let sorter = cfg.sorter_builder.build_sync();
+
+    let mut count_checked = 0;
+    let mut count_moved = 0;
+    let files = cfg.scanner.scan();
+    for mut file in files {
+        processor.process(&mut file);
+        sorter.check_date(&mut file);
+        count_checked += 1;
+        match sorter.recheck(&file, cfg.output_dir.as_path()) {
+            Ok(None) => if console::shows(Verbosity::Verbose) {
+                println!("ok \"{}\"", file.path().to_str().unwrap_or(PATHSTR_FB));
+            },
+            Ok(Some(target)) => {
+                count_moved += 1;
+                println!("\"{}\" -> \"{}\"",
+                         file.path().to_str().unwrap_or(PATHSTR_FB),
+                         target.to_str().unwrap_or(PATHSTR_FB)
+                );
+            },
+            Err(e) => eprintln!("Error while rechecking file: {}", e)
+        }
+    }
+    print_scan_errors(&cfg.scanner);
+    println!("{} of {} file(s) do not match the current configuration", count_moved, count_checked);
+}
+
+/// read the metadata of all input files and print their statistics, as JSON for
+/// [OutputFormat::Json]
+fn process_stats(mut cfg: RuntimeCfg) {
+    let processor = cfg.proc_builder.build_clone();
+    let mut stats = LibraryStats::new();
+    cfg.scanner.scan_with(|mut file| {
+        processor.process(&mut file);
+        stats.add(&file);
+    });
+    print_scan_errors(&cfg.scanner);
+    match console::output_format() {
+        OutputFormat::Text | OutputFormat::Script => print!("{}", stats),
+        OutputFormat::Json => match stats.to_json() {
+            Ok(json) => println!("{}", json),
+            Err(e) => eprintln!("[ERROR] failed to serialize statistics: {}", e)
+        }
+    }
+}
+
+/// find files with identical content in the output directory and apply `action` to them
+fn process_dedupe(mut cfg: RuntimeCfg, args: &MArgs, action: DedupeAction) {
+    let comparer = FileComparer::new(false, cfg.hash_algo)
+        .partial_hash(args.partial_hash)
+        .hash_cache(cfg.hash_cache.clone())
+        .mtime_tolerance(args.mtime_tolerance.unwrap_or_default());
+    let result = dedupe::find_duplicates(cfg.scanner.scan(), &comparer);
+    print_scan_errors(&cfg.scanner);
+    for (path, reason) in &result.errors {
+        eprintln!("[ERROR] could not hash \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), reason);
+    }
+
+    let mut count_duplicates = 0;
+    let mut count_failed = 0;
+    let mut reclaimable = 0;
+    for group in &result.groups {
+        println!("\"{}\" ({})", group.kept.to_str().unwrap_or(PATHSTR_FB), format_byte_size(group.size));
+        for dup in &group.duplicates {
+            println!("  = \"{}\"", dup.to_str().unwrap_or(PATHSTR_FB));
+        }
+        let failed = group.apply(action, &comparer);
+        for (path, reason) in &failed {
+            eprintln!("[ERROR] could not deduplicate \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), reason);
+        }
+        count_duplicates += group.duplicates.len();
+        count_failed += failed.len();
+        reclaimable += group.reclaimable();
+    }
+    println!("found {} duplicate(s) of {} file(s), {} reclaimable",
+             count_duplicates, result.groups.len(), format_byte_size(reclaimable));
+    match action {
+        DedupeAction::Report => (),
+        DedupeAction::Hardlink => println!("hard linked {} duplicate(s)", count_duplicates - count_failed),
+        DedupeAction::Remove => println!("removed {} duplicate(s)", count_duplicates - count_failed)
+    }
+}
+
+/// move all files in the output directory whose computed location differs from their current
+/// one, recording every move in `journal_path`
+fn process_migrate(mut cfg: RuntimeCfg, journal_path: &Path) {
+    let processor = cfg.proc_builder.build_clone();
+    let mut sorter = cfg.sorter_builder.build_sync();
+    let mut journal = match Journal::create(journal_path) {
+        Ok(j) => j,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+
+    let mut count_checked = 0;
+    let mut count_moved = 0;
+    let files = cfg.scanner.scan();
+    for mut file in files {
+        processor.process(&mut file);
+        sorter.check_date(&mut file);
+        count_checked += 1;
+        match sorter.migrate(&file, cfg.output_dir.as_path(), &mut journal) {
+            Ok(None) => if console::shows(Verbosity::Verbose) {
+                println!("ok \"{}\"", file.path().to_str().unwrap_or(PATHSTR_FB));
+            },
+            Ok(Some(target)) => {
+                count_moved += 1;
+                println!("moved \"{}\" -> \"{}\"",
+                         file.path().to_str().unwrap_or(PATHSTR_FB),
+                         target.to_str().unwrap_or(PATHSTR_FB)
+                );
+            },
+            Err(e) => eprintln!("Error while migrating file: {}", e)
+        }
+    }
+    print_scan_errors(&cfg.scanner);
+    println!("migrated {} of {} file(s), revert with: undo \"{}\"",
+             count_moved,
+             count_checked,
+             journal.path().to_str().unwrap_or(PATHSTR_FB)
     );
-    println!("{}", report);
 }
 
-/// main procedure for single-threaded scenarios
-fn process_sync(mut cfg: RuntimeCfg, args: &MArgs) {
+/// merge the archive scanned by `cfg` and all archives in `other_roots` into the output
+/// directory. Files with identical content are only merged once.
+fn process_merge(mut cfg: RuntimeCfg, args: &MArgs, other_roots: &[PathBuf]) -> (Report, Vec<(PathBuf, String)>) {
+    let mut files = cfg.scanner.scan();
+    let mut errors = cfg.scanner.errors().to_vec();
+    // files extracted from a backup are removed with its scanner, so all are kept until the end
+    let mut scanners = Vec::with_capacity(other_roots.len());
+    for root in other_roots {
+        let scanner = configure_scanner(Scanner::builder(root.as_path()), args, &cfg.scanner_cfg)
+            .pair_detection(cfg.sorter_builder.get_pair_policy().map(|p| p.preferred()))
+            .build();
+        let mut scanner = match scanner {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("[ERROR] {}", e);
+                std::process::exit(EXIT_ERRORS);
+            }
+        };
+        if scanner.is_backup() && matches!(cfg.operation, Operation::Move) {
+            eprintln!("[ERROR] \"{}\" is a device backup, its files can only be merged by copying them", root.to_str().unwrap_or(PATHSTR_FB));
+            std::process::exit(EXIT_ERRORS);
+        }
+        files.extend(scanner.scan());
+        print_scan_errors(&scanner);
+        errors.extend_from_slice(scanner.errors());
+        scanners.push(scanner);
+    }
+    print_scan_errors(&cfg.scanner);
+
+    let merge_set = merge::dedupe(files, &cfg.sorter_builder.comparer());
+    if console::shows(Verbosity::Normal) {
+        for (skipped, kept) in &merge_set.duplicates {
+            println!("identical \"{}\" == \"{}\"",
+                     skipped.to_str().unwrap_or(PATHSTR_FB),
+                     kept.to_str().unwrap_or(PATHSTR_FB)
+            );
+        }
+    }
+
     let mut pipeline = Pipeline::new(
         cfg.proc_builder.build_clone(),
         cfg.sorter_builder.build_sync(),
@@ -240,100 +1448,750 @@ fn process_sync(mut cfg: RuntimeCfg, args: &MArgs) {
         cfg.output_dir.as_path(),
         cfg.dup_policy
     );
+    pipeline.set_top_n(args.top_n);
+    if let Some(log) = cfg.log.take() {
+        pipeline.set_log(log);
+    }
+    if let Some(manifest) = cfg.manifest.take() {
+        pipeline.set_manifest(manifest);
+    }
+    if let Some(hook) = cfg.post_action.take() {
+        pipeline.set_post_action(hook);
+    }
+    for file in merge_set.unique {
+        if cfg.interrupt.load(Ordering::Relaxed) {
+            break;
+        }
+        if let Err(e) = pipeline.process(file) {
+            eprintln!("Error while merging file: {}", e);
+        }
+    }
+    let mut report = pipeline.report();
+    report.count_duplicate += merge_set.duplicates.len() as u64;
+    if console::shows(Verbosity::Normal) {
+        println!("{}", report);
+    }
+    (report, errors)
+}
 
-    let files = cfg.scanner.scan();
-    for file in files {
-        let fpath = String::from(file.path().to_str().unwrap_or(PATHSTR_FB));
-        match pipeline.process(file) {
-            Err(e) => panic!("Error while processing file: {}", e),
-            Ok(r) => if args.debug > 0 {
-                match r {
-                    ActionResult::Moved => {
-                        println!("moved \"{}\"", fpath);
-                    }
-                    ActionResult::Copied => {
-                        println!("copied \"{}\"", fpath);
-                    }
-                    ActionResult::Skipped => {
-                        println!("skipped \"{}\"", fpath);
-                    }
-                }
+/// revert all moves of a migration journal
+fn process_undo(journal_path: &Path) {
+    match journal::undo(journal_path) {
+        Ok((restored, errors)) => {
+            for e in &errors {
+                eprintln!("Error while reverting: {}", e);
+            }
+            println!("restored {} file(s), {} error(s)", restored, errors.len());
+        },
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    }
+}
+
+/// re-hash all files of the manifest at `path`, printing missing, corrupted and unreadable ones
+fn process_verify(path: &Path) {
+    let result = manifest::verify(path, |target, status| {
+        let target = target.to_str().unwrap_or(PATHSTR_FB);
+        match status {
+            EntryStatus::Ok => if console::shows(Verbosity::Verbose) {
+                println!("ok \"{}\"", target);
+            },
+            EntryStatus::Missing => println!("missing \"{}\"", target),
+            EntryStatus::Corrupted => println!("corrupted \"{}\"", target),
+            EntryStatus::Unreadable(e) => println!("unreadable \"{}\": {}", target, e)
+        }
+    });
+    match result {
+        Ok(verification) => {
+            println!("{}", verification);
+            if !verification.is_intact() {
+                std::process::exit(EXIT_ERRORS);
             }
+        },
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    }
+}
+
+/// print all problems of the config file at `path`, exiting with an error if it cannot be loaded
+fn process_validate_config(path: &Path) {
+    let diagnostics = match validation::validate_file(path) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("[ERROR] failed to read config file \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), e);
+            std::process::exit(EXIT_CONFIG_ERROR);
+        }
+    };
+    for d in &diagnostics {
+        println!("{}", d);
+    }
+    let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+    println!("\"{}\": {} error(s), {} warning(s)", path.to_str().unwrap_or(PATHSTR_FB), errors, diagnostics.len() - errors);
+    if errors > 0 {
+        std::process::exit(EXIT_CONFIG_ERROR);
+    }
+}
+
+/// write the config of the built-in defaults to `path` or print it, see [RunMode::InitConfig]
+fn process_init_config(path: Option<&Path>, force: bool) {
+    let path = match path {
+        Some(p) => p,
+        None => {
+            print!("{}", default_config::to_toml());
+            return;
+        }
+    };
+    let path_str = path.to_str().unwrap_or(PATHSTR_FB);
+    if path.exists() && !force {
+        eprintln!("[ERROR] \"{}\" already exists, use --force to overwrite it", path_str);
+        std::process::exit(EXIT_ERRORS);
+    }
+    if ConfigFormat::from_path(path) != ConfigFormat::Toml {
+        println!("[WARN] the config is written as TOML, it is only read as TOML with the extension '.toml'");
+    }
+    match std::fs::write(path, default_config::to_toml()) {
+        Ok(_) => println!("wrote default config to \"{}\"", path_str),
+        Err(e) => {
+            eprintln!("[ERROR] failed to write \"{}\": {}", path_str, e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    }
+}
+
+/// migrate the config at `path` and write it to `output` or print it, see [RunMode::MigrateConfig]
+fn process_migrate_config(path: &Path, output: Option<&Path>, force: bool) {
+    let path_str = path.to_str().unwrap_or(PATHSTR_FB);
+    let (xml, migrated) = match migration::migrate_file(path) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("[ERROR] failed to migrate config file \"{}\": {}", path_str, e);
+            std::process::exit(EXIT_CONFIG_ERROR);
+        }
+    };
+    let output = match output {
+        Some(o) => o,
+        None => {
+            print!("{}", xml);
+            return;
+        }
+    };
+    let output_str = output.to_str().unwrap_or(PATHSTR_FB);
+    if output.exists() && !force {
+        eprintln!("[ERROR] \"{}\" already exists, use --force to overwrite it", output_str);
+        std::process::exit(EXIT_ERRORS);
+    }
+    if ConfigFormat::from_path(output) != ConfigFormat::Xml {
+        println!("[WARN] the config is written as XML, it is only read as XML with the extension '.xml'");
+    }
+    for change in &migrated.changes {
+        println!("[INFO] {}", change);
+    }
+    match std::fs::write(output, xml) {
+        Ok(_) => println!("migrated config of version {} to version {} in \"{}\"", migrated.version, migration::CONFIG_VERSION, output_str),
+        Err(e) => {
+            eprintln!("[ERROR] failed to write \"{}\": {}", output_str, e);
+            std::process::exit(EXIT_ERRORS);
         }
     }
+}
+
+/// send a summary of the run to all configured notification targets
+#[cfg(feature = "notifications")]
+fn send_notifications(args: &NotifyArgs, report: &Report, errors: &[(PathBuf, String)], elapsed: time::Duration) {
+    let mut notifiers = Vec::new();
+    if let Some(url) = &args.webhook {
+        notifiers.push(Notifier::Webhook(url.clone()));
+    }
+    if let Some(to) = &args.email {
+        notifiers.push(Notifier::Email {
+            server: args.smtp_server.clone(),
+            from: args.from.clone(),
+            to: to.clone()
+        });
+    }
 
+    let summary = RunSummary { report, errors, elapsed };
+    for notifier in notifiers {
+        if let Err(e) = notifier.notify(&summary) {
+            eprintln!("[WARN] {}", e);
+        }
+    }
 }
 
-/// helper to parse an XML-based config file including pre-checks
+#[cfg(not(feature = "notifications"))]
+fn send_notifications(_args: &NotifyArgs, _report: &Report, _errors: &[(PathBuf, String)], _elapsed: time::Duration) {
+    eprintln!("[WARN] notifications requested, but dcim-sort was built without the \"notifications\" feature");
+}
+
+/// write the report as JSON to `path`
+fn write_report(path: &Path, report: &Report) -> Result<(), String> {
+    let json = match report.to_json() {
+        Ok(j) => j,
+        Err(e) => return Err(format!("failed to serialize report: {}", e))
+    };
+    match std::fs::write(path, json) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("failed to write report to \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), e))
+    }
+}
+
+/// print a summary of paths that were skipped because they could not be scanned
+fn print_scan_errors(scanner: &Scanner) {
+    if !scanner.errors().is_empty() {
+        eprintln!("{} path(s) could not be scanned:", scanner.errors().len());
+        for (path, msg) in scanner.errors() {
+            eprintln!("  \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), msg);
+        }
+    }
+}
+
+/// helper to parse a config file in any supported format including pre-checks
 fn parse_config_file(filepath: &Path) -> Result<RootCfg, String>{
     let path_str = filepath.to_str().unwrap_or(dcim_sort::sorting::PATHSTR_FB);
     if !filepath.is_file() {
         return Err(format!("Invalid config file: {}", path_str)
         );
     }
-    let mut file = match File::open(filepath) {
-        Ok(f) => f,
-        Err(e) => return Err(format!("Error opening config file \"{}\": {}", path_str, e))
-    };
 
-    match RootCfg::read_file(&mut file) {
+    match RootCfg::read_config(filepath) {
         Ok(cfg) => Ok(cfg),
-        Err(e) => Err(format!("Error parsing config file: {:?}", e))
+        Err(CfgError::IoError(e)) => Err(format!("Error opening config file \"{}\": {}", path_str, e)),
+        Err(e) => Err(format!("Error parsing config file: {}\n  run 'dcim-sort config validate {}' to list all problems", e, path_str))
+    }
+}
+
+/// write the config `root_cfg` (the built-in defaults if none is used) with all overrides of
+/// the environment and command line applied to `path`, `-` for stdout
+fn dump_effective_config(path: &Path, root_cfg: Option<RootCfg>, args: &MArgs, dup_policy: DuplicateResolution,
+                         hash_algo: HashAlgorithm, scanner_cfg: &ScannerCfg, logger_cfg: Option<&LoggerCfg>) -> Result<(), String> {
+    let mut cfg = match root_cfg {
+        Some(c) => c,
+        None => match RootCfg::parse(default_config::to_toml().as_str(), ConfigFormat::Toml) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("failed to generate the default config: {}", e))
+        }
+    };
+    let sorter_cfg = cfg.get_sorter_cfg_mut();
+    sorter_cfg.set_duplicate_handling(dup_policy);
+    sorter_cfg.set_hash_algorithm(hash_algo);
+    if let Some(tolerance) = args.mtime_tolerance {
+        sorter_cfg.set_mtime_tolerance(tolerance);
+    }
+    if let Some(root) = &args.fallback_root {
+        sorter_cfg.set_fallback_root(root.clone());
+    }
+    *cfg.get_scanner_cfg_mut() = scanner_cfg.clone();
+    cfg.set_logger_cfg(logger_cfg.cloned());
+
+    let xml = cfg.to_effective_xml();
+    if path == Path::new("-") {
+        print!("{}", xml);
+        return Ok(());
+    }
+    let path_str = path.to_str().unwrap_or(PATHSTR_FB);
+    match std::fs::write(path, xml) {
+        Ok(_) => {
+            println!("[INFO] wrote effective config to \"{}\"", path_str);
+            Ok(())
+        },
+        Err(e) => Err(format!("failed to write effective config to \"{}\": {}", path_str, e))
     }
 }
 
 /// helper for constructing pipeline configuration from args and wrap it up in a struct
-fn create_config(args: &MArgs) -> RuntimeCfg {
-    let (dup_policy, sorter_builder) = match &args.config_path {
-        None => (SorterBuilder::default_duplicate_handling(), generate_default_sorter()),
+fn create_config(args: &MArgs) -> Result<RuntimeCfg, String> {
+    if args.config_path.is_none() && args.profile.is_some() {
+        return Err(String::from("a sorter profile requires a config file"));
+    }
+    let (dup_policy, cfg_hash_algo, sorter_builder, mut scanner_cfg, meta_proc_builder, logger_cfg, root_cfg) = match &args.config_path {
+        None => (SorterBuilder::default_duplicate_handling(), None, default_config::default_sorter(),
+                 ScannerCfg::default(), ProcessorCfg::default().generate_builder(), None, None),
         Some(path) => {
-            let root_cfg = parse_config_file(path.as_path()).unwrap();
-            let dup_handling = root_cfg.get_sorter_cfg().get_duplicate_handling();
-            let sorter_builder = root_cfg.generate_sorter_builder().unwrap()
-                .hash_algorithm(args.hash_operation);
-            (dup_handling, sorter_builder)
+            let mut root_cfg = parse_config_file(path.as_path())?;
+            if let Err(e) = root_cfg.select_profile(args.profile.as_deref()) {
+                return Err(format!("Error selecting sorter profile: {}", e));
+            }
+            if let Some(profile) = root_cfg.selected_profile().filter(|_| console::shows(Verbosity::Normal)) {
+                println!("[INFO] using sorter profile \"{}\"", profile);
+            }
+            if args.dry_run {
+                let sorter_cfg = root_cfg.get_sorter_cfg();
+                for (name, segments) in [("supported", sorter_cfg.get_supported_segments()), ("fallback", sorter_cfg.get_fallback_segments())] {
+                    let order: Vec<String> = segments.iter()
+                        .map(|s| format!("{} (index {})", s.seg_type(), s.index()))
+                        .collect();
+                    println!("[INFO] order of the {} segments: {}", name, order.join(" > "));
+                }
+            }
+            let sorter_builder = match root_cfg.generate_sorter_builder() {
+                Ok(b) => b,
+                Err(e) => return Err(format!("Error generating sorter from config: {}", e))
+            };
+            let proc_builder = match root_cfg.generate_processor_builder() {
+                Ok(b) => b,
+                Err(e) => return Err(format!("Error generating metadata processors from config: {}", e))
+            };
+            (root_cfg.get_duplicate_handling(), root_cfg.get_sorter_cfg().get_hash_algorithm(), sorter_builder, root_cfg.get_scanner_cfg().clone(), proc_builder, root_cfg.get_logger_cfg().cloned(), Some(root_cfg))
         }
     };
+    let logger_cfg = log_settings(logger_cfg, args)?;
+    let hash_algo = Layered::new(SorterBuilder::default_hash_algorithm())
+        .layer(cfg_hash_algo, SettingSource::Config)
+        .layer(args.hash_operation, SettingSource::Cli)
+        .into_value();
+    let mut sorter_builder = sorter_builder.hash_algorithm(hash_algo);
+    let dup_policy = Layered::new(SorterBuilder::default_duplicate_handling())
+        .layer(args.config_path.as_ref().map(|_| dup_policy), SettingSource::Config)
+        .layer(args.env_dup_policy, SettingSource::Env)
+        .layer(args.cli_dup_policy, SettingSource::Cli);
+    if dup_policy.source() == SettingSource::Env && console::shows(Verbosity::Normal) {
+        println!("[INFO] using duplicate handling \"{}\" of environment variable {}", dup_policy.value().name(), overrides::ENV_DUPLICATE_RESOLUTION);
+    }
+    if matches!(args.mode, RunMode::Dedupe(_)) && matches!(hash_algo, HashAlgorithm::None) {
+        return Err(String::from("finding duplicates requires a hash algorithm!"));
+    }
+    scanner_cfg.override_size_limits(args.min_size, args.max_size);
+    scanner_cfg.override_max_depth(args.max_recursion);
+    scanner_cfg.force_ignore_unknown_types(args.ignore_unknown_types);
+    scanner_cfg.add_patterns(&args.excludes, &args.includes);
+    if let Some(size) = args.partial_hash {
+        sorter_builder.set_partial_hash(size);
+    }
+    let hash_cache = match &args.hash_cache {
+        Some(path) => Some(HashCache::open(path.as_path())?),
+        None => None
+    };
+    if let Some(cache) = &hash_cache {
+        sorter_builder.set_hash_cache(cache.clone());
+    }
+    if let Some(tolerance) = args.mtime_tolerance {
+        sorter_builder.set_mtime_tolerance(tolerance);
+    }
+    if let Some(root) = &args.fallback_root {
+        sorter_builder.set_fallback_root(root.clone());
+    }
+    if let Some(path) = &args.dump_config {
+        dump_effective_config(path.as_path(), root_cfg, args, *dup_policy.value(), hash_algo, &scanner_cfg, logger_cfg.as_ref())?;
+    }
+    #[cfg(not(feature = "rexiv2"))]
+    if args.session_id.is_some() {
+        return Err(String::from("provenance requested, but dcim-sort was built without the \"rexiv2\" feature"));
+    }
+    if let Some(session_id) = &args.session_id {
+        if console::shows(Verbosity::Normal) {
+            println!("provenance session ID: {}", session_id);
+        }
+        sorter_builder.set_provenance(session_id);
+    }
 
-    let meta_proc_builder = MetaProcessor::new()
-        .processor(Rexiv2Processor::new(), Priority::None)
-        .processor(KadamakExifProcessor::new(), Priority::Lowest);
+    // a recheck or migration scans the already sorted files instead of the input, a merge
+    // scans the first archive here
+    let input_file = match &args.mode {
+        RunMode::Sort | RunMode::Watch(..) | RunMode::Stats => PathBuf::from(&args.file),
+        RunMode::Merge(roots) => roots[0].clone(),
+        _ => PathBuf::from(&args.target_root)
+    };
+    if args.relative_paths {
+        let relative_to = match (&args.files_from, input_file.is_file()) {
+            (Some(_), _) => std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            (None, true) => input_file.parent().map_or(PathBuf::from("."), Path::to_path_buf),
+            (None, false) => input_file.clone()
+        };
+        sorter_builder.set_simulation_printer(SimulationPrinter::new().relative_paths(relative_to));
+    }
+    let mut scanner_builder = match (&args.mode, &args.files_from) {
+        (RunMode::Sort, Some(list)) => configure_scanner(Scanner::list_builder(read_file_list(list)?), args, &scanner_cfg),
+        _ => configure_scanner(Scanner::builder(input_file.as_path()), args, &scanner_cfg)
+    };
+    // only sorting runs are checkpointed, rechecks and migrations have their own state
+    if let (Some(path), RunMode::Sort, true) = (&args.checkpoint, &args.mode, args.resume) {
+        let processed = checkpoint::load(path.as_path())?;
+        if console::shows(Verbosity::Normal) {
+            println!("resuming from checkpoint with {} processed files", processed.len());
+        }
+        scanner_builder.set_skip_paths(processed);
+    }
+    // the operation of a script is only printed, the files are sorted with the operation "print"
+    let (scanner, operation) = match args.script {
+        Some((format, op)) => {
+            let (scanner, op) = Runner::prepare_input(scanner_builder, &sorter_builder, op)?;
+            sorter_builder.set_simulation_printer(SimulationPrinter::new().script(format, op));
+            (scanner, args.operation)
+        },
+        None => Runner::prepare_input(scanner_builder, &sorter_builder, args.operation)?
+    };
 
-    let input_file = PathBuf::from(&args.file);
-    if !input_file.exists() {
-        panic!("Input file does not exist: \"{}\"", &args.file);
+    // a dry run reads an existing checkpoint, but writes no state files
+    let dry_run = simulation::is_dry_run();
+    if dry_run && (args.checkpoint.is_some() || args.manifest.is_some() || args.catalog.is_some()) && console::shows(Verbosity::Normal) {
+        println!("[INFO] dry run writes no checkpoint, manifest or catalog");
     }
-    let mut scanner = Scanner::new(input_file.as_path()).unwrap();
-    scanner.debug(args.debug > 0);
-    scanner.set_max_depth(args.max_recursion);
-    scanner.ignore_unknown_types(args.ignore_unknown_types);
+    let checkpoint = match (&args.checkpoint, &args.mode) {
+        (Some(path), RunMode::Sort) if !dry_run => Some(Checkpoint::open(path.as_path())?),
+        _ => None
+    };
 
+    // files are only written to the archive by sorts and merges
+    let manifest = match (&args.manifest, &args.mode) {
+        (Some(path), RunMode::Sort | RunMode::Watch(..) | RunMode::Merge(_)) if !dry_run => Some(Manifest::open(path.as_path())?),
+        _ => None
+    };
+
+    let post_action = match &args.post_action {
+        Some(cmd) => Some(PostActionHook::parse(cmd)?),
+        None => None
+    };
+
+    // like the checkpoint, the catalog only applies to sorting the input
+    #[cfg(feature = "catalog")]
+    let catalog = match (&args.catalog, &args.mode) {
+        (Some(path), RunMode::Sort | RunMode::Watch(..)) if !dry_run => Some(Catalog::open(path.as_path())?),
+        _ => None
+    };
+    #[cfg(not(feature = "catalog"))]
+    if args.catalog.is_some() {
+        return Err(String::from("catalog requested, but dcim-sort was built without the \"catalog\" feature"));
+    }
 
     let output_root = PathBuf::from(&args.target_root);
     if output_root.is_file() {
-        panic!("specified output directory is an existing normal file: {}", &args.target_root);
+        return Err(format!("specified output directory is an existing normal file: {}", &args.target_root));
+    }
+    // simulations and rechecks never write to the output directory
+    if args.preflight && !matches!(args.operation, Operation::Print) {
+        if let Err(e) = fs_support::preflight(output_root.as_path()) {
+            return Err(format!("preflight of the output directory failed: {}", e));
+        }
     }
+    // only copies are made in the workspace, moves are renamed directly
+    let workspace = match operation {
+        Operation::Copy => {
+            let ws = match &args.temp_dir {
+                Some(dir) => Workspace::new(dir.as_path()),
+                None => Workspace::in_target(output_root.as_path())
+            };
+            if args.preflight {
+                if let Err(e) = ws.validate(output_root.as_path()) {
+                    return Err(format!("invalid temp directory: {}", e));
+                }
+            }
+            sorter_builder.set_workspace(ws.clone());
+            if let Some(rate) = args.bwlimit {
+                sorter_builder.set_throttle(Throttle::new(rate));
+            }
+            Some(ws)
+        },
+        _ => None
+    };
 
-    RuntimeCfg{
+    Ok(RuntimeCfg{
         scanner: scanner,
         proc_builder: meta_proc_builder,
         sorter_builder: sorter_builder,
         output_dir: output_root,
-        operation: args.operation,
-        dup_policy: dup_policy,
-        thread_count: args.thread_count
+        operation,
+        dup_policy: dup_policy.into_value(),
+        hash_algo,
+        checkpoint,
+        manifest,
+        post_action,
+        scanner_cfg,
+        workspace,
+        hash_cache,
+        #[cfg(feature = "catalog")]
+        catalog,
+        logger_cfg,
+        log: None,
+        interrupt: Arc::new(AtomicBool::new(false))
+    })
+}
+
+/// apply --log-file, --log-dir and --no-log to the logger settings of the config
+fn log_settings(logger_cfg: Option<LoggerCfg>, args: &MArgs) -> Result<Option<LoggerCfg>, String> {
+    if args.no_log {
+        return Ok(None);
     }
+    if args.log_file.is_none() && args.log_dir.is_none() {
+        return Ok(logger_cfg);
+    }
+    let mut logger_cfg = logger_cfg.unwrap_or_default();
+    if let Some(file) = &args.log_file {
+        let filename = match file.file_name().and_then(|f| f.to_str()) {
+            Some(f) => f,
+            None => return Err(format!("invalid log file: \"{}\"", file.to_str().unwrap_or(PATHSTR_FB)))
+        };
+        logger_cfg.set_filename(filename);
+        logger_cfg.set_dir(match file.parent() {
+            Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+            _ => PathBuf::from(".")
+        });
+    }
+    if let Some(dir) = &args.log_dir {
+        logger_cfg.set_dir(dir.clone());
+    }
+    Ok(Some(logger_cfg))
+}
+
+/// start the logger configured in `cfg` writing to the output directory unless configured
+/// otherwise, connecting all pipelines created from `cfg` to it. Dry runs write no log file, as
+/// they leave the file system untouched.
+fn start_logger(cfg: &mut RuntimeCfg) -> Option<LogHandle> {
+    let logger_cfg = cfg.logger_cfg.as_ref()?;
+    if simulation::is_dry_run() && logger_cfg.backend() == LogBackend::File {
+        if console::shows(Verbosity::Normal) {
+            println!("[INFO] dry run writes no log file");
+        }
+        return None;
+    }
+    match Logger::new(&cfg.output_dir, logger_cfg).and_then(|l| l.spawn()) {
+        Ok(handle) => {
+            cfg.log = Some(handle.sender());
+            Some(handle)
+        },
+        Err(e) => {
+            eprintln!("[WARN] failed to start logger, continuing without: {}", e);
+            None
+        }
+    }
+}
+
+/// read the list of input files given by --files-from, `-` reads from stdin
+fn read_file_list(list: &str) -> Result<Vec<PathBuf>, String> {
+    let files = match list {
+        "-" => index::read_file_list(std::io::stdin().lock()),
+        _ => File::open(list).and_then(|f| index::read_file_list(std::io::BufReader::new(f)))
+    };
+    files.map_err(|e| format!("could not read file list \"{}\": {}", list, e))
+}
+
+/// configure a scanner with the settings of `cfg` and the options given in `args`
+fn configure_scanner(mut builder: ScannerBuilder, args: &MArgs, cfg: &ScannerCfg) -> ScannerBuilder {
+    cfg.apply(&mut builder);
+    // leftovers of an interrupted run, when scanning the output directory
+    builder.add_exclude(WORKSPACE_DIR_NAME);
+    builder.set_ignore_files(!args.no_ignore_files);
+    builder.set_order(args.order);
+    builder.set_one_file_system(args.one_file_system);
+    builder
 }
 
 fn main() {
     let args = parse_args();
-    let cfg = create_config(&args);
+    if let RunMode::Undo(journal) = &args.mode {
+        process_undo(journal.as_path());
+        return;
+    }
+    if let RunMode::Verify(manifest) = &args.mode {
+        process_verify(manifest.as_path());
+        return;
+    }
+    if let RunMode::ValidateConfig(path) = &args.mode {
+        process_validate_config(path.as_path());
+        return;
+    }
+    if let RunMode::InitConfig(path, force) = &args.mode {
+        process_init_config(path.as_deref(), *force);
+        return;
+    }
+    if let RunMode::MigrateConfig(path, output, force) = &args.mode {
+        process_migrate_config(path.as_path(), output.as_deref(), *force);
+        return;
+    }
+    // simulations leave the file system untouched, whatever is configured
+    if matches!(args.operation, Operation::Print) {
+        simulation::set_dry_run(true);
+    }
 
-    if args.thread_count <= 0 {
-        process_sync(cfg, &args);
+    let mut cfg = match create_config(&args) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            std::process::exit(EXIT_CONFIG_ERROR);
+        }
+    };
+
+    if args.clock_drift != DriftMode::Off {
+        let drift = detect_clock_drift(&mut cfg);
+        if args.clock_drift == DriftMode::Correct && !drift.is_empty() {
+            cfg.sorter_builder.set_clock_drift(drift);
+        }
     }
-    else {
-        process_threaded(cfg, &args);
+
+    match &args.mode {
+        RunMode::Recheck => return process_recheck(cfg),
+        RunMode::Migrate(journal) => return process_migrate(cfg, journal.as_path()),
+        RunMode::Dedupe(action) => return process_dedupe(cfg, &args, *action),
+        RunMode::Stats => return process_stats(cfg),
+        _ => ()
+    }
+
+    // a list read from stdin can only be scanned once
+    if args.space_check != SpaceCheck::Off && matches!(args.mode, RunMode::Sort) && !matches!(cfg.operation, Operation::Print)
+        && args.files_from.as_deref() != Some("-") {
+        check_free_space(&mut cfg, &args.space_check);
+    }
+
+    let time_start = time::Instant::now();
+    let interrupt = cfg.interrupt.clone();
+    handle_interrupts(interrupt.clone());
+    console::emit(&Event::Start {
+        operation: cfg.operation.to_str(),
+        input: Path::new(&args.file),
+        output: cfg.output_dir.as_path()
+    });
+    let workspace = cfg.workspace.clone();
+    let logger = start_logger(&mut cfg);
+    if let Some((format, _)) = args.script {
+        println!("{}", format.header());
     }
+    let (report, errors) = match &args.mode {
+        RunMode::Merge(roots) => process_merge(cfg, &args, &roots[1..]),
+        RunMode::Watch(settle, idle_timeout) => process_watch(cfg, &args, *settle, *idle_timeout),
+        _ => process_sort(cfg, &args)
+    };
+    if let Some(ws) = workspace {
+        ws.cleanup();
+    }
+    if let Some(handle) = logger {
+        handle.shutdown();
+    }
+    console::emit(&Event::Report {
+        report: &report,
+        elapsed_ms: time_start.elapsed().as_millis()
+    });
 
-}
\ No newline at end of file
+    if let Some(format) = args.histogram {
+        print!("{}", report.histogram(format));
+    }
+
+    if let Some(path) = &args.report_file {
+        if let Err(e) = write_report(path.as_path(), &report) {
+            eprintln!("[ERROR] {}", e);
+        }
+    }
+
+    if args.notify.is_enabled() {
+        send_notifications(&args.notify, &report, &errors, time_start.elapsed());
+    }
+
+    if interrupt.load(Ordering::Relaxed) {
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+    if report.count_error > 0 || !errors.is_empty() {
+        std::process::exit(EXIT_ERRORS);
+    }
+}
+
+/// scan the input in advance, calculate the target of every file and compare the total size of
+/// the files going to each file system with its free space, so target routes and the cold
+/// storage are checked as well. Moves within a file system need no space, so moved files only
+/// count if their target is on another file system.
+fn check_free_space(cfg: &mut RuntimeCfg, mode: &SpaceCheck) {
+    let processor = cfg.proc_builder.build_clone();
+    let sorter = cfg.sorter_builder.build_sync();
+    let moving = matches!(cfg.operation, Operation::Move);
+    // bytes required on each file system with the directory its free space is determined for
+    let mut required: HashMap<Option<u64>, (PathBuf, u64)> = HashMap::new();
+    for mut file in cfg.scanner.iter().filter_map(Result::ok) {
+        processor.process(&mut file);
+        sorter.apply_clock_drift(&mut file);
+        sorter.check_date(&mut file);
+        let target = match sorter.preview(&file, cfg.output_dir.as_path()).target {
+            Ok(t) => t,
+            Err(_) => continue
+        };
+        let dir = fs_support::nearest_existing(target.as_path()).to_path_buf();
+        let device = fs_support::device_id(dir.as_path());
+        if moving && device.is_some() && device == fs_support::device_id(file.path()) {
+            continue;
+        }
+        required.entry(device).or_insert_with(|| (dir, 0)).1 += *file.size() as u64;
+    }
+
+    let mut exceeded = Vec::new();
+    for (dir, bytes) in required.into_values() {
+        let available = match fs_support::available_space(dir.as_path()) {
+            Ok(a) => a,
+            Err(e) => {
+                eprintln!("[WARN] could not determine the free space of \"{}\", skipping the space check for it: {}",
+                          dir.to_str().unwrap_or(PATHSTR_FB), e);
+                continue;
+            }
+        };
+        if console::shows(Verbosity::Verbose) {
+            println!("sorting up to {} to \"{}\" with {} free", format_byte_size(bytes), dir.to_str().unwrap_or(PATHSTR_FB), format_byte_size(available));
+        }
+        if bytes > available {
+            exceeded.push(format!("sorting the input requires up to {} on the file system of \"{}\", but only {} are free",
+                                  format_byte_size(bytes), dir.to_str().unwrap_or(PATHSTR_FB), format_byte_size(available)));
+        }
+    }
+    for msg in &exceeded {
+        match mode {
+            SpaceCheck::Abort => eprintln!("[ERROR] {}", msg),
+            _ => console::warn(msg.as_str())
+        }
+    }
+    if !exceeded.is_empty() && *mode == SpaceCheck::Abort {
+        eprintln!("[ERROR] use --space-check warn to sort anyway");
+        std::process::exit(EXIT_ERRORS);
+    }
+}
+
+/// stop scanning on the first Ctrl-C and let the files in progress finish, so the checkpoint,
+/// report and log stay consistent. A second Ctrl-C exits immediately.
+fn handle_interrupts(flag: Arc<AtomicBool>) {
+    let result = ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::SeqCst) {
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        eprintln!("[WARN] interrupted, finishing the files in progress (press Ctrl-C again to abort)");
+    });
+    if let Err(e) = result {
+        eprintln!("[WARN] failed to handle Ctrl-C, interrupting aborts the run: {}", e);
+    }
+}
+#[cfg(test)]
+mod tests {
+
+    mod dry_run {
+        use std::path::PathBuf;
+        use dcim_sort::sorting::simulation;
+        use crate::{create_config, parse_args_from, start_logger};
+
+        #[test]
+        fn creates_no_state_files() {
+            let dir = std::env::temp_dir().join(format!("dcim-sort-test-{}-dry-run", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(dir.join("in")).unwrap();
+            std::fs::write(dir.join("in/a.jpg"), b"a").unwrap();
+            let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+            let mut args = vec![String::from("dcim-sort"), String::from("--no-config"),
+                                String::from("--output"), path("out"), String::from("--log-dir"), path("."),
+                                String::from("--checkpoint"), path("checkpoint"), String::from("--manifest"), path("manifest"),
+                                String::from("--hash-cache"), path("hashes")];
+            if cfg!(feature = "catalog") {
+                args.extend([String::from("--catalog"), path("catalog.db")]);
+            }
+            args.extend([path("in"), String::from("simulate")]);
+
+            simulation::set_dry_run(true);
+            let mut cfg = create_config(&parse_args_from(args)).unwrap();
+            assert!(start_logger(&mut cfg).is_none());
+            let file = dir.join("in/a.jpg");
+            if let Some(cache) = &cfg.hash_cache {
+                cache.insert(file.as_path(), &file.metadata().unwrap(), "sha256", &[0u8; 32]);
+            }
+            drop(cfg);
+
+            let mut created: Vec<PathBuf> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect();
+            created.sort();
+            let _ = std::fs::remove_dir_all(&dir);
+            assert_eq!(created, vec![dir.join("in")]);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Local;
+use rusqlite::{Connection, OptionalExtension, params};
+use sha2::Sha256;
+
+use crate::media::ImgInfo;
+use crate::sorting::comparison::FileComparer;
+use crate::sorting::PATHSTR_FB;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS imported (
+        hash TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        source TEXT NOT NULL,
+        mtime INTEGER NOT NULL,
+        target TEXT NOT NULL,
+        imported_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS imported_content ON imported (hash, size);
+    CREATE INDEX IF NOT EXISTS imported_source ON imported (source, size, mtime);";
+
+/// how long to wait for another run holding the database lock before failing a lookup or record
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Result of looking up a file in the [Catalog].
+///
+/// # Variants
+/// - [CatalogMatch::Imported] the file has been imported before, with the target it was sorted to
+/// - [CatalogMatch::New] the file has not been imported yet, with its content hash to pass to
+///   [Catalog::record] once it has been sorted
+pub enum CatalogMatch {
+    Imported(PathBuf),
+    New(String)
+}
+
+/// A SQLite database recording every file ever imported by its SHA-256 hash, size, source path
+/// and target, so later runs can skip files that have already been sorted, e.g. when importing
+/// from a card that is not wiped in between. Unlike a [crate::checkpoint::Checkpoint], the
+/// catalog is kept across runs and matches files by content, so an imported file is also
+/// recognized under a different path.
+///
+/// A file is looked up by its source path, size and modification time first, so unchanged
+/// files are not hashed again on every run. Handles are cheap to clone and share one
+/// connection, so all pipeline threads can use the same catalog. Concurrent runs can share a
+/// catalog as well, as SQLite serializes their writes with its own file locks (see
+/// [BUSY_TIMEOUT]).
+#[derive(Clone)]
+pub struct Catalog {
+    path: PathBuf,
+    conn: Arc<Mutex<Connection>>
+}
+
+impl Catalog {
+    /// open the catalog at `path`, creating it if it does not exist yet
+    pub fn open(path: &Path) -> Result<Catalog, String> {
+        let conn = Connection::open(path)
+            .and_then(|c| c.busy_timeout(BUSY_TIMEOUT).map(|_| c))
+            .and_then(|c| c.execute_batch(SCHEMA).map(|_| c))
+            .map_err(|e| format!("failed to open catalog \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), e))?;
+        Ok(Catalog {
+            path: path.to_path_buf(),
+            conn: Arc::new(Mutex::new(conn))
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// check if `file` has been imported before
+    pub fn find(&self, file: &ImgInfo) -> Result<CatalogMatch, String> {
+        let source = file.path().to_string_lossy();
+        let size = *file.size() as i64;
+        let known = self.query("SELECT target FROM imported WHERE source = ?1 AND size = ?2 AND mtime = ?3",
+                               params![source, size, file.changed_at().timestamp()])?;
+        if let Some(target) = known {
+            return Ok(CatalogMatch::Imported(target));
+        }
+
+        let hash = match FileComparer::hash::<Sha256>(file.path()) {
+            Ok(h) => h.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+            Err(_) => return Err(format!("could not hash \"{}\" for the catalog", file.path().to_str().unwrap_or(PATHSTR_FB)))
+        };
+        match self.query("SELECT target FROM imported WHERE hash = ?1 AND size = ?2", params![hash, size])? {
+            Some(target) => Ok(CatalogMatch::Imported(target)),
+            None => Ok(CatalogMatch::New(hash))
+        }
+    }
+
+    /// record `file` with its content `hash` (see [CatalogMatch::New]) as imported to `target`
+    pub fn record(&self, file: &ImgInfo, hash: &str, target: &Path) {
+        let result = self.lock().execute(
+            "INSERT INTO imported (hash, size, source, mtime, target, imported_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                hash,
+                *file.size() as i64,
+                file.path().to_string_lossy(),
+                file.changed_at().timestamp(),
+                target.to_string_lossy(),
+                Local::now().timestamp()
+            ]);
+        if let Err(e) = result {
+            eprintln!("[ERROR] failed to write catalog \"{}\": {}", self.path.to_str().unwrap_or(PATHSTR_FB), e);
+        }
+    }
+
+    fn query<P: rusqlite::Params>(&self, sql: &str, params: P) -> Result<Option<PathBuf>, String> {
+        self.lock().query_row(sql, params, |row| row.get::<_, String>(0))
+            .optional()
+            .map(|t| t.map(PathBuf::from))
+            .map_err(|e| format!("failed to read catalog \"{}\": {}", self.path.to_str().unwrap_or(PATHSTR_FB), e))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        match self.conn.lock() {
+            Ok(guard) => guard,
+            // every statement is atomic, so the connection can still be used
+            Err(poisoned) => poisoned.into_inner()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod catalog {
+        use std::path::Path;
+
+        use crate::catalog::{Catalog, CatalogMatch};
+        use crate::media::ImgInfo;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn recognizes_imported_content() {
+            let dir = TempDir::new("catalog");
+            std::fs::write(dir.join("a.jpg"), b"content").unwrap();
+            std::fs::write(dir.join("b.jpg"), b"content").unwrap();
+            std::fs::write(dir.join("c.jpg"), b"other").unwrap();
+
+            let catalog = Catalog::open(dir.join("catalog.db").as_path()).unwrap();
+            let a = ImgInfo::new(dir.join("a.jpg")).unwrap();
+            match catalog.find(&a).unwrap() {
+                CatalogMatch::New(hash) => catalog.record(&a, hash.as_str(), Path::new("/sorted/a.jpg")),
+                CatalogMatch::Imported(_) => panic!("empty catalog matched a file")
+            }
+            let copy = catalog.find(&ImgInfo::new(dir.join("b.jpg")).unwrap()).unwrap();
+            let other = catalog.find(&ImgInfo::new(dir.join("c.jpg")).unwrap()).unwrap();
+            assert!(matches!(copy, CatalogMatch::Imported(t) if t == Path::new("/sorted/a.jpg")));
+            assert!(matches!(other, CatalogMatch::New(_)));
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::sorting::fs_support::FileLock;
+use crate::sorting::PATHSTR_FB;
+
+/// count of recorded files after which the checkpoint is flushed to disk
+pub const FLUSH_INTERVAL: usize = 100;
+
+/// Records the source paths of processed files, one per line, so an interrupted run can be
+/// resumed without re-evaluating them (see [load]). Entries are buffered and flushed every
+/// [FLUSH_INTERVAL] files and when the last handle is dropped, so a crash loses at most the
+/// last few entries, which are then simply processed again.
+///
+/// Handles are cheap to clone and share the same file, so all pipeline threads can record to
+/// one checkpoint. A checkpoint can only be used by one run at a time, which is ensured by an
+/// advisory lock on a file next to it (see [lock_path] and [FileLock]), released by the OS if the
+/// run crashes. Concurrent runs over the same source therefore need distinct checkpoints, as
+/// each of them records its own progress.
+#[derive(Clone)]
+pub struct Checkpoint {
+    inner: Arc<Mutex<CheckpointWriter>>
+}
+
+struct CheckpointWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    pending: usize,
+    /// released after the remaining entries have been flushed when dropping the writer
+    _lock: FileLock
+}
+
+impl Checkpoint {
+    /// open the checkpoint at `path`, appending to it if it already exists
+    pub fn open(path: &Path) -> Result<Checkpoint, String> {
+        let lock = match FileLock::try_acquire(lock_path(path).as_path()) {
+            Ok(Some(l)) => l,
+            Ok(None) => return Err(format!("checkpoint \"{}\" is in use by another run", path.to_str().unwrap_or(PATHSTR_FB))),
+            Err(e) => return Err(format!("failed to lock checkpoint \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), e))
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| format!("failed to open checkpoint \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), e))?;
+        Ok(Checkpoint {
+            inner: Arc::new(Mutex::new(CheckpointWriter {
+                path: path.to_path_buf(),
+                writer: BufWriter::new(file),
+                pending: 0,
+                _lock: lock
+            }))
+        })
+    }
+
+    /// record `source` as processed. Paths that are not valid UTF-8 or contain a line break can
+    /// not be recorded and will be processed again when resuming.
+    pub fn record(&self, source: &Path) {
+        let line = match source.to_str() {
+            Some(s) if !s.contains('\n') => s,
+            _ => return
+        };
+        let mut w = match self.inner.lock() {
+            Ok(guard) => guard,
+            // a half-written line is ignored when loading, so the state can still be used
+            Err(poisoned) => poisoned.into_inner()
+        };
+        if let Err(e) = writeln!(w.writer, "{}", line) {
+            eprintln!("[ERROR] failed to write checkpoint \"{}\": {}", w.path.to_str().unwrap_or(PATHSTR_FB), e);
+            return;
+        }
+        w.pending += 1;
+        if w.pending >= FLUSH_INTERVAL {
+            w.flush();
+        }
+    }
+
+    /// write all buffered entries to disk
+    pub fn flush(&self) {
+        match self.inner.lock() {
+            Ok(mut guard) => guard.flush(),
+            Err(poisoned) => poisoned.into_inner().flush()
+        }
+    }
+}
+
+impl CheckpointWriter {
+    fn flush(&mut self) {
+        if let Err(e) = self.writer.flush().and_then(|_| self.writer.get_ref().sync_data()) {
+            eprintln!("[ERROR] failed to flush checkpoint \"{}\": {}", self.path.to_str().unwrap_or(PATHSTR_FB), e);
+        }
+        self.pending = 0;
+    }
+}
+
+impl Drop for CheckpointWriter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// the lock file locked while the checkpoint at `path` is open, named `<checkpoint>.lock`
+pub fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+/// read the source paths recorded in the checkpoint at `path`. A missing checkpoint is treated
+/// as empty, so `--resume` can be used for the first run as well.
+pub fn load(path: &Path) -> Result<HashSet<PathBuf>, String> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(format!("failed to open checkpoint \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), e))
+    };
+    let mut processed = HashSet::new();
+    for line in BufReader::new(file).lines() {
+        match line {
+            Ok(l) if !l.is_empty() => { processed.insert(PathBuf::from(l)); },
+            Ok(_) => (),
+            // a truncated last line after a crash is not valid UTF-8 in the worst case
+            Err(_) => break
+        }
+    }
+    Ok(processed)
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod checkpoint {
+        use std::path::Path;
+
+        use crate::checkpoint::{Checkpoint, load, lock_path};
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn recorded_paths_are_loaded() {
+            let dir = TempDir::new("checkpoint");
+            let path = dir.join("run.checkpoint");
+            {
+                let checkpoint = Checkpoint::open(path.as_path()).unwrap();
+                checkpoint.record(Path::new("/src/a.jpg"));
+                checkpoint.clone().record(Path::new("/src/b.jpg"));
+            }
+            let processed = load(path.as_path()).unwrap();
+            assert_eq!(processed.len(), 2);
+            assert!(processed.contains(Path::new("/src/b.jpg")));
+        }
+
+        #[test]
+        fn concurrent_use_is_rejected() {
+            let dir = TempDir::new("checkpoint-lock");
+            let path = dir.join("run.checkpoint");
+            let first = Checkpoint::open(path.as_path()).unwrap();
+            assert!(Checkpoint::open(path.as_path()).is_err());
+            drop(first);
+            assert!(Checkpoint::open(path.as_path()).is_ok());
+        }
+
+        #[test]
+        fn lock_of_crashed_run_is_ignored() {
+            let dir = TempDir::new("checkpoint-stale");
+            let path = dir.join("run.checkpoint");
+            std::fs::write(lock_path(path.as_path()), b"12345\n").unwrap();
+            assert!(Checkpoint::open(path.as_path()).is_ok());
+        }
+    }
+}
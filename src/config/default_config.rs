@@ -0,0 +1,272 @@
+use crate::config::migration::CONFIG_VERSION;
+use crate::config::processor_config::{priority_name, ProcessorKind};
+use crate::index::Scanner;
+use crate::pattern::device::{CaseNormalization, DevicePart, MakeModelPattern};
+use crate::pattern::fallback::SimpleFileTypePattern;
+use crate::pattern::general::{DateTimePart, DateTimePattern, ScreenshotPattern};
+use crate::pattern::FolderDefaults;
+use crate::sorting::{Sorter, SorterBuilder};
+use crate::sorting::comparison::HashAlgorithm;
+
+/// fallback of the device segment of the built-in sorter if neither make nor model is known
+pub const DEFAULT_DEVICE_FALLBACK: &str = "unknown_device";
+
+/// the built-in sorter used without a config file: `<make>_<model>/[screenshots/]<year>-<month>`
+/// for supported files and the type of the file for all others
+pub fn default_sorter() -> SorterBuilder {
+    Sorter::builder()
+        .segment(MakeModelPattern::new()
+            .part(DevicePart::Make)
+            .part(DevicePart::Model)
+            .separator('_')
+            .replace_spaces(true)
+            .case_normalization(CaseNormalization::Lowercase)
+            .fallback(String::from(DEFAULT_DEVICE_FALLBACK))
+            .build())
+        .segment(ScreenshotPattern::new(String::from("screenshots")))
+        .segment(DateTimePattern::new()
+            .part(DateTimePart::Year)
+            .part(DateTimePart::Month)
+            .build())
+        .fallback(SimpleFileTypePattern::new().build())
+}
+
+/// generate a TOML config equivalent to the built-in sorter used without a config file, with
+/// every setting commented and all optional settings as commented out examples
+pub fn to_toml() -> String {
+    let defaults = FolderDefaults::new();
+    let case = match MakeModelPattern::def_case() {
+        CaseNormalization::Lowercase => "Lowercase",
+        CaseNormalization::Uppercase => "Uppercase",
+        CaseNormalization::None => "None"
+    };
+    let processors: String = ProcessorKind::ALL.iter()
+        .map(|k| format!("[[processors]]\nname = \"{}\"\nenabled = true\npriority = \"{}\"\n", k.name(), priority_name(k.default_priority())))
+        .collect::<Vec<String>>()
+        .join("\n");
+    format!(r##"# dcim-sort config equivalent to the built-in defaults used without a config file. Load it with
+# "dcim-sort --config <FILE>", check it with "dcim-sort config validate <FILE>".
+#
+# Tables and keys are named like the elements and attributes of the XML config, see
+# config/template_config.xml of the sources for a description of every element. Settings commented out with a
+# single "#" are optional and show an example value.
+
+# layout version of the config, older configs are migrated when loaded
+version = {config_version}
+
+# the sorter used by default. Further sorters with settings of their own are added as profiles, e.g. [profiles.camera]
+# with the same keys as [sorter], and selected with "dcim-sort --profile camera"
+[sorter]
+# handling of files already existing in the target: "ignore" (keep the existing file), "overwrite" or "compare",
+# the latter with comparison "rename" (keep both), "favor_target" or "favor_source" if the files differ, e.g.
+# duplicateResolution = {{ strategy = "compare", comparison = "rename" }}
+duplicateResolution = {{ strategy = "ignore" }}
+# algorithm comparing files for the "compare" strategy: {hash_algos}. Overridden
+# by --hash-algorithm
+# hashAlgorithm = "{hash_algo}"
+
+# names of all generated folders for unknown values and fixed categories. Segments with an explicit value take
+# precedence
+[sorter.defaults]
+unknownMake = "{unknown_make}"
+unknownModel = "{unknown_model}"
+unknownDate = "{unknown_date}"
+screenshots = "{screenshots}"
+video = "{video}"
+picture = "{picture}"
+audio = "{audio}"
+text = "{text}"
+document = "{document}"
+other = "{other}"
+# folder for files with implausible timestamps if datePlausibility handling is "quarantine"
+quarantine = "{quarantine}"
+
+# quotas limiting branches of the target tree by size (suffixes K, M, G, T allowed) and/or number of files. The
+# overflow is "skip" or "spillover" into the given folder, evicting the oldest files of the branch
+# [[sorter.quotas]]
+# branch = "screenshots"
+# maxBytes = "5G"
+# overflow = "spillover"
+# spillover = "spillover"
+
+# send files to other target roots (relative ones are placed below the output directory), e.g. on another volume.
+# The first target whose conditions (types, make and/or minBytes) all match is used
+# [[sorter.targets]]
+# root = "/mnt/hdd/sorted"
+# types = "video,audio"
+
+# metadata timestamps in the future or before minYear are implausible. handling is "accept", "mtime" (use the file
+# system timestamp) or "quarantine"
+# [sorter.datePlausibility]
+# minYear = 1990
+# handling = "accept"
+
+# detect shots existing as both HEIC and JPEG. The other format is left at its source ("skip") or sorted below
+# branch ("route")
+# [sorter.heicJpegPairs]
+# prefer = "heic"
+# secondary = "skip"
+
+# retry moving/copying files failing with transient IO errors, doubling the delay for each retry
+# [sorter.retry]
+# attempts = 3
+# backoffMs = 500
+
+# split target folders with more than maxFiles files into numbered sub-buckets below them, e.g. "2023-08/part_02"
+# [sorter.folderSplit]
+# maxFiles = 10000
+# prefix = "part_"
+# digits = 2
+
+# settings for comparing files with the hash algorithm: size of the blocks read (suffixes K, M, G allowed) and
+# the tolerance of modification times for the "size-mtime" algorithm, optionally including a one hour DST shift
+# [sorter.comparison]
+# bufferSize = "1M"
+# mtimeToleranceSecs = 2
+# dstShift = false
+
+# sort files older than maxAgeDays into an alternative target root
+# [sorter.coldStorage]
+# maxAgeDays = 730
+# root = "/mnt/archive/sorted"
+
+# target filenames: "original", "contentHash" (<date>_<hash>.<ext>) or "sequence" (<prefix><number>.<ext>, with
+# digits and scope "run" or "folder")
+[sorter.rename]
+scheme = "original"
+# append a suffix ("hash" or "timestamp") to filenames of cameras resetting their file counters
+# [[sorter.rename.rolloverSuffix]]
+# make = "Canon"
+# suffix = "hash"
+
+# route files by their source path, the first matching rule is used. Each rule has a glob (relative ones match the
+# end of the path) or a regex (searched in the full path), a target root replacing the output directory (relative
+# roots are placed below it) and/or own supported and fallback segments, those not set are the ones of [sorter]
+# [[sorter.sourceRules]]
+# glob = "WhatsApp/**"
+# root = "messenger"
+# [[sorter.sourceRules.supported.segments]]
+# type = "DateTimePattern"
+# parts = ["Year"]
+
+# segments of the target path for supported files, in order. The first one is the device
+[[sorter.supported.segments]]
+type = "MakeModelPattern"
+# parts of the device name, "Make" and/or "Model"
+parts = ["Make", "Model"]
+# replace spaces in the make and model
+replaceSpaces = {replace_spaces}
+# single character between the parts
+separator = "{device_separator}"
+# "Lowercase", "Uppercase" or "None"
+caseNormalization = "{case}"
+# folder name if neither make nor model is known
+fallback = "{device_fallback}"
+# folder names for an unknown make or model, default to the [sorter.defaults]
+# defaultMake = "unknown"
+# defaultModel = "unknown"
+
+# folder for screenshots, skipped for all other files
+[[sorter.supported.segments]]
+type = "ScreenshotPattern"
+value = "{screenshots}"
+# additionally detect screenshots by a regular expression on the filename
+# filenamePattern = "^screenshot.*$"
+# caseInsensitive = true
+
+# folder by the time a picture was taken
+[[sorter.supported.segments]]
+type = "DateTimePattern"
+# parts of the timestamp: "Year", "Month", "Day", "Hour", "Minute", "Second"
+parts = ["Year", "Month"]
+# single character between the parts
+separator = "{date_separator}"
+# use the file system timestamp if the metadata contains none
+fallbackFsTimestamp = {fs_fallback}
+# folder name if no timestamp is known, defaults to the [sorter.defaults]
+# defaultValue = "unknown"
+
+# segments of the target path for files without supported metadata, optionally placed below a root folder
+[sorter.fallback]
+# root = "unsorted"
+
+# folder by the type of the file, the folder names default to the [sorter.defaults]
+[[sorter.fallback.segments]]
+type = "SimpleFileTypePattern"
+# defaultVideo = "videos"
+
+# settings of the scanner reading the input. Command line arguments take precedence, excludes and includes given
+# there are added to the ones listed here
+[scanner]
+# maximum recursion level below the input
+maxRecursion = {max_depth}
+# skip files with unknown file types (based on the file ending)
+ignoreUnknownTypes = false
+# follow symbolic links below the input, links to a directory containing them are skipped
+followSymlinks = true
+# skip files and directories matching any of these patterns, only process files matching any of the includes.
+# '*' and '?' do not match '/', '**' does. Patterns containing a '/' are matched against the path relative to the
+# input, others against the name only
+# excludes = ["@eaDir", "*.tmp"]
+# includes = ["*.jpg"]
+
+# skip files smaller than minBytes or larger than maxBytes (suffixes K, M, G, T allowed)
+# [scanner.sizeFilter]
+# minBytes = "10K"
+# maxBytes = "4G"
+
+# write a log file of the run to dir (default: the output directory), no log is written without this table. level
+# is "error", "warn", "info" or "debug", format "text", "compact" or "json" (one JSON object per line)
+# [logging]
+# dir = "/var/log/dcim-sort"
+# filename = "import.log"
+# level = "info"
+# format = "text"
+# logSkipped = false
+# second file in dir receiving only the warnings and errors
+# errorFilename = "import-errors.log"
+# "file" or "syslog" (local syslog daemon or journald, requires the "syslog" feature)
+# backend = "file"
+
+# metadata backends ("rexiv2" or "kamadak-exif"), the first one supporting a file type reads its metadata. Set
+# enabled = false to disable one, priority is "highest", "lowest", "none" or a position among the backends with
+# priority "none"
+{processors}"##,
+            unknown_make = defaults.unknown_make,
+            unknown_model = defaults.unknown_model,
+            unknown_date = defaults.unknown_date,
+            screenshots = ScreenshotPattern::def_value(),
+            video = defaults.video,
+            picture = defaults.picture,
+            audio = defaults.audio,
+            text = defaults.text,
+            document = defaults.document,
+            other = defaults.other,
+            quarantine = defaults.quarantine,
+            replace_spaces = MakeModelPattern::def_replace_spaces(),
+            device_separator = MakeModelPattern::def_separator(),
+            case = case,
+            device_fallback = DEFAULT_DEVICE_FALLBACK,
+            date_separator = DateTimePattern::def_separator(),
+            fs_fallback = DateTimePattern::def_fs_timestamp_fallback(),
+            max_depth = Scanner::DEFAULT_MAX_DEPTH,
+            hash_algos = HashAlgorithm::names().iter().map(|n| format!("\"{}\"", n)).collect::<Vec<String>>().join(", "),
+            hash_algo = SorterBuilder::default_hash_algorithm().name(),
+            config_version = CONFIG_VERSION,
+            processors = processors)
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod default_config {
+        use crate::config::{ConfigFormat, default_config};
+        use crate::config::validation::validate;
+
+        #[test]
+        fn generated_config_is_valid() {
+            let diagnostics = validate(default_config::to_toml().as_str(), ConfigFormat::Toml);
+            assert!(diagnostics.is_empty(), "{}", diagnostics.iter().map(|d| d.to_string()).collect::<Vec<String>>().join("\n"));
+        }
+    }
+}
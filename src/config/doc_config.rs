@@ -0,0 +1,408 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use minidom::Element;
+use serde::Deserialize;
+
+use crate::config::{CfgError, ConfigFormat};
+
+/// A value of a config key. All values are passed on as text, so they are validated exactly like
+/// the corresponding XML attribute or element.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String)
+}
+
+impl Display for Scalar {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scalar::Bool(b) => write!(f, "{}", b),
+            Scalar::Int(i) => write!(f, "{}", i),
+            Scalar::Float(x) => write!(f, "{}", x),
+            Scalar::Str(s) => write!(f, "{}", s)
+        }
+    }
+}
+
+/// keys of a table that map to the attributes (or for `<defaults>` and segments the text
+/// children) of an XML element with the same name
+type Attrs = BTreeMap<String, Scalar>;
+
+/// The TOML, JSON or YAML representation of a config file. Tables and keys are named like the
+/// elements and attributes of the XML config, e.g. `<retry attempts="3"/>` below `<sorter>` is
+/// written as `[sorter.retry]` with `attempts = 3` in TOML or `{"sorter": {"retry": {"attempts": 3}}}`
+/// in JSON. The document is converted to the equivalent XML element tree, so all formats share
+/// their semantics and validation. Sorter profiles are tables below `profiles` named like the
+/// profile, e.g. `[profiles.phone.rename]`. The top level key `version` is the `version`
+/// attribute of `<config>`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ConfigDoc {
+    version: Option<Scalar>,
+    sorter: Option<SorterDoc>,
+    #[serde(default)]
+    profiles: BTreeMap<String, SorterDoc>,
+    scanner: Option<ScannerDoc>,
+    #[serde(default)]
+    processors: Vec<Attrs>,
+    logging: Option<Attrs>
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct SorterDoc {
+    duplicate_resolution: Option<DuplicateResolutionDoc>,
+    hash_algorithm: Option<Scalar>,
+    defaults: Option<Attrs>,
+    #[serde(default)]
+    quotas: Vec<Attrs>,
+    #[serde(default)]
+    targets: Vec<Attrs>,
+    date_plausibility: Option<Attrs>,
+    heic_jpeg_pairs: Option<Attrs>,
+    retry: Option<Attrs>,
+    folder_split: Option<Attrs>,
+    comparison: Option<Attrs>,
+    cold_storage: Option<ColdStorageDoc>,
+    rename: Option<RenameDoc>,
+    #[serde(default)]
+    source_rules: Vec<SourceRuleDoc>,
+    supported: Option<SegmentsDoc>,
+    fallback: Option<SegmentsDoc>
+}
+
+/// `strategy` and the `comparison` given as text of `<duplicateResolution>` in XML
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct DuplicateResolutionDoc {
+    strategy: Option<Scalar>,
+    comparison: Option<Scalar>
+}
+
+/// `maxAgeDays` and the alternative target `root` given as text of `<coldStorage>` in XML
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ColdStorageDoc {
+    max_age_days: Option<Scalar>,
+    root: Option<Scalar>
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RenameDoc {
+    #[serde(default)]
+    rollover_suffix: Vec<Attrs>,
+    #[serde(flatten)]
+    attrs: Attrs
+}
+
+/// a `<rule>` of `<sourceRules>` with its pattern and target `root` as attributes
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct SourceRuleDoc {
+    glob: Option<Scalar>,
+    regex: Option<Scalar>,
+    root: Option<Scalar>,
+    supported: Option<SegmentsDoc>,
+    fallback: Option<SegmentsDoc>
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct SegmentsDoc {
+    root: Option<Scalar>,
+    ordered: Option<Scalar>,
+    #[serde(default)]
+    segments: Vec<SegmentDoc>
+}
+
+/// A segment with its `type`, the `parts` in order and all other settings as keys named like
+/// the child elements in XML. `index` defaults to the position in the list.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SegmentDoc {
+    #[serde(rename = "type")]
+    seg_type: Option<Scalar>,
+    index: Option<Scalar>,
+    #[serde(default)]
+    parts: Vec<Scalar>,
+    filename_pattern: Option<Scalar>,
+    case_insensitive: Option<Scalar>,
+    #[serde(flatten)]
+    values: Attrs
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ScannerDoc {
+    size_filter: Option<Attrs>,
+    max_recursion: Option<Scalar>,
+    ignore_unknown_types: Option<Scalar>,
+    follow_symlinks: Option<Scalar>,
+    #[serde(default)]
+    excludes: Vec<Scalar>,
+    #[serde(default)]
+    includes: Vec<Scalar>
+}
+
+impl ConfigDoc {
+    /// parse `data` in `format`, which must not be [ConfigFormat::Xml]
+    pub fn parse(data: &str, format: ConfigFormat) -> Result<ConfigDoc, CfgError> {
+        match format {
+            ConfigFormat::Toml => toml::from_str(data).map_err(CfgError::TomlParseFailure),
+            ConfigFormat::Json => serde_json::from_str(data).map_err(CfgError::JsonParseFailure),
+            ConfigFormat::Yaml => serde_yaml::from_str(data).map_err(CfgError::YamlParseFailure),
+            ConfigFormat::Xml => Err(CfgError::val_err("XML configs are not parsed as a document"))
+        }
+    }
+
+    /// convert to the `<config>` element of the equivalent XML config
+    pub fn to_element(&self) -> Element {
+        let mut root = element("config");
+        set_attr(&mut root, "version", &self.version);
+        if let Some(sorter) = &self.sorter {
+            root.append_child(sorter.to_element());
+        }
+        for (name, sorter) in &self.profiles {
+            let mut el = sorter.to_element();
+            el.set_attr("name", name.as_str());
+            root.append_child(el);
+        }
+        if let Some(scanner) = &self.scanner {
+            let mut el = element("scanner");
+            append_attrs_child(&mut el, "sizeFilter", &scanner.size_filter);
+            append_text_child(&mut el, "maxRecursion", &scanner.max_recursion);
+            append_text_child(&mut el, "ignoreUnknownTypes", &scanner.ignore_unknown_types);
+            append_text_child(&mut el, "followSymlinks", &scanner.follow_symlinks);
+            for pattern in &scanner.excludes {
+                append_text_child(&mut el, "exclude", &Some(pattern));
+            }
+            for pattern in &scanner.includes {
+                append_text_child(&mut el, "include", &Some(pattern));
+            }
+            root.append_child(el);
+        }
+        if !self.processors.is_empty() {
+            let mut el = element("processors");
+            for p in &self.processors {
+                el.append_child(attrs_element("processor", p));
+            }
+            root.append_child(el);
+        }
+        if let Some(logging) = &self.logging {
+            let mut el = element("logging");
+            append_text_children(&mut el, logging);
+            root.append_child(el);
+        }
+        root
+    }
+}
+
+impl SorterDoc {
+    fn to_element(&self) -> Element {
+        let mut el = element("sorter");
+        if let Some(dup) = &self.duplicate_resolution {
+            let mut child = element("duplicateResolution");
+            set_attr(&mut child, "strategy", &dup.strategy);
+            append_text(&mut child, &dup.comparison);
+            el.append_child(child);
+        }
+        append_text_child(&mut el, "hashAlgorithm", &self.hash_algorithm);
+        if let Some(defaults) = &self.defaults {
+            let mut child = element("defaults");
+            append_text_children(&mut child, defaults);
+            el.append_child(child);
+        }
+        if !self.quotas.is_empty() {
+            let mut child = element("quotas");
+            for q in &self.quotas {
+                child.append_child(attrs_element("quota", q));
+            }
+            el.append_child(child);
+        }
+        if !self.targets.is_empty() {
+            let mut child = element("targets");
+            for t in &self.targets {
+                child.append_child(attrs_element("target", t));
+            }
+            el.append_child(child);
+        }
+        append_attrs_child(&mut el, "datePlausibility", &self.date_plausibility);
+        append_attrs_child(&mut el, "heicJpegPairs", &self.heic_jpeg_pairs);
+        append_attrs_child(&mut el, "retry", &self.retry);
+        append_attrs_child(&mut el, "folderSplit", &self.folder_split);
+        append_attrs_child(&mut el, "comparison", &self.comparison);
+        if let Some(cold) = &self.cold_storage {
+            let mut child = element("coldStorage");
+            set_attr(&mut child, "maxAgeDays", &cold.max_age_days);
+            append_text(&mut child, &cold.root);
+            el.append_child(child);
+        }
+        if let Some(rename) = &self.rename {
+            let mut child = attrs_element("rename", &rename.attrs);
+            for r in &rename.rollover_suffix {
+                child.append_child(attrs_element("rolloverSuffix", r));
+            }
+            el.append_child(child);
+        }
+        if !self.source_rules.is_empty() {
+            let mut child = element("sourceRules");
+            for r in &self.source_rules {
+                let mut rule = element("rule");
+                set_attr(&mut rule, "glob", &r.glob);
+                set_attr(&mut rule, "regex", &r.regex);
+                set_attr(&mut rule, "root", &r.root);
+                if let Some(supported) = &r.supported {
+                    rule.append_child(supported.to_element("supported"));
+                }
+                if let Some(fallback) = &r.fallback {
+                    rule.append_child(fallback.to_element("fallback"));
+                }
+                child.append_child(rule);
+            }
+            el.append_child(child);
+        }
+        if let Some(supported) = &self.supported {
+            el.append_child(supported.to_element("supported"));
+        }
+        if let Some(fallback) = &self.fallback {
+            el.append_child(fallback.to_element("fallback"));
+        }
+        el
+    }
+}
+
+impl SegmentsDoc {
+    fn to_element(&self, name: &str) -> Element {
+        let mut el = element(name);
+        set_attr(&mut el, "root", &self.root);
+        let mut segments = element("segments");
+        set_attr(&mut segments, "ordered", &self.ordered);
+        for (i, seg) in self.segments.iter().enumerate() {
+            segments.append_child(seg.to_element(i));
+        }
+        el.append_child(segments);
+        el
+    }
+}
+
+impl SegmentDoc {
+    fn to_element(&self, position: usize) -> Element {
+        let mut el = element("segment");
+        set_attr(&mut el, "type", &self.seg_type);
+        match &self.index {
+            Some(i) => el.set_attr("index", i.to_string()),
+            None => el.set_attr("index", position.to_string())
+        }
+        if !self.parts.is_empty() {
+            let mut parts = element("parts");
+            for (i, p) in self.parts.iter().enumerate() {
+                let mut part = element("part");
+                part.set_attr("index", i.to_string());
+                part.append_text_node(p.to_string());
+                parts.append_child(part);
+            }
+            el.append_child(parts);
+        }
+        if let Some(pattern) = &self.filename_pattern {
+            let mut child = element("filenamePattern");
+            set_attr(&mut child, "caseInsensitive", &self.case_insensitive);
+            child.append_text_node(pattern.to_string());
+            el.append_child(child);
+        }
+        append_text_children(&mut el, &self.values);
+        el
+    }
+}
+
+/// an empty element in the empty namespace the config parsers look up children in
+pub(crate) fn element(name: &str) -> Element {
+    format!("<{} xmlns=\"\"/>", name).parse().expect("element names are valid XML")
+}
+
+fn attrs_element(name: &str, attrs: &Attrs) -> Element {
+    let mut el = element(name);
+    for (k, v) in attrs {
+        el.set_attr(k.as_str(), v.to_string());
+    }
+    el
+}
+
+fn append_attrs_child(parent: &mut Element, name: &str, attrs: &Option<Attrs>) {
+    if let Some(a) = attrs {
+        parent.append_child(attrs_element(name, a));
+    }
+}
+
+fn append_text_children(parent: &mut Element, values: &Attrs) {
+    for (k, v) in values {
+        let mut child = element(k.as_str());
+        child.append_text_node(v.to_string());
+        parent.append_child(child);
+    }
+}
+
+pub(crate) fn append_text_child<S: ToString>(parent: &mut Element, name: &str, value: &Option<S>) {
+    if let Some(v) = value {
+        let mut child = element(name);
+        child.append_text_node(v.to_string());
+        parent.append_child(child);
+    }
+}
+
+fn set_attr(el: &mut Element, name: &str, value: &Option<Scalar>) {
+    if let Some(v) = value {
+        el.set_attr(name, v.to_string());
+    }
+}
+
+fn append_text(el: &mut Element, value: &Option<Scalar>) {
+    if let Some(v) = value {
+        el.append_text_node(v.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod doc_config {
+        use crate::config::{ConfigFormat, RootCfg};
+        use crate::sorting::{Comparison, DuplicateResolution};
+
+        fn parse_toml(data: &str) -> Result<RootCfg, crate::config::CfgError> {
+            RootCfg::parse(data, ConfigFormat::Toml)
+        }
+
+        #[test]
+        fn template_matches_xml_semantics() {
+            let cfg = parse_toml(include_str!("../../config/template_config.toml")).unwrap();
+            assert!(matches!(cfg.get_sorter_cfg().get_duplicate_handling(), DuplicateResolution::Ignore));
+            assert_eq!(cfg.get_scanner_cfg().min_size(), Some(10 << 10));
+            assert!(cfg.generate_sorter_builder().is_ok());
+
+            let cfg = parse_toml("[sorter.duplicateResolution]\nstrategy = \"compare\"\ncomparison = \"rename\"\n").unwrap();
+            assert!(matches!(cfg.get_sorter_cfg().get_duplicate_handling(), DuplicateResolution::Compare(Comparison::Rename)));
+            assert!(parse_toml("[sorter.retry]\nattempts = 0\n").is_err());
+            assert!(parse_toml("[sorter.unknown]\n").is_err());
+        }
+
+        #[test]
+        fn json_and_yaml_are_equivalent() {
+            let json = r#"{"sorter": {
+                "duplicateResolution": {"strategy": "compare", "comparison": "favor_source"},
+                "supported": {"segments": [{"type": "DateTimePattern", "parts": ["Year"], "separator": "-"}]}
+            }, "scanner": {"sizeFilter": {"minBytes": 1024}}}"#;
+            let yaml = "sorter:\n  duplicateResolution:\n    strategy: compare\n    comparison: favor_source\n  supported:\n    segments:\n      - type: DateTimePattern\n        parts: [Year]\n        separator: \"-\"\nscanner:\n  sizeFilter:\n    minBytes: 1024\n";
+            for cfg in [RootCfg::parse(json, ConfigFormat::Json).unwrap(), RootCfg::parse(yaml, ConfigFormat::Yaml).unwrap()] {
+                assert!(matches!(cfg.get_sorter_cfg().get_duplicate_handling(), DuplicateResolution::Compare(Comparison::FavorSource)));
+                assert_eq!(cfg.get_scanner_cfg().min_size(), Some(1024));
+                assert!(cfg.generate_sorter_builder().is_ok());
+            }
+            assert!(RootCfg::parse("{\"sorter\": {\"retry\": []}}", ConfigFormat::Json).is_err());
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use minidom::{Element, Node};
+
+use crate::config::CfgError;
+use crate::config::doc_config::element;
+use crate::sorting::PATHSTR_FB;
+
+/// Replace all `<include file="..."/>` elements below `root` by the children of the XML file
+/// they name, so families of configs can share fragments like a common segment library. The
+/// root element of an included file must be named like the element containing the `<include>`,
+/// e.g. `<segments>` for an include in `<segments>`. Relative paths are resolved against `dir`,
+/// those in included files against the directory of the file including them.
+pub fn resolve_includes(root: &Element, dir: &Path) -> Result<Element, CfgError> {
+    resolve(root, dir, &mut Vec::new())
+}
+
+/// true if `el` or any of its descendants is an `<include>`
+pub fn has_includes(el: &Element) -> bool {
+    el.children().any(|c| is_include(c) || has_includes(c))
+}
+
+/// true if `el` includes a file. The `<include>` patterns of the scanner have no attributes and
+/// are kept as they are.
+fn is_include(el: &Element) -> bool {
+    el.name() == "include" && el.attr("file").is_some()
+}
+
+/// copy `el` with its includes resolved, `stack` holds the files currently being included to
+/// detect cycles
+fn resolve(el: &Element, dir: &Path, stack: &mut Vec<PathBuf>) -> Result<Element, CfgError> {
+    let mut copy = element(el.name());
+    for (name, value) in el.attrs() {
+        copy.set_attr(name, value);
+    }
+    for node in el.nodes() {
+        match node {
+            Node::Element(child) if is_include(child) => {
+                for included in include(child, el.name(), dir, stack)?.nodes() {
+                    copy.append_node(included.clone());
+                }
+            },
+            Node::Element(child) => {
+                copy.append_child(resolve(child, dir, stack)?);
+            },
+            other => copy.append_node(other.clone())
+        }
+    }
+    Ok(copy)
+}
+
+/// read the file of the `<include>` element `el` inside an element named `parent` and resolve
+/// its own includes
+fn include(el: &Element, parent: &str, dir: &Path, stack: &mut Vec<PathBuf>) -> Result<Element, CfgError> {
+    let file = match el.attr("file") {
+        Some(f) if !f.trim().is_empty() => dir.join(f.trim()),
+        _ => return Err(CfgError::val_err("empty attribute \"file\" on include"))
+    };
+    let path_str = file.to_str().unwrap_or(PATHSTR_FB).to_string();
+    let path = file.canonicalize().map_err(|e| CfgError::val_err(
+        format!("failed to read included config \"{}\": {}", path_str, e).as_str()
+    ))?;
+    if stack.contains(&path) {
+        return Err(CfgError::val_err(format!("config \"{}\" includes itself", path_str).as_str()));
+    }
+    let mut data = String::new();
+    if let Err(e) = File::open(&path).and_then(|mut f| f.read_to_string(&mut data)) {
+        return Err(CfgError::val_err(format!("failed to read included config \"{}\": {}", path_str, e).as_str()));
+    }
+    let fragment = match data.parse::<Element>() {
+        Ok(f) if f.name() == parent => f,
+        Ok(f) => return Err(CfgError::val_err(format!(
+            "root element of included config \"{}\" must be \"{}\" like the element including it, found \"{}\"", path_str, parent, f.name()
+        ).as_str())),
+        Err(e) => return Err(CfgError::val_err(format!("invalid XML in included config \"{}\": {}", path_str, e).as_str()))
+    };
+    let fragment_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    stack.push(path);
+    let resolved = resolve(&fragment, fragment_dir.as_path(), stack);
+    stack.pop();
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod include {
+        use crate::config::RootCfg;
+        use crate::config::include::resolve_includes;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn splices_fragments_relative_to_including_file() {
+            let dir = TempDir::new("include");
+            std::fs::create_dir_all(dir.join("common")).unwrap();
+            std::fs::write(dir.join("common/segments.xml"), "<segments xmlns=\"\"><include file=\"date.xml\"/></segments>").unwrap();
+            std::fs::write(dir.join("common/date.xml"), "<segments xmlns=\"\"><segment type=\"DateTimePattern\" index=\"0\"><parts><part index=\"0\">Year</part></parts></segment></segments>").unwrap();
+            std::fs::write(dir.join("common/loop.xml"), "<sorter xmlns=\"\"><include file=\"loop.xml\"/></sorter>").unwrap();
+
+            let config = "<config xmlns=\"\"><sorter><duplicateResolution strategy=\"ignore\"/><supported><segments><include file=\"common/segments.xml\"/></segments></supported></sorter><scanner><include>*.jpg</include></scanner></config>";
+            let resolved = resolve_includes(&config.parse().unwrap(), dir.as_path());
+            let looping = "<config xmlns=\"\"><sorter><include file=\"common/loop.xml\"/></sorter></config>";
+            let looped = resolve_includes(&looping.parse().unwrap(), dir.as_path());
+            let wrong_root = "<config xmlns=\"\"><include file=\"common/date.xml\"/></config>";
+            let wrong = resolve_includes(&wrong_root.parse().unwrap(), dir.as_path());
+
+            let resolved = resolved.unwrap();
+            let segments = resolved.get_child("sorter", "").unwrap()
+                .get_child("supported", "").unwrap()
+                .get_child("segments", "").unwrap();
+            assert_eq!(segments.children().map(|c| c.name()).collect::<Vec<&str>>(), vec!["segment"]);
+            assert_eq!(resolved.get_child("scanner", "").unwrap().children().count(), 1);
+            assert!(RootCfg::from(&resolved).is_ok());
+            assert!(looped.is_err());
+            assert!(wrong.is_err());
+        }
+    }
+}
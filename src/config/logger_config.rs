@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use minidom::Element;
+
+use crate::config::CfgError;
+use crate::config::doc_config::{append_text_child, element};
+use crate::logging::{LogBackend, LogFormat, LogLevel};
+
+/// Settings of the [crate::logging::Logger] read from the optional `<logging>` element. A log
+/// file is only written if the element is present.
+#[derive(Clone)]
+pub struct LoggerCfg {
+    dir: Option<PathBuf>,
+    filename: Option<String>,
+    level: LogLevel,
+    format: LogFormat,
+    log_skipped: bool,
+    error_filename: Option<String>,
+    backend: LogBackend
+}
+
+impl Default for LoggerCfg {
+    fn default() -> Self {
+        LoggerCfg {
+            dir: None,
+            filename: None,
+            level: LogLevel::Info,
+            format: LogFormat::Text,
+            log_skipped: false,
+            error_filename: None,
+            backend: LogBackend::File
+        }
+    }
+}
+
+impl LoggerCfg {
+    pub fn from(el: &Element) -> Result<LoggerCfg, CfgError> {
+        let mut cfg = LoggerCfg::default();
+        for child in el.children() {
+            cfg.parse_child(child)?;
+        }
+        Ok(cfg)
+    }
+
+    /// apply the child element `child` of `<logging>`, returning false if it is not a known
+    /// element and has been ignored
+    pub(crate) fn parse_child(&mut self, child: &Element) -> Result<bool, CfgError> {
+        let text = child.text();
+        let value = text.trim();
+        match child.name() {
+            "dir" if value.is_empty() => return Err(CfgError::val_err("logging dir must not be empty")),
+            "dir" => self.dir = Some(PathBuf::from(value)),
+            "filename" if value.contains('/') || value.contains(std::path::MAIN_SEPARATOR) => return Err(CfgError::val_err(
+                format!("logging filename must not contain a path, use dir instead: \"{}\"", value).as_str()
+            )),
+            "filename" => self.filename = Some(String::from(value)),
+            "errorFilename" if value.is_empty() || value.contains('/') || value.contains(std::path::MAIN_SEPARATOR) => return Err(CfgError::val_err(
+                format!("logging errorFilename must be a file name without a path: \"{}\"", value).as_str()
+            )),
+            "errorFilename" => self.error_filename = Some(String::from(value)),
+            "level" => match LogLevel::parse(value) {
+                Some(level) => self.level = level,
+                None => return Err(CfgError::val_err(
+                    format!("Illegal value for logging level: \"{}\"", value).as_str()
+                ))
+            },
+            "format" => match LogFormat::parse(value) {
+                Some(format) => self.format = format,
+                None => return Err(CfgError::val_err(
+                    format!("Illegal value for logging format: \"{}\"", value).as_str()
+                ))
+            },
+            "backend" => match LogBackend::parse(value) {
+                Some(backend) => self.backend = backend,
+                None => return Err(CfgError::val_err(
+                    format!("Illegal value for logging backend: \"{}\"", value).as_str()
+                ))
+            },
+            "logSkipped" => match bool::from_str(value) {
+                Ok(b) => self.log_skipped = b,
+                Err(_) => return Err(CfgError::val_err(
+                    format!("Illegal value for logging logSkipped: \"{}\"", value).as_str()
+                ))
+            },
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    /// directory of the log file, the output directory if `None`
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    pub fn set_dir(&mut self, dir: PathBuf) {
+        self.dir = Some(dir);
+    }
+
+    /// name of the log file, generated from the current date if `None`
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    pub fn set_filename(&mut self, filename: &str) {
+        self.filename = Some(String::from(filename));
+    }
+
+    pub fn level(&self) -> LogLevel {
+        self.level
+    }
+
+    pub fn format(&self) -> LogFormat {
+        self.format
+    }
+
+    /// write a message for every skipped file, e.g. because it already exists in the target
+    pub fn log_skipped(&self) -> bool {
+        self.log_skipped
+    }
+
+    /// destination of the log, the file settings only apply to [LogBackend::File]
+    pub fn backend(&self) -> LogBackend {
+        self.backend
+    }
+
+    /// name of a second file in [LoggerCfg::dir] receiving only the warnings and errors, for
+    /// reviewing the problems of a run without searching the full log
+    pub fn error_filename(&self) -> Option<&str> {
+        self.error_filename.as_deref()
+    }
+
+    /// the `<logging>` element of these settings
+    pub fn to_element(&self) -> Element {
+        let mut el = element("logging");
+        append_text_child(&mut el, "dir", &self.dir.as_ref().and_then(|d| d.to_str()));
+        append_text_child(&mut el, "filename", &self.filename);
+        append_text_child(&mut el, "level", &Some(self.level.name()));
+        append_text_child(&mut el, "format", &Some(self.format.name()));
+        append_text_child(&mut el, "logSkipped", &Some(self.log_skipped));
+        append_text_child(&mut el, "errorFilename", &self.error_filename);
+        append_text_child(&mut el, "backend", &Some(self.backend.name()));
+        el
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod logger_config {
+        use crate::config::logger_config::LoggerCfg;
+        use crate::logging::{LogBackend, LogFormat, LogLevel};
+
+        #[test]
+        fn parses_all_settings() {
+            let el = "<logging xmlns=\"\"><dir>/var/log/dcim-sort</dir><filename>import.log</filename><level>WARN</level><format>compact</format><logSkipped>true</logSkipped><errorFilename>import-errors</errorFilename></logging>"
+                .parse().unwrap();
+            let cfg = LoggerCfg::from(&el).unwrap();
+            assert_eq!(cfg.dir().and_then(|d| d.to_str()), Some("/var/log/dcim-sort"));
+            assert_eq!(cfg.filename(), Some("import.log"));
+            assert_eq!(cfg.level(), LogLevel::Warn);
+            assert_eq!(cfg.format(), LogFormat::Compact);
+            assert!(cfg.log_skipped());
+            assert_eq!(cfg.error_filename(), Some("import-errors"));
+            assert_eq!(cfg.backend(), LogBackend::File);
+
+            let level = "<logging xmlns=\"\"><level>verbose</level></logging>".parse().unwrap();
+            assert!(LoggerCfg::from(&level).is_err());
+            let filename = "<logging xmlns=\"\"><filename>logs/import.log</filename></logging>".parse().unwrap();
+            assert!(LoggerCfg::from(&filename).is_err());
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use minidom::{Element, Node};
+
+use crate::config::{CfgError, ConfigFormat, segment_registry, write_xml};
+use crate::config::doc_config::{ConfigDoc, element};
+
+/// Version of the config layout read and written by this version of dcim-sort, set as `version`
+/// attribute of the `<config>` root. Configs without a version have version 1.
+///
+/// # Versions
+/// - 1: segments are inserted at the position of their `index` while reading them in document
+///   order, so indices out of order or used twice result in an unexpected order
+/// - 2: segments are ordered by their unique `index` or use the document order with
+///   `<segments ordered="true">`
+pub const CONFIG_VERSION: u32 = 2;
+
+/// migrations of each version to the next one, starting with version 1. Each one describes the
+/// changes it made in the passed `Vec`.
+const MIGRATIONS: [fn(&Element, &mut Vec<String>) -> Element; (CONFIG_VERSION - 1) as usize] = [legacy_segment_order];
+
+/// A config migrated to [CONFIG_VERSION] by [migrate].
+pub struct Migrated {
+    pub root: Element,
+    /// the version of the config before the migration
+    pub version: u32,
+    /// descriptions of the changes, empty if the config means the same in the current version
+    pub changes: Vec<String>
+}
+
+/// the layout version of the `<config>` element `root`. Fails for versions newer than
+/// [CONFIG_VERSION], which may contain settings this version would misread.
+pub fn config_version(root: &Element) -> Result<u32, CfgError> {
+    let version = match root.attr("version") {
+        Some(v) => match u32::from_str(v.trim()) {
+            Ok(n) if n > 0 => n,
+            _ => return Err(CfgError::val_err(format!("Illegal value for config version: \"{}\"", v).as_str()))
+        },
+        None => 1
+    };
+    if version > CONFIG_VERSION {
+        return Err(CfgError::val_err(format!(
+            "config version {} is newer than version {} supported by this version of dcim-sort, update dcim-sort to use it",
+            version, CONFIG_VERSION
+        ).as_str()));
+    }
+    Ok(version)
+}
+
+/// Migrate the `<config>` element `root` to [CONFIG_VERSION]. Configs of the current version
+/// are returned unchanged.
+pub fn migrate(root: &Element) -> Result<Migrated, CfgError> {
+    let version = config_version(root)?;
+    let mut migrated = root.clone();
+    let mut changes = Vec::new();
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        migrated = migration(&migrated, &mut changes);
+    }
+    migrated.set_attr("version", CONFIG_VERSION.to_string());
+    Ok(Migrated {
+        root: migrated,
+        version,
+        changes
+    })
+}
+
+/// Read the config file at `path` and [migrate] it, returning it as XML config. Includes are
+/// kept, so segments of included files are not migrated.
+pub fn migrate_file(path: &Path) -> Result<(String, Migrated), CfgError> {
+    let mut data = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut data))
+        .map_err(CfgError::IoError)?;
+    let root = match ConfigFormat::from_path(path) {
+        ConfigFormat::Xml => data.parse::<Element>().map_err(CfgError::XmlParseFailure)?,
+        format => ConfigDoc::parse(data.as_str(), format)?.to_element()
+    };
+    let migrated = migrate(&root)?;
+    Ok((write_xml(&migrated.root), migrated))
+}
+
+/// migrate version 1 to 2: renumber the segments of all `<segments>` elements whose order
+/// differs between the versions in the order version 1 applied them
+fn legacy_segment_order(el: &Element, changes: &mut Vec<String>) -> Element {
+    let mut copy = element(el.name());
+    for (name, value) in el.attrs() {
+        copy.set_attr(name, value);
+    }
+    for node in el.nodes() {
+        match node {
+            Node::Element(child) if child.name() == "segments" => {
+                copy.append_child(renumber_segments(child, el.name(), changes));
+            },
+            Node::Element(child) => {
+                copy.append_child(legacy_segment_order(child, changes));
+            },
+            other => copy.append_node(other.clone())
+        }
+    }
+    copy
+}
+
+/// the `<segments>` element `el` inside `parent` with the indices of the legacy order if that
+/// differs from the order by index
+fn renumber_segments(el: &Element, parent: &str, changes: &mut Vec<String>) -> Element {
+    let legacy = match legacy_order(el) {
+        Some(l) => l,
+        None => return el.clone()
+    };
+    let mut by_index = legacy.clone();
+    by_index.sort_by_key(|(_, index)| *index);
+    let unique = by_index.windows(2).all(|w| w[0].1 != w[1].1);
+    if unique && legacy.iter().zip(&by_index).all(|(a, b)| std::ptr::eq(a.0, b.0)) {
+        return el.clone();
+    }
+    let mut copy = element(el.name());
+    for (name, value) in el.attrs() {
+        copy.set_attr(name, value);
+    }
+    for (position, (seg, _)) in legacy.iter().enumerate() {
+        let mut seg = (*seg).clone();
+        seg.set_attr("index", position.to_string());
+        copy.append_child(seg);
+    }
+    let types: Vec<&str> = legacy.iter().map(|(s, _)| s.attr("type").unwrap_or("")).collect();
+    changes.push(format!("renumbered the segments of <{}> in the order they were applied: {}", parent, types.join(", ")));
+    copy
+}
+
+/// the children of `<segments>` with their index in the order version 1 applied them: each
+/// supported segment was inserted at its index if that was below the number of segments read so
+/// far, at the beginning if it was negative and appended otherwise. Segments of unsupported
+/// types were ignored and are placed last. `None` if the order cannot be determined, e.g.
+/// because an index is missing, which is reported when reading the config.
+fn legacy_order(el: &Element) -> Option<Vec<(&Element, i32)>> {
+    let mut ordered: Vec<(&Element, i32)> = Vec::new();
+    let mut ignored = Vec::new();
+    for child in el.children() {
+        if child.name() != "segment" {
+            return None;
+        }
+        let index = i32::from_str(child.attr("index")?.trim()).ok()?;
+        if child.attr("type").and_then(segment_registry::parser).is_none() {
+            ignored.push((child, i32::MAX));
+        }
+        else if index < 0 {
+            ordered.insert(0, (child, index));
+        }
+        else if index < ordered.len() as i32 {
+            ordered.insert(index as usize, (child, index));
+        }
+        else {
+            ordered.push((child, index));
+        }
+    }
+    ordered.extend(ignored);
+    Some(ordered)
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod migration {
+        use crate::config::{ConfigFormat, RootCfg};
+        use crate::config::migration::{CONFIG_VERSION, migrate};
+
+        #[test]
+        fn keeps_order_of_legacy_segments() {
+            let legacy = "<config xmlns=\"\"><sorter><supported><segments><segment type=\"DateTimePattern\" index=\"2\"/><segment type=\"ScreenshotPattern\" index=\"0\"/><segment type=\"MakeModelPattern\" index=\"0\"/></segments></supported></sorter></config>";
+            let migrated = migrate(&legacy.parse().unwrap()).unwrap();
+            assert_eq!(migrated.version, 1);
+            assert_eq!(migrated.changes.len(), 1);
+            assert_eq!(migrated.root.attr("version"), Some(CONFIG_VERSION.to_string().as_str()));
+            let segments = migrated.root.get_child("sorter", "").unwrap()
+                .get_child("supported", "").unwrap()
+                .get_child("segments", "").unwrap();
+            let order: Vec<(&str, &str)> = segments.children()
+                .map(|s| (s.attr("type").unwrap(), s.attr("index").unwrap()))
+                .collect();
+            assert_eq!(order, vec![("MakeModelPattern", "0"), ("ScreenshotPattern", "1"), ("DateTimePattern", "2")]);
+            assert!(RootCfg::parse(legacy, ConfigFormat::Xml).is_ok());
+            let ordered = "<config xmlns=\"\"><sorter><supported><segments><segment type=\"DateTimePattern\" index=\"1\"/><segment type=\"ScreenshotPattern\" index=\"4\"/></segments></supported></sorter></config>";
+            assert!(migrate(&ordered.parse().unwrap()).unwrap().changes.is_empty());
+
+            let future = format!("<config xmlns=\"\" version=\"{}\"><sorter/></config>", CONFIG_VERSION + 1);
+            assert!(RootCfg::parse(future.as_str(), ConfigFormat::Xml).is_err());
+            assert!(RootCfg::parse("version = 0\n[sorter]\n", ConfigFormat::Toml).is_err());
+        }
+    }
+}
@@ -7,22 +7,56 @@ use std::path::{Path, PathBuf};
 use minidom;
 use minidom::Element;
 
+use crate::config::logger_config::LoggerCfg;
+use crate::config::processor_config::ProcessorCfg;
+use crate::config::scanner_config::ScannerCfg;
 use crate::config::sorter_config::SorterCfg;
+use crate::config::doc_config::ConfigDoc;
+use crate::config::include::resolve_includes;
+use crate::logging::console::{self, Verbosity};
 use crate::sorting::DuplicateResolution;
+use crate::media::metadata_processor::MetaProcessorBuilder;
 use crate::pattern::PatternElement;
 use crate::sorting::SorterBuilder;
+use crate::sorting::comparison::HashAlgorithm;
 
 mod sorter_config;
 mod seg_config;
+mod doc_config;
+mod include;
+pub mod scanner_config;
+pub mod default_config;
+pub mod processor_config;
+pub mod logger_config;
+pub mod migration;
+pub mod overrides;
+pub mod segment_registry;
+pub mod validation;
 
 #[derive(Debug)]
 pub enum CfgError {
     XmlParseFailure(minidom::Error),
+    TomlParseFailure(toml::de::Error),
+    JsonParseFailure(serde_json::Error),
+    YamlParseFailure(serde_yaml::Error),
     IllegalValue(CfgValueError),
     UnsupportedSegment(CfgValueError),
     IoError(std::io::Error)
 }
 
+impl Display for CfgError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CfgError::XmlParseFailure(e) => write!(f, "invalid XML: {}", e),
+            CfgError::TomlParseFailure(e) => write!(f, "invalid TOML: {}", e),
+            CfgError::JsonParseFailure(e) => write!(f, "invalid JSON: {}", e),
+            CfgError::YamlParseFailure(e) => write!(f, "invalid YAML: {}", e),
+            CfgError::IllegalValue(e) | CfgError::UnsupportedSegment(e) => write!(f, "{}", e),
+            CfgError::IoError(e) => write!(f, "{}", e)
+        }
+    }
+}
+
 impl CfgError {
     pub fn val_err(msg: &str) -> CfgError {
         CfgError::IllegalValue(CfgValueError::new(msg))
@@ -52,30 +86,165 @@ impl CfgValueError {
     }
 }
 
+/// parse a size in bytes with an optional binary unit suffix (`K`, `M`, `G`, `T`), e.g. `"5G"`.
+pub fn parse_byte_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (num, factor) = match s.chars().last()?.to_ascii_uppercase() {
+        'K' => (&s[..s.len() - 1], 1u64 << 10),
+        'M' => (&s[..s.len() - 1], 1u64 << 20),
+        'G' => (&s[..s.len() - 1], 1u64 << 30),
+        'T' => (&s[..s.len() - 1], 1u64 << 40),
+        _ => (s, 1u64)
+    };
+    num.trim().parse::<u64>().ok()?.checked_mul(factor)
+}
+
+/// write `el` as an XML document indented by two spaces per level
+pub(crate) fn write_xml(el: &Element) -> String {
+    let mut data = Vec::new();
+    // writing to memory only fails for invalid names, which the config does not contain
+    if let Err(e) = indent(el, 0).write_to(&mut data) {
+        return format!("<!-- failed to write config: {} -->", e);
+    }
+    let xml = String::from_utf8_lossy(data.as_slice());
+    // every element is written with the empty namespace of the parser, which is only needed on
+    // the root
+    let mut parts = xml.splitn(2, " xmlns=\"\"");
+    let head = parts.next().unwrap_or_default().replacen("?><", "?>\n<", 1);
+    match parts.next() {
+        Some(tail) => format!("{} xmlns=\"\"{}\n", head, tail.replace(" xmlns=\"\"", "")),
+        None => format!("{}\n", head)
+    }
+}
+
+/// copy `el` with line breaks and indentation before every child element, elements with text
+/// are left as they are
+fn indent(el: &Element, depth: usize) -> Element {
+    if el.children().next().is_none() {
+        return el.clone();
+    }
+    let mut copy = doc_config::element(el.name());
+    for (name, value) in el.attrs() {
+        copy.set_attr(name, value);
+    }
+    for child in el.children() {
+        copy.append_text_node(format!("\n{}", "  ".repeat(depth + 1)));
+        copy.append_child(indent(child, depth + 1));
+    }
+    copy.append_text_node(format!("\n{}", "  ".repeat(depth)));
+    copy
+}
+
+/// name of the config in the working directory found by [discover_config]
+pub const LOCAL_CONFIG_FILE: &str = "dcim-sort.xml";
+
+/// the standard locations of a config used if none is given explicitly, in the order they are
+/// searched: [LOCAL_CONFIG_FILE] in the working directory and `dcim-sort/config.xml` in
+/// `$XDG_CONFIG_HOME` (`~/.config` if it is not set)
+pub fn config_locations() -> Vec<PathBuf> {
+    let mut locations = vec![PathBuf::from(LOCAL_CONFIG_FILE)];
+    // relative paths in XDG_CONFIG_HOME are invalid and ignored by the spec
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")));
+    if let Some(dir) = config_home {
+        locations.push(dir.join("dcim-sort").join("config.xml"));
+    }
+    locations
+}
+
+/// the first existing config of [config_locations] or `None` to use the built-in defaults
+pub fn discover_config() -> Option<PathBuf> {
+    config_locations().into_iter().find(|p| p.is_file())
+}
+
+/// Formats of config files. XML is the original format, the others are read as a [ConfigDoc]
+/// with the same structure and semantics.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ConfigFormat {
+    Xml,
+    Toml,
+    Json,
+    Yaml
+}
+
+impl ConfigFormat {
+    /// detect the format by the extension of `path`, defaulting to XML
+    pub fn from_path(path: &Path) -> ConfigFormat {
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+            Some("toml") => ConfigFormat::Toml,
+            Some("json") => ConfigFormat::Json,
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Xml
+        }
+    }
+}
+
+/// The settings of one segment type read from a `<segment>` element, see
+/// [segment_registry::register_segment_type] for making custom types usable in config files.
 pub trait SegmentConfig {
     fn generate(&self) -> Result<Box<dyn PatternElement + Send>, CfgError>;
+
+    /// write all settings as children of a `<segment>` element, the `type` and `index`
+    /// attributes are set by the caller
+    fn to_element(&self) -> Element;
 }
 
+/// The settings of a config file. A config may contain several `<sorter>` elements, all but one
+/// of them with a unique `name` attribute, which are selected as profiles with
+/// [RootCfg::select_profile].
 pub struct RootCfg {
-    sorter: SorterCfg,
+    /// all sorters with their profile name, `None` for the unnamed one
+    sorters: Vec<(Option<String>, SorterCfg)>,
+    /// index of the sorter in use
+    selected: usize,
+    scanner: ScannerCfg,
+    processors: ProcessorCfg,
+    logging: Option<LoggerCfg>
 }
 
 impl RootCfg {
     pub fn from(el: &Element) -> Result<RootCfg, CfgError> {
-        let mut sorter: Option<SorterCfg> = None;
+        let mut sorters: Vec<(Option<String>, SorterCfg)> = Vec::new();
+        let mut scanner = ScannerCfg::default();
+        let mut processors = ProcessorCfg::default();
+        let mut logging = None;
 
         for child in el.children() {
             match child.name() {
                 "sorter" => {
-                    sorter = Some(SorterCfg::from(child)?);
+                    let name = Self::parse_profile_name(child)?;
+                    if sorters.iter().any(|(n, _)| *n == name) {
+                        return Err(CfgError::val_err(match &name {
+                            Some(n) => format!("duplicate sorter profile \"{}\"", n),
+                            None => String::from("more than one sorter without a profile name")
+                        }.as_str()));
+                    }
+                    sorters.push((name, SorterCfg::from(child)?));
+                },
+                "scanner" => {
+                    scanner = ScannerCfg::from(child)?;
+                },
+                "processors" => {
+                    processors = ProcessorCfg::from(child)?;
+                },
+                "logging" => {
+                    logging = Some(LoggerCfg::from(child)?);
                 },
                 _ => continue
             }
         }
 
-        if let Some(s) = sorter {
+        if !sorters.is_empty() {
+            // the unnamed sorter is the default profile, otherwise the first one
+            let selected = sorters.iter().position(|(n, _)| n.is_none()).unwrap_or(0);
             Ok(RootCfg{
-                sorter: s
+                sorters,
+                selected,
+                scanner,
+                processors,
+                logging
             })
         }
         else {
@@ -83,31 +252,212 @@ impl RootCfg {
         }
     }
 
+    /// the profile name of the `<sorter>` element `el`, `None` if it has none
+    pub(crate) fn parse_profile_name(el: &Element) -> Result<Option<String>, CfgError> {
+        match el.attr("name") {
+            Some(n) if n.trim().is_empty() => Err(CfgError::val_err("the sorter profile name must not be empty")),
+            Some(n) => Ok(Some(String::from(n.trim()))),
+            None => Ok(None)
+        }
+    }
+
+    /// the names of all sorter profiles in the order they are defined
+    pub fn profiles(&self) -> Vec<&str> {
+        self.sorters.iter().filter_map(|(n, _)| n.as_deref()).collect()
+    }
+
+    /// the name of the sorter profile in use, `None` for the unnamed sorter
+    pub fn selected_profile(&self) -> Option<&str> {
+        self.sorters[self.selected].0.as_deref()
+    }
+
+    /// use the sorter profile `name`, or the default one if `None`. Without a name this fails if
+    /// the config has several profiles but no unnamed sorter, as the choice would be arbitrary.
+    pub fn select_profile(&mut self, name: Option<&str>) -> Result<(), CfgError> {
+        let available = self.profiles().iter().map(|n| format!("\"{}\"", n)).collect::<Vec<String>>().join(", ");
+        match name {
+            Some(name) => match self.sorters.iter().position(|(n, _)| n.as_deref() == Some(name)) {
+                Some(i) => {
+                    self.selected = i;
+                    Ok(())
+                },
+                None => Err(CfgError::val_err(
+                    format!("unknown sorter profile \"{}\", available profiles: {}", name, available).as_str()
+                ))
+            },
+            None => match self.sorters.iter().position(|(n, _)| n.is_none()) {
+                Some(i) => {
+                    self.selected = i;
+                    Ok(())
+                },
+                None if self.sorters.len() == 1 => {
+                    self.selected = 0;
+                    Ok(())
+                },
+                None => Err(CfgError::val_err(
+                    format!("the config has no default sorter, select one of the profiles {}", available).as_str()
+                ))
+            }
+        }
+    }
+
+    /// read the config file at `path` in the format detected by [ConfigFormat::from_path].
+    /// Includes of an XML config are resolved relative to the directory of `path`.
+    pub fn read_config(path: &Path) -> Result<RootCfg, CfgError> {
+        let mut file = File::open(path).map_err(CfgError::IoError)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        Self::parse_in(Self::read_data(&mut file)?.as_str(), ConfigFormat::from_path(path), dir)
+    }
+
+    /// read an XML config from `file`
     pub fn read_file(file: &mut File) -> Result<RootCfg, CfgError> {
-        let data = &mut String::new();
-        match file.read_to_string(data) {
+        Self::parse(Self::read_data(file)?.as_str(), ConfigFormat::Xml)
+    }
+
+    /// parse a config in `format`, see [ConfigDoc] for the mapping of TOML, JSON and YAML to
+    /// the XML config. Includes are resolved relative to the working directory.
+    pub fn parse(data: &str, format: ConfigFormat) -> Result<RootCfg, CfgError> {
+        Self::parse_in(data, format, Path::new("."))
+    }
+
+    /// parse a config in `format` like [Self::parse], resolving the `<include>` elements of an
+    /// XML config relative to `dir`
+    pub fn parse_in(data: &str, format: ConfigFormat, dir: &Path) -> Result<RootCfg, CfgError> {
+        let root = match format {
+            ConfigFormat::Xml => match data.parse::<Element>() {
+                Ok(root_el) if root_el.name() == "config" => resolve_includes(&root_el, dir)?,
+                Ok(root_el) => return Err(CfgError::val_err(format!("unexpected root element: \"{}\"", root_el.name()).as_str())),
+                Err(e) => return Err(CfgError::XmlParseFailure(e))
+            },
+            _ => ConfigDoc::parse(data, format)?.to_element()
+        };
+        // older layouts are migrated, so they are read like they were before
+        let migrated = migration::migrate(&root)?;
+        for change in &migrated.changes {
+            console::warn(format!("config of version {}: {}, run 'dcim-sort config migrate' to update it", migrated.version, change).as_str());
+        }
+        Self::from(&migrated.root)
+    }
+
+    fn read_data(file: &mut File) -> Result<String, CfgError> {
+        let mut data = String::new();
+        match file.read_to_string(&mut data) {
             Err(e) => Err(CfgError::IoError(e)),
             Ok(sz) => {
-                println!("[INFO] successfully read {} bytes of config", sz);
-
-                let root_el: Element = match data.parse() {
-                    Ok(e) => Ok(e),
-                    Err(e) => Err(CfgError::XmlParseFailure(e))
-                }?;
-                match root_el.name() {
-                    "config" => Self::from(&root_el),
-                    x => Err(CfgError::val_err(format!("unexpected root element: \"{}\"", x).as_str()))
+                if console::shows(Verbosity::Normal) {
+                    println!("[INFO] successfully read {} bytes of config", sz);
                 }
+                Ok(data)
             }
         }
     }
 
+    /// generate the builders of all sorter profiles to check them, see [validation::validate]
+    pub(crate) fn check_profiles(&self) -> Result<(), CfgError> {
+        for (_, sorter) in &self.sorters {
+            sorter.generate_builder()?;
+        }
+        Ok(())
+    }
+
+    /// generate the builder of the selected sorter profile
     pub fn generate_sorter_builder(&self) -> Result<SorterBuilder, CfgError> {
-        self.sorter.generate_builder()
+        self.get_sorter_cfg().generate_builder()
     }
 
+    pub fn generate_processor_builder(&self) -> Result<MetaProcessorBuilder, CfgError> {
+        Ok(self.processors.generate_builder())
+    }
+
+    /// the settings of the selected sorter profile
     pub fn get_sorter_cfg(&self) -> &SorterCfg {
-        &self.sorter
+        &self.sorters[self.selected].1
+    }
+
+    /// the effective duplicate handling of the selected sorter profile. It is only set by
+    /// `<duplicateResolution>`, there is no command line flag overriding it.
+    pub fn get_duplicate_handling(&self) -> DuplicateResolution {
+        self.get_sorter_cfg().get_duplicate_handling()
+    }
+
+    /// the effective hash algorithm of the selected sorter profile, set by `<hashAlgorithm>` or
+    /// [SorterBuilder::default_hash_algorithm]. The command line of dcim-sort overrides it, see
+    /// [crate::config::overrides].
+    pub fn get_hash_algorithm(&self) -> HashAlgorithm {
+        self.get_sorter_cfg().get_hash_algorithm()
+            .unwrap_or_else(SorterBuilder::default_hash_algorithm)
+    }
+
+    pub fn get_scanner_cfg(&self) -> &ScannerCfg {
+        &self.scanner
+    }
+
+    pub fn get_processor_cfg(&self) -> &ProcessorCfg {
+        &self.processors
+    }
+
+    /// the logger settings or `None` if no log file is configured
+    pub fn get_logger_cfg(&self) -> Option<&LoggerCfg> {
+        self.logging.as_ref()
+    }
+
+    /// replace the logger settings, `None` disables the log
+    pub fn set_logger_cfg(&mut self, cfg: Option<LoggerCfg>) {
+        self.logging = cfg;
+    }
+
+    /// the settings of the selected sorter profile, e.g. to apply overrides of the command line
+    /// before writing the effective config
+    pub fn get_sorter_cfg_mut(&mut self) -> &mut SorterCfg {
+        &mut self.sorters[self.selected].1
+    }
+
+    pub fn get_scanner_cfg_mut(&mut self) -> &mut ScannerCfg {
+        &mut self.scanner
+    }
+
+    /// the `<config>` element of all settings including all sorter profiles, with the defaults
+    /// filled in. Parsing it results in the same settings.
+    pub fn to_element(&self) -> Element {
+        let mut root = doc_config::element("config");
+        root.set_attr("version", migration::CONFIG_VERSION.to_string());
+        for (name, sorter) in &self.sorters {
+            let mut el = sorter.to_element();
+            if let Some(n) = name {
+                el.set_attr("name", n.as_str());
+            }
+            root.append_child(el);
+        }
+        self.append_sections(&mut root);
+        root
+    }
+
+    /// the `<config>` element of the settings in use, i.e. only the selected sorter profile
+    /// written as the default sorter
+    pub fn to_effective_element(&self) -> Element {
+        let mut root = doc_config::element("config");
+        root.set_attr("version", migration::CONFIG_VERSION.to_string());
+        root.append_child(self.get_sorter_cfg().to_element());
+        self.append_sections(&mut root);
+        root
+    }
+
+    fn append_sections(&self, root: &mut Element) {
+        root.append_child(self.scanner.to_element());
+        root.append_child(self.processors.to_element());
+        if let Some(logging) = &self.logging {
+            root.append_child(logging.to_element());
+        }
+    }
+
+    /// write all settings as an indented XML config, see [Self::to_element]
+    pub fn to_xml(&self) -> String {
+        write_xml(&self.to_element())
+    }
+
+    /// write the settings in use as an indented XML config, see [Self::to_effective_element]
+    pub fn to_effective_xml(&self) -> String {
+        write_xml(&self.to_effective_element())
     }
 
     /*
@@ -115,4 +465,73 @@ impl RootCfg {
         self.sorter.generate(outdir)
     }
      */
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod root_config {
+        use crate::config::{ConfigFormat, default_config, RootCfg};
+        use crate::config::validation::validate;
+        use crate::sorting::{Comparison, DuplicateResolution};
+        use crate::sorting::comparison::HashAlgorithm;
+
+        #[test]
+        fn selects_sorter_profiles() {
+            let xml = "<config xmlns=\"\"><sorter name=\"phone\"><duplicateResolution strategy=\"overwrite\"/></sorter><sorter name=\"camera\"><duplicateResolution strategy=\"compare\">rename</duplicateResolution></sorter></config>";
+            let mut cfg = RootCfg::parse(xml, ConfigFormat::Xml).unwrap();
+            assert_eq!(cfg.profiles(), vec!["phone", "camera"]);
+            assert!(cfg.select_profile(None).is_err());
+            cfg.select_profile(Some("camera")).unwrap();
+            assert_eq!(cfg.selected_profile(), Some("camera"));
+            assert!(matches!(cfg.get_sorter_cfg().get_duplicate_handling(), DuplicateResolution::Compare(Comparison::Rename)));
+            assert!(cfg.select_profile(Some("tablet")).is_err());
+
+            let toml = "[sorter]\nduplicateResolution = { strategy = \"ignore\" }\n[profiles.phone]\nduplicateResolution = { strategy = \"overwrite\" }\n";
+            let mut cfg = RootCfg::parse(toml, ConfigFormat::Toml).unwrap();
+            cfg.select_profile(None).unwrap();
+            assert!(matches!(cfg.get_sorter_cfg().get_duplicate_handling(), DuplicateResolution::Ignore));
+            cfg.select_profile(Some("phone")).unwrap();
+            assert!(matches!(cfg.get_sorter_cfg().get_duplicate_handling(), DuplicateResolution::Overwrite));
+
+            let duplicate = "<config xmlns=\"\"><sorter name=\"a\"/><sorter name=\"a\"/></config>";
+            assert!(RootCfg::parse(duplicate, ConfigFormat::Xml).is_err());
+        }
+
+        #[test]
+        fn reads_effective_hash_algorithm() {
+            let toml = "[sorter]\nhashAlgorithm = \"BLAKE3\"\n[profiles.fast]\nduplicateResolution = { strategy = \"overwrite\" }\n";
+            let mut cfg = RootCfg::parse(toml, ConfigFormat::Toml).unwrap();
+            cfg.select_profile(None).unwrap();
+            assert_eq!(cfg.get_hash_algorithm().name(), "blake3");
+            cfg.select_profile(Some("fast")).unwrap();
+            assert_eq!(cfg.get_hash_algorithm().name(), "md5");
+            assert!(matches!(cfg.get_duplicate_handling(), DuplicateResolution::Overwrite));
+
+            let unknown = "<config xmlns=\"\"><sorter><hashAlgorithm>crc32</hashAlgorithm></sorter></config>";
+            assert!(RootCfg::parse(unknown, ConfigFormat::Xml).is_err());
+        }
+
+        #[test]
+        fn written_config_has_same_settings() {
+            let mut cfg = RootCfg::parse(default_config::to_toml().as_str(), ConfigFormat::Toml).unwrap();
+            assert!(validate(cfg.to_xml().as_str(), ConfigFormat::Xml).is_empty());
+            cfg.get_sorter_cfg_mut().set_hash_algorithm(HashAlgorithm::SHA256);
+            let written = RootCfg::parse(cfg.to_xml().as_str(), ConfigFormat::Xml).unwrap();
+            assert_eq!(written.to_xml(), cfg.to_xml());
+            assert_eq!(written.get_hash_algorithm().name(), "sha256");
+
+            let toml = "[sorter]\nhashAlgorithm = \"BLAKE3\"\n[profiles.fast]\nduplicateResolution = { strategy = \"overwrite\" }\n";
+            let mut cfg = RootCfg::parse(toml, ConfigFormat::Toml).unwrap();
+            cfg.select_profile(Some("fast")).unwrap();
+            let mut written = RootCfg::parse(cfg.to_xml().as_str(), ConfigFormat::Xml).unwrap();
+            assert_eq!(written.profiles(), vec!["fast"]);
+            let mut effective = RootCfg::parse(cfg.to_effective_xml().as_str(), ConfigFormat::Xml).unwrap();
+            assert!(effective.profiles().is_empty());
+            effective.select_profile(None).unwrap();
+            written.select_profile(Some("fast")).unwrap();
+            assert!(matches!(effective.get_duplicate_handling(), DuplicateResolution::Overwrite));
+            assert_eq!(effective.get_hash_algorithm().name(), written.get_hash_algorithm().name());
+        }
+    }
+}
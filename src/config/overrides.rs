@@ -0,0 +1,127 @@
+use std::fmt::Display;
+
+/// environment variable overriding the output directory
+pub const ENV_OUTPUT_DIR: &str = "DCIM_SORT_OUTPUT_DIR";
+/// environment variable overriding the maximum count of threads
+pub const ENV_THREADS: &str = "DCIM_SORT_THREADS";
+/// environment variable overriding the duplicate handling of the config, see
+/// [crate::sorting::DuplicateResolution::parse]
+pub const ENV_DUPLICATE_RESOLUTION: &str = "DCIM_SORT_DUPLICATE_RESOLUTION";
+
+/// The sources a setting can be taken from, in increasing precedence.
+///
+/// # Variants
+/// - [SettingSource::Default] the built-in default
+/// - [SettingSource::Config] the config file
+/// - [SettingSource::Env] an environment variable, e.g. of a container
+/// - [SettingSource::Cli] a command line argument
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub enum SettingSource {
+    Default,
+    Config,
+    Env,
+    Cli
+}
+
+impl SettingSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SettingSource::Default => "default",
+            SettingSource::Config => "config",
+            SettingSource::Env => "environment",
+            SettingSource::Cli => "command line"
+        }
+    }
+}
+
+/// A setting resolved from layered sources (defaults < config file < environment variables <
+/// command line flags), so deployments can override single values without editing the config.
+/// Layers can be applied in any order, a value only replaces the current one if its source
+/// takes precedence.
+///
+/// ```
+/// use dcim_sort::config::overrides::{Layered, SettingSource};
+///
+/// let threads = Layered::new(0)
+///     .layer(Some(4), SettingSource::Cli)
+///     .layer(Some(2), SettingSource::Env);
+/// assert_eq!(*threads.value(), 4);
+/// assert_eq!(threads.source(), SettingSource::Cli);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Layered<T> {
+    value: T,
+    source: SettingSource
+}
+
+impl<T> Layered<T> {
+    pub fn new(default: T) -> Layered<T> {
+        Layered {
+            value: default,
+            source: SettingSource::Default
+        }
+    }
+
+    /// use `value` if it is given and `source` takes precedence over the current source
+    pub fn layer(mut self, value: Option<T>, source: SettingSource) -> Layered<T> {
+        self.set_layer(value, source);
+        self
+    }
+
+    pub fn set_layer(&mut self, value: Option<T>, source: SettingSource) {
+        if let Some(v) = value {
+            if source >= self.source {
+                self.value = v;
+                self.source = source;
+            }
+        }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_value(self) -> T {
+        self.value
+    }
+
+    pub fn source(&self) -> SettingSource {
+        self.source
+    }
+}
+
+/// read the environment variable `name` and convert it with `parse`. Unset and empty variables
+/// are `Ok(None)`, values `parse` rejects are an error.
+pub fn env_value<T, E: Display, F: Fn(&str) -> Result<T, E>>(name: &str, parse: F) -> Result<Option<T>, String> {
+    match std::env::var(name) {
+        Ok(v) if v.trim().is_empty() => Ok(None),
+        Ok(v) => match parse(v.trim()) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => Err(format!("invalid value of environment variable {}: \"{}\" ({})", name, v, e))
+        },
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(format!("invalid value of environment variable {}: {}", name, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod layered {
+        use crate::config::overrides::{env_value, Layered, SettingSource};
+
+        #[test]
+        fn higher_sources_take_precedence() {
+            let mut dir = Layered::new("sorted")
+                .layer(None, SettingSource::Cli)
+                .layer(Some("/data/config"), SettingSource::Config);
+            assert_eq!((*dir.value(), dir.source()), ("/data/config", SettingSource::Config));
+            dir.set_layer(Some("/data/env"), SettingSource::Env);
+            dir.set_layer(Some("/data/other"), SettingSource::Config);
+            assert_eq!((*dir.value(), dir.source()), ("/data/env", SettingSource::Env));
+
+            let missing: Result<Option<usize>, String> = env_value("DCIM_SORT_TEST_UNSET_VARIABLE", |s| s.parse::<usize>());
+            assert_eq!(missing, Ok(None));
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::str::FromStr;
+
+use minidom::Element;
+
+use crate::config::CfgError;
+use crate::config::doc_config::element;
+use crate::media::FileMetaProcessor;
+use crate::media::kadamak_exif::KadamakExifProcessor;
+use crate::media::metadata_processor::{MetaProcessor, MetaProcessorBuilder, Priority};
+#[cfg(feature = "rexiv2")]
+use crate::media::rexiv_proc::Rexiv2Processor;
+
+/// The metadata backends that can be configured in `<processors>`.
+///
+/// # Variants
+/// - [ProcessorKind::Rexiv2] the `Rexiv2Processor` based on gexiv2, named `rexiv2`. Only
+///   available with the `rexiv2` feature, it is skipped otherwise.
+/// - [ProcessorKind::KamadakExif] the [KadamakExifProcessor] in pure Rust, named `kamadak-exif`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ProcessorKind {
+    Rexiv2,
+    KamadakExif
+}
+
+impl ProcessorKind {
+    /// all backends in the order they are added to the builder by default
+    pub const ALL: [ProcessorKind; 2] = [ProcessorKind::Rexiv2, ProcessorKind::KamadakExif];
+
+    pub fn parse(name: &str) -> Option<ProcessorKind> {
+        ProcessorKind::ALL.iter().copied().find(|k| k.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProcessorKind::Rexiv2 => "rexiv2",
+            ProcessorKind::KamadakExif => "kamadak-exif"
+        }
+    }
+
+    /// the priority used if the backend is not configured
+    pub fn default_priority(&self) -> Priority {
+        match self {
+            ProcessorKind::Rexiv2 => Priority::None,
+            ProcessorKind::KamadakExif => Priority::Lowest
+        }
+    }
+
+    /// create the backend, `None` if dcim-sort was built without it
+    pub fn create(&self) -> Option<Box<dyn FileMetaProcessor + Send>> {
+        match self {
+            #[cfg(feature = "rexiv2")]
+            ProcessorKind::Rexiv2 => Some(Rexiv2Processor::new()),
+            #[cfg(not(feature = "rexiv2"))]
+            ProcessorKind::Rexiv2 => None,
+            ProcessorKind::KamadakExif => Some(KadamakExifProcessor::new())
+        }
+    }
+}
+
+/// format a [Priority] like it is written in the config
+pub fn priority_name(priority: Priority) -> String {
+    match priority {
+        Priority::Highest => String::from("highest"),
+        Priority::Lowest => String::from("lowest"),
+        Priority::None => String::from("none"),
+        Priority::Fixed(i) => i.to_string()
+    }
+}
+
+/// Settings of the metadata processors read from the optional `<processors>` element. Each
+/// `<processor>` enables or disables a backend by `name` and sets its `priority`, backends not
+/// listed are used with their default priority.
+#[derive(Clone)]
+pub struct ProcessorCfg {
+    processors: Vec<(ProcessorKind, bool, Priority)>
+}
+
+impl Default for ProcessorCfg {
+    fn default() -> Self {
+        ProcessorCfg {
+            processors: ProcessorKind::ALL.iter().map(|k| (*k, true, k.default_priority())).collect()
+        }
+    }
+}
+
+impl ProcessorCfg {
+    pub fn from(el: &Element) -> Result<ProcessorCfg, CfgError> {
+        let mut cfg = ProcessorCfg::default();
+        for child in el.children() {
+            cfg.parse_child(child)?;
+        }
+        Ok(cfg)
+    }
+
+    /// apply the child element `child` of `<processors>`, returning false if it is not a known
+    /// element and has been ignored
+    pub(crate) fn parse_child(&mut self, child: &Element) -> Result<bool, CfgError> {
+        if child.name() != "processor" {
+            return Ok(false);
+        }
+        let kind = match child.attr("name") {
+            Some(n) => match ProcessorKind::parse(n) {
+                Some(k) => k,
+                None => return Err(CfgError::val_err(format!("unknown processor name: \"{}\"", n).as_str()))
+            },
+            None => return Err(CfgError::val_err("missing mandatory attribute \"name\" on processor"))
+        };
+        let enabled = match child.attr("enabled") {
+            Some(s) => match bool::from_str(s) {
+                Ok(b) => b,
+                Err(_) => return Err(CfgError::val_err(
+                    format!("Illegal value for processor enabled: \"{}\"", s).as_str()
+                ))
+            },
+            None => true
+        };
+        let priority = match child.attr("priority") {
+            Some(s) => match Self::parse_priority(s) {
+                Some(p) => p,
+                None => return Err(CfgError::val_err(
+                    format!("Illegal value for processor priority: \"{}\"", s).as_str()
+                ))
+            },
+            None => kind.default_priority()
+        };
+        // a configured backend is moved to the end, so processors of the same priority are
+        // used in the order they are listed
+        self.processors.retain(|(k, _, _)| *k != kind);
+        self.processors.push((kind, enabled, priority));
+        Ok(true)
+    }
+
+    fn parse_priority(s: &str) -> Option<Priority> {
+        match s {
+            "highest" => Some(Priority::Highest),
+            "lowest" => Some(Priority::Lowest),
+            "none" => Some(Priority::None),
+            _ => usize::from_str(s).ok().map(Priority::Fixed)
+        }
+    }
+
+    /// the enabled backends with their priority
+    pub fn enabled(&self) -> Vec<(ProcessorKind, Priority)> {
+        self.processors.iter()
+            .filter(|(_, enabled, _)| *enabled)
+            .map(|(k, _, p)| (*k, *p))
+            .collect()
+    }
+
+    /// the `<processors>` element listing all backends in order
+    pub fn to_element(&self) -> Element {
+        let mut el = element("processors");
+        for (kind, enabled, priority) in &self.processors {
+            let mut child = element("processor");
+            child.set_attr("name", kind.name());
+            child.set_attr("enabled", enabled.to_string());
+            child.set_attr("priority", priority_name(*priority));
+            el.append_child(child);
+        }
+        el
+    }
+
+    pub fn generate_builder(&self) -> MetaProcessorBuilder {
+        let mut builder = MetaProcessor::new();
+        for (kind, priority) in self.enabled() {
+            if let Some(processor) = kind.create() {
+                builder = builder.processor(processor, priority);
+            }
+        }
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod processor_config {
+        use crate::config::processor_config::{ProcessorCfg, ProcessorKind};
+        use crate::media::metadata_processor::Priority;
+
+        #[test]
+        fn overrides_listed_processors_only() {
+            let el = "<processors xmlns=\"\"><processor name=\"kamadak-exif\" priority=\"highest\"/><processor name=\"rexiv2\" enabled=\"false\"/></processors>"
+                .parse().unwrap();
+            let cfg = ProcessorCfg::from(&el).unwrap();
+            assert_eq!(cfg.enabled(), vec![(ProcessorKind::KamadakExif, Priority::Highest)]);
+            assert_eq!(ProcessorCfg::default().enabled(), vec![(ProcessorKind::Rexiv2, Priority::None), (ProcessorKind::KamadakExif, Priority::Lowest)]);
+
+            let unknown = "<processors xmlns=\"\"><processor name=\"exiftool\"/></processors>".parse().unwrap();
+            assert!(ProcessorCfg::from(&unknown).is_err());
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::str::FromStr;
+
+use minidom::Element;
+
+use crate::config::{CfgError, parse_byte_size};
+use crate::config::doc_config::{append_text_child, element};
+use crate::index::{Glob, Scanner, ScannerBuilder};
+
+/// Settings of the [Scanner] read from the optional `<scanner>` element
+#[derive(Clone, Default)]
+pub struct ScannerCfg {
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    max_depth: Option<u8>,
+    ignore_unknown_types: Option<bool>,
+    follow_symlinks: Option<bool>,
+    excludes: Vec<String>,
+    includes: Vec<String>
+}
+
+impl ScannerCfg {
+    pub fn from(el: &Element) -> Result<ScannerCfg, CfgError> {
+        let mut cfg = ScannerCfg::default();
+        for child in el.children() {
+            cfg.parse_child(child)?;
+        }
+        Ok(cfg)
+    }
+
+    /// apply the child element `child` of `<scanner>`, returning false if it is not a known
+    /// element and has been ignored
+    pub(crate) fn parse_child(&mut self, child: &Element) -> Result<bool, CfgError> {
+        match child.name() {
+            "sizeFilter" => {
+                self.min_size = Self::parse_size_attr(child, "minBytes")?;
+                self.max_size = Self::parse_size_attr(child, "maxBytes")?;
+            },
+            "maxRecursion" => {
+                let text = child.text();
+                match u8::from_str(text.trim()) {
+                    Ok(depth) => self.max_depth = Some(depth),
+                    Err(_) => return Err(CfgError::val_err(
+                        format!("Illegal value for scanner maxRecursion: \"{}\"", text).as_str()
+                    ))
+                }
+            },
+            "ignoreUnknownTypes" => self.ignore_unknown_types = Some(Self::parse_bool(child)?),
+            "followSymlinks" => self.follow_symlinks = Some(Self::parse_bool(child)?),
+            "exclude" => self.excludes.push(Self::parse_glob(child)?),
+            "include" => self.includes.push(Self::parse_glob(child)?),
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    fn parse_size_attr(el: &Element, name: &str) -> Result<Option<u64>, CfgError> {
+        match el.attr(name) {
+            Some(s) => match parse_byte_size(s) {
+                Some(size) => Ok(Some(size)),
+                None => Err(CfgError::val_err(
+                    format!("Illegal value for sizeFilter {}: \"{}\"", name, s).as_str()
+                ))
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn parse_bool(el: &Element) -> Result<bool, CfgError> {
+        let text = el.text();
+        match bool::from_str(text.trim()) {
+            Ok(b) => Ok(b),
+            Err(_) => Err(CfgError::val_err(
+                format!("Illegal value for scanner {}: \"{}\"", el.name(), text).as_str()
+            ))
+        }
+    }
+
+    fn parse_glob(el: &Element) -> Result<String, CfgError> {
+        let pattern = el.text();
+        match Glob::new(pattern.as_str()) {
+            Ok(_) => Ok(pattern),
+            Err(e) => Err(CfgError::val_err(
+                format!("Illegal pattern for scanner {}: {}", el.name(), e).as_str()
+            ))
+        }
+    }
+
+    pub fn min_size(&self) -> Option<u64> {
+        self.min_size
+    }
+
+    pub fn max_size(&self) -> Option<u64> {
+        self.max_size
+    }
+
+    pub fn max_depth(&self) -> Option<u8> {
+        self.max_depth
+    }
+
+    pub fn excludes(&self) -> &[String] {
+        &self.excludes
+    }
+
+    pub fn includes(&self) -> &[String] {
+        &self.includes
+    }
+
+    /// replace the configured size limits, e.g. by command line arguments. `None` keeps the
+    /// configured limit.
+    pub fn override_size_limits(&mut self, min: Option<u64>, max: Option<u64>) {
+        self.min_size = min.or(self.min_size);
+        self.max_size = max.or(self.max_size);
+    }
+
+    /// replace the configured maximum recursion depth, e.g. by a command line argument. `None`
+    /// keeps the configured depth.
+    pub fn override_max_depth(&mut self, max: Option<u8>) {
+        self.max_depth = max.or(self.max_depth);
+    }
+
+    /// ignore unknown file types regardless of the config if `b` is true, e.g. if requested by a
+    /// command line argument
+    pub fn force_ignore_unknown_types(&mut self, b: bool) {
+        if b {
+            self.ignore_unknown_types = Some(true);
+        }
+    }
+
+    /// add patterns to the configured excludes and includes, e.g. given as command line
+    /// arguments
+    pub fn add_patterns(&mut self, excludes: &[String], includes: &[String]) {
+        self.excludes.extend(excludes.iter().cloned());
+        self.includes.extend(includes.iter().cloned());
+    }
+
+    /// the `<scanner>` element of these settings with the defaults of the [Scanner] filled in
+    pub fn to_element(&self) -> Element {
+        let mut el = element("scanner");
+        if self.min_size.is_some() || self.max_size.is_some() {
+            let mut filter = element("sizeFilter");
+            if let Some(min) = self.min_size {
+                filter.set_attr("minBytes", min.to_string());
+            }
+            if let Some(max) = self.max_size {
+                filter.set_attr("maxBytes", max.to_string());
+            }
+            el.append_child(filter);
+        }
+        append_text_child(&mut el, "maxRecursion", &Some(self.max_depth.unwrap_or(Scanner::DEFAULT_MAX_DEPTH)));
+        append_text_child(&mut el, "ignoreUnknownTypes", &Some(self.ignore_unknown_types.unwrap_or(false)));
+        append_text_child(&mut el, "followSymlinks", &Some(self.follow_symlinks.unwrap_or(true)));
+        for pattern in &self.excludes {
+            append_text_child(&mut el, "exclude", &Some(pattern));
+        }
+        for pattern in &self.includes {
+            append_text_child(&mut el, "include", &Some(pattern));
+        }
+        el
+    }
+
+    /// apply all settings to `builder`, invalid patterns are reported by [ScannerBuilder::build]
+    pub fn apply(&self, builder: &mut ScannerBuilder) {
+        builder.set_size_limits(self.min_size, self.max_size);
+        if let Some(depth) = self.max_depth {
+            builder.set_max_depth(depth);
+        }
+        if let Some(b) = self.ignore_unknown_types {
+            builder.set_ignore_unknown(b);
+        }
+        if let Some(b) = self.follow_symlinks {
+            builder.set_follow_symlinks(b);
+        }
+        for pattern in &self.excludes {
+            builder.add_exclude(pattern.as_str());
+        }
+        for pattern in &self.includes {
+            builder.add_include(pattern.as_str());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod scanner_config {
+        use crate::config::scanner_config::ScannerCfg;
+
+        #[test]
+        fn command_line_extends_config() {
+            let el = "<scanner xmlns=\"\"><maxRecursion>4</maxRecursion><ignoreUnknownTypes>true</ignoreUnknownTypes><followSymlinks>false</followSymlinks><exclude>@eaDir</exclude><exclude>*.tmp</exclude></scanner>"
+                .parse().unwrap();
+            let mut cfg = ScannerCfg::from(&el).unwrap();
+            assert_eq!(cfg.max_depth(), Some(4));
+            cfg.override_max_depth(None);
+            assert_eq!(cfg.max_depth(), Some(4));
+            cfg.override_max_depth(Some(2));
+            cfg.add_patterns(&[String::from("thumbs")], &[String::from("*.jpg")]);
+            assert_eq!(cfg.max_depth(), Some(2));
+            assert_eq!(cfg.excludes(), ["@eaDir", "*.tmp", "thumbs"]);
+            assert_eq!(cfg.includes(), ["*.jpg"]);
+
+            let depth = "<scanner xmlns=\"\"><maxRecursion>300</maxRecursion></scanner>".parse().unwrap();
+            assert!(ScannerCfg::from(&depth).is_err());
+            let glob = "<scanner xmlns=\"\"><exclude>[ab</exclude></scanner>".parse().unwrap();
+            assert!(ScannerCfg::from(&glob).is_err());
+        }
+    }
+}
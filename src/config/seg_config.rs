@@ -3,10 +3,11 @@ use std::str::FromStr;
 use minidom::Element;
 
 use crate::config::{CfgError, CfgValueError, SegmentConfig};
+use crate::config::doc_config::{append_text_child, element};
 use crate::pattern::device::{CaseNormalization, DevicePart, MakeModelPattern};
 use crate::pattern::fallback::SimpleFileTypePattern;
 use crate::pattern::general::{DateTimePart, DateTimePattern, ScreenshotPattern};
-use crate::pattern::PatternElement;
+use crate::pattern::{FolderDefaults, PatternElement};
 
 pub struct SegPart {
     index: i32,
@@ -83,6 +84,18 @@ fn parse_boolean(el: &Element) -> Result<Option<bool>, CfgError> {
     }
 }
 
+/// the `<parts>` element of `parts` indexed by their position
+fn parts_element(parts: &[SegPart]) -> Element {
+    let mut el = element("parts");
+    for (i, part) in parts.iter().enumerate() {
+        let mut child = element("part");
+        child.set_attr("index", i.to_string());
+        child.append_text_node(part.value.as_str());
+        el.append_child(child);
+    }
+    el
+}
+
 fn parse_string(el: &Element) -> Option<String> {
     let s = el.text();
     if !s.is_empty() {
@@ -128,11 +141,13 @@ impl SegPart {
 
 
 impl MakeModelPatternCfg {
-    pub fn from(el: &Element) -> Result<Box<dyn SegmentConfig + Send>, CfgError> {
+    pub const CHILDREN: &'static [&'static str] = &["parts", "replaceSpaces", "defaultMake", "defaultModel", "separator", "caseNormalization", "fallback"];
+
+    pub fn from(el: &Element, defaults: &FolderDefaults) -> Result<Box<dyn SegmentConfig + Send>, CfgError> {
         let mut parts: Vec<SegPart> = Vec::new();
         let mut replace_spaces = MakeModelPattern::def_replace_spaces();
-        let mut def_make = MakeModelPattern::def_default_make();
-        let mut def_model = MakeModelPattern::def_default_model();
+        let mut def_make = defaults.unknown_make.clone();
+        let mut def_model = defaults.unknown_model.clone();
         let mut case_normalization = MakeModelPattern::def_case();
         let mut separator = MakeModelPattern::def_separator();
         let mut fallback = String::new();
@@ -217,12 +232,33 @@ impl SegmentConfig for MakeModelPatternCfg {
 
         Ok(builder.build())
     }
+
+    fn to_element(&self) -> Element {
+        let mut el = element("segment");
+        el.append_child(parts_element(&self.parts));
+        append_text_child(&mut el, "replaceSpaces", &Some(self.replace_spaces));
+        append_text_child(&mut el, "defaultMake", &Some(&self.default_make));
+        append_text_child(&mut el, "defaultModel", &Some(&self.default_model));
+        append_text_child(&mut el, "separator", &Some(self.separator));
+        let case = match self.case_normalization {
+            CaseNormalization::Lowercase => "Lowercase",
+            CaseNormalization::Uppercase => "Uppercase",
+            CaseNormalization::None => "None"
+        };
+        append_text_child(&mut el, "caseNormalization", &Some(case));
+        if !self.fallback.is_empty() {
+            append_text_child(&mut el, "fallback", &Some(&self.fallback));
+        }
+        el
+    }
 }
 
 
 impl ScreenshotPatternCfg {
-    pub fn from(el: &Element) -> Result<Box<dyn SegmentConfig + Send>, CfgError> {
-        let mut value = ScreenshotPattern::def_value();
+    pub const CHILDREN: &'static [&'static str] = &["value", "filenamePattern"];
+
+    pub fn from(el: &Element, defaults: &FolderDefaults) -> Result<Box<dyn SegmentConfig + Send>, CfgError> {
+        let mut value = defaults.screenshots.clone();
         let mut filename_pattern: Option<String> = None;
         let mut case_insensitive = false;
         for child in el.children() {
@@ -273,14 +309,28 @@ impl SegmentConfig for ScreenshotPatternCfg {
             }
         }
     }
+
+    fn to_element(&self) -> Element {
+        let mut el = element("segment");
+        append_text_child(&mut el, "value", &Some(&self.value));
+        if let Some((pattern, case_insensitive)) = &self.filename_pattern {
+            let mut child = element("filenamePattern");
+            child.set_attr("caseInsensitive", case_insensitive.to_string());
+            child.append_text_node(pattern.as_str());
+            el.append_child(child);
+        }
+        el
+    }
 }
 
 
 impl DateTimePatternCfg {
-    pub fn from(el: &Element) -> Result<Box<dyn SegmentConfig + Send>, CfgError> {
+    pub const CHILDREN: &'static [&'static str] = &["parts", "separator", "defaultValue", "fallbackFsTimestamp"];
+
+    pub fn from(el: &Element, defaults: &FolderDefaults) -> Result<Box<dyn SegmentConfig + Send>, CfgError> {
         let mut parts: Vec<SegPart> = Vec::new();
         let mut separator = DateTimePattern::def_separator();
-        let mut def_val = DateTimePattern::def_default();
+        let mut def_val = defaults.unknown_date.clone();
         let mut fallback = DateTimePattern::def_fs_timestamp_fallback();
 
         for child in el.children() {
@@ -335,17 +385,28 @@ impl SegmentConfig for DateTimePatternCfg {
 
         Ok(builder.build())
     }
+
+    fn to_element(&self) -> Element {
+        let mut el = element("segment");
+        el.append_child(parts_element(&self.parts));
+        append_text_child(&mut el, "separator", &Some(self.separator));
+        append_text_child(&mut el, "defaultValue", &Some(&self.default_value));
+        append_text_child(&mut el, "fallbackFsTimestamp", &Some(self.fallback_fs_timestamp));
+        el
+    }
 }
 
 
 impl SimpleFileTypePatternCfg {
-    pub fn from(el: &Element) -> Result<Box<dyn SegmentConfig + Send>, CfgError> {
-        let mut video = SimpleFileTypePattern::def_video();
-        let mut pic = SimpleFileTypePattern::def_picture();
-        let mut audio = SimpleFileTypePattern::def_audio();
-        let mut text = SimpleFileTypePattern::def_text();
-        let mut doc = SimpleFileTypePattern::def_document();
-        let mut other = SimpleFileTypePattern::def_other();
+    pub const CHILDREN: &'static [&'static str] = &["defaultVideo", "defaultPicture", "defaultAudio", "defaultText", "defaultDocument", "defaultOther"];
+
+    pub fn from(el: &Element, defaults: &FolderDefaults) -> Result<Box<dyn SegmentConfig + Send>, CfgError> {
+        let mut video = defaults.video.clone();
+        let mut pic = defaults.picture.clone();
+        let mut audio = defaults.audio.clone();
+        let mut text = defaults.text.clone();
+        let mut doc = defaults.document.clone();
+        let mut other = defaults.other.clone();
 
         for child in el.children() {
             match child.name() {
@@ -408,4 +469,45 @@ impl SegmentConfig for SimpleFileTypePatternCfg {
             .build()
         )
     }
-}
\ No newline at end of file
+
+    fn to_element(&self) -> Element {
+        let mut el = element("segment");
+        append_text_child(&mut el, "defaultVideo", &Some(&self.default_video));
+        append_text_child(&mut el, "defaultPicture", &Some(&self.default_picture));
+        append_text_child(&mut el, "defaultAudio", &Some(&self.default_audio));
+        append_text_child(&mut el, "defaultText", &Some(&self.default_text));
+        append_text_child(&mut el, "defaultDocument", &Some(&self.default_document));
+        append_text_child(&mut el, "defaultOther", &Some(&self.default_other));
+        el
+    }
+}
+
+/// parse the `<defaults>` block overriding the [FolderDefaults], each given as a child element
+/// with the folder name as text
+pub fn parse_folder_defaults(el: &Element) -> Result<FolderDefaults, CfgError> {
+    let mut defaults = FolderDefaults::new();
+    for child in el.children() {
+        let value = match parse_string(child) {
+            Some(v) => v,
+            None => return Err(CfgError::val_err(
+                format!("empty value for default folder name \"{}\"", child.name()).as_str()
+            ))
+        };
+        let target = match child.name() {
+            "unknownMake" => &mut defaults.unknown_make,
+            "unknownModel" => &mut defaults.unknown_model,
+            "unknownDate" => &mut defaults.unknown_date,
+            "screenshots" => &mut defaults.screenshots,
+            "video" => &mut defaults.video,
+            "picture" => &mut defaults.picture,
+            "audio" => &mut defaults.audio,
+            "text" => &mut defaults.text,
+            "document" => &mut defaults.document,
+            "other" => &mut defaults.other,
+            "quarantine" => &mut defaults.quarantine,
+            n => return Err(CfgError::val_err(format!("unknown default folder name: \"{}\"", n).as_str()))
+        };
+        *target = value;
+    }
+    Ok(defaults)
+}
@@ -0,0 +1,168 @@
+use std::sync::RwLock;
+
+use minidom::Element;
+
+use crate::config::{CfgError, SegmentConfig};
+use crate::config::seg_config::{DateTimePatternCfg, MakeModelPatternCfg, ScreenshotPatternCfg, SimpleFileTypePatternCfg};
+use crate::pattern::FolderDefaults;
+
+/// Function reading a `<segment>` element of one type into its [SegmentConfig]. The element is
+/// the complete `<segment>` including the `type` and `index` attributes, `defaults` are the
+/// folder names of the sorter the segment belongs to.
+pub type SegmentParser = fn(&Element, &FolderDefaults) -> Result<Box<dyn SegmentConfig + Send>, CfgError>;
+
+/// A segment type usable as `type` attribute of a `<segment>` in the config.
+#[derive(Clone)]
+struct SegmentType {
+    name: String,
+    /// names of the child elements, others are reported by the validation
+    children: &'static [&'static str],
+    parser: SegmentParser
+}
+
+/// the segment types registered with [register_segment_type]
+static REGISTRY: RwLock<Vec<SegmentType>> = RwLock::new(Vec::new());
+
+/// the segment types of this crate
+fn builtin() -> [SegmentType; 4] {
+    [
+        SegmentType { name: String::from("MakeModelPattern"), children: MakeModelPatternCfg::CHILDREN, parser: MakeModelPatternCfg::from },
+        SegmentType { name: String::from("ScreenshotPattern"), children: ScreenshotPatternCfg::CHILDREN, parser: ScreenshotPatternCfg::from },
+        SegmentType { name: String::from("DateTimePattern"), children: DateTimePatternCfg::CHILDREN, parser: DateTimePatternCfg::from },
+        SegmentType { name: String::from("SimpleFileTypePattern"), children: SimpleFileTypePatternCfg::CHILDREN, parser: SimpleFileTypePatternCfg::from }
+    ]
+}
+
+/// Make the segment type `name` usable in config files, so a custom
+/// [crate::pattern::PatternElement] can be configured like the built-in ones. `parser` reads
+/// the `<segment>` element into a [SegmentConfig] generating the pattern, `children` are the
+/// names of the child elements it reads. Types are registered for the whole process and must
+/// be registered before the config is read.
+///
+/// Fails if a segment type with this name already exists.
+pub fn register_segment_type(name: &str, children: &'static [&'static str], parser: SegmentParser) -> Result<(), CfgError> {
+    if name.trim().is_empty() {
+        return Err(CfgError::val_err("segment type name must not be empty"));
+    }
+    if lookup(name).is_some() {
+        return Err(CfgError::val_err(format!("segment type \"{}\" is already registered", name).as_str()));
+    }
+    let mut registry = match REGISTRY.write() {
+        Ok(r) => r,
+        Err(poisoned) => poisoned.into_inner()
+    };
+    registry.push(SegmentType {
+        name: String::from(name),
+        children,
+        parser
+    });
+    Ok(())
+}
+
+/// names of all segment types, the built-in ones first
+pub fn segment_types() -> Vec<String> {
+    let mut names: Vec<String> = builtin().iter().map(|t| t.name.clone()).collect();
+    names.extend(registered().into_iter().map(|t| t.name));
+    names
+}
+
+/// the parser of segment type `name`
+pub(crate) fn parser(name: &str) -> Option<SegmentParser> {
+    lookup(name).map(|t| t.parser)
+}
+
+/// the child elements of segment type `name`
+pub(crate) fn children(name: &str) -> Option<&'static [&'static str]> {
+    lookup(name).map(|t| t.children)
+}
+
+fn lookup(name: &str) -> Option<SegmentType> {
+    builtin().iter().cloned()
+        .chain(registered())
+        .find(|t| t.name == name)
+}
+
+fn registered() -> Vec<SegmentType> {
+    match REGISTRY.read() {
+        Ok(r) => r.clone(),
+        // entries are pushed in one step, so the registry can still be used
+        Err(poisoned) => poisoned.into_inner().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod segment_registry {
+        use minidom::Element;
+
+        use crate::config::{CfgError, ConfigFormat, RootCfg, SegmentConfig};
+        use crate::config::doc_config::element;
+        use crate::config::segment_registry::{register_segment_type, segment_types};
+        use crate::config::validation::validate;
+        use crate::media::ImgInfo;
+        use crate::pattern::{FolderDefaults, PatternElement};
+
+        #[derive(Clone)]
+        struct Album {
+            name: String
+        }
+
+        impl PatternElement for Album {
+            fn is_optional(&self) -> bool {
+                false
+            }
+
+            fn translate(&self, _info: &ImgInfo) -> Option<String> {
+                Some(self.name.clone())
+            }
+
+            fn display(&self) -> String {
+                format!("Album({})", self.name)
+            }
+
+            fn name(&self) -> &str {
+                "Album"
+            }
+
+            fn clone_boxed(&self) -> Box<dyn PatternElement + Send> {
+                Box::new(self.clone())
+            }
+        }
+
+        impl SegmentConfig for Album {
+            fn generate(&self) -> Result<Box<dyn PatternElement + Send>, CfgError> {
+                Ok(self.clone_boxed())
+            }
+
+            fn to_element(&self) -> Element {
+                let mut el = element("segment");
+                let mut album = element("album");
+                album.append_text_node(self.name.as_str());
+                el.append_child(album);
+                el
+            }
+        }
+
+        fn parse_album(el: &Element, _defaults: &FolderDefaults) -> Result<Box<dyn SegmentConfig + Send>, CfgError> {
+            match el.get_child("album", "") {
+                Some(a) => Ok(Box::new(Album { name: a.text() })),
+                None => Err(CfgError::val_err("missing album"))
+            }
+        }
+
+        #[test]
+        fn parses_registered_types() {
+            let xml = "<config xmlns=\"\"><sorter><duplicateResolution strategy=\"ignore\"/><supported><segments><segment type=\"TestAlbumPattern\" index=\"0\"><album>holidays</album></segment></segments></supported></sorter></config>";
+            assert!(!validate(xml, ConfigFormat::Xml).is_empty());
+
+            register_segment_type("TestAlbumPattern", &["album"], parse_album).unwrap();
+            assert!(register_segment_type("DateTimePattern", &[], parse_album).is_err());
+            assert!(segment_types().contains(&String::from("TestAlbumPattern")));
+            let cfg = RootCfg::parse(xml, ConfigFormat::Xml).unwrap();
+            assert!(cfg.generate_sorter_builder().is_ok());
+            assert!(validate(xml, ConfigFormat::Xml).is_empty());
+            assert!(cfg.to_xml().contains("<album>holidays</album>"));
+        }
+    }
+}
@@ -1,16 +1,58 @@
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+use chrono::Duration;
 use minidom::Element;
 
-use crate::config::{CfgError, CfgValueError, SegmentConfig};
-use crate::config::seg_config::{DateTimePatternCfg, MakeModelPatternCfg, ScreenshotPatternCfg, SimpleFileTypePatternCfg};
+use crate::config::{CfgError, CfgValueError, parse_byte_size, segment_registry, SegmentConfig};
+use crate::config::doc_config::{append_text_child, element};
+use crate::config::seg_config::{DateTimePatternCfg, MakeModelPatternCfg, parse_folder_defaults, ScreenshotPatternCfg, SimpleFileTypePatternCfg};
+use crate::logging::console;
+use crate::pattern::{FolderDefaults, PatternElement};
+use crate::pattern::fallback::GeneralFileType;
 use crate::sorting::{Comparison, DuplicateResolution, SorterBuilder, Sorter};
+use crate::sorting::comparison::{DEFAULT_BUFFER_SIZE, HashAlgorithm, MtimeTolerance};
+use crate::sorting::naming::{DEFAULT_SEQUENCE_DIGITS, NamingScheme, RolloverRule, SequencePattern, SequenceScope, SuffixKind};
+use crate::sorting::PATHSTR_FB;
+use crate::sorting::quota::{BranchQuota, QuotaOverflow};
+use crate::sorting::retry::{MAX_ATTEMPTS, MAX_BACKOFF, RetryPolicy};
+use crate::sorting::routing::{AgeRoute, DatePlausibility, ImplausibleDate, PairFormat, PairHandling, PairPolicy, SourcePattern, SourceRule, TargetRoute};
+use crate::sorting::split::FolderSplit;
+use crate::sorting::translation::Translator;
+
+/// largest accepted coldStorage maxAgeDays, about 1000 years
+const MAX_AGE_DAYS: i64 = 365_250;
 
 pub struct SorterCfg {
     supported: Vec<SegmentCfg>,
     fallback: Vec<SegmentCfg>,
-    dup_handling: DuplicateResolution
+    dup_handling: DuplicateResolution,
+    quotas: Vec<BranchQuota>,
+    targets: Vec<TargetRoute>,
+    age_route: Option<AgeRoute>,
+    rollover_rules: Vec<RolloverRule>,
+    naming: NamingScheme,
+    date_check: Option<DatePlausibility>,
+    pairs: Option<PairPolicy>,
+    retry: Option<RetryPolicy>,
+    folder_split: Option<FolderSplit>,
+    fallback_root: Option<PathBuf>,
+    hash_algo: Option<HashAlgorithm>,
+    hash_buffer_size: Option<usize>,
+    mtime_tolerance: Option<MtimeTolerance>,
+    source_rules: Vec<SourceRuleCfg>
+}
+
+/// A `<rule>` of `<sourceRules>`. Segments not given by the rule are the ones of the sorter.
+struct SourceRuleCfg {
+    pattern: SourcePattern,
+    /// the pattern attribute as written in the config, e.g. `("glob", "WhatsApp/**")`
+    pattern_attr: (&'static str, String),
+    root: Option<PathBuf>,
+    supported: Option<Vec<SegmentCfg>>,
+    /// the fallback root and segments
+    fallback: Option<(Option<PathBuf>, Vec<SegmentCfg>)>
 }
 
 pub struct SegmentCfg {
@@ -28,28 +70,23 @@ pub enum SegmentType {
 }
 
 impl SegmentCfg {
-    pub fn from(el: &Element) -> Result<SegmentCfg, CfgError> {
-        let mut seg_tp = String::new();
+    pub fn from(el: &Element, defaults: &FolderDefaults) -> Result<SegmentCfg, CfgError> {
+        Self::parse(el, defaults, true)
+    }
+
+    /// read the `<segment>` element `el`. The `index` attribute may only be left out if
+    /// `index_required` is false, i.e. for segments in document order.
+    pub(crate) fn parse(el: &Element, defaults: &FolderDefaults, index_required: bool) -> Result<SegmentCfg, CfgError> {
+        let seg_tp = String::from(el.attr("type").unwrap_or(""));
         let mut index = 0;
 
         // get 'type' attribute
         let cfg = match el.attr("type") {
             Some(tp) => {
-                match tp {
-                    "MakeModelPattern" => {
-                        MakeModelPatternCfg::from(el)
-                    },
-                    "ScreenshotPattern" => {
-                        ScreenshotPatternCfg::from(el)
-                    },
-                    "DateTimePattern" => {
-                        DateTimePatternCfg::from(el)
-                    },
-                    "SimpleFileTypePattern" => {
-                        SimpleFileTypePatternCfg::from(el)
-                    }
-                    _ => {
-                        println!("[WARN] found unsupported segment type: {}", tp);
+                match segment_registry::parser(tp) {
+                    Some(parse) => parse(el, defaults),
+                    None => {
+                        console::warn(format!("found unsupported segment type: {}", tp).as_str());
                         Err(CfgError::unsupported_segment("unsupported segment type"))
                     }
                 }
@@ -68,7 +105,7 @@ impl SegmentCfg {
                 )?
             };
         }
-        else {
+        else if index_required {
             return Err(
                 CfgError::val_err("missing mandatory attribute \"index\"")
             )
@@ -84,74 +121,524 @@ impl SegmentCfg {
         )
     }
 
-    pub fn from_multiple(el: &Element) -> Result<Vec<SegmentCfg>, CfgError> {
+    pub fn seg_type(&self) -> &str {
+        self.seg_type.as_str()
+    }
+
+    /// the configured index, the position for segments in document order
+    pub fn index(&self) -> i32 {
+        self.index
+    }
+
+    /// names of the child elements of a segment of type `seg_type`, `None` for unsupported types
+    pub fn known_children(seg_type: &str) -> Option<&'static [&'static str]> {
+        segment_registry::children(seg_type)
+    }
+
+    /// the `<segment>` element of this segment at `position` of its chain
+    pub fn to_element(&self, position: usize) -> Element {
+        let mut el = self.cfg.to_element();
+        el.set_attr("type", self.seg_type.as_str());
+        el.set_attr("index", position.to_string());
+        el
+    }
+
+    /// the `<segments>` element of `segments` in order
+    pub fn to_segments_element(segments: &[SegmentCfg]) -> Element {
+        let mut el = element("segments");
+        for (i, seg) in segments.iter().enumerate() {
+            el.append_child(seg.to_element(i));
+        }
+        el
+    }
+
+    /// check if the pattern element can be generated, e.g. if all parts and patterns are valid
+    pub fn check(&self) -> Result<(), CfgError> {
+        self.cfg.generate().map(|_| ())
+    }
+
+    /// read all segments of the `<segments>` element `el`, ordered by their index. With
+    /// `ordered="true"` the segments are used in document order and their indices are ignored.
+    pub fn from_multiple(el: &Element, defaults: &FolderDefaults) -> Result<Vec<SegmentCfg>, CfgError> {
+        let ordered = Self::is_ordered(el)?;
         let mut segments: Vec<SegmentCfg> = Vec::new();
 
-        for child in el.children() {
-            let mut i = 0;
-            if child.name() == "segment" {
-                if let Some(seg) = match Self::from(child) {
-                    Ok(s) => Ok(Some(s)),
-                    Err(e) => match e {
-                        CfgError::XmlParseFailure(_) | CfgError::IllegalValue(_) | CfgError::IoError(_) => Err(e),
-                        CfgError::UnsupportedSegment(x) => {
-                            println!("[WARN] ignoring segment at index={}", i);
-                            Ok(None)
-                        }
-                    }
-                }? {
-                    // if negative, insert at beginning
-                    if seg.index < 0 {
-                        segments.insert(0, seg);
-                    }
-                    // if between 0 and last item, insert at index
-                    else if seg.index < segments.len() as i32 {
-                        segments.insert(seg.index as usize, seg);
-                    }
-                    // append at end (most cases if XML was ordered)
-                    else {
-                        segments.push(seg);
+        for (i, child) in el.children().filter(|c| c.name() == "segment").enumerate() {
+            match Self::parse(child, defaults, !ordered) {
+                Ok(mut seg) => {
+                    if ordered {
+                        seg.index = i as i32;
                     }
-                }
-                i += 1;
+                    segments.push(seg);
+                },
+                Err(CfgError::UnsupportedSegment(_)) => console::warn(format!("ignoring segment at position={}", i).as_str()),
+                Err(e) => return Err(e)
             }
         }
 
+        if !ordered {
+            if let Some(index) = Self::duplicate_index(segments.iter().map(|s| s.index)) {
+                return Err(CfgError::val_err(format!(
+                    "duplicate segment index {}, use unique indices or <segments ordered=\"true\"> for the document order", index
+                ).as_str()));
+            }
+            segments.sort_by_key(|s| s.index);
+        }
         Ok(segments)
     }
+
+    /// true if the segments of the `<segments>` element `el` are used in document order
+    pub(crate) fn is_ordered(el: &Element) -> Result<bool, CfgError> {
+        match el.attr("ordered") {
+            Some(s) => bool::from_str(s.trim()).map_err(|_| CfgError::val_err(
+                format!("Illegal value for segments ordered: \"{}\"", s).as_str()
+            )),
+            None => Ok(false)
+        }
+    }
+
+    /// the first index occurring more than once in `indices`
+    fn duplicate_index(mut indices: impl Iterator<Item = i32>) -> Option<i32> {
+        let mut seen = HashSet::new();
+        indices.find(|i| !seen.insert(*i))
+    }
 }
 
 impl SorterCfg {
 
     pub fn from(el: &Element) -> Result<SorterCfg, CfgError> {
-        let mut fallback: Vec<SegmentCfg> = Vec::new();
-        let mut supported: Vec<SegmentCfg> = Vec::new();
-        let mut dup_handling = SorterBuilder::default_duplicate_handling();
+        let mut cfg = SorterCfg::empty();
+        // defaults apply to all segments, so they are parsed first
+        let defaults = match el.get_child("defaults", "") {
+            Some(d) => parse_folder_defaults(d)?,
+            None => FolderDefaults::new()
+        };
 
         for child in el.children() {
-            match child.name() {
-                "supported" => {
-                    if let Some(segs) = child.get_child("segments", "") {
-                        supported = SegmentCfg::from_multiple(segs)?;
+            cfg.parse_child(child, &defaults)?;
+        }
+        Ok(cfg)
+    }
+
+    /// a config without any segments or settings
+    pub(crate) fn empty() -> SorterCfg {
+        SorterCfg{
+            supported: Vec::new(),
+            fallback: Vec::new(),
+            dup_handling: SorterBuilder::default_duplicate_handling(),
+            quotas: Vec::new(),
+            targets: Vec::new(),
+            age_route: None,
+            rollover_rules: Vec::new(),
+            naming: NamingScheme::Original,
+            date_check: None,
+            pairs: None,
+            retry: None,
+            folder_split: None,
+            fallback_root: None,
+            hash_algo: None,
+            hash_buffer_size: None,
+            mtime_tolerance: None,
+            source_rules: Vec::new()
+        }
+    }
+
+    /// apply the child element `child` of `<sorter>`, returning false if it is not a known
+    /// element and has been ignored. The `<defaults>` are parsed beforehand and passed as
+    /// `defaults`.
+    pub(crate) fn parse_child(&mut self, child: &Element, defaults: &FolderDefaults) -> Result<bool, CfgError> {
+        match child.name() {
+            "defaults" => (),
+            "supported" => {
+                if let Some(segs) = child.get_child("segments", "") {
+                    self.supported = SegmentCfg::from_multiple(segs, defaults)?;
+                }
+            },
+            "fallback" => {
+                self.fallback_root = Self::parse_fallback_root(child)?;
+                if let Some(segs) = child.get_child("segments", "") {
+                    self.fallback = SegmentCfg::from_multiple(segs, defaults)?;
+                }
+            },
+            "duplicateResolution" => {
+                self.dup_handling = Self::parse_duplicate_resolution(child)?;
+            },
+            "datePlausibility" => {
+                self.date_check = Some(Self::parse_date_plausibility(child, defaults)?);
+            },
+            "heicJpegPairs" => {
+                self.pairs = Some(Self::parse_pairs(child)?);
+            },
+            "retry" => {
+                self.retry = Some(Self::parse_retry(child)?);
+            },
+            "folderSplit" => {
+                self.folder_split = Some(Self::parse_folder_split(child)?);
+            },
+            "hashAlgorithm" => {
+                let name = child.text();
+                self.hash_algo = match HashAlgorithm::from_name(name.trim()) {
+                    Some(algo) => Some(algo),
+                    None => return Err(CfgError::val_err(
+                        format!("Illegal value for hashAlgorithm: \"{}\"", name).as_str()
+                    ))
+                };
+            },
+            "comparison" => {
+                let (buffer_size, tolerance) = Self::parse_comparison(child)?;
+                self.hash_buffer_size = buffer_size;
+                self.mtime_tolerance = tolerance;
+            },
+            "coldStorage" => {
+                self.age_route = Some(Self::parse_cold_storage(child)?);
+            },
+            "sourceRules" => {
+                for r in child.children() {
+                    if r.name() == "rule" {
+                        self.source_rules.push(Self::parse_source_rule(r, defaults)?);
                     }
-                },
-                "fallback" => {
-                    if let Some(segs) = child.get_child("segments", "") {
-                        fallback = SegmentCfg::from_multiple(segs)?;
+                }
+            },
+            "quotas" => {
+                for q in child.children() {
+                    if q.name() == "quota" {
+                        self.quotas.push(Self::parse_quota(q)?);
                     }
-                },
-                "duplicateResolution" => {
-                    dup_handling = Self::parse_duplicate_resolution(child)?;
-                },
-                _ => continue
+                }
+            },
+            "targets" => {
+                for t in child.children() {
+                    if t.name() == "target" {
+                        self.targets.push(Self::parse_target(t)?);
+                    }
+                }
+            },
+            "rename" => {
+                self.naming = match child.attr("scheme").unwrap_or("original") {
+                    "original" => NamingScheme::Original,
+                    "contentHash" => NamingScheme::ContentAddressed,
+                    "sequence" => NamingScheme::Sequence(Self::parse_sequence(child)?),
+                    s => return Err(CfgError::val_err(
+                        format!("Illegal value for rename scheme: \"{}\"", s).as_str()
+                    ))
+                };
+                for r in child.children() {
+                    if r.name() == "rolloverSuffix" {
+                        self.rollover_rules.push(Self::parse_rollover_suffix(r)?);
+                    }
+                }
+            },
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    pub fn parse_fallback_root(el: &Element) -> Result<Option<PathBuf>, CfgError> {
+        match el.attr("root") {
+            Some(r) if r.is_empty() || Path::new(r).is_absolute() => Err(CfgError::val_err(
+                format!("Illegal value for fallback root, must be a relative path: \"{}\"", r).as_str()
+            )),
+            Some(r) => Ok(Some(PathBuf::from(r))),
+            None => Ok(None)
+        }
+    }
+
+    fn parse_source_rule(el: &Element, defaults: &FolderDefaults) -> Result<SourceRuleCfg, CfgError> {
+        let (pattern, pattern_attr) = match (el.attr("glob"), el.attr("regex")) {
+            (Some(g), None) => (SourcePattern::glob(g), ("glob", g)),
+            (None, Some(r)) => (SourcePattern::regex(r), ("regex", r)),
+            _ => return Err(CfgError::val_err("a source rule requires exactly one of the attributes \"glob\" and \"regex\""))
+        };
+        let pattern = pattern.map_err(|e| CfgError::val_err(format!("Illegal pattern for source rule: {}", e).as_str()))?;
+        let root = match el.attr("root") {
+            Some("") => return Err(CfgError::val_err("Illegal value for source rule root: \"\"")),
+            r => r.map(PathBuf::from)
+        };
+        let mut rule = SourceRuleCfg {
+            pattern,
+            pattern_attr: (pattern_attr.0, String::from(pattern_attr.1)),
+            root,
+            supported: None,
+            fallback: None
+        };
+        for child in el.children() {
+            let segments = match child.get_child("segments", "") {
+                Some(segs) => SegmentCfg::from_multiple(segs, defaults)?,
+                None => Vec::new()
+            };
+            match child.name() {
+                "supported" => rule.supported = Some(segments),
+                "fallback" => rule.fallback = Some((Self::parse_fallback_root(child)?, segments)),
+                _ => ()
+            }
+        }
+        if rule.root.is_none() && rule.supported.is_none() && rule.fallback.is_none() {
+            return Err(CfgError::val_err("a source rule requires a root and/or segments"));
+        }
+        Ok(rule)
+    }
+
+    pub fn parse_cold_storage(el: &Element) -> Result<AgeRoute, CfgError> {
+        let days = match el.attr("maxAgeDays") {
+            Some(s) => match i64::from_str(s) {
+                Ok(d) if (0..=MAX_AGE_DAYS).contains(&d) => d,
+                _ => return Err(CfgError::val_err(
+                    format!("Illegal value for coldStorage maxAgeDays: \"{}\" (0 to {})", s, MAX_AGE_DAYS).as_str()
+                ))
+            },
+            None => return Err(CfgError::val_err("missing mandatory attribute \"maxAgeDays\" on coldStorage"))
+        };
+        let root = el.text();
+        if root.is_empty() {
+            return Err(CfgError::val_err("coldStorage requires the alternative target root as text"));
+        }
+        Ok(AgeRoute::new(Duration::days(days), PathBuf::from(root).as_path()))
+    }
+
+    pub fn parse_date_plausibility(el: &Element, defaults: &FolderDefaults) -> Result<DatePlausibility, CfgError> {
+        let min_year = match el.attr("minYear") {
+            Some(s) => match i32::from_str(s) {
+                Ok(y) => y,
+                Err(_) => return Err(CfgError::val_err(
+                    format!("Illegal value for datePlausibility minYear: \"{}\"", s).as_str()
+                ))
+            },
+            None => DatePlausibility::default_min_year()
+        };
+        let handling = match el.attr("handling").unwrap_or("accept") {
+            "accept" => ImplausibleDate::Accept,
+            "mtime" => ImplausibleDate::FallbackMtime,
+            "quarantine" => ImplausibleDate::Quarantine(PathBuf::from(el.attr("quarantine").unwrap_or(defaults.quarantine.as_str()))),
+            h => return Err(CfgError::val_err(
+                format!("Illegal value for datePlausibility handling: \"{}\"", h).as_str()
+            ))
+        };
+        Ok(DatePlausibility::new(min_year, handling))
+    }
+
+    pub fn parse_pairs(el: &Element) -> Result<PairPolicy, CfgError> {
+        let preferred = match el.attr("prefer") {
+            Some(s) => match PairFormat::parse(s) {
+                Some(f) => f,
+                None => return Err(CfgError::val_err(
+                    format!("Illegal value for heicJpegPairs prefer: \"{}\"", s).as_str()
+                ))
+            },
+            None => PairFormat::Heic
+        };
+        let handling = match el.attr("secondary").unwrap_or("skip") {
+            "skip" => PairHandling::Skip,
+            "route" => match el.attr("branch") {
+                Some(b) if !b.is_empty() && !Path::new(b).is_absolute() => PairHandling::Route(PathBuf::from(b)),
+                Some(b) => return Err(CfgError::val_err(
+                    format!("Illegal value for heicJpegPairs branch, must be a relative path: \"{}\"", b).as_str()
+                )),
+                None => PairHandling::Route(PathBuf::from("derived"))
+            },
+            s => return Err(CfgError::val_err(
+                format!("Illegal value for heicJpegPairs secondary: \"{}\"", s).as_str()
+            ))
+        };
+        Ok(PairPolicy::new(preferred, handling))
+    }
+
+    pub fn parse_retry(el: &Element) -> Result<RetryPolicy, CfgError> {
+        let attempts = match el.attr("attempts") {
+            Some(s) => match u32::from_str(s) {
+                Ok(a) if (1..=MAX_ATTEMPTS).contains(&a) => a,
+                _ => return Err(CfgError::val_err(
+                    format!("Illegal value for retry attempts: \"{}\" (1 to {})", s, MAX_ATTEMPTS).as_str()
+                ))
+            },
+            None => return Err(CfgError::val_err("missing mandatory attribute \"attempts\" on retry"))
+        };
+        let backoff = match el.attr("backoffMs") {
+            Some(s) => match u64::from_str(s) {
+                Ok(ms) if ms <= MAX_BACKOFF.as_millis() as u64 => ms,
+                _ => return Err(CfgError::val_err(
+                    format!("Illegal value for retry backoffMs: \"{}\" (0 to {})", s, MAX_BACKOFF.as_millis()).as_str()
+                ))
+            },
+            None => 500
+        };
+        Ok(RetryPolicy::new(attempts, std::time::Duration::from_millis(backoff)))
+    }
+
+    pub fn parse_folder_split(el: &Element) -> Result<FolderSplit, CfgError> {
+        let max_files = match el.attr("maxFiles") {
+            Some(s) => match u64::from_str(s) {
+                Ok(n) if n > 0 => n,
+                _ => return Err(CfgError::val_err(
+                    format!("Illegal value for folderSplit maxFiles: \"{}\"", s).as_str()
+                ))
+            },
+            None => return Err(CfgError::val_err("missing mandatory attribute \"maxFiles\" on folderSplit"))
+        };
+        let prefix = match el.attr("prefix") {
+            Some(p) if p.is_empty() || p.contains('/') || p.contains('\\') => return Err(CfgError::val_err(
+                format!("Illegal value for folderSplit prefix: \"{}\"", p).as_str()
+            )),
+            Some(p) => p,
+            None => FolderSplit::default_prefix()
+        };
+        let digits = match el.attr("digits") {
+            Some(s) => match usize::from_str(s) {
+                Ok(d) if d > 0 && d <= 20 => d,
+                _ => return Err(CfgError::val_err(
+                    format!("Illegal value for folderSplit digits: \"{}\"", s).as_str()
+                ))
+            },
+            None => FolderSplit::default_digits()
+        };
+        Ok(FolderSplit::new(max_files, prefix, digits))
+    }
+
+    /// parse the buffer size and modification time tolerance of a comparison element, if set
+    pub fn parse_comparison(el: &Element) -> Result<(Option<usize>, Option<MtimeTolerance>), CfgError> {
+        let buffer_size = match el.attr("bufferSize") {
+            None => None,
+            Some(s) => match parse_byte_size(s) {
+                Some(b) if b > 0 && b <= usize::MAX as u64 => Some(b as usize),
+                _ => return Err(CfgError::val_err(
+                    format!("Illegal value for comparison bufferSize: \"{}\"", s).as_str()
+                ))
+            }
+        };
+        let window = match el.attr("mtimeToleranceSecs") {
+            None => None,
+            Some(s) => match u64::from_str(s) {
+                Ok(secs) => Some(std::time::Duration::from_secs(secs)),
+                Err(_) => return Err(CfgError::val_err(
+                    format!("Illegal value for comparison mtimeToleranceSecs: \"{}\"", s).as_str()
+                ))
+            }
+        };
+        let dst_shift = match el.attr("dstShift") {
+            None => None,
+            Some(s) => match bool::from_str(s) {
+                Ok(b) => Some(b),
+                Err(_) => return Err(CfgError::val_err(
+                    format!("Illegal value for comparison dstShift: \"{}\"", s).as_str()
+                ))
+            }
+        };
+        let tolerance = match (window, dst_shift) {
+            (None, None) => None,
+            (w, d) => {
+                let default = MtimeTolerance::default();
+                Some(MtimeTolerance::new(w.unwrap_or(default.window), d.unwrap_or(default.dst_shift)))
+            }
+        };
+        Ok((buffer_size, tolerance))
+    }
+
+    pub fn parse_target(el: &Element) -> Result<TargetRoute, CfgError> {
+        let mut route = match el.attr("root") {
+            Some(r) if !r.is_empty() => TargetRoute::new(Path::new(r)),
+            _ => return Err(CfgError::val_err("missing mandatory attribute \"root\" on target"))
+        };
+        if let Some(types) = el.attr("types") {
+            let mut parsed = Vec::new();
+            for t in types.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                match GeneralFileType::parse(t) {
+                    Some(ft) => parsed.push(ft),
+                    None => return Err(CfgError::val_err(
+                        format!("Illegal value for target types: \"{}\"", t).as_str()
+                    ))
+                }
+            }
+            route = route.types(parsed);
+        }
+        if let Some(make) = el.attr("make") {
+            route = route.make(make);
+        }
+        if let Some(s) = el.attr("minBytes") {
+            match parse_byte_size(s) {
+                Some(b) => route = route.min_bytes(b),
+                None => return Err(CfgError::val_err(
+                    format!("Illegal value for target minBytes: \"{}\"", s).as_str()
+                ))
+            }
+        }
+        Ok(route)
+    }
+
+    pub fn parse_quota(el: &Element) -> Result<BranchQuota, CfgError> {
+        let branch = match el.attr("branch") {
+            Some(b) if !b.is_empty() => PathBuf::from(b),
+            _ => return Err(CfgError::val_err("missing mandatory attribute \"branch\" on quota"))
+        };
+
+        let max_bytes = match el.attr("maxBytes") {
+            None => None,
+            Some(s) => match parse_byte_size(s) {
+                Some(b) => Some(b),
+                None => return Err(CfgError::val_err(
+                    format!("Illegal value for quota maxBytes: \"{}\"", s).as_str()
+                ))
+            }
+        };
+        let max_count = match el.attr("maxCount") {
+            None => None,
+            Some(s) => match u64::from_str(s) {
+                Ok(c) => Some(c),
+                Err(_) => return Err(CfgError::val_err(
+                    format!("Illegal value for quota maxCount: \"{}\"", s).as_str()
+                ))
             }
+        };
+        if max_bytes.is_none() && max_count.is_none() {
+            return Err(CfgError::val_err("quota requires at least one of \"maxBytes\" or \"maxCount\""));
         }
 
-        Ok(SorterCfg{
-            supported,
-            fallback,
-            dup_handling
-        })
+        let overflow = match el.attr("overflow").unwrap_or("skip") {
+            "skip" => QuotaOverflow::Skip,
+            "spillover" => match el.attr("spillover") {
+                Some(dir) if !dir.is_empty() => QuotaOverflow::Spillover(PathBuf::from(dir)),
+                _ => return Err(CfgError::val_err("overflow=\"spillover\" requires attribute \"spillover\" on quota"))
+            },
+            o => return Err(CfgError::val_err(
+                format!("Illegal value for quota overflow: \"{}\"", o).as_str()
+            ))
+        };
+
+        Ok(BranchQuota::new(branch.as_path(), max_bytes, max_count, overflow))
+    }
+
+    pub fn parse_sequence(el: &Element) -> Result<SequencePattern, CfgError> {
+        let digits = match el.attr("digits") {
+            Some(s) => match usize::from_str(s) {
+                Ok(d) if d > 0 && d <= 20 => d,
+                _ => return Err(CfgError::val_err(
+                    format!("Illegal value for rename digits: \"{}\"", s).as_str()
+                ))
+            },
+            None => DEFAULT_SEQUENCE_DIGITS
+        };
+        let scope = match el.attr("scope").unwrap_or("run") {
+            "run" => SequenceScope::Run,
+            "folder" => SequenceScope::Folder,
+            s => return Err(CfgError::val_err(
+                format!("Illegal value for rename scope: \"{}\"", s).as_str()
+            ))
+        };
+        Ok(SequencePattern::new(el.attr("prefix").unwrap_or(""), digits, scope))
+    }
+
+    pub fn parse_rollover_suffix(el: &Element) -> Result<RolloverRule, CfgError> {
+        let make = el.attr("make");
+        let model = el.attr("model");
+        if make.is_none() && model.is_none() {
+            return Err(CfgError::val_err("rolloverSuffix requires at least one of \"make\" or \"model\""));
+        }
+        let kind = match el.attr("suffix").unwrap_or("hash") {
+            "hash" => SuffixKind::ContentHash,
+            "timestamp" => SuffixKind::Timestamp,
+            s => return Err(CfgError::val_err(
+                format!("Illegal value for rolloverSuffix suffix: \"{}\"", s).as_str()
+            ))
+        };
+        Ok(RolloverRule::new(make, model, kind))
     }
 
     pub fn parse_duplicate_resolution(el: &Element) -> Result<DuplicateResolution, CfgError> {
@@ -181,6 +668,30 @@ impl SorterCfg {
         }
     }
 
+    fn generate_segments(segments: &[SegmentCfg]) -> Result<Vec<Box<dyn PatternElement + Send>>, CfgError> {
+        segments.iter().map(|s| s.cfg.generate()).collect()
+    }
+
+    fn generate_source_rule(&self, rule: &SourceRuleCfg) -> Result<SourceRule, CfgError> {
+        let mut source_rule = SourceRule::new(rule.pattern.clone());
+        if let Some(root) = &rule.root {
+            source_rule = source_rule.root(root.as_path());
+        }
+        if rule.supported.is_none() && rule.fallback.is_none() {
+            return Ok(source_rule);
+        }
+        let supported = Self::generate_segments(rule.supported.as_ref().unwrap_or(&self.supported))?;
+        let (fallback_root, fallback) = match &rule.fallback {
+            Some((root, segs)) => (root.as_ref(), Self::generate_segments(segs)?),
+            None => (self.fallback_root.as_ref(), Self::generate_segments(&self.fallback)?)
+        };
+        let mut translator = Translator::new(supported, fallback);
+        if let Some(root) = fallback_root {
+            translator.set_fallback_root(root.clone());
+        }
+        Ok(source_rule.translator(translator))
+    }
+
     pub fn generate_builder(&self) -> Result<SorterBuilder, CfgError> {
         let mut builder = Sorter::builder()
             .duplicate_handling(self.dup_handling);
@@ -192,12 +703,285 @@ impl SorterCfg {
         for seg in &self.fallback {
             builder.push_segment_fallback(seg.cfg.generate()?);
         }
+
+        for quota in &self.quotas {
+            builder.push_quota(quota.clone());
+        }
+
+        for route in &self.targets {
+            builder.push_target_route(route.clone());
+        }
+
+        if let Some(route) = &self.age_route {
+            builder.set_age_route(route.clone());
+        }
+
+        for rule in &self.source_rules {
+            builder.push_source_rule(self.generate_source_rule(rule)?);
+        }
+
+        for rule in &self.rollover_rules {
+            builder.push_rollover_rule(rule.clone());
+        }
+        builder.set_naming_scheme(self.naming.clone());
+
+        if let Some(check) = &self.date_check {
+            builder.set_date_plausibility(check.clone());
+        }
+
+        if let Some(policy) = &self.pairs {
+            builder.set_pair_policy(policy.clone());
+        }
+
+        if let Some(policy) = &self.retry {
+            builder.set_retry_policy(*policy);
+        }
+
+        if let Some(split) = &self.folder_split {
+            builder.set_folder_split(split.clone());
+        }
+
+        if let Some(root) = &self.fallback_root {
+            builder.set_fallback_root(root.clone());
+        }
+
+        if let Some(algo) = self.hash_algo {
+            builder.set_hash_algorithm(algo);
+        }
+
+        if let Some(size) = self.hash_buffer_size {
+            builder.set_hash_buffer_size(size);
+        }
+
+        if let Some(tolerance) = self.mtime_tolerance {
+            builder.set_mtime_tolerance(tolerance);
+        }
         Ok(builder)
     }
 
+    /// the segments for supported files in the order they are applied
+    pub fn get_supported_segments(&self) -> &[SegmentCfg] {
+        self.supported.as_slice()
+    }
+
+    /// the segments for unsupported files in the order they are applied
+    pub fn get_fallback_segments(&self) -> &[SegmentCfg] {
+        self.fallback.as_slice()
+    }
+
     pub fn get_duplicate_handling(&self) -> DuplicateResolution {
         self.dup_handling.clone()
     }
+
+    /// the hash algorithm set by `<hashAlgorithm>` or `None` if the sorter does not set one
+    pub fn get_hash_algorithm(&self) -> Option<HashAlgorithm> {
+        self.hash_algo
+    }
+
+    /// replace the duplicate handling, e.g. by an override of the environment
+    pub fn set_duplicate_handling(&mut self, policy: DuplicateResolution) {
+        self.dup_handling = policy;
+    }
+
+    /// replace the hash algorithm, e.g. by the one given on the command line
+    pub fn set_hash_algorithm(&mut self, algo: HashAlgorithm) {
+        self.hash_algo = Some(algo);
+    }
+
+    pub fn set_mtime_tolerance(&mut self, tolerance: MtimeTolerance) {
+        self.mtime_tolerance = Some(tolerance);
+    }
+
+    pub fn set_fallback_root(&mut self, root: PathBuf) {
+        self.fallback_root = Some(root);
+    }
+
+    /// the `<sorter>` element of these settings with all defaults filled in. The `<defaults>`
+    /// are not written, as they are already applied to the segments.
+    pub fn to_element(&self) -> Element {
+        let mut el = element("sorter");
+
+        let mut dup = element("duplicateResolution");
+        match self.dup_handling {
+            DuplicateResolution::Ignore => dup.set_attr("strategy", "ignore"),
+            DuplicateResolution::Overwrite => dup.set_attr("strategy", "overwrite"),
+            DuplicateResolution::Compare(c) => {
+                dup.set_attr("strategy", "compare");
+                dup.append_text_node(match c {
+                    Comparison::Rename => "rename",
+                    Comparison::FavorTarget => "favor_target",
+                    Comparison::FavorSource => "favor_source"
+                });
+            }
+        }
+        el.append_child(dup);
+        let algo = self.hash_algo.unwrap_or_else(SorterBuilder::default_hash_algorithm);
+        append_text_child(&mut el, "hashAlgorithm", &Some(algo.name()));
+
+        if !self.quotas.is_empty() {
+            let mut quotas = element("quotas");
+            for q in &self.quotas {
+                let mut quota = element("quota");
+                quota.set_attr("branch", q.branch().to_str().unwrap_or(PATHSTR_FB));
+                if let Some(b) = q.max_bytes() {
+                    quota.set_attr("maxBytes", b.to_string());
+                }
+                if let Some(c) = q.max_count() {
+                    quota.set_attr("maxCount", c.to_string());
+                }
+                match q.overflow() {
+                    QuotaOverflow::Skip => quota.set_attr("overflow", "skip"),
+                    QuotaOverflow::Spillover(dir) => {
+                        quota.set_attr("overflow", "spillover");
+                        quota.set_attr("spillover", dir.to_str().unwrap_or(PATHSTR_FB));
+                    }
+                }
+                quotas.append_child(quota);
+            }
+            el.append_child(quotas);
+        }
+
+        if !self.targets.is_empty() {
+            let mut targets = element("targets");
+            for t in &self.targets {
+                let mut target = element("target");
+                target.set_attr("root", t.get_root().to_str().unwrap_or(PATHSTR_FB));
+                if !t.get_types().is_empty() {
+                    target.set_attr("types", t.get_types().iter().map(|ft| ft.name()).collect::<Vec<&str>>().join(","));
+                }
+                if let Some(make) = t.get_make() {
+                    target.set_attr("make", make);
+                }
+                if let Some(b) = t.get_min_bytes() {
+                    target.set_attr("minBytes", b.to_string());
+                }
+                targets.append_child(target);
+            }
+            el.append_child(targets);
+        }
+
+        if let Some(check) = &self.date_check {
+            let mut child = element("datePlausibility");
+            child.set_attr("minYear", check.min_year().to_string());
+            match check.handling() {
+                ImplausibleDate::Accept => child.set_attr("handling", "accept"),
+                ImplausibleDate::FallbackMtime => child.set_attr("handling", "mtime"),
+                ImplausibleDate::Quarantine(dir) => {
+                    child.set_attr("handling", "quarantine");
+                    child.set_attr("quarantine", dir.to_str().unwrap_or(PATHSTR_FB));
+                }
+            }
+            el.append_child(child);
+        }
+
+        if let Some(pairs) = &self.pairs {
+            let mut child = element("heicJpegPairs");
+            child.set_attr("prefer", pairs.preferred().name());
+            match pairs.handling() {
+                PairHandling::Skip => child.set_attr("secondary", "skip"),
+                PairHandling::Route(branch) => {
+                    child.set_attr("secondary", "route");
+                    child.set_attr("branch", branch.to_str().unwrap_or(PATHSTR_FB));
+                }
+            }
+            el.append_child(child);
+        }
+
+        if let Some(retry) = &self.retry {
+            let mut child = element("retry");
+            child.set_attr("attempts", retry.max_attempts().to_string());
+            child.set_attr("backoffMs", retry.initial_backoff().as_millis().to_string());
+            el.append_child(child);
+        }
+
+        if let Some(split) = &self.folder_split {
+            let mut child = element("folderSplit");
+            child.set_attr("maxFiles", split.max_files().to_string());
+            child.set_attr("prefix", split.prefix());
+            child.set_attr("digits", split.digits().to_string());
+            el.append_child(child);
+        }
+
+        let tolerance = self.mtime_tolerance.unwrap_or_default();
+        let mut comparison = element("comparison");
+        comparison.set_attr("bufferSize", self.hash_buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE).to_string());
+        comparison.set_attr("mtimeToleranceSecs", tolerance.window.as_secs().to_string());
+        comparison.set_attr("dstShift", tolerance.dst_shift.to_string());
+        el.append_child(comparison);
+
+        if let Some(route) = &self.age_route {
+            let mut child = element("coldStorage");
+            child.set_attr("maxAgeDays", route.max_age().num_days().to_string());
+            child.append_text_node(route.root().to_str().unwrap_or(PATHSTR_FB));
+            el.append_child(child);
+        }
+
+        let mut rename = element("rename");
+        match &self.naming {
+            NamingScheme::Original => rename.set_attr("scheme", "original"),
+            NamingScheme::ContentAddressed => rename.set_attr("scheme", "contentHash"),
+            NamingScheme::Sequence(seq) => {
+                rename.set_attr("scheme", "sequence");
+                rename.set_attr("prefix", seq.prefix());
+                rename.set_attr("digits", seq.digits().to_string());
+                rename.set_attr("scope", match seq.scope() {
+                    SequenceScope::Run => "run",
+                    SequenceScope::Folder => "folder"
+                });
+            }
+        }
+        for rule in &self.rollover_rules {
+            let mut child = element("rolloverSuffix");
+            if let Some(make) = rule.make() {
+                child.set_attr("make", make);
+            }
+            if let Some(model) = rule.model() {
+                child.set_attr("model", model);
+            }
+            child.set_attr("suffix", match rule.kind() {
+                SuffixKind::ContentHash => "hash",
+                SuffixKind::Timestamp => "timestamp"
+            });
+            rename.append_child(child);
+        }
+        el.append_child(rename);
+
+        if !self.source_rules.is_empty() {
+            let mut rules = element("sourceRules");
+            for r in &self.source_rules {
+                let mut rule = element("rule");
+                rule.set_attr(r.pattern_attr.0, r.pattern_attr.1.as_str());
+                if let Some(root) = &r.root {
+                    rule.set_attr("root", root.to_str().unwrap_or(PATHSTR_FB));
+                }
+                if let Some(segs) = &r.supported {
+                    let mut supported = element("supported");
+                    supported.append_child(SegmentCfg::to_segments_element(segs));
+                    rule.append_child(supported);
+                }
+                if let Some((root, segs)) = &r.fallback {
+                    rule.append_child(Self::fallback_element(root.as_deref(), segs));
+                }
+                rules.append_child(rule);
+            }
+            el.append_child(rules);
+        }
+
+        let mut supported = element("supported");
+        supported.append_child(SegmentCfg::to_segments_element(&self.supported));
+        el.append_child(supported);
+        el.append_child(Self::fallback_element(self.fallback_root.as_deref(), &self.fallback));
+        el
+    }
+
+    fn fallback_element(root: Option<&Path>, segments: &[SegmentCfg]) -> Element {
+        let mut el = element("fallback");
+        if let Some(root) = root {
+            el.set_attr("root", root.to_str().unwrap_or(PATHSTR_FB));
+        }
+        el.append_child(SegmentCfg::to_segments_element(segments));
+        el
+    }
     /*
     pub fn generate(&self, target_dir: PathBuf, mpsc::) -> Result<Sorter, CfgError> {
         let mut builder = self.generate_builder(target_dir);
@@ -205,4 +989,54 @@ impl SorterCfg {
         Ok(builder.build())
     }
      */
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod segment_cfg {
+        use minidom::Element;
+
+        use crate::config::sorter_config::SegmentCfg;
+        use crate::pattern::FolderDefaults;
+
+        fn order(xml: &str) -> Result<Vec<(String, i32)>, String> {
+            let el: Element = xml.parse().unwrap();
+            SegmentCfg::from_multiple(&el, &FolderDefaults::new())
+                .map(|segs| segs.iter().map(|s| (String::from(s.seg_type()), s.index())).collect())
+                .map_err(|e| e.to_string())
+        }
+
+        #[test]
+        fn orders_by_index_or_document() {
+            let by_index = order("<segments xmlns=\"\"><segment type=\"DateTimePattern\" index=\"3\"/><segment type=\"ScreenshotPattern\" index=\"1\"/><segment type=\"MakeModelPattern\" index=\"2\"/></segments>");
+            assert_eq!(by_index.unwrap(), vec![(String::from("ScreenshotPattern"), 1), (String::from("MakeModelPattern"), 2), (String::from("DateTimePattern"), 3)]);
+
+            let duplicate = order("<segments xmlns=\"\"><segment type=\"DateTimePattern\" index=\"0\"/><segment type=\"ScreenshotPattern\" index=\"0\"/></segments>");
+            assert!(duplicate.unwrap_err().contains("duplicate segment index 0"));
+
+            let ordered = order("<segments xmlns=\"\" ordered=\"true\"><segment type=\"DateTimePattern\" index=\"0\"/><segment type=\"ScreenshotPattern\"/></segments>");
+            assert_eq!(ordered.unwrap(), vec![(String::from("DateTimePattern"), 0), (String::from("ScreenshotPattern"), 1)]);
+            assert!(order("<segments xmlns=\"\" ordered=\"yes\"/>").is_err());
+        }
+    }
+
+    mod sorter_cfg {
+        use minidom::Element;
+
+        use crate::config::sorter_config::SorterCfg;
+
+        fn element(xml: &str) -> Element {
+            xml.parse().unwrap()
+        }
+
+        #[test]
+        fn rejects_out_of_range_values() {
+            assert!(SorterCfg::parse_cold_storage(&element("<coldStorage xmlns=\"\" maxAgeDays=\"730\">/archive</coldStorage>")).is_ok());
+            assert!(SorterCfg::parse_cold_storage(&element("<coldStorage xmlns=\"\" maxAgeDays=\"9223372036854775807\">/archive</coldStorage>")).is_err());
+            assert!(SorterCfg::parse_retry(&element("<retry xmlns=\"\" attempts=\"3\" backoffMs=\"500\"/>")).is_ok());
+            assert!(SorterCfg::parse_retry(&element("<retry xmlns=\"\" attempts=\"4000000000\"/>")).is_err());
+            assert!(SorterCfg::parse_retry(&element("<retry xmlns=\"\" attempts=\"3\" backoffMs=\"18446744073709551615\"/>")).is_err());
+        }
+    }
+}
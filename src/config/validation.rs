@@ -0,0 +1,517 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use minidom::Element;
+
+use crate::config::{CfgError, ConfigFormat, migration, RootCfg, segment_registry};
+use crate::config::doc_config::ConfigDoc;
+use crate::config::include::{has_includes, resolve_includes};
+use crate::config::logger_config::LoggerCfg;
+use crate::config::processor_config::ProcessorCfg;
+use crate::config::scanner_config::ScannerCfg;
+use crate::config::seg_config::parse_folder_defaults;
+use crate::config::sorter_config::{SegmentCfg, SorterCfg};
+use crate::pattern::FolderDefaults;
+
+const ROOT_CHILDREN: &[&str] = &["sorter", "scanner", "processors", "logging"];
+const SORTER_CHILDREN: &[&str] = &["duplicateResolution", "hashAlgorithm", "defaults", "quotas", "targets", "datePlausibility", "heicJpegPairs",
+    "retry", "folderSplit", "comparison", "coldStorage", "rename", "sourceRules", "supported", "fallback"];
+const SCANNER_CHILDREN: &[&str] = &["sizeFilter", "maxRecursion", "ignoreUnknownTypes", "followSymlinks", "exclude", "include"];
+const PROCESSORS_CHILDREN: &[&str] = &["processor"];
+const LOGGING_CHILDREN: &[&str] = &["dir", "filename", "level", "format", "logSkipped", "errorFilename", "backend"];
+
+/// Severity of a [Diagnostic].
+///
+/// # Variants
+/// - [Severity::Error] the config cannot be loaded
+/// - [Severity::Warning] the config can be loaded, but parts of it are ignored
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+/// A problem found by [validate], located by the path of the element (e.g. `sorter/retry`, or
+/// `sorter.retry` for TOML, JSON and YAML) and the line if it is known.
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub element: String,
+    pub line: Option<usize>,
+    pub message: String,
+    pub suggestion: Option<String>
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning"
+        };
+        write!(f, "{}: {}", severity, self.element)?;
+        if let Some(line) = self.line {
+            write!(f, " (line {})", line)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(s) = &self.suggestion {
+            write!(f, "\n  help: {}", s)?;
+        }
+        Ok(())
+    }
+}
+
+/// read and [validate] the config file at `path` in the format detected by
+/// [ConfigFormat::from_path]
+pub fn validate_file(path: &Path) -> Result<Vec<Diagnostic>, CfgError> {
+    let mut data = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut data))
+        .map_err(CfgError::IoError)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    Ok(validate_in(data.as_str(), ConfigFormat::from_path(path), dir))
+}
+
+/// check a config in `format` for all problems instead of stopping at the first one like
+/// [RootCfg::parse]. Unknown elements, which are ignored when loading the config, are reported
+/// as warnings with the most similar known name. An empty result means the config is valid.
+pub fn validate(data: &str, format: ConfigFormat) -> Vec<Diagnostic> {
+    validate_in(data, format, Path::new("."))
+}
+
+/// [validate] a config with the includes of an XML config resolved relative to `dir`
+pub fn validate_in(data: &str, format: ConfigFormat, dir: &Path) -> Vec<Diagnostic> {
+    let mut includes = false;
+    let root = match format {
+        ConfigFormat::Xml => data.parse::<Element>()
+            .map_err(CfgError::XmlParseFailure)
+            .and_then(|r| {
+                includes = has_includes(&r);
+                resolve_includes(&r, dir)
+            }),
+        _ => ConfigDoc::parse(data, format).map(|d| d.to_element())
+    };
+    let root = match root {
+        Ok(r) => r,
+        Err(e) => return vec![Diagnostic {
+            severity: Severity::Error,
+            element: String::from("config"),
+            line: error_line(&e),
+            message: e.to_string(),
+            suggestion: None
+        }]
+    };
+
+    // configs of an older layout are checked as they are, but loaded like they are migrated
+    let migrated = match migration::migrate(&root) {
+        Ok(m) => m,
+        Err(e) => return vec![Diagnostic {
+            severity: Severity::Error,
+            element: String::from("config"),
+            line: None,
+            message: e.to_string(),
+            suggestion: None
+        }]
+    };
+
+    let mut validator = Validator {
+        format,
+        legacy: migrated.version < migration::CONFIG_VERSION,
+        // lines of included elements are not known, so they are left out if there are any
+        lines: match format {
+            ConfigFormat::Xml if !includes => element_lines(&root, data),
+            _ => HashMap::new()
+        },
+        diagnostics: Vec::new()
+    };
+    validator.check_root(&root);
+    for change in &migrated.changes {
+        validator.push(&root, Severity::Warning, String::from("config"),
+                       format!("config of version {} is migrated when loaded: {}", migrated.version, change),
+                       Some(String::from("run 'dcim-sort config migrate' to update it")));
+    }
+
+    // everything is checked above, this only catches problems spanning several elements
+    if !validator.has_errors() {
+        if let Err(e) = RootCfg::from(&migrated.root).and_then(|c| c.check_profiles()) {
+            validator.error(&root, String::from("config"), e);
+        }
+    }
+    validator.diagnostics
+}
+
+struct Validator {
+    format: ConfigFormat,
+    /// the config has an older layout, whose segment indices need not be unique
+    legacy: bool,
+    lines: HashMap<*const Element, usize>,
+    diagnostics: Vec<Diagnostic>
+}
+
+impl Validator {
+    fn check_root(&mut self, root: &Element) {
+        if root.name() != "config" {
+            self.push(root, Severity::Error, String::from(root.name()),
+                      format!("unexpected root element \"{}\"", root.name()),
+                      Some(String::from("the root element must be <config>")));
+            return;
+        }
+        if root.get_child("sorter", "").is_none() {
+            self.push(root, Severity::Error, String::from("config"),
+                      String::from("mandatory element \"sorter\" not found"),
+                      Some(String::from("add a sorter with at least one segment, see config/template_config.xml")));
+        }
+        let mut profiles: Vec<Option<String>> = Vec::new();
+        for child in root.children() {
+            match child.name() {
+                "sorter" => {
+                    let name = match RootCfg::parse_profile_name(child) {
+                        Ok(n) => n,
+                        Err(e) => {
+                            self.error(child, String::from("sorter"), e);
+                            continue;
+                        }
+                    };
+                    let path = self.sorter_path(name.as_deref());
+                    if profiles.contains(&name) {
+                        let message = match &name {
+                            Some(n) => format!("duplicate sorter profile \"{}\"", n),
+                            None => String::from("more than one sorter without a profile name")
+                        };
+                        self.push(child, Severity::Error, path.clone(), message,
+                                  Some(String::from("give every additional sorter a unique name attribute")));
+                    }
+                    self.check_sorter(child, path);
+                    profiles.push(name);
+                },
+                "scanner" => self.check_scanner(child),
+                "processors" => self.check_processors(child),
+                "logging" => self.check_logging(child),
+                _ => self.unknown(child, String::from("config"), ROOT_CHILDREN)
+            }
+        }
+    }
+
+    /// the path of the sorter with the profile name `name`
+    fn sorter_path(&self, name: Option<&str>) -> String {
+        match (name, self.format) {
+            (None, _) => String::from("sorter"),
+            (Some(n), ConfigFormat::Xml) => format!("sorter[{}]", n),
+            (Some(n), _) => format!("profiles.{}", n)
+        }
+    }
+
+    fn check_sorter(&mut self, el: &Element, path: String) {
+        let defaults = match el.get_child("defaults", "").map(|d| (d, parse_folder_defaults(d))) {
+            Some((_, Ok(defaults))) => defaults,
+            Some((d, Err(e))) => {
+                self.error(d, self.child_path(&path, "defaults"), e);
+                FolderDefaults::new()
+            },
+            None => FolderDefaults::new()
+        };
+
+        let mut cfg = SorterCfg::empty();
+        for child in el.children() {
+            let child_path = self.child_path(&path, child.name());
+            match child.name() {
+                // segments are checked one by one to report all of them
+                "supported" | "fallback" => {
+                    if child.name() == "fallback" {
+                        if let Err(e) = SorterCfg::parse_fallback_root(child) {
+                            self.error(child, child_path.clone(), e);
+                        }
+                    }
+                    if let Some(segs) = child.get_child("segments", "") {
+                        self.check_segments(segs, child_path, &defaults);
+                    }
+                },
+                _ => match cfg.parse_child(child, &defaults) {
+                    Ok(true) => (),
+                    Ok(false) => self.unknown(child, path.clone(), SORTER_CHILDREN),
+                    Err(e) => self.error(child, child_path, e)
+                }
+            }
+        }
+    }
+
+    fn check_segments(&mut self, el: &Element, path: String, defaults: &FolderDefaults) {
+        let ordered = match SegmentCfg::is_ordered(el) {
+            Ok(o) => o,
+            Err(e) => {
+                self.error(el, self.child_path(&path, "segments"), e);
+                false
+            }
+        };
+        let mut indices = Vec::new();
+        let segments: Vec<&Element> = el.children().filter(|c| c.name() == "segment").collect();
+        for (i, seg) in segments.into_iter().enumerate() {
+            let seg_path = match self.format {
+                ConfigFormat::Xml => format!("{}/segments/segment[{}]", path, i),
+                _ => format!("{}.segments[{}]", path, i)
+            };
+            let seg_type = seg.attr("type").unwrap_or("");
+            match SegmentCfg::known_children(seg_type) {
+                Some(known) => for child in seg.children() {
+                    if !known.contains(&child.name()) {
+                        self.unknown(child, seg_path.clone(), known);
+                    }
+                },
+                None => {
+                    let types = segment_registry::segment_types();
+                    let types: Vec<&str> = types.iter().map(String::as_str).collect();
+                    let suggestion = match closest(seg_type, types.as_slice()) {
+                        Some(s) => format!("did you mean \"{}\"?", s),
+                        None => format!("use one of {}", quoted(types.as_slice()))
+                    };
+                    self.push(seg, Severity::Error, seg_path, format!("unsupported segment type \"{}\"", seg_type), Some(suggestion));
+                    continue;
+                }
+            }
+            let parsed = SegmentCfg::parse(seg, defaults, !ordered);
+            if let Ok(s) = &parsed {
+                if !ordered && !self.legacy && indices.contains(&s.index()) {
+                    self.push(seg, Severity::Error, seg_path, format!("duplicate segment index {}", s.index()),
+                              Some(String::from("use unique indices or <segments ordered=\"true\"> to apply the segments in document order")));
+                    continue;
+                }
+                indices.push(s.index());
+            }
+            match parsed.and_then(|s| s.check()) {
+                Ok(_) => (),
+                Err(e) => self.error(seg, seg_path, e)
+            }
+        }
+    }
+
+    fn check_scanner(&mut self, el: &Element) {
+        let path = String::from("scanner");
+        let mut cfg = ScannerCfg::default();
+        for child in el.children() {
+            match cfg.parse_child(child) {
+                Ok(true) => (),
+                Ok(false) => self.unknown(child, path.clone(), SCANNER_CHILDREN),
+                Err(e) => self.error(child, self.child_path(&path, child.name()), e)
+            }
+        }
+    }
+
+    fn check_processors(&mut self, el: &Element) {
+        let path = String::from("processors");
+        let mut cfg = ProcessorCfg::default();
+        for (i, child) in el.children().enumerate() {
+            let child_path = match self.format {
+                ConfigFormat::Xml => format!("{}/{}[{}]", path, child.name(), i),
+                _ => format!("{}[{}]", path, i)
+            };
+            match cfg.parse_child(child) {
+                Ok(true) => (),
+                Ok(false) => self.unknown(child, path.clone(), PROCESSORS_CHILDREN),
+                Err(e) => self.error(child, child_path, e)
+            }
+        }
+    }
+
+    fn check_logging(&mut self, el: &Element) {
+        let path = String::from("logging");
+        let mut cfg = LoggerCfg::default();
+        for child in el.children() {
+            match cfg.parse_child(child) {
+                Ok(true) => (),
+                Ok(false) => self.unknown(child, path.clone(), LOGGING_CHILDREN),
+                Err(e) => self.error(child, self.child_path(&path, child.name()), e)
+            }
+        }
+    }
+
+    fn child_path(&self, parent: &str, name: &str) -> String {
+        match self.format {
+            ConfigFormat::Xml => format!("{}/{}", parent, name),
+            _ => format!("{}.{}", parent, name)
+        }
+    }
+
+    fn unknown(&mut self, el: &Element, parent: String, known: &[&str]) {
+        let suggestion = match closest(el.name(), known) {
+            Some(s) => format!("did you mean \"{}\"?", s),
+            None => format!("known elements are {}", quoted(known))
+        };
+        self.push(el, Severity::Warning, parent, format!("unknown element \"{}\" is ignored", el.name()), Some(suggestion));
+    }
+
+    fn error(&mut self, el: &Element, path: String, e: CfgError) {
+        self.push(el, Severity::Error, path, e.to_string(), hint(el.name()).map(String::from));
+    }
+
+    fn push(&mut self, el: &Element, severity: Severity, element: String, message: String, suggestion: Option<String>) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            element,
+            line: self.lines.get(&(el as *const Element)).copied(),
+            message,
+            suggestion
+        });
+    }
+
+    fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+}
+
+/// the valid values of an element, shown with errors on it
+fn hint(element: &str) -> Option<&'static str> {
+    match element {
+        "duplicateResolution" => Some("set strategy to \"ignore\", \"overwrite\" or \"compare\", the latter with \"rename\", \"favor_target\" or \"favor_source\" as comparison"),
+        "hashAlgorithm" => Some("one of \"md5\", \"sha256\", \"blake3\", \"xxh3\", \"bytes\", \"metadata\", \"size-mtime\", \"none\""),
+        "defaults" => Some("every default needs a non-empty folder name, known defaults are \"unknownMake\", \"unknownModel\", \"unknownDate\", \"screenshots\", \"video\", \"picture\", \"audio\", \"text\", \"document\", \"other\", \"quarantine\""),
+        "quota" | "quotas" => Some("a quota needs a branch and maxBytes (suffixes K, M, G, T allowed) and/or maxCount, overflow is \"skip\" or \"spillover\" with a spillover folder"),
+        "target" | "targets" => Some("a target needs a root (relative ones are placed below the output directory) and may be limited to types (\"video\", \"picture\", \"audio\", \"text\", \"document\", \"other\"), a make and minBytes"),
+        "datePlausibility" => Some("minYear is a year, handling is \"accept\", \"mtime\" or \"quarantine\""),
+        "heicJpegPairs" => Some("prefer is \"heic\" or \"jpeg\", secondary is \"skip\" or \"route\" with a relative branch"),
+        "retry" => Some("attempts is a number greater than 0, backoffMs a number of milliseconds"),
+        "folderSplit" => Some("maxFiles is a number greater than 0, prefix a folder name prefix and digits a number from 1 to 20"),
+        "comparison" => Some("bufferSize is a size greater than 0 (suffixes K, M, G allowed), mtimeToleranceSecs a number of seconds and dstShift \"true\" or \"false\""),
+        "coldStorage" => Some("maxAgeDays is a number of days, the alternative target root is required"),
+        "rename" | "rolloverSuffix" => Some("scheme is \"original\", \"contentHash\" or \"sequence\" (with digits from 1 to 20 and scope \"run\" or \"folder\"), rolloverSuffix needs make and/or model and suffix \"hash\" or \"timestamp\""),
+        "sourceRules" | "rule" => Some("every rule needs exactly one of the attributes glob and regex, an optional target root and optional supported and fallback segments"),
+        "fallback" => Some("the fallback root must be a relative path, e.g. \"unsorted\""),
+        "processor" => Some("name is \"rexiv2\" or \"kamadak-exif\", enabled \"true\" or \"false\" and priority \"highest\", \"lowest\", \"none\" or a position"),
+        "sizeFilter" => Some("minBytes and maxBytes are sizes with optional suffixes K, M, G, T, e.g. \"10K\""),
+        "dir" | "filename" => Some("the log file is written to dir (default: the output directory) with the given filename, \".log\" is appended if missing"),
+        "level" => Some("level is \"error\", \"warn\", \"info\" or \"debug\""),
+        "format" => Some("format is \"text\" (with timestamp and thread) or \"compact\""),
+        "maxRecursion" => Some("the maximum recursion level is a number from 0 to 255"),
+        "ignoreUnknownTypes" | "followSymlinks" | "logSkipped" => Some("the value is \"true\" or \"false\""),
+        "exclude" | "include" => Some("patterns may contain '*', '?', '**' and closed character classes like \"[0-9]\""),
+        "segment" => Some("check the parts (e.g. \"Make\", \"Model\" or \"Year\", \"Month\"), the index, single-character separators and the filename pattern"),
+        _ => None
+    }
+}
+
+/// the name in `known` closest to `name` if it is similar enough to be a typo
+fn closest<'a>(name: &str, known: &[&'a str]) -> Option<&'a str> {
+    let name = name.to_lowercase();
+    known.iter()
+        .map(|k| (*k, edit_distance(name.as_str(), k.to_lowercase().as_str())))
+        .filter(|(k, d)| *d <= 2.max(k.len() / 3))
+        .min_by_key(|(_, d)| *d)
+        .map(|(k, _)| k)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = match ca == *cb {
+                true => prev,
+                false => 1 + prev.min(row[j]).min(current)
+            };
+            prev = current;
+        }
+    }
+    row[b.len()]
+}
+
+fn quoted(names: &[&str]) -> String {
+    names.iter().map(|n| format!("\"{}\"", n)).collect::<Vec<String>>().join(", ")
+}
+
+/// the line of the error position of a failed parse, if known
+fn error_line(e: &CfgError) -> Option<usize> {
+    match e {
+        CfgError::TomlParseFailure(e) => e.line_col().map(|(l, _)| l + 1),
+        CfgError::JsonParseFailure(e) if e.line() > 0 => Some(e.line()),
+        CfgError::YamlParseFailure(e) => e.location().map(|l| l.line()),
+        _ => None
+    }
+}
+
+/// map all elements of the tree parsed from the XML `data` to the line of their start tag. The
+/// start tags appear in `data` in the same order as a pre-order traversal of the tree.
+fn element_lines(root: &Element, data: &str) -> HashMap<*const Element, usize> {
+    let mut tag_lines = Vec::new();
+    let mut line = 1;
+    let mut rest = data;
+    while let Some(pos) = rest.find(['<', '\n']) {
+        let (skipped, tail) = rest.split_at(pos);
+        debug_assert!(!skipped.contains('\n'));
+        if let Some(next) = tail.strip_prefix('\n') {
+            line += 1;
+            rest = next;
+            continue;
+        }
+        // skip comments, CDATA and declarations including their line breaks
+        let end = match () {
+            _ if tail.starts_with("<!--") => tail.find("-->").map(|i| i + 3),
+            _ if tail.starts_with("<![CDATA[") => tail.find("]]>").map(|i| i + 3),
+            _ if tail.starts_with("<?") || tail.starts_with("<!") || tail.starts_with("</") => tail.find('>').map(|i| i + 1),
+            _ => {
+                tag_lines.push(line);
+                Some(1)
+            }
+        }.unwrap_or(tail.len());
+        line += tail[..end].matches('\n').count();
+        rest = &tail[end..];
+    }
+
+    let mut lines = HashMap::new();
+    let mut stack = vec![root];
+    let mut tags = tag_lines.into_iter();
+    while let Some(el) = stack.pop() {
+        if let Some(l) = tags.next() {
+            lines.insert(el as *const Element, l);
+        }
+        let children: Vec<&Element> = el.children().collect();
+        stack.extend(children.into_iter().rev());
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod validation {
+        use crate::config::ConfigFormat;
+        use crate::config::validation::{Severity, validate};
+
+        #[test]
+        fn reports_all_problems_with_lines() {
+            let xml = "<config xmlns=\"\">\n  <sorter>\n    <retry attempts=\"0\"/>\n    <dupicateResolution strategy=\"ignore\"/>\n    <supported>\n      <segments>\n        <segment type=\"DateTimePatern\" index=\"0\"/>\n      </segments>\n    </supported>\n  </sorter>\n</config>";
+            let diagnostics = validate(xml, ConfigFormat::Xml);
+            assert_eq!(diagnostics.len(), 3);
+            assert_eq!(diagnostics[0].severity, Severity::Error);
+            assert_eq!(diagnostics[0].element, "sorter/retry");
+            assert_eq!(diagnostics[0].line, Some(3));
+            assert_eq!(diagnostics[1].severity, Severity::Warning);
+            assert_eq!(diagnostics[1].line, Some(4));
+            assert_eq!(diagnostics[1].suggestion.as_deref(), Some("did you mean \"duplicateResolution\"?"));
+            assert_eq!(diagnostics[2].element, "sorter/supported/segments/segment[0]");
+            assert_eq!(diagnostics[2].suggestion.as_deref(), Some("did you mean \"DateTimePattern\"?"));
+
+            let toml = validate("[sorter.retry]\nattempts = 2\n[[sorter.supported.segments]]\ntype = \"DateTimePattern\"\nparts = [\"Year\"]\n", ConfigFormat::Toml);
+            assert!(toml.is_empty());
+            let broken = validate("[sorter]\nretry = \n", ConfigFormat::Toml);
+            assert_eq!(broken[0].line, Some(2));
+        }
+
+        #[test]
+        fn reports_duplicate_segment_indices() {
+            let toml = "version = 2\n[[sorter.supported.segments]]\ntype = \"DateTimePattern\"\nindex = 1\n[[sorter.supported.segments]]\ntype = \"ScreenshotPattern\"\nindex = 1\n";
+            let diagnostics = validate(toml, ConfigFormat::Toml);
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].element, "sorter.supported.segments[1]");
+            assert_eq!(diagnostics[0].message, "duplicate segment index 1");
+
+            let ordered = format!("{}[sorter.supported]\nordered = true\n", toml);
+            assert!(validate(ordered.as_str(), ConfigFormat::Toml).is_empty());
+            let legacy = validate(&toml["version = 2\n".len()..], ConfigFormat::Toml);
+            assert_eq!(legacy.len(), 1);
+            assert_eq!(legacy[0].severity, Severity::Warning);
+        }
+    }
+}
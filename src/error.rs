@@ -0,0 +1,176 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::sorting::PATHSTR_FB;
+use crate::sorting::retry::RetryPolicy;
+
+/// Errors that may occur while sorting a single file, from calculating its target to executing
+/// the operation.
+///
+/// # Variants
+/// - [SortError::SourceNotFound] the source file of an action does not exist (anymore)
+/// - [SortError::Io] an IO operation on the given path failed
+/// - [SortError::DirCreation] a target directory could not be created
+/// - [SortError::Comparison] comparing an existing target with its source failed
+/// - [SortError::Translation] the target path of a file could not be calculated
+/// - [SortError::Policy] a policy (duplicate handling, quotas) could not be applied
+#[derive(Debug, Error)]
+pub enum SortError {
+    #[error("source file does not exist: \"{}\"", .0.to_str().unwrap_or(PATHSTR_FB))]
+    SourceNotFound(PathBuf),
+    #[error("failed to {op} \"{}\": {source}", .path.to_str().unwrap_or(PATHSTR_FB))]
+    Io {
+        op: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error
+    },
+    #[error("could not create target directory \"{}\": {reason}{}", .path.to_str().unwrap_or(PATHSTR_FB), hint_suffix(.kind))]
+    DirCreation {
+        path: PathBuf,
+        kind: DirErrorKind,
+        reason: String
+    },
+    #[error("error accessing file=\"{}\": {reason}", .path.to_str().unwrap_or(PATHSTR_FB))]
+    Comparison {
+        path: PathBuf,
+        reason: String
+    },
+    #[error("failed to calculate target for \"{}\": {reason}", .path.to_str().unwrap_or(PATHSTR_FB))]
+    Translation {
+        path: PathBuf,
+        reason: String
+    },
+    #[error("{0}")]
+    Policy(String)
+}
+
+impl SortError {
+    pub fn io(op: &'static str, path: PathBuf, source: io::Error) -> SortError {
+        SortError::Io { op, path, source }
+    }
+
+    pub fn policy(msg: &str) -> SortError {
+        SortError::Policy(String::from(msg))
+    }
+
+    /// creating the directory `path` failed with `e`
+    pub fn dir_creation(path: &Path, e: &io::Error) -> SortError {
+        SortError::DirCreation {
+            path: path.to_path_buf(),
+            kind: DirErrorKind::of(e),
+            reason: e.to_string()
+        }
+    }
+}
+
+/// Why a target directory could not be created, see [SortError::DirCreation].
+///
+/// # Variants
+/// - [DirErrorKind::PermissionDenied] directories may not be created in the parent directory
+/// - [DirErrorKind::ReadOnly] the target is on a read-only file system
+/// - [DirErrorKind::NameConflict] a file that is not a directory exists at the path or one of its
+///   parents
+/// - [DirErrorKind::NoSpace] the file system of the target is full
+/// - [DirErrorKind::Transient] a transient error, e.g. a timeout of a network share
+/// - [DirErrorKind::Stopped] the directory manager creating directories for the sorter has
+///   stopped, e.g. at the end of a run
+/// - [DirErrorKind::Other] any other error
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DirErrorKind {
+    PermissionDenied,
+    ReadOnly,
+    NameConflict,
+    NoSpace,
+    Transient,
+    Stopped,
+    Other
+}
+
+impl DirErrorKind {
+    pub fn of(e: &io::Error) -> DirErrorKind {
+        #[cfg(unix)]
+        match e.raw_os_error() {
+            Some(libc::EROFS) => return DirErrorKind::ReadOnly,
+            Some(libc::ENOSPC) | Some(libc::EDQUOT) => return DirErrorKind::NoSpace,
+            Some(libc::EEXIST) | Some(libc::ENOTDIR) => return DirErrorKind::NameConflict,
+            _ => ()
+        }
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => DirErrorKind::PermissionDenied,
+            io::ErrorKind::AlreadyExists => DirErrorKind::NameConflict,
+            _ if RetryPolicy::is_transient(e) => DirErrorKind::Transient,
+            _ => DirErrorKind::Other
+        }
+    }
+
+    /// check if creating the directory again may succeed, e.g. after another thread or process
+    /// changed the file system concurrently
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, DirErrorKind::Transient | DirErrorKind::Other)
+    }
+
+    /// a hint what to do about the error, if there is anything the user can do
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            DirErrorKind::PermissionDenied => Some("check that the target is writable by the user running dcim-sort"),
+            DirErrorKind::ReadOnly => Some("the target is mounted read-only"),
+            DirErrorKind::NameConflict => Some("move the file blocking the directory out of the way"),
+            DirErrorKind::NoSpace => Some("free up space on the target"),
+            DirErrorKind::Transient | DirErrorKind::Stopped | DirErrorKind::Other => None
+        }
+    }
+}
+
+fn hint_suffix(kind: &DirErrorKind) -> String {
+    kind.hint().map_or_else(String::new, |h| format!(" ({})", h))
+}
+
+/// Errors that may occur while scanning the input for files, see [crate::index::Scanner::iter].
+/// The path that could not be scanned is available with [ScanError::path], the message does not
+/// repeat it.
+///
+/// # Variants
+/// - [ScanError::NotFound] a file given in a file list does not exist
+/// - [ScanError::Io] reading a file or directory failed
+/// - [ScanError::IgnoreRule] an ignore file contains an invalid rule
+/// - [ScanError::Backup] reading a device backup or one of its files failed
+#[derive(Debug, Error)]
+pub enum ScanError {
+    #[error("listed file does not exist")]
+    NotFound(PathBuf),
+    #[error("error {op}: {source}")]
+    Io {
+        op: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error
+    },
+    #[error("invalid ignore rule: {reason}")]
+    IgnoreRule {
+        path: PathBuf,
+        reason: String
+    },
+    #[error("{reason}")]
+    Backup {
+        path: PathBuf,
+        reason: String
+    }
+}
+
+impl ScanError {
+    pub fn io(op: &'static str, path: PathBuf, source: io::Error) -> ScanError {
+        ScanError::Io { op, path, source }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            ScanError::NotFound(path) => path,
+            ScanError::Io { path, .. } => path,
+            ScanError::IgnoreRule { path, .. } => path,
+            ScanError::Backup { path, .. } => path
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::logging::console::{self, OutputFormat};
+use crate::sorting::PATHSTR_FB;
+
+/// placeholders replaced in every argument of a [PostActionHook]
+const PLACEHOLDER_SOURCE: &str = "{source}";
+const PLACEHOLDER_TARGET: &str = "{target}";
+const PLACEHOLDER_ACTION: &str = "{action}";
+
+/// An external command run after every successfully moved or copied file, e.g. to trigger the
+/// generation of thumbnails or notify a photo indexer.
+///
+/// The command is split into arguments at whitespace outside of single or double quotes and run
+/// without a shell, `sh -c '...'` has to be given explicitly for pipes or redirections. The
+/// placeholders `{source}`, `{target}` and `{action}` (`moved` or `copied`) are replaced in every
+/// argument, the same values are passed in the environment variables `DCIM_SORT_SOURCE`,
+/// `DCIM_SORT_TARGET` and `DCIM_SORT_ACTION`. Paths which are not valid UTF-8 are passed
+/// unchanged in both. Commands run by a shell should use the environment
+/// variables, e.g. `sh -c 'echo "$DCIM_SORT_SOURCE" >> log'`, as a placeholder within the shell
+/// string would let quotes in file names end it. The pipeline waits for the command to exit, a
+/// failing command is reported as a warning and does not affect the result of the file.
+#[derive(Clone, Debug)]
+pub struct PostActionHook {
+    program: String,
+    args: Vec<String>
+}
+
+impl PostActionHook {
+    pub fn parse(command: &str) -> Result<PostActionHook, String> {
+        let mut parts = split_args(command)?.into_iter();
+        match parts.next() {
+            Some(program) => Ok(PostActionHook {
+                program,
+                args: parts.collect()
+            }),
+            None => Err(String::from("the post-action command is empty"))
+        }
+    }
+
+    /// run the command for `source` which has been moved or copied (`action`) to `target`
+    pub fn run(&self, action: &str, source: &Path, target: &Path) {
+        let values = [
+            (PLACEHOLDER_SOURCE, source.as_os_str()),
+            (PLACEHOLDER_TARGET, target.as_os_str()),
+            (PLACEHOLDER_ACTION, OsStr::new(action))
+        ];
+        let args = self.args.iter().map(|a| substitute(a, &values));
+        // output of the command would corrupt the JSON lines on stdout
+        let stdout = match console::output_format() {
+            OutputFormat::Text => Stdio::inherit(),
+            OutputFormat::Json | OutputFormat::Script => Stdio::null()
+        };
+        let status = Command::new(&self.program)
+            .args(args)
+            .env("DCIM_SORT_SOURCE", source)
+            .env("DCIM_SORT_TARGET", target)
+            .env("DCIM_SORT_ACTION", action)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .status();
+        match status {
+            Ok(s) if s.success() => (),
+            Ok(s) => console::warn(format!("post-action command failed for \"{}\": {}", target.to_str().unwrap_or(PATHSTR_FB), s).as_str()),
+            Err(e) => console::warn(format!("could not run post-action command \"{}\": {}", self.program, e).as_str())
+        }
+    }
+}
+
+/// replace the placeholders of `values` in `arg` in a single pass, so a placeholder within a
+/// substituted path is kept as it is
+fn substitute(arg: &str, values: &[(&str, &OsStr)]) -> OsString {
+    let mut result = OsString::new();
+    let mut rest = arg;
+    while let Some(start) = rest.find('{') {
+        result.push(&rest[..start]);
+        rest = &rest[start..];
+        match values.iter().find(|(placeholder, _)| rest.starts_with(placeholder)) {
+            Some((placeholder, value)) => {
+                result.push(value);
+                rest = &rest[placeholder.len()..];
+            },
+            None => {
+                result.push("{");
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push(rest);
+    result
+}
+
+/// split `command` into arguments at whitespace, keeping whitespace within single or double quotes
+fn split_args(command: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            },
+            (None, c) if c.is_whitespace() => args.extend(current.take()),
+            (None, c) => current.get_or_insert_with(String::new).push(c)
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("unterminated quote in post-action command: {}", command));
+    }
+    args.extend(current);
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod substitute {
+        use std::ffi::{OsStr, OsString};
+
+        use crate::hook::substitute;
+
+        #[test]
+        fn replaces_placeholders_once() {
+            let values = [("{source}", OsStr::new("/in/{target}.jpg")), ("{target}", OsStr::new("/out/a.jpg"))];
+            assert_eq!(substitute("{{source}}:{target}{action}", &values), OsString::from("{/in/{target}.jpg}:/out/a.jpg{action}"));
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn keeps_non_utf8_paths() {
+            use std::os::unix::ffi::OsStrExt;
+
+            let source = OsStr::from_bytes(b"/in/\xff.jpg");
+            assert_eq!(substitute("src={source}", &[("{source}", source)]).as_bytes(), b"src=/in/\xff.jpg");
+        }
+    }
+
+    mod split_args {
+        use crate::hook::split_args;
+
+        #[test]
+        fn keeps_quoted_whitespace() {
+            assert_eq!(split_args("thumb -s 256 '{target}'").unwrap(), vec!["thumb", "-s", "256", "{target}"]);
+            assert_eq!(split_args("sh -c 'echo \"$DCIM_SORT_SOURCE\" >> log'  ''").unwrap(), vec!["sh", "-c", "echo \"$DCIM_SORT_SOURCE\" >> log", ""]);
+            assert!(split_args("echo 'open").is_err());
+        }
+    }
+}
@@ -1,8 +1,22 @@
-use std::io::{Error, ErrorKind};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::ReadDir;
+use std::io::{BufRead, Error, ErrorKind};
+use std::time::SystemTime;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use regex::Regex;
+
+#[cfg(feature = "backups")]
+use crate::backup::Backup;
+use crate::error::ScanError;
+use crate::logging::console::{self, Verbosity};
 use crate::media::{FileType, ImgInfo};
 use crate::pipeline::{PipelineController};
+use crate::sorting::PATHSTR_FB;
+use crate::sorting::fs_support::device_id;
+use crate::sorting::routing::{find_pairs, find_primary, PairFormat};
 
 pub enum PathBox {
     Directory(PathBuf),
@@ -19,15 +33,590 @@ impl PathBox {
     }
 }
 
+/// read a list of newline-separated paths, e.g. the output of `find`, as accepted by
+/// [Scanner::from_list]. Empty lines are skipped.
+pub fn read_file_list<R: BufRead>(reader: R) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.strip_suffix('\r').unwrap_or(line.as_str());
+        if !line.is_empty() {
+            paths.push(PathBuf::from(line));
+        }
+    }
+    Ok(paths)
+}
+
+/// Order in which the entries of a directory are scanned.
+///
+/// # Variants
+/// - [ScanOrder::Unordered] the order returned by the file system (default), which depends on the
+///   file system and may change between runs
+/// - [ScanOrder::Name] ordered by name
+/// - [ScanOrder::Mtime] ordered by modification time, oldest first, and by name for equal times
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScanOrder {
+    Unordered,
+    Name,
+    Mtime
+}
+
+impl ScanOrder {
+    pub fn parse(s: &str) -> Option<ScanOrder> {
+        match s {
+            "none" => Some(ScanOrder::Unordered),
+            "name" => Some(ScanOrder::Name),
+            "mtime" => Some(ScanOrder::Mtime),
+            _ => None
+        }
+    }
+}
+
+/// the entries of a directory being scanned in the configured [ScanOrder]
+enum DirEntries {
+    Unordered(ReadDir),
+    Sorted(std::vec::IntoIter<Result<PathBuf, Error>>)
+}
+
+impl DirEntries {
+    fn read(dir: &Path, order: ScanOrder) -> Result<DirEntries, Error> {
+        let children = dir.read_dir()?;
+        let key = match order {
+            ScanOrder::Unordered => return Ok(DirEntries::Unordered(children)),
+            ScanOrder::Name => |_: &std::fs::DirEntry| SystemTime::UNIX_EPOCH,
+            ScanOrder::Mtime => |c: &std::fs::DirEntry| c.metadata()
+                .and_then(|m| m.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        };
+        let mut entries = Vec::new();
+        let mut errors = Vec::new();
+        for child in children {
+            match child {
+                Ok(c) => entries.push((key(&c), c.path())),
+                Err(e) => errors.push(Err(e))
+            }
+        }
+        entries.sort();
+        errors.extend(entries.into_iter().map(|(_, p)| Ok(p)));
+        Ok(DirEntries::Sorted(errors.into_iter()))
+    }
+}
+
+impl Iterator for DirEntries {
+    type Item = Result<PathBuf, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            DirEntries::Unordered(children) => children.next().map(|c| c.map(|c| c.path())),
+            DirEntries::Sorted(children) => children.next()
+        }
+    }
+}
+
+fn is_symlink(path: &Path) -> bool {
+    path.symlink_metadata().is_ok_and(|m| m.file_type().is_symlink())
+}
+
+/// name of the ignore files evaluated while scanning, see [ScannerBuilder::ignore_files]
+pub const IGNORE_FILE_NAME: &str = ".dcimignore";
+
+/// A shell-style pattern matched case-insensitively against file and directory names. `*` and
+/// `?` match any characters except `/`, `**` matches across directories and `[...]` matches a
+/// class of characters. Patterns containing a `/` are matched against the path relative to the
+/// entry point of the scan instead of the name only, a leading `/` is ignored.
+#[derive(Clone)]
+pub struct Glob {
+    pattern: String,
+    regex: Regex,
+    match_path: bool
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Glob, String> {
+        let mut re = String::from("(?i)^");
+        let mut chars = pattern.strip_prefix('/').unwrap_or(pattern).chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    // "**/" also matches no directory at all
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        re.push_str("(?:.*/)?");
+                    }
+                    else {
+                        re.push_str(".*");
+                    }
+                },
+                '*' => re.push_str("[^/]*"),
+                '?' => re.push_str("[^/]"),
+                '[' => {
+                    re.push('[');
+                    if chars.peek() == Some(&'!') {
+                        chars.next();
+                        re.push('^');
+                    }
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == ']' {
+                            closed = true;
+                            break;
+                        }
+                        if c == '\\' || c == '[' {
+                            re.push('\\');
+                        }
+                        re.push(c);
+                    }
+                    if !closed {
+                        return Err(format!("unclosed character class in pattern \"{}\"", pattern));
+                    }
+                    re.push(']');
+                },
+                c => re.push_str(regex::escape(c.to_string().as_str()).as_str())
+            }
+        }
+        re.push('$');
+        match Regex::new(re.as_str()) {
+            Ok(regex) => Ok(Glob {
+                pattern: String::from(pattern),
+                regex,
+                match_path: pattern.contains('/')
+            }),
+            Err(e) => Err(format!("invalid pattern \"{}\": {}", pattern, e))
+        }
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.pattern.as_str()
+    }
+
+    /// check if the glob matches `path`, which is relative to the entry point of the scan
+    pub fn matches(&self, path: &Path) -> bool {
+        let candidate = match self.match_path {
+            true => path.to_str(),
+            false => path.file_name().and_then(|n| n.to_str())
+        };
+        match candidate {
+            Some(c) => self.regex.is_match(c),
+            None => false
+        }
+    }
+}
+
+/// A range of timestamps files are processed in, e.g. to import only the files of one month.
+/// Both bounds are optional, `since` is inclusive and `until` exclusive.
+#[derive(Clone, Copy)]
+pub struct DateRange {
+    since: Option<DateTime<Local>>,
+    until: Option<DateTime<Local>>
+}
+
+impl DateRange {
+    pub fn new(since: Option<DateTime<Local>>, until: Option<DateTime<Local>>) -> DateRange {
+        DateRange {
+            since,
+            until
+        }
+    }
+
+    /// parse a range from periods given as `YYYY-mm`, `YYYY-mm-dd` or `YYYY-mm-ddTHH:MM:SS`. The
+    /// range starts with the period of `since` and includes the whole period of `until`, e.g.
+    /// `--since 2023-01 --until 2023-03` covers the first quarter of 2023.
+    pub fn parse(since: Option<&str>, until: Option<&str>) -> Result<DateRange, String> {
+        let since = match since {
+            Some(s) => Some(Self::parse_period(s)?.0),
+            None => None
+        };
+        let until = match until {
+            Some(s) => Some(Self::parse_period(s)?.1),
+            None => None
+        };
+        if let (Some(s), Some(u)) = (since, until) {
+            if s >= u {
+                return Err(String::from("the start of the date range is after its end"));
+            }
+        }
+        Ok(DateRange::new(since, until))
+    }
+
+    /// get the first second of the period `s` and the first second after it
+    fn parse_period(s: &str) -> Result<(DateTime<Local>, DateTime<Local>), String> {
+        let invalid = || format!("invalid date \"{}\", expected YYYY-mm, YYYY-mm-dd or YYYY-mm-ddTHH:MM:SS", s);
+        let (start, end) = if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%FT%T") {
+            (dt, dt + Duration::seconds(1))
+        }
+        else if let Ok(d) = NaiveDate::parse_from_str(s, "%F") {
+            let start = d.and_hms_opt(0, 0, 0).ok_or_else(invalid)?;
+            (start, start + Duration::days(1))
+        }
+        else {
+            let first = NaiveDate::parse_from_str(format!("{}-01", s).as_str(), "%F").map_err(|_| invalid())?;
+            let next = match first.month() {
+                12 => NaiveDate::from_ymd_opt(first.year() + 1, 1, 1),
+                m => NaiveDate::from_ymd_opt(first.year(), m + 1, 1)
+            }.ok_or_else(invalid)?;
+            (first.and_hms_opt(0, 0, 0).ok_or_else(invalid)?, next.and_hms_opt(0, 0, 0).ok_or_else(invalid)?)
+        };
+        match (Local.from_local_datetime(&start).earliest(), Local.from_local_datetime(&end).earliest()) {
+            (Some(start), Some(end)) => Ok((start, end)),
+            _ => Err(invalid())
+        }
+    }
+
+    pub fn contains(&self, ts: &DateTime<Local>) -> bool {
+        self.since.is_none_or(|s| *ts >= s) && self.until.is_none_or(|u| *ts < u)
+    }
+}
+
+/// A rule of an ignore file, see [IgnoreFile]
+struct IgnoreRule {
+    glob: Glob,
+    negate: bool,
+    dir_only: bool
+}
+
+/// The rules of an ignore file in gitignore syntax, applying to the directory containing it and
+/// all of its children. Rules are evaluated in order, with the last matching rule deciding if a
+/// path is ignored:
+/// - blank lines and lines starting with `#` are ignored
+/// - a leading `!` re-includes paths excluded by a previous rule
+/// - a trailing `/` only matches directories
+/// - rules containing a `/` (other than a trailing one) are relative to the directory of the
+///   ignore file, others match the name in any subdirectory
+/// - a leading `\` escapes a literal `#` or `!`
+struct IgnoreFile {
+    dir: PathBuf,
+    rules: Vec<IgnoreRule>
+}
+
+impl IgnoreFile {
+    /// parse the ignore file in `dir`, returning the rules and errors of invalid lines
+    fn read(dir: &Path) -> Result<(IgnoreFile, Vec<String>), Error> {
+        let content = std::fs::read_to_string(dir.join(IGNORE_FILE_NAME))?;
+        let mut rules = Vec::new();
+        let mut errors = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            let mut line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let negate = line.starts_with('!');
+            if negate {
+                line = &line[1..];
+            }
+            let line = line.strip_prefix('\\').unwrap_or(line);
+            let dir_only = line.ends_with('/');
+            let pattern = line.trim_end_matches('/');
+            if pattern.is_empty() {
+                continue;
+            }
+            match Glob::new(pattern) {
+                Ok(glob) => rules.push(IgnoreRule { glob, negate, dir_only }),
+                Err(e) => errors.push(format!("line {}: {}", i + 1, e))
+            }
+        }
+        Ok((IgnoreFile { dir: dir.to_path_buf(), rules }, errors))
+    }
+}
+
+/// what a [ScannerBuilder] creates a scanner for
+enum ScanInput {
+    Root(PathBuf),
+    List(Vec<PathBuf>)
+}
+
+/// Builder for a [Scanner] with the settings which cannot change once scanning started, created
+/// with [Scanner::builder] or [Scanner::list_builder].
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use dcim_sort::index::Scanner;
+///
+/// let scanner = Scanner::builder(Path::new("/media/card/DCIM"))
+///     .max_depth(4)
+///     .exclude("@eaDir")
+///     .follow_symlinks(false)
+///     .build()
+///     .unwrap();
+/// ```
+pub struct ScannerBuilder {
+    input: ScanInput,
+    max_depth: u8,
+    debug: bool,
+    ignore_unknown_types: bool,
+    excludes: Vec<String>,
+    includes: Vec<String>,
+    follow_symlinks: bool,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    skip: HashSet<PathBuf>,
+    use_ignore_files: bool,
+    date_range: Option<DateRange>,
+    order: ScanOrder,
+    one_file_system: bool,
+    pairs: Option<PairFormat>
+}
+
+impl ScannerBuilder {
+    fn new(input: ScanInput) -> ScannerBuilder {
+        ScannerBuilder {
+            input,
+            max_depth: Scanner::DEFAULT_MAX_DEPTH,
+            debug: false,
+            ignore_unknown_types: false,
+            excludes: Vec::new(),
+            includes: Vec::new(),
+            follow_symlinks: true,
+            min_size: None,
+            max_size: None,
+            skip: HashSet::new(),
+            use_ignore_files: true,
+            date_range: None,
+            order: ScanOrder::Unordered,
+            one_file_system: false,
+            pairs: None
+        }
+    }
+
+    /// descend at most `max` directories below the entry point (default:
+    /// [Scanner::DEFAULT_MAX_DEPTH])
+    pub fn max_depth(mut self, max: u8) -> ScannerBuilder {
+        self.set_max_depth(max);
+        self
+    }
+
+    /// see [ScannerBuilder::max_depth]
+    pub fn set_max_depth(&mut self, max: u8) {
+        self.max_depth = max;
+    }
+
+    /// skip files of types not supported instead of passing them on as unknown (default: false)
+    pub fn ignore_unknown(mut self, b: bool) -> ScannerBuilder {
+        self.set_ignore_unknown(b);
+        self
+    }
+
+    /// see [ScannerBuilder::ignore_unknown]
+    pub fn set_ignore_unknown(&mut self, b: bool) {
+        self.ignore_unknown_types = b;
+    }
+
+    /// skip files and directories matching `pattern` (see [Glob]), e.g. `@eaDir` or `*.tmp`.
+    /// Invalid patterns are reported by [ScannerBuilder::build].
+    pub fn exclude(mut self, pattern: &str) -> ScannerBuilder {
+        self.add_exclude(pattern);
+        self
+    }
+
+    /// see [ScannerBuilder::exclude]
+    pub fn add_exclude(&mut self, pattern: &str) {
+        self.excludes.push(String::from(pattern));
+    }
+
+    /// only scan files matching `pattern` (see [Glob]), e.g. `*.jpg`. If given multiple times,
+    /// files matching any of the patterns are scanned. Directories are always descended into
+    /// unless excluded.
+    pub fn include(mut self, pattern: &str) -> ScannerBuilder {
+        self.add_include(pattern);
+        self
+    }
+
+    /// see [ScannerBuilder::include]
+    pub fn add_include(&mut self, pattern: &str) {
+        self.includes.push(String::from(pattern));
+    }
+
+    /// follow symbolic links to files and directories below the entry point (default: true). The
+    /// entry point itself and listed files are always followed. Links to a directory containing
+    /// the link are skipped, so loops only end at the maximum depth if the same directory is
+    /// reached by different paths.
+    pub fn follow_symlinks(mut self, b: bool) -> ScannerBuilder {
+        self.set_follow_symlinks(b);
+        self
+    }
+
+    /// see [ScannerBuilder::follow_symlinks]
+    pub fn set_follow_symlinks(&mut self, b: bool) {
+        self.follow_symlinks = b;
+    }
+
+    /// only scan files with a size of at least `min` and at most `max` bytes
+    pub fn size_limits(mut self, min: Option<u64>, max: Option<u64>) -> ScannerBuilder {
+        self.set_size_limits(min, max);
+        self
+    }
+
+    /// see [ScannerBuilder::size_limits]
+    pub fn set_size_limits(&mut self, min: Option<u64>, max: Option<u64>) {
+        self.min_size = min;
+        self.max_size = max;
+    }
+
+    /// print every scanned path and why paths are skipped, which is also done for
+    /// [Verbosity::Verbose]
+    pub fn debug(mut self, b: bool) -> ScannerBuilder {
+        self.set_debug(b);
+        self
+    }
+
+    /// see [ScannerBuilder::debug]
+    pub fn set_debug(&mut self, b: bool) {
+        self.debug = b;
+    }
+
+    /// skip the given files while scanning, e.g. the ones recorded in a
+    /// [crate::checkpoint::Checkpoint]
+    pub fn skip_paths(mut self, paths: HashSet<PathBuf>) -> ScannerBuilder {
+        self.set_skip_paths(paths);
+        self
+    }
+
+    /// see [ScannerBuilder::skip_paths]
+    pub fn set_skip_paths(&mut self, paths: HashSet<PathBuf>) {
+        self.skip = paths;
+    }
+
+    /// evaluate ignore files named [IGNORE_FILE_NAME] in scanned directories (default: true). See
+    /// [IgnoreFile] for the syntax. Ignore files are never evaluated for a file list.
+    pub fn ignore_files(mut self, b: bool) -> ScannerBuilder {
+        self.set_ignore_files(b);
+        self
+    }
+
+    /// see [ScannerBuilder::ignore_files]
+    pub fn set_ignore_files(&mut self, b: bool) {
+        self.use_ignore_files = b;
+    }
+
+    /// only scan files with a modification time in `range`. The metadata timestamp is not known
+    /// while scanning, use [crate::pipeline::Pipeline::set_date_range] to filter by it instead.
+    pub fn date_range(mut self, range: DateRange) -> ScannerBuilder {
+        self.set_date_range(range);
+        self
+    }
+
+    /// see [ScannerBuilder::date_range]
+    pub fn set_date_range(&mut self, range: DateRange) {
+        self.date_range = Some(range);
+    }
+
+    /// scan the entries of each directory in `order` (default: [ScanOrder::Unordered]), e.g. for
+    /// stable output of repeated simulations. Files of a file list are scanned in the order given.
+    pub fn order(mut self, order: ScanOrder) -> ScannerBuilder {
+        self.set_order(order);
+        self
+    }
+
+    /// see [ScannerBuilder::order]
+    pub fn set_order(&mut self, order: ScanOrder) {
+        self.order = order;
+    }
+
+    /// do not descend into directories on other file systems than the entry point (default:
+    /// false), e.g. a backup mounted below the input. Mount points are detected by device IDs,
+    /// which are only available on Unix.
+    pub fn one_file_system(mut self, b: bool) -> ScannerBuilder {
+        self.set_one_file_system(b);
+        self
+    }
+
+    /// see [ScannerBuilder::one_file_system]
+    pub fn set_one_file_system(&mut self, b: bool) {
+        self.one_file_system = b;
+    }
+
+    /// detect HEIC/JPEG pairs with the `preferred` format while scanning and record the primary
+    /// file in the [ImgInfo] of each secondary file, see [crate::sorting::routing::PairPolicy].
+    /// Pairs are detected when a directory is entered, before any of its files is sorted.
+    pub fn pair_detection(mut self, preferred: Option<PairFormat>) -> ScannerBuilder {
+        self.set_pair_detection(preferred);
+        self
+    }
+
+    /// see [ScannerBuilder::pair_detection]
+    pub fn set_pair_detection(&mut self, preferred: Option<PairFormat>) {
+        self.pairs = preferred;
+    }
+
+    /// create the scanner, fails if the entry point does not exist or a pattern is invalid
+    pub fn build(self) -> Result<Scanner, String> {
+        let mut scanner = match self.input {
+            ScanInput::Root(root) => Scanner::new(root.as_path())
+                .map_err(|e| format!("input \"{}\" cannot be scanned: {}", root.to_str().unwrap_or(PATHSTR_FB), e))?,
+            ScanInput::List(files) => Scanner::from_list(files)
+        };
+        scanner.excludes = self.excludes.iter().map(|p| Glob::new(p.as_str())).collect::<Result<_, _>>()?;
+        scanner.includes = self.includes.iter().map(|p| Glob::new(p.as_str())).collect::<Result<_, _>>()?;
+        scanner.max_depth = self.max_depth;
+        scanner.debug = self.debug;
+        scanner.ignore_unknown_types = self.ignore_unknown_types;
+        scanner.follow_symlinks = self.follow_symlinks;
+        scanner.min_size = self.min_size;
+        scanner.max_size = self.max_size;
+        scanner.skip = self.skip;
+        scanner.use_ignore_files = self.use_ignore_files && scanner.file_list.is_none();
+        scanner.date_range = self.date_range;
+        scanner.order = self.order;
+        scanner.one_file_system = self.one_file_system;
+        scanner.pairs = self.pairs;
+        Ok(scanner)
+    }
+}
+
 pub struct Scanner {
     entry_point: PathBuf,
     max_depth: u8,
-    depth: u8,
     debug: bool,
-    ignore_unknown_types: bool
+    ignore_unknown_types: bool,
+    errors: Vec<(PathBuf, String)>,
+    /// files that have already been processed by a previous run
+    skip: HashSet<PathBuf>,
+    skipped: usize,
+    /// files and directories matching any of these are not scanned
+    excludes: Vec<Glob>,
+    /// if not empty, only files matching any of these are scanned
+    includes: Vec<Glob>,
+    use_ignore_files: bool,
+    /// ignore files of the directory currently scanned and all of its parents, outermost first
+    ignore_files: Vec<IgnoreFile>,
+    /// only files modified in this range are scanned
+    date_range: Option<DateRange>,
+    /// files smaller than this many bytes are not scanned, e.g. thumbnails
+    min_size: Option<u64>,
+    /// files larger than this many bytes are not scanned
+    max_size: Option<u64>,
+    /// files to scan instead of walking the entry point, see [Scanner::from_list]
+    file_list: Option<Vec<PathBuf>>,
+    order: ScanOrder,
+    /// do not descend into directories on other file systems than the entry point
+    one_file_system: bool,
+    /// follow symbolic links below the entry point
+    follow_symlinks: bool,
+    /// preferred format of HEIC/JPEG pairs to detect, see [ScannerBuilder::pair_detection]
+    pairs: Option<PairFormat>,
+    /// set if the entry point is a device backup instead of a plain directory
+    #[cfg(feature = "backups")]
+    backup: Option<Backup>
 }
 
 impl Scanner {
+    /// maximum recursion level unless set by [ScannerBuilder::max_depth]
+    pub const DEFAULT_MAX_DEPTH: u8 = 10;
+
+    /// configure a scanner for the directory or file `root`, see [ScannerBuilder]
+    pub fn builder(root: &Path) -> ScannerBuilder {
+        ScannerBuilder::new(ScanInput::Root(root.to_path_buf()))
+    }
+
+    /// configure a scanner for an explicit list of files, see [Scanner::from_list]
+    pub fn list_builder(files: Vec<PathBuf>) -> ScannerBuilder {
+        ScannerBuilder::new(ScanInput::List(files))
+    }
+
+    /// create a scanner for `root` with the default settings, use [Scanner::builder] to change
+    /// them
     pub fn new(root_path: &Path) -> Result<Scanner, Error> {
         if !root_path.exists() {
             return Err(Error::new(ErrorKind::NotFound, "root must be a directory!"));
@@ -35,120 +624,552 @@ impl Scanner {
         else {
             Ok(Scanner{
                 entry_point: root_path.to_path_buf(),
-                max_depth: 10,
-                depth: 0,
+                max_depth: Scanner::DEFAULT_MAX_DEPTH,
                 debug: false,
-                ignore_unknown_types: false
+                ignore_unknown_types: false,
+                errors: Vec::new(),
+                skip: HashSet::new(),
+                skipped: 0,
+                excludes: Vec::new(),
+                includes: Vec::new(),
+                use_ignore_files: true,
+                ignore_files: Vec::new(),
+                date_range: None,
+                min_size: None,
+                max_size: None,
+                file_list: None,
+                order: ScanOrder::Unordered,
+                one_file_system: false,
+                follow_symlinks: true,
+                pairs: None,
+                #[cfg(feature = "backups")]
+                backup: Backup::detect(root_path)
             })
         }
     }
 
-    pub fn debug(&mut self, b: bool) {
-        self.debug = b;
-    }
-
-    pub fn ignore_unknown_types(&mut self, b: bool) {
-        self.ignore_unknown_types = b;
+    /// create a scanner for an explicit list of files instead of a directory, e.g. selected by
+    /// `find`. Directories in the list are skipped rather than descended into, so they do not have
+    /// to be filtered out of the list. All other filters apply as usual except for ignore files,
+    /// globs are matched against the paths as listed.
+    pub fn from_list(files: Vec<PathBuf>) -> Scanner {
+        Scanner{
+            entry_point: PathBuf::new(),
+            max_depth: Scanner::DEFAULT_MAX_DEPTH,
+            debug: false,
+            ignore_unknown_types: false,
+            errors: Vec::new(),
+            skip: HashSet::new(),
+            skipped: 0,
+            excludes: Vec::new(),
+            includes: Vec::new(),
+            use_ignore_files: false,
+            ignore_files: Vec::new(),
+            date_range: None,
+            min_size: None,
+            max_size: None,
+            file_list: Some(files),
+            order: ScanOrder::Unordered,
+            one_file_system: false,
+            follow_symlinks: true,
+            pairs: None,
+            #[cfg(feature = "backups")]
+            backup: None
+        }
     }
 
-    pub fn set_max_depth(&mut self, max: u8) {
-        self.max_depth = max;
+    fn is_verbose(&self) -> bool {
+        self.debug || console::shows(Verbosity::Verbose)
     }
 
     pub fn get_max_depth(&self) -> u8 {
         self.max_depth
     }
 
+    /// check if `file` passes the date range and size limits
+    fn accepts(&self, file: &ImgInfo) -> bool {
+        let size = *file.size() as u64;
+        self.date_range.is_none_or(|r| r.contains(file.changed_at()))
+            && self.min_size.is_none_or(|min| size >= min)
+            && self.max_size.is_none_or(|max| size <= max)
+    }
+
+    /// check if `path` is filtered out by the exclude or include globs or an ignore file
+    fn is_filtered(&self, path: &Path, is_dir: bool) -> bool {
+        let rel = path.strip_prefix(&self.entry_point).unwrap_or(path);
+        if rel.as_os_str().is_empty() {
+            return false;
+        }
+        if self.excludes.iter().any(|g| g.matches(rel)) || self.is_ignored(path, is_dir) {
+            return true;
+        }
+        !is_dir && !self.includes.is_empty() && !self.includes.iter().any(|g| g.matches(rel))
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for file in &self.ignore_files {
+            let rel = match path.strip_prefix(&file.dir) {
+                Ok(r) => r,
+                Err(_) => continue
+            };
+            for rule in &file.rules {
+                if (is_dir || !rule.dir_only) && rule.glob.matches(rel) {
+                    ignored = !rule.negate;
+                }
+            }
+        }
+        ignored
+    }
+
+    /// check if the entry point is a device backup, whose files must not be moved or modified
+    pub fn is_backup(&self) -> bool {
+        #[cfg(feature = "backups")]
+        return self.backup.is_some();
+        #[cfg(not(feature = "backups"))]
+        return false;
+    }
+
+    /// get the entry point if it is a single plain file (and e.g. not a backup), which can be
+    /// processed without setting up a pipeline
+    pub fn single_file(&self) -> Option<&Path> {
+        #[cfg(feature = "backups")]
+        if self.backup.is_some() {
+            return None;
+        }
+        if self.file_list.is_some() {
+            return None;
+        }
+        match self.entry_point.is_file() {
+            true => Some(self.entry_point.as_path()),
+            false => None
+        }
+    }
+
+    /// get the count of files skipped during the last scan, see [ScannerBuilder::skip_paths]
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// get all paths that could not be scanned during the last scan with the reason. Such paths
+    /// are skipped instead of aborting the scan.
+    pub fn errors(&self) -> &[(PathBuf, String)] {
+        self.errors.as_slice()
+    }
+
+    /// keep track of the outcome of a scanned path for [Self::errors]
+    fn track(&mut self, result: Result<ImgInfo, ScanError>) -> Result<ImgInfo, ScanError> {
+        if let Err(e) = &result {
+            self.errors.push((e.path().to_path_buf(), e.to_string()));
+        }
+        result
+    }
+
     pub fn scan(&mut self) -> Vec<ImgInfo> {
         let mut index : Vec<ImgInfo> =  Vec::new();
-        self.depth = 0;
-        let root = self.entry_point.clone();
-        self.scan_path(PathBox::from(root), &mut index);
+        self.scan_with(|i| index.push(i));
         index
     }
 
     pub fn scan_pipeline(&mut self, controller: &mut PipelineController) {
-        if self.debug {
+        self.scan_with(|i| controller.process(i));
+    }
+
+    /// scan the entry point, passing every file found to `f`. Errors are printed and skipped.
+    pub fn scan_with<F: FnMut(ImgInfo)>(&mut self, f: F) {
+        self.scan_until(&AtomicBool::new(false), f);
+    }
+
+    /// like [Self::scan_with], but stops scanning once `stop` is set, e.g. by a signal handler
+    pub fn scan_until<F: FnMut(ImgInfo)>(&mut self, stop: &AtomicBool, mut f: F) {
+        for result in self.iter() {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            match result {
+                Ok(i) => f(i),
+                Err(e) => eprintln!("Error scanning path \"{}\": {}", e.path().to_str().unwrap_or(PATHSTR_FB), e)
+            }
+        }
+    }
+
+    /// scan `paths` instead of the entry point, applying the same filters except for ignore
+    /// files, e.g. for files which arrived in a watched directory
+    pub fn scan_paths_with<F: FnMut(ImgInfo)>(&mut self, paths: Vec<PathBuf>, f: F) {
+        let list = self.file_list.replace(paths);
+        self.scan_with(f);
+        self.file_list = list;
+    }
+
+    /// scan the entry point lazily, only holding the directories currently being read in memory.
+    /// Every file found is returned as it is scanned, errors are returned in between and the scan
+    /// continues with the next path. [Self::skipped] and [Self::errors] are reset and then updated
+    /// while iterating.
+    pub fn iter(&mut self) -> ScanIter<'_> {
+        if self.is_verbose() {
             println!("starting with root={}", self.entry_point.to_str().unwrap_or("<INVALID_UTF-8>"));
         }
-        let root =self.entry_point.clone();
-        self.scan_path_ch(PathBox::from(root), controller);
+        self.errors.clear();
+        self.skipped = 0;
+        self.ignore_files.clear();
+        let mut iter = ScanIter {
+            root_device: match self.one_file_system {
+                true => device_id(self.entry_point.as_path()),
+                false => None
+            },
+            pending: None,
+            dirs: Vec::new(),
+            list: None,
+            pairs: HashMap::new(),
+            queue: VecDeque::new(),
+            scanner: self
+        };
+        if let Some(results) = iter.scanner.scan_backup() {
+            iter.queue.extend(results);
+        }
+        else if let Some(files) = &iter.scanner.file_list {
+            iter.list = Some(files.clone().into_iter());
+        }
+        else {
+            iter.pending = Some(iter.scanner.entry_point.clone());
+        }
+        iter
+    }
+
+    /// get the files of the backup at the entry point or `None` if it is not a backup
+    #[cfg(feature = "backups")]
+    fn scan_backup(&mut self) -> Option<Vec<Result<ImgInfo, ScanError>>> {
+        let verbose = self.is_verbose();
+        let backup = self.backup.as_mut()?;
+        if verbose {
+            println!("reading {} at {}", backup.name(), self.entry_point.to_str().unwrap_or(PATHSTR_FB));
+        }
+        let mut errors = Vec::new();
+        let mut results = Vec::new();
+        let files = match backup.files(&mut errors) {
+            Ok(files) => files,
+            Err(e) => {
+                results.push(Err(ScanError::Backup {
+                    path: self.entry_point.clone(),
+                    reason: format!("error reading {}: {}", backup.name(), e)
+                }));
+                Vec::new()
+            }
+        };
+        results.extend(errors.into_iter().map(|(path, reason)| Err(ScanError::Backup { path, reason })));
+        let ignore_unknown = self.ignore_unknown_types;
+        results.extend(files.into_iter()
+            .filter(|f| !ignore_unknown || !matches!(f.file_type(), FileType::Other))
+            .filter(|f| match f.file_name() {
+                Some(name) => !self.is_filtered(Path::new(name), false),
+                None => true
+            })
+            .filter(|f| self.accepts(f))
+            .map(Ok));
+        Some(results)
     }
 
-    fn scan_path(&mut self, d: PathBox, index: &mut Vec<ImgInfo>) {
-        if self.debug {
+    #[cfg(not(feature = "backups"))]
+    fn scan_backup(&mut self) -> Option<Vec<Result<ImgInfo, ScanError>>> {
+        None
+    }
+
+    /// read the ignore file in `dir` if there is one, returning true if it has been added and the
+    /// errors encountered
+    fn push_ignore_file(&mut self, dir: &Path) -> (bool, Vec<ScanError>) {
+        let path = dir.join(IGNORE_FILE_NAME);
+        if !path.is_file() {
+            return (false, Vec::new());
+        }
+        match IgnoreFile::read(dir) {
+            Ok((file, errors)) => {
+                self.ignore_files.push(file);
+                (true, errors.into_iter()
+                    .map(|reason| ScanError::IgnoreRule { path: path.clone(), reason })
+                    .collect())
+            },
+            Err(e) => (false, vec![ScanError::io("reading ignore file", path, e)])
+        }
+    }
+}
+
+/// Lazy iterator over the files of a [Scanner], see [Scanner::iter].
+pub struct ScanIter<'a> {
+    scanner: &'a mut Scanner,
+    /// path to be visited next
+    pending: Option<PathBuf>,
+    /// directories currently being read, outermost first, and if they have an ignore file
+    dirs: Vec<(DirEntries, PathBuf, bool)>,
+    /// remaining paths if scanning a file list
+    list: Option<std::vec::IntoIter<PathBuf>>,
+    /// device of the entry point if directories on other devices are skipped
+    root_device: Option<u64>,
+    /// secondary files of HEIC/JPEG pairs in the directories being read with their primary file
+    pairs: HashMap<PathBuf, PathBuf>,
+    /// results available before visiting further paths, e.g. the files of a backup
+    queue: VecDeque<Result<ImgInfo, ScanError>>
+}
+
+impl ScanIter<'_> {
+    /// visit a single path, returning the file or error if there is one to return for it
+    fn visit(&mut self, path: PathBuf) -> Option<Result<ImgInfo, ScanError>> {
+        let is_link = !self.dirs.is_empty() && is_symlink(path.as_path());
+        if is_link && !self.scanner.follow_symlinks {
+            if self.scanner.is_verbose() {
+                println!("skipping symbolic link: {}", path.to_str().unwrap_or(PATHSTR_FB));
+            }
+            return None;
+        }
+        let loops = is_link && self.is_ancestor(path.as_path());
+        let d = PathBox::from(path);
+        let scanner = &mut *self.scanner;
+        if scanner.is_verbose() {
             let tmp = match &d{
                 PathBox::Directory(d) => ("d", String::from(d.to_str().unwrap_or("?"))),
                 PathBox::File(d) => ("f", String::from(d.to_str().unwrap_or("?")))
             };
-            println!("depth={:03} type={} p={}", self.depth, tmp.0, tmp.1);
+            println!("depth={:03} type={} p={}", self.dirs.len(), tmp.0, tmp.1);
         }
         match d {
-            PathBox::File(f) => {
-                match ImgInfo::new(f) {
-                    Ok(i) => {
-                        if self.ignore_unknown_types {
-                            match i.file_type() {
-                                FileType::Other => {},
-                                _ => { index.push(i); },
-                            }
-                        }
-                        else {
-                            index.push(i);
-                        }
-                    },
-                    Err(e) => println!("Error processing file: {}", e)
-                }
+            PathBox::File(p) if p.file_name() == Some(IGNORE_FILE_NAME.as_ref()) => None,
+            PathBox::File(p) if scanner.is_filtered(p.as_path(), false) => None,
+            PathBox::Directory(p) if scanner.is_filtered(p.as_path(), true) => None,
+            PathBox::File(p) if scanner.skip.contains(&p) => {
+                scanner.skipped += 1;
+                None
+            },
+            PathBox::File(p) => match ImgInfo::new(p.clone()) {
+                Ok(i) if !scanner.accepts(&i) => None,
+                Ok(i) if scanner.ignore_unknown_types && matches!(i.file_type(), FileType::Other) => None,
+                Ok(mut i) => {
+                    // listed files are not scanned with their directory
+                    let primary = match (scanner.pairs, self.dirs.is_empty()) {
+                        (None, _) => None,
+                        (Some(preferred), true) => find_primary(p.as_path(), preferred),
+                        (Some(_), false) => self.pairs.remove(&p)
+                    };
+                    i.set_pair_primary(primary);
+                    Some(Ok(i))
+                },
+                Err(e) => Some(Err(ScanError::io("processing file", p, e)))
             },
             PathBox::Directory(d) => {
-                if self.depth < self.max_depth {
-                    self.depth += 1;
-                    for child in d.read_dir().expect("Error reading path a directory!") {
-                        let child_path = child.expect("Error reading child!").path();
-                        self.scan_path(PathBox::from(child_path), index);
+                if self.dirs.len() >= scanner.max_depth as usize {
+                    return None;
+                }
+                if loops {
+                    if scanner.is_verbose() {
+                        println!("skipping symbolic link to a parent directory: {}", d.to_str().unwrap_or(PATHSTR_FB));
+                    }
+                    return None;
+                }
+                if self.root_device.is_some() && device_id(d.as_path()) != self.root_device {
+                    if scanner.is_verbose() {
+                        println!("skipping directory on another file system: {}", d.to_str().unwrap_or(PATHSTR_FB));
+                    }
+                    return None;
+                }
+                let has_ignore_file = match scanner.use_ignore_files {
+                    true => {
+                        let (added, errors) = scanner.push_ignore_file(d.as_path());
+                        self.queue.extend(errors.into_iter().map(Err));
+                        added
+                    },
+                    false => false
+                };
+                match DirEntries::read(d.as_path(), scanner.order) {
+                    Ok(children) => {
+                        if let Some(preferred) = scanner.pairs {
+                            self.pairs.extend(find_pairs(d.as_path(), preferred));
+                        }
+                        self.dirs.push((children, d, has_ignore_file));
+                        None
+                    },
+                    Err(e) => {
+                        if has_ignore_file {
+                            scanner.ignore_files.pop();
+                        }
+                        Some(Err(ScanError::io("reading directory", d, e)))
                     }
-                    self.depth -= 1;
                 }
             }
         }
+    }
 
+    /// check if the directory `dir` resolves to one of the directories currently being read
+    fn is_ancestor(&self, dir: &Path) -> bool {
+        let target = match dir.canonicalize() {
+            Ok(p) => p,
+            Err(_) => return false
+        };
+        self.dirs.iter().any(|(_, p, _)| p.canonicalize().is_ok_and(|p| p == target))
     }
 
-    fn scan_path_ch(&mut self, d: PathBox, controller: &mut PipelineController) {
-        if self.debug {
-            let tmp = match &d{
-                PathBox::Directory(d) => ("d", String::from(d.to_str().unwrap_or("?"))),
-                PathBox::File(d) => ("f", String::from(d.to_str().unwrap_or("?")))
-            };
-            println!("depth={:03} type={} p={}", self.depth, tmp.0, tmp.1);
+    /// get the next path of the file list, skipping directories
+    fn next_listed(&mut self) -> Option<PathBuf> {
+        loop {
+            let file = self.list.as_mut()?.next()?;
+            if file.is_dir() {
+                if self.scanner.is_verbose() {
+                    println!("skipping listed directory {}", file.to_str().unwrap_or(PATHSTR_FB));
+                }
+            }
+            else if !file.exists() {
+                self.queue.push_back(Err(ScanError::NotFound(file)));
+                return None;
+            }
+            else {
+                return Some(file);
+            }
         }
-        match d {
-            PathBox::File(f) => {
-                match ImgInfo::new(f) {
-                    Ok(i) => {
-                        if self.ignore_unknown_types {
-                            match i.file_type() {
-                                FileType::Other => {},
-                                _ => { controller.process(i); },
-                            }
-                        }
-                        else {
-                            controller.process(i);
-                        }
-                    },
-                    Err(e) => println!("Error processing file: {}", e)
+    }
+}
+
+impl Iterator for ScanIter<'_> {
+    type Item = Result<ImgInfo, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(result) = self.queue.pop_front() {
+                return Some(self.scanner.track(result));
+            }
+            if let Some(path) = self.pending.take() {
+                if let Some(result) = self.visit(path) {
+                    self.queue.push_back(result);
                 }
-            },
-            PathBox::Directory(d) => {
-                if self.depth < self.max_depth {
-                    self.depth += 1;
-                    for child in d.read_dir().expect("Error reading path a directory!") {
-                        let child_path = child.expect("Error reading child!").path();
-                        self.scan_path_ch(PathBox::from(child_path), controller);
+                continue;
+            }
+            if let Some((children, dir, _)) = self.dirs.last_mut() {
+                match children.next() {
+                    Some(Ok(c)) => self.pending = Some(c),
+                    Some(Err(e)) => self.queue.push_back(Err(ScanError::io("reading directory entry", dir.clone(), e))),
+                    None => {
+                        if let Some((_, _, true)) = self.dirs.pop() {
+                            self.scanner.ignore_files.pop();
+                        }
                     }
-                    self.depth -= 1;
                 }
+                continue;
             }
+            match self.next_listed() {
+                Some(file) => self.pending = Some(file),
+                None if self.queue.is_empty() => return None,
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod date_range {
+        use chrono::{Local, TimeZone};
+
+        use crate::index::DateRange;
+
+        #[test]
+        fn whole_periods() {
+            let range = DateRange::parse(Some("2023-01"), Some("2023-03")).unwrap();
+            assert!(range.contains(&Local.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap()));
+            assert!(range.contains(&Local.with_ymd_and_hms(2023, 3, 31, 23, 59, 59).unwrap()));
+            assert!(!range.contains(&Local.with_ymd_and_hms(2023, 4, 1, 0, 0, 0).unwrap()));
+            let range = DateRange::parse(None, Some("2023-12-31")).unwrap();
+            assert!(range.contains(&Local.with_ymd_and_hms(2023, 12, 31, 12, 0, 0).unwrap()));
+            assert!(DateRange::parse(Some("2023-02"), Some("2023-01")).is_err());
+            assert!(DateRange::parse(Some("2023-13"), None).is_err());
+        }
+    }
+
+    mod file_list {
+        use std::path::PathBuf;
+
+        use crate::index::read_file_list;
+
+        #[test]
+        fn skips_empty_lines() {
+            let list = read_file_list("a.jpg\r\n\nDCIM/b c.jpg\n".as_bytes()).unwrap();
+            assert_eq!(list, vec![PathBuf::from("a.jpg"), PathBuf::from("DCIM/b c.jpg")]);
+        }
+    }
+
+    mod scanner {
+        use std::path::{Path, PathBuf};
+
+        use crate::error::ScanError;
+        use crate::index::{ScanOrder, Scanner};
+        use crate::sorting::routing::PairFormat;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn iterates_file_list() {
+            let mut scanner = Scanner::from_list(vec![
+                PathBuf::from("src/lib.rs"),
+                PathBuf::from("src"),
+                PathBuf::from("nonexistent.jpg")
+            ]);
+            let results: Vec<_> = scanner.iter().collect();
+            assert_eq!(results.len(), 2);
+            assert!(results[0].is_ok());
+            assert!(matches!(&results[1], Err(ScanError::NotFound(_))));
+            assert_eq!(scanner.errors().len(), 1);
+        }
+
+        #[test]
+        fn builder_applies_options() {
+            let mut scanner = Scanner::list_builder(vec![PathBuf::from("src/lib.rs"), PathBuf::from("src/index.rs")])
+                .max_depth(2)
+                .exclude("index.rs")
+                .build()
+                .unwrap();
+            assert_eq!(scanner.get_max_depth(), 2);
+            assert_eq!(scanner.iter().count(), 1);
+            assert!(Scanner::builder(Path::new("src")).exclude("[").build().is_err());
+            assert!(Scanner::builder(Path::new("nonexistent")).build().is_err());
+        }
+
+        #[test]
+        fn detects_pairs_before_sorting_the_directory() {
+            let dir = TempDir::new("scan-pairs");
+            for name in ["IMG_0001.HEIC", "IMG_0001.JPG"] {
+                std::fs::write(dir.join(name), b"not an image").unwrap();
+            }
+            let mut scanner = Scanner::builder(&dir)
+                .order(ScanOrder::Name)
+                .pair_detection(Some(PairFormat::Heic))
+                .build()
+                .unwrap();
+            let mut iter = scanner.iter();
+            let primary = iter.next().unwrap().unwrap();
+            assert_eq!(primary.pair_primary(), None);
+            // the primary file is sorted before its secondary file is scanned
+            std::fs::remove_file(primary.path()).unwrap();
+            let secondary = iter.next().unwrap().unwrap();
+            assert_eq!(secondary.pair_primary(), Some(dir.join("IMG_0001.HEIC").as_path()));
+        }
+    }
+
+    mod glob {
+        use std::path::Path;
+
+        use crate::index::Glob;
+
+        #[test]
+        fn matches_names() {
+            let glob = Glob::new("*.jp[e]g").unwrap();
+            assert!(glob.matches(Path::new("DCIM/100APPLE/IMG_0001.JPEG")));
+            assert!(!glob.matches(Path::new("DCIM/IMG_0001.jpg.tmp")));
+            assert!(Glob::new("@eaDir").unwrap().matches(Path::new("photos/@eaDir")));
+        }
+
+        #[test]
+        fn matches_relative_paths() {
+            let glob = Glob::new("DCIM/**/.thumbnails").unwrap();
+            assert!(glob.matches(Path::new("DCIM/Camera/.thumbnails")));
+            assert!(!glob.matches(Path::new("Pictures/.thumbnails")));
+            assert!(!Glob::new("DCIM/*").unwrap().matches(Path::new("DCIM/Camera/a.jpg")));
+            assert!(Glob::new("**/a.jpg").unwrap().matches(Path::new("a.jpg")));
+            assert!(Glob::new("/DCIM/**/a.jpg").unwrap().matches(Path::new("DCIM/a.jpg")));
         }
     }
 }
@@ -0,0 +1,182 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::config::RootCfg;
+use crate::config::default_config;
+use crate::config::processor_config::ProcessorCfg;
+use crate::config::scanner_config::ScannerCfg;
+use crate::index::Scanner;
+use crate::observer::SortObserver;
+use crate::pipeline::Report;
+use crate::runner::Runner;
+use crate::sorting::{DuplicateResolution, Operation, PATHSTR_FB, SorterBuilder};
+
+/// Description of a complete sort for [run]: the input, the output directory, the operation and
+/// optionally a config file and the number of threads. Without a config, files are sorted like
+/// dcim-sort does without one (see [default_config::default_sorter]).
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use dcim_sort::SortJob;
+/// use dcim_sort::sorting::Operation;
+///
+/// let job = SortJob::new(Path::new("/media/card/DCIM"), Path::new("/srv/photos"), Operation::Copy)
+///     .config(Path::new("dcim-sort.toml"))
+///     .threads(4);
+/// let report = dcim_sort::run(job).unwrap();
+/// println!("{}", report);
+/// ```
+#[derive(Clone)]
+pub struct SortJob {
+    source: PathBuf,
+    target: PathBuf,
+    operation: Operation,
+    config: Option<PathBuf>,
+    dup_policy: Option<DuplicateResolution>,
+    threads: usize,
+    observer: Option<Arc<dyn SortObserver>>
+}
+
+impl SortJob {
+    /// sort the file or directory `source` into `target` with `operation`
+    pub fn new(source: &Path, target: &Path, operation: Operation) -> SortJob {
+        SortJob {
+            source: source.to_path_buf(),
+            target: target.to_path_buf(),
+            operation,
+            config: None,
+            dup_policy: None,
+            threads: 0,
+            observer: None
+        }
+    }
+
+    /// read the sorter, scanner and metadata processors from the config file at `path`, see
+    /// [RootCfg::read_config]
+    pub fn config(mut self, path: &Path) -> SortJob {
+        self.set_config(path);
+        self
+    }
+
+    pub fn set_config(&mut self, path: &Path) {
+        self.config = Some(path.to_path_buf());
+    }
+
+    /// handle existing targets with `policy` instead of the duplicate handling of the config
+    pub fn duplicate_handling(mut self, policy: DuplicateResolution) -> SortJob {
+        self.set_duplicate_handling(policy);
+        self
+    }
+
+    pub fn set_duplicate_handling(&mut self, policy: DuplicateResolution) {
+        self.dup_policy = Some(policy);
+    }
+
+    /// process files on `threads` pipeline threads, 0 (the default) processes them on the
+    /// calling thread
+    pub fn threads(mut self, threads: usize) -> SortJob {
+        self.set_threads(threads);
+        self
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads;
+    }
+
+    /// pass the progress of every file and the final report to `observer`
+    pub fn observer(mut self, observer: Arc<dyn SortObserver>) -> SortJob {
+        self.set_observer(observer);
+        self
+    }
+
+    pub fn set_observer(&mut self, observer: Arc<dyn SortObserver>) {
+        self.observer = Some(observer);
+    }
+}
+
+/// run `job` to completion, returning the merged report of all pipelines. Fails before
+/// processing any file if the config or the input is invalid, errors of single files are
+/// recorded in the report.
+pub fn run(job: SortJob) -> Result<Report, String> {
+    let (sorter_builder, proc_builder, scanner_cfg, dup_policy) = match &job.config {
+        None => (
+            default_config::default_sorter().hash_algorithm(SorterBuilder::default_hash_algorithm()),
+            ProcessorCfg::default().generate_builder(),
+            ScannerCfg::default(),
+            SorterBuilder::default_duplicate_handling()
+        ),
+        Some(path) => {
+            let cfg = RootCfg::read_config(path.as_path())
+                .map_err(|e| format!("failed to read config \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), e))?;
+            let sorter_builder = cfg.generate_sorter_builder()
+                .map_err(|e| format!("failed to generate sorter from config: {}", e))?
+                .hash_algorithm(cfg.get_hash_algorithm());
+            let proc_builder = cfg.generate_processor_builder()
+                .map_err(|e| format!("failed to generate metadata processors from config: {}", e))?;
+            (sorter_builder, proc_builder, cfg.get_scanner_cfg().clone(), cfg.get_duplicate_handling())
+        }
+    };
+    let mut scanner_builder = Scanner::builder(job.source.as_path());
+    scanner_cfg.apply(&mut scanner_builder);
+    let (scanner, operation) = Runner::prepare_input(scanner_builder, &sorter_builder, job.operation)?;
+
+    let mut runner = Runner::new(
+        scanner,
+        proc_builder,
+        sorter_builder,
+        job.target.as_path(),
+        operation,
+        job.dup_policy.unwrap_or(dup_policy)
+    );
+    runner.set_threads(job.threads, 0);
+    if let Some(observer) = job.observer {
+        runner.set_observer(observer);
+    }
+    Ok(runner.run())
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod run {
+        use crate::config::default_config;
+        use crate::job::{run, SortJob};
+        use crate::sorting::Operation;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn sorts_with_defaults() {
+            let dir = TempDir::new("job");
+            std::fs::create_dir_all(dir.join("in")).unwrap();
+            std::fs::write(dir.join("in").join("clip.mp4"), b"clip").unwrap();
+
+            let report = run(SortJob::new(&dir.join("in"), &dir.join("out"), Operation::Copy).threads(2)).unwrap();
+            let sorted = dir.join("out").join("videos").join("clip.mp4").is_file();
+            let missing = run(SortJob::new(&dir.join("missing"), &dir.join("out"), Operation::Copy));
+            assert_eq!(report.count_success, 1);
+            assert!(sorted);
+            assert!(missing.is_err());
+        }
+
+        #[test]
+        fn skips_secondary_files_of_pairs() {
+            let dir = TempDir::new("job-pairs");
+            std::fs::create_dir_all(dir.join("in")).unwrap();
+            for name in ["IMG_0001.HEIC", "IMG_0001.JPG"] {
+                std::fs::write(dir.join("in").join(name), b"not an image").unwrap();
+            }
+            let config = default_config::to_toml()
+                .replace("# [sorter.heicJpegPairs]\n# prefer = \"heic\"\n# secondary = \"skip\"", "[sorter.heicJpegPairs]\nprefer = \"heic\"\nsecondary = \"skip\"");
+            std::fs::write(dir.join("dcim-sort.toml"), config).unwrap();
+
+            let job = SortJob::new(&dir.join("in"), &dir.join("out"), Operation::Move)
+                .config(&dir.join("dcim-sort.toml"));
+            let report = run(job).unwrap();
+            assert_eq!(report.count_success, 1);
+            assert!(!dir.join("in").join("IMG_0001.HEIC").exists());
+            assert!(dir.join("in").join("IMG_0001.JPG").is_file());
+        }
+    }
+}
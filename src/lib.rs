@@ -1,7 +1,27 @@
+pub mod checkpoint;
+pub mod manifest;
+pub mod observer;
+pub mod error;
+pub mod hook;
+pub mod job;
 pub mod index;
 pub mod media;
 pub mod pattern;
 pub mod sorting;
 pub mod config;
 pub mod pipeline;
-mod logging;
\ No newline at end of file
+pub mod runner;
+#[cfg(feature = "notifications")]
+pub mod notification;
+#[cfg(feature = "backups")]
+pub mod backup;
+#[cfg(feature = "watch")]
+pub mod watch;
+#[cfg(feature = "catalog")]
+pub mod catalog;
+pub mod logging;
+pub mod stats;
+#[cfg(test)]
+mod test_util;
+
+pub use job::{run, SortJob};
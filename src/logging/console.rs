@@ -0,0 +1,171 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use serde::Serialize;
+
+use crate::pipeline::Report;
+
+/// Amount of output printed to stdout by all components, set for the whole process with
+/// [set_verbosity]. Warnings and errors are printed regardless of the verbosity.
+///
+/// # Variants
+/// - [Verbosity::Quiet] only warnings and errors
+/// - [Verbosity::Normal] additionally the progress and results, e.g. the targets of a simulation
+/// - [Verbosity::Verbose] additionally details for finding problems, e.g. every scanned path
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose
+    }
+}
+
+/// check if output of `level` is printed, e.g. `shows(Verbosity::Verbose)` for details
+pub fn shows(level: Verbosity) -> bool {
+    verbosity() >= level
+}
+
+/// Format of the output on stdout, set for the whole process with [set_output_format].
+///
+/// # Variants
+/// - [OutputFormat::Text] human readable text as configured by the [Verbosity]
+/// - [OutputFormat::Json] only [Event]s, one JSON object per line. Human readable text except
+///   for errors on stderr is suppressed.
+/// - [OutputFormat::Script] only the script printed by a
+///   [crate::sorting::simulation::SimulationPrinter], warnings are printed as comments
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Script
+}
+
+impl OutputFormat {
+    pub fn parse(s: &str) -> Option<OutputFormat> {
+        match s.to_lowercase().as_str() {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            _ => None
+        }
+    }
+}
+
+static OUTPUT_FORMAT: AtomicU8 = AtomicU8::new(OutputFormat::Text as u8);
+
+/// set the format of the output, [OutputFormat::Json] and [OutputFormat::Script] also set
+/// [Verbosity::Quiet]
+pub fn set_output_format(format: OutputFormat) {
+    OUTPUT_FORMAT.store(format as u8, Ordering::Relaxed);
+    if format != OutputFormat::Text {
+        set_verbosity(Verbosity::Quiet);
+    }
+}
+
+pub fn output_format() -> OutputFormat {
+    match OUTPUT_FORMAT.load(Ordering::Relaxed) {
+        0 => OutputFormat::Text,
+        1 => OutputFormat::Json,
+        _ => OutputFormat::Script
+    }
+}
+
+/// A machine-readable progress event printed by [emit] for [OutputFormat::Json], tagged with its
+/// name in the `event` field.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum Event<'a> {
+    /// a sort started
+    #[serde(rename_all = "camelCase")]
+    Start {
+        operation: &'a str,
+        input: &'a Path,
+        output: &'a Path
+    },
+    /// a file was moved, copied, skipped or failed. The target is set for moved and copied files.
+    #[serde(rename_all = "camelCase")]
+    File {
+        source: &'a Path,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<&'a Path>,
+        result: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>
+    },
+    /// the outcome of a simulated file, see [crate::sorting::simulation::SimOutcome]
+    #[serde(rename_all = "camelCase")]
+    Simulated {
+        source: &'a Path,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<&'a Path>,
+        outcome: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reason: Option<&'a str>
+    },
+    /// a warning otherwise printed with the prefix `[WARN]`
+    Warning {
+        message: &'a str
+    },
+    /// the final report of a sort
+    #[serde(rename_all = "camelCase")]
+    Report {
+        report: &'a Report,
+        elapsed_ms: u128
+    }
+}
+
+/// print `event` as a single line of JSON if the output format is [OutputFormat::Json]
+pub fn emit(event: &Event) {
+    if output_format() != OutputFormat::Json {
+        return;
+    }
+    match serde_json::to_string(event) {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("[ERROR] failed to serialize event: {}", e)
+    }
+}
+
+/// print a warning as `[WARN] <message>`, as [Event::Warning] for [OutputFormat::Json] or as a
+/// comment for [OutputFormat::Script]
+pub fn warn(message: &str) {
+    match output_format() {
+        OutputFormat::Text => println!("[WARN] {}", message),
+        OutputFormat::Json => emit(&Event::Warning { message }),
+        OutputFormat::Script => println!("# [WARN] {}", message.replace(['\n', '\r'], " "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod event {
+        use std::path::Path;
+
+        use crate::logging::console::Event;
+
+        #[test]
+        fn serializes_tagged_lines() {
+            let event = Event::File {
+                source: Path::new("/in/a.jpg"),
+                target: Some(Path::new("/out/2021/a.jpg")),
+                result: "copied",
+                error: None
+            };
+            assert_eq!(serde_json::to_string(&event).unwrap(), r#"{"event":"file","source":"/in/a.jpg","target":"/out/2021/a.jpg","result":"copied"}"#);
+            let warning = Event::Warning { message: "x" };
+            assert_eq!(serde_json::to_string(&warning).unwrap(), r#"{"event":"warning","message":"x"}"#);
+        }
+    }
+}
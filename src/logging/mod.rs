@@ -1,15 +1,125 @@
 use std::{fs, io};
 use std::fs::{File, OpenOptions};
 use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::sync::mpsc::Sender;
+use std::thread;
+use std::thread::JoinHandle;
 use std::time::Duration;
 
 use chrono;
 use chrono::{Datelike, SecondsFormat};
+use serde::Serialize;
 
+use crate::config::logger_config::LoggerCfg;
+use crate::logging::console::Verbosity;
 use crate::pipeline::ControlMsg;
+use crate::sorting::{PATHSTR_FB, simulation};
+
+pub mod console;
+#[cfg(feature = "syslog")]
+pub mod syslog;
+
+/// Severity of a [LogMsg], ordered from the most to the least severe. A [Logger] writes all
+/// messages up to its configured level.
+///
+/// # Variants
+/// - [LogLevel::Error] a file could not be processed
+/// - [LogLevel::Warn] a file has been processed differently than requested or vanished
+/// - [LogLevel::Info] the outcome of processing a file, e.g. why it has been skipped
+/// - [LogLevel::Debug] details for finding problems
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug
+}
+
+impl LogLevel {
+    pub fn parse(s: &str) -> Option<LogLevel> {
+        match s.to_lowercase().as_str() {
+            "error" => Some(LogLevel::Error),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug"
+        }
+    }
+}
+
+/// Layout of the lines written by a [Logger].
+///
+/// # Variants
+/// - [LogFormat::Text] timestamp, level and sender followed by the message
+/// - [LogFormat::Compact] level and message only
+/// - [LogFormat::Json] one JSON object per line (JSONL) with the keys `timestamp`, `level`,
+///   `sender`, `message`, `source`, `target`, `action`, `result`, `durationMs` and `bytes`, the
+///   latter six are `null` if they do not apply to the message
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Compact,
+    Json
+}
+
+impl LogFormat {
+    pub fn parse(s: &str) -> Option<LogFormat> {
+        match s.to_lowercase().as_str() {
+            "text" => Some(LogFormat::Text),
+            "compact" => Some(LogFormat::Compact),
+            "json" | "jsonl" => Some(LogFormat::Json),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::Compact => "compact",
+            LogFormat::Json => "json"
+        }
+    }
+}
+
+/// Destination of the messages of a [Logger].
+///
+/// # Variants
+/// - [LogBackend::File] a log file in the output or configured directory
+/// - [LogBackend::Syslog] the local syslog daemon or journald, e.g. when running as a service.
+///   Requires the `syslog` feature
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LogBackend {
+    File,
+    Syslog
+}
+
+impl LogBackend {
+    pub fn parse(s: &str) -> Option<LogBackend> {
+        match s.to_lowercase().as_str() {
+            "file" => Some(LogBackend::File),
+            "syslog" | "journald" => Some(LogBackend::Syslog),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogBackend::File => "file",
+            LogBackend::Syslog => "syslog"
+        }
+    }
+}
 
 pub enum LogReq {
     Msg(LogMsg),
@@ -17,56 +127,243 @@ pub enum LogReq {
 }
 pub struct LogMsg {
     sender: String,
-    msg: String
+    level: LogLevel,
+    /// set for messages about skipped files, which are only written if enabled
+    skipped: bool,
+    msg: String,
+    /// the file the message is about
+    source: Option<PathBuf>,
+    /// the target of the file
+    target: Option<PathBuf>,
+    /// the operation applied to the file, e.g. `copy`
+    action: Option<&'static str>,
+    /// the outcome of the operation, e.g. `skipped`
+    result: Option<&'static str>,
+    /// the time the operation took
+    duration: Option<Duration>,
+    /// the number of bytes transferred
+    bytes: Option<u64>
+}
+
+/// a [LogMsg] written by [LogFormat::Json]
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    timestamp: String,
+    level: &'static str,
+    sender: &'a str,
+    message: &'a str,
+    source: Option<&'a str>,
+    target: Option<&'a str>,
+    action: Option<&'static str>,
+    result: Option<&'static str>,
+    #[serde(rename = "durationMs")]
+    duration_ms: Option<u128>,
+    bytes: Option<u64>
 }
 
 impl LogMsg {
     pub fn new(sender_id: String, msg: String) -> LogMsg {
+        Self::with_level(sender_id, LogLevel::Info, msg)
+    }
+
+    pub fn with_level(sender_id: String, level: LogLevel, msg: String) -> LogMsg {
         LogMsg {
             sender: sender_id,
-            msg
+            level,
+            skipped: false,
+            msg,
+            source: None,
+            target: None,
+            action: None,
+            result: None,
+            duration: None,
+            bytes: None
+        }
+    }
+
+    /// the file the message is about
+    pub fn source(mut self, path: &Path) -> LogMsg {
+        self.source = Some(path.to_path_buf());
+        self
+    }
+
+    /// the target the file is sorted to
+    pub fn target(mut self, path: &Path) -> LogMsg {
+        self.target = Some(path.to_path_buf());
+        self
+    }
+
+    /// the operation applied to the file, e.g. [crate::sorting::Operation::to_str]
+    pub fn action(mut self, action: &'static str) -> LogMsg {
+        self.action = Some(action);
+        self
+    }
+
+    /// the outcome of the operation, e.g. `copied` or `error`
+    pub fn result(mut self, result: &'static str) -> LogMsg {
+        self.result = Some(result);
+        self
+    }
+
+    /// the time the operation took
+    pub fn duration(mut self, duration: Duration) -> LogMsg {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// the number of bytes transferred by the operation
+    pub fn bytes(mut self, bytes: u64) -> LogMsg {
+        self.bytes = Some(bytes);
+        self
+    }
+
+    /// a message about a skipped file, see [LoggerCfg::log_skipped]
+    pub fn skipped(sender_id: String, msg: String) -> LogMsg {
+        LogMsg {
+            skipped: true,
+            ..Self::new(sender_id, msg)
+        }
+    }
+
+    /// emit this message as `tracing` event in the current span, with the file, target and
+    /// outcome as fields
+    #[cfg(feature = "tracing")]
+    pub(crate) fn trace(&self) {
+        let source = self.source.as_ref().and_then(|p| p.to_str());
+        let target = self.target.as_ref().and_then(|p| p.to_str());
+        let duration_ms = self.duration.map(|d| d.as_millis() as u64);
+        // the level of an event must be known at compile time
+        macro_rules! event {
+            ($level:expr) => {
+                tracing::event!($level,
+                    sender = self.sender.as_str(),
+                    source,
+                    target,
+                    action = self.action,
+                    result = self.result,
+                    duration_ms,
+                    bytes = self.bytes,
+                    skipped = self.skipped,
+                    "{}", self.msg)
+            };
+        }
+        match self.level {
+            LogLevel::Error => event!(tracing::Level::ERROR),
+            LogLevel::Warn => event!(tracing::Level::WARN),
+            LogLevel::Info => event!(tracing::Level::INFO),
+            LogLevel::Debug => event!(tracing::Level::DEBUG)
         }
     }
 }
 
 pub struct Logger {
     outfile: PathBuf,
-    file_handle: Option<File>,
-    print_sender: bool
+    format: LogFormat,
+    level: LogLevel,
+    log_skipped: bool,
+    /// the file all warnings and errors are written to in addition to `outfile`
+    error_file: Option<PathBuf>,
+    /// replaces the log files if set
+    #[cfg(feature = "syslog")]
+    syslog: Option<syslog::SyslogWriter>
 }
 impl Logger {
-    pub fn new(outdir: &PathBuf, filename: Option<String>) -> io::Result<Logger> {
-        let fname = match filename {
+    /// create a logger writing to the file configured in `cfg`, which is placed in `outdir`
+    /// unless `cfg` has a directory of its own. In a dry run (see [simulation::set_dry_run]) no
+    /// log file is written, only syslog can be used.
+    pub fn new(outdir: &Path, cfg: &LoggerCfg) -> io::Result<Logger> {
+        if let LogBackend::Syslog = cfg.backend() {
+            return Self::new_syslog(cfg);
+        }
+        let fname = match cfg.filename() {
             None => Self::generate_filename(),
             Some(s) => {
                 if s.is_empty() {
                     Self::generate_filename()
                 }
-                else if !s.ends_with(".log") {
-                    format!("{}.log", s)
-                }
                 else {
-                    s
+                    Self::with_extension(s)
                 }
             }
         };
+        let outdir = match cfg.dir() {
+            Some(dir) => dir.to_path_buf(),
+            None => outdir.to_path_buf()
+        };
         if outdir.is_file() {
             return Err(io::Error::new(io::ErrorKind::InvalidInput, "outdir is a file"));
         }
+        if simulation::is_dry_run() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "a dry run writes no log file"));
+        }
         if !outdir.exists() {
-            fs::create_dir_all(outdir)?;
+            fs::create_dir_all(&outdir)?;
         }
-        let mut outfile = outdir.clone();
+        let error_file = cfg.error_filename().map(|f| outdir.join(Self::with_extension(f)));
+        let mut outfile = outdir;
         outfile.push(fname);
-        println!("writing logfile to: {}", outfile.to_str().unwrap_or("<INVALID UTF-8>"));
+        if console::shows(Verbosity::Normal) {
+            println!("writing logfile to: {}", outfile.to_str().unwrap_or("<INVALID UTF-8>"));
+            if let Some(f) = &error_file {
+                println!("writing warnings and errors to: {}", f.to_str().unwrap_or("<INVALID UTF-8>"));
+            }
+        }
 
         Ok(Logger {
             outfile: outfile,
-            file_handle: None,
-            print_sender: true
+            format: cfg.format(),
+            level: cfg.level(),
+            log_skipped: cfg.log_skipped(),
+            error_file,
+            #[cfg(feature = "syslog")]
+            syslog: None
+        })
+    }
+
+    /// create a logger sending to the local syslog daemon
+    #[cfg(feature = "syslog")]
+    fn new_syslog(cfg: &LoggerCfg) -> io::Result<Logger> {
+        let writer = syslog::SyslogWriter::connect("dcim-sort")?;
+        if console::shows(Verbosity::Normal) {
+            println!("writing log to syslog");
+        }
+        Ok(Logger {
+            outfile: PathBuf::new(),
+            format: cfg.format(),
+            level: cfg.level(),
+            log_skipped: cfg.log_skipped(),
+            error_file: None,
+            syslog: Some(writer)
         })
     }
 
+    #[cfg(not(feature = "syslog"))]
+    fn new_syslog(_cfg: &LoggerCfg) -> io::Result<Logger> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "dcim-sort was built without the \"syslog\" feature"))
+    }
+
+    /// run the logger on a new thread, see [LogHandle]
+    pub fn spawn(mut self) -> io::Result<LogHandle> {
+        let (tx, rx) = mpsc::channel::<LogReq>();
+        let handle = thread::Builder::new()
+            .name(String::from("logger01"))
+            .spawn(move || self.run(rx))?;
+        Ok(LogHandle { tx, handle })
+    }
+
+    /// check if `msg` is written according to the configured level and filters
+    fn accepts(&self, msg: &LogMsg) -> bool {
+        msg.level <= self.level && (self.log_skipped || !msg.skipped)
+    }
+
+    /// `fname` ending with `.log`
+    fn with_extension(fname: &str) -> String {
+        match fname.ends_with(".log") {
+            true => String::from(fname),
+            false => format!("{}.log", fname)
+        }
+    }
+
     fn generate_filename() -> String {
         let now = chrono::Local::now();
         format!("dcim-sort_{}-{}-{}.log", now.year(), now.month(), now.day())
@@ -74,37 +371,24 @@ impl Logger {
 
     pub fn run(&mut self, rx_input: mpsc::Receiver<LogReq>) {
         // failing to open the file for writing should not crash the program
-        let mut buff = match OpenOptions::new().create(true).append(true).open(&self.outfile) {
-            Ok(file) => {
-                Some(BufWriter::new(file))
-            }
-            Err(_) => {
-                eprintln!("[WARN] failed to open log file: {}",
-                          &self.outfile.to_str().unwrap_or("<INVALID UTF-8>")
-                );
-                None
-            }
+        let mut buff = match self.writes_syslog() {
+            true => None,
+            false => self.open(self.outfile.as_path())
+        };
+        let mut errors = match &self.error_file {
+            Some(f) => self.open(f.as_path()),
+            None => None
         };
-
-        if let Some(b) = &mut buff {
-            write!(b, "==============[ start log ]==============\n[{}] log started\n",
-                   chrono::Local::now().to_rfc3339_opts(SecondsFormat::Millis, false)
-            );
-        }
         let mut callback: Option<Sender<ControlMsg>> = None;
         let mut shutdown = false;
         let mut has_data = false;
         loop {
             while let Ok(request) = rx_input.recv_timeout(Duration::from_millis(500)) {
                 match request {
-                    LogReq::Msg(msg) => match &mut buff {
-                        Some(b) => {
-                            self.write_msg(b, msg);
-                            has_data = true;
-                        },
-                        None => {
-                            self.print_msg(msg);
-                        }
+                    LogReq::Msg(msg) if !self.accepts(&msg) => (),
+                    LogReq::Msg(msg) => {
+                        self.handle_msg(&mut buff, &mut errors, msg);
+                        has_data = true;
                     },
                     LogReq::Cmd(msg) => match msg {
                         ControlMsg::Shutdown(cb) => {
@@ -116,10 +400,10 @@ impl Logger {
                 };
             }
             if has_data {
-                if let Some(b) = &mut buff {
+                for b in buff.iter_mut().chain(errors.iter_mut()) {
                     b.flush();
-                    has_data = false;
                 }
+                has_data = false;
             }
             if shutdown {
                 break;
@@ -128,12 +412,8 @@ impl Logger {
 
         while let Ok(request) = rx_input.try_recv() {
             match request {
-                LogReq::Msg(msg) => match &mut buff {
-                    Some(b) => {
-                        self.write_msg(b, msg);
-                    },
-                    None => self.print_msg(msg)
-                },
+                LogReq::Msg(msg) if !self.accepts(&msg) => (),
+                LogReq::Msg(msg) => self.handle_msg(&mut buff, &mut errors, msg),
                 _ => ()
             };
         }
@@ -142,16 +422,105 @@ impl Logger {
             cb.send(ControlMsg::Ack);
         }
 
-        if let Some(mut b) = buff {
-            write!(b, "[{}] closing log\n", chrono::Local::now().to_rfc3339_opts(SecondsFormat::Millis, false));
+        for b in buff.iter_mut().chain(errors.iter_mut()) {
+            match self.format {
+                LogFormat::Json => self.write_msg(b, &Self::own_msg("closing log")),
+                _ => {
+                    let _ = writeln!(b, "[{}] closing log", chrono::Local::now().to_rfc3339_opts(SecondsFormat::Millis, false));
+                }
+            }
             b.flush();
         }
     }
 
-    fn write_msg(&self, buf: &mut BufWriter<File>, msg: LogMsg) {
-        match match self.print_sender {
-            true  => write!(buf, "[{}] {}\n", msg.sender, msg.msg),
-            false => write!(buf, "{}\n", msg.msg)
+    /// open the log file at `path` for appending and mark the start of the log, `None` if it
+    /// cannot be opened
+    fn open(&self, path: &Path) -> Option<BufWriter<File>> {
+        let mut b = match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => BufWriter::new(file),
+            Err(_) => {
+                eprintln!("[WARN] failed to open log file: {}", path.to_str().unwrap_or("<INVALID UTF-8>"));
+                return None;
+            }
+        };
+        match self.format {
+            LogFormat::Json => self.write_msg(&mut b, &Self::own_msg("log started")),
+            _ => {
+                let _ = write!(b, "==============[ start log ]==============\n[{}] log started\n",
+                               chrono::Local::now().to_rfc3339_opts(SecondsFormat::Millis, false)
+                );
+            }
+        }
+        Some(b)
+    }
+
+    /// write an accepted message to the log, or print it if the log could not be opened. Warnings
+    /// and errors are also written to the error log.
+    fn handle_msg(&self, buff: &mut Option<BufWriter<File>>, errors: &mut Option<BufWriter<File>>, msg: LogMsg) {
+        #[cfg(feature = "syslog")]
+        if let Some(s) = &self.syslog {
+            let line = match self.format {
+                LogFormat::Json => Self::json_line(&msg),
+                LogFormat::Text => format!("[{}] {}", msg.sender, msg.msg),
+                LogFormat::Compact => msg.msg
+            };
+            if let Err(e) = s.send(msg.level, line.as_str()) {
+                eprintln!("WARN: failed to send log message to syslog: {}", e);
+            }
+            return;
+        }
+        if let Some(e) = errors {
+            if msg.level <= LogLevel::Warn {
+                self.write_msg(e, &msg);
+            }
+        }
+        match buff {
+            Some(b) => self.write_msg(b, &msg),
+            None => self.print_msg(msg)
+        }
+    }
+
+    #[cfg(feature = "syslog")]
+    fn writes_syslog(&self) -> bool {
+        self.syslog.is_some()
+    }
+
+    #[cfg(not(feature = "syslog"))]
+    fn writes_syslog(&self) -> bool {
+        false
+    }
+
+    /// a message of the logger itself
+    fn own_msg(msg: &str) -> LogMsg {
+        LogMsg::new(String::from(thread::current().name().unwrap_or("logger")), String::from(msg))
+    }
+
+    /// `msg` as a line of JSON, see [LogFormat::Json]
+    fn json_line(msg: &LogMsg) -> String {
+        let entry = JsonEntry {
+            timestamp: chrono::Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+            level: msg.level.name(),
+            sender: msg.sender.as_str(),
+            message: msg.msg.as_str(),
+            source: msg.source.as_ref().map(|p| p.to_str().unwrap_or(PATHSTR_FB)),
+            target: msg.target.as_ref().map(|p| p.to_str().unwrap_or(PATHSTR_FB)),
+            action: msg.action,
+            result: msg.result,
+            duration_ms: msg.duration.map(|d| d.as_millis()),
+            bytes: msg.bytes
+        };
+        // serializing strings and options cannot fail
+        serde_json::to_string(&entry).unwrap_or_default()
+    }
+
+    fn write_msg(&self, buf: &mut BufWriter<File>, msg: &LogMsg) {
+        let level = msg.level.name().to_uppercase();
+        match match self.format {
+            LogFormat::Text => writeln!(buf, "[{}] [{}] [{}] {}",
+                                        chrono::Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+                                        level, msg.sender, msg.msg),
+            LogFormat::Compact => writeln!(buf, "[{}] {}", level, msg.msg),
+            LogFormat::Json => writeln!(buf, "{}", Self::json_line(msg))
         } {
             Ok(_) => (),
             Err(err) => eprintln!("WARN: failed to write log message: {}", err)
@@ -159,11 +528,91 @@ impl Logger {
     }
 
     fn print_msg(&self, msg: LogMsg) {
-        if self.print_sender {
-            println!("[INFO][{}] {}", msg.sender, msg.msg);
+        let level = msg.level.name().to_uppercase();
+        match self.format {
+            LogFormat::Text => println!("[{}][{}] {}", level, msg.sender, msg.msg),
+            LogFormat::Compact => println!("[{}] {}", level, msg.msg),
+            LogFormat::Json => println!("{}", Self::json_line(&msg))
+        }
+    }
+}
+
+/// A [Logger] running on its own thread, see [Logger::spawn]. Messages are sent through the
+/// channels returned by [LogHandle::sender], e.g. to [crate::pipeline::Pipeline::set_log].
+pub struct LogHandle {
+    tx: Sender<LogReq>,
+    handle: JoinHandle<()>
+}
+
+impl LogHandle {
+    pub fn sender(&self) -> Sender<LogReq> {
+        self.tx.clone()
+    }
+
+    /// write all pending messages, close the log and wait for the logger thread to exit
+    pub fn shutdown(self) {
+        let (tx_cb, rx_cb) = mpsc::channel();
+        if self.tx.send(LogReq::Cmd(ControlMsg::Shutdown(tx_cb))).is_ok() {
+            let _ = rx_cb.recv();
+        }
+        if self.handle.join().is_err() {
+            eprintln!("[WARN] logger thread panicked");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod logger {
+        use std::path::Path;
+        use std::time::Duration;
+
+        use crate::config::logger_config::LoggerCfg;
+        use crate::logging::{LogLevel, LogMsg, LogReq, Logger};
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn writes_json_entries() {
+            let msg = LogMsg::with_level(String::from("pipeline01"), LogLevel::Warn, String::from("skipped \"a\""))
+                .source(Path::new("/in/a.jpg"))
+                .action("copy")
+                .result("skipped");
+            let entry: serde_json::Value = serde_json::from_str(Logger::json_line(&msg).as_str()).unwrap();
+            assert_eq!(entry["level"], "warn");
+            assert_eq!(entry["sender"], "pipeline01");
+            assert_eq!(entry["message"], "skipped \"a\"");
+            assert_eq!(entry["source"], "/in/a.jpg");
+            assert!(entry["target"].is_null());
+            assert_eq!(entry["action"], "copy");
+            assert_eq!(entry["result"], "skipped");
+            assert!(entry["durationMs"].is_null());
+
+            let copied = LogMsg::new(String::from("pipeline01"), String::from("copied"))
+                .duration(Duration::from_millis(1500))
+                .bytes(2048);
+            let entry: serde_json::Value = serde_json::from_str(Logger::json_line(&copied).as_str()).unwrap();
+            assert_eq!(entry["durationMs"], 1500);
+            assert_eq!(entry["bytes"], 2048);
+            assert!(entry["timestamp"].is_string());
         }
-        else {
-            println!("[INFO] {}", msg.msg);
+
+        #[test]
+        fn writes_problems_to_error_log() {
+            let dir = TempDir::new("error-log");
+            let el = "<logging xmlns=\"\"><filename>run</filename><format>compact</format><errorFilename>problems</errorFilename></logging>"
+                .parse().unwrap();
+            let log = Logger::new(&dir, &LoggerCfg::from(&el).unwrap()).unwrap().spawn().unwrap();
+            for (level, msg) in [(LogLevel::Info, "copied"), (LogLevel::Warn, "vanished"), (LogLevel::Error, "failed")] {
+                log.sender().send(LogReq::Msg(LogMsg::with_level(String::from("pipeline01"), level, String::from(msg)))).unwrap();
+            }
+            log.shutdown();
+            let full = std::fs::read_to_string(dir.join("run.log")).unwrap();
+            let problems = std::fs::read_to_string(dir.join("problems.log")).unwrap();
+
+            assert!(full.contains("[INFO] copied") && full.contains("[WARN] vanished") && full.contains("[ERROR] failed"));
+            assert!(!problems.contains("copied"));
+            assert!(problems.contains("[WARN] vanished") && problems.contains("[ERROR] failed"));
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,70 @@
+use std::io;
+use std::os::unix::net::UnixDatagram;
+
+use crate::logging::LogLevel;
+
+/// sockets of the local syslog daemon, `/dev/log` is also provided by journald
+const SOCKETS: [&str; 3] = ["/dev/log", "/var/run/syslog", "/var/run/log"];
+
+/// facility `user` of RFC 3164
+const FACILITY_USER: u8 = 1;
+
+/// Sends log messages to the local syslog daemon or journald as RFC 3164 datagrams.
+pub struct SyslogWriter {
+    socket: UnixDatagram,
+    ident: String,
+    pid: u32
+}
+
+impl SyslogWriter {
+    /// connect to the first available syslog socket, messages are tagged with `ident`
+    pub fn connect(ident: &str) -> io::Result<SyslogWriter> {
+        let socket = UnixDatagram::unbound()?;
+        let mut last_err = io::Error::new(io::ErrorKind::NotFound, "no syslog socket found");
+        for path in SOCKETS.iter() {
+            match socket.connect(path) {
+                Ok(_) => return Ok(SyslogWriter {
+                    socket,
+                    ident: String::from(ident),
+                    pid: std::process::id()
+                }),
+                Err(e) => last_err = e
+            }
+        }
+        Err(last_err)
+    }
+
+    pub fn send(&self, level: LogLevel, msg: &str) -> io::Result<()> {
+        self.socket.send(packet(level, self.ident.as_str(), self.pid, msg).as_bytes()).map(|_| ())
+    }
+}
+
+/// the syslog severity of `level`
+fn severity(level: LogLevel) -> u8 {
+    match level {
+        LogLevel::Error => 3,
+        LogLevel::Warn => 4,
+        LogLevel::Info => 6,
+        LogLevel::Debug => 7
+    }
+}
+
+/// a message without timestamp and hostname, which are added by the daemon
+fn packet(level: LogLevel, ident: &str, pid: u32, msg: &str) -> String {
+    format!("<{}>{}[{}]: {}", FACILITY_USER * 8 + severity(level), ident, pid, msg)
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod syslog {
+        use crate::logging::LogLevel;
+        use crate::logging::syslog::packet;
+
+        #[test]
+        fn prefixes_priority_and_ident() {
+            assert_eq!(packet(LogLevel::Warn, "dcim-sort", 42, "source vanished"), "<12>dcim-sort[42]: source vanished");
+            assert_eq!(packet(LogLevel::Debug, "dcim-sort", 42, "x"), "<15>dcim-sort[42]: x");
+        }
+    }
+}
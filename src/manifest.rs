@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use sha2::Sha256;
+
+use crate::checkpoint::FLUSH_INTERVAL;
+use crate::sorting::PATHSTR_FB;
+use crate::sorting::comparison::FileComparer;
+
+/// Records the SHA-256 hash of every sorted file with its absolute target path, one line
+/// `<hash>  <path>` per file in the format of `sha256sum`, so the archive can be checked for
+/// missing or corrupted files later (see [verify]). The target is hashed after it has been
+/// written, so the manifest describes the content actually stored in the archive.
+///
+/// Entries are buffered like those of a [crate::checkpoint::Checkpoint] and handles are cheap to
+/// clone, so all pipeline threads can record to one manifest.
+#[derive(Clone)]
+pub struct Manifest {
+    inner: Arc<Mutex<ManifestWriter>>
+}
+
+struct ManifestWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    pending: usize
+}
+
+impl Manifest {
+    /// open the manifest at `path`, appending to it if it already exists
+    pub fn open(path: &Path) -> Result<Manifest, String> {
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| format!("failed to open manifest \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), e))?;
+        Ok(Manifest {
+            inner: Arc::new(Mutex::new(ManifestWriter {
+                path: path.to_path_buf(),
+                writer: BufWriter::new(file),
+                pending: 0
+            }))
+        })
+    }
+
+    /// hash `target` and record it. Targets which can not be hashed or whose path is not valid
+    /// UTF-8 or contains a line break are reported and not recorded.
+    pub fn record(&self, target: &Path) {
+        let target = std::fs::canonicalize(target).unwrap_or_else(|_| target.to_path_buf());
+        let line = match target.to_str() {
+            Some(s) if !s.contains('\n') => s,
+            _ => {
+                eprintln!("[ERROR] path can not be recorded in the manifest: \"{}\"", target.to_str().unwrap_or(PATHSTR_FB));
+                return;
+            }
+        };
+        let hash = match FileComparer::hash::<Sha256>(target.as_path()) {
+            Ok(h) => h.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+            Err(_) => {
+                eprintln!("[ERROR] could not hash \"{}\" for the manifest", line);
+                return;
+            }
+        };
+        let mut w = match self.inner.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        if let Err(e) = writeln!(w.writer, "{}  {}", hash, line) {
+            eprintln!("[ERROR] failed to write manifest \"{}\": {}", w.path.to_str().unwrap_or(PATHSTR_FB), e);
+            return;
+        }
+        w.pending += 1;
+        if w.pending >= FLUSH_INTERVAL {
+            w.flush();
+        }
+    }
+}
+
+impl ManifestWriter {
+    fn flush(&mut self) {
+        if let Err(e) = self.writer.flush().and_then(|_| self.writer.get_ref().sync_data()) {
+            eprintln!("[ERROR] failed to flush manifest \"{}\": {}", self.path.to_str().unwrap_or(PATHSTR_FB), e);
+        }
+        self.pending = 0;
+    }
+}
+
+impl Drop for ManifestWriter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// The state of a file recorded in a manifest, see [verify].
+///
+/// # Variants
+/// - [EntryStatus::Ok] the file exists with the recorded content
+/// - [EntryStatus::Missing] the file does not exist anymore
+/// - [EntryStatus::Corrupted] the content of the file differs from the recorded hash
+/// - [EntryStatus::Unreadable] the file exists, but could not be read
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntryStatus {
+    Ok,
+    Missing,
+    Corrupted,
+    Unreadable(String)
+}
+
+/// counts of the entries of a manifest by their [EntryStatus]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Verification {
+    pub count_ok: u64,
+    pub count_missing: u64,
+    pub count_corrupted: u64,
+    pub count_unreadable: u64
+}
+
+impl Verification {
+    /// check if all files exist with their recorded content
+    pub fn is_intact(&self) -> bool {
+        self.count_missing == 0 && self.count_corrupted == 0 && self.count_unreadable == 0
+    }
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "verified {} file(s): {} ok, {} missing, {} corrupted, {} unreadable",
+               self.count_ok + self.count_missing + self.count_corrupted + self.count_unreadable,
+               self.count_ok, self.count_missing, self.count_corrupted, self.count_unreadable)
+    }
+}
+
+/// re-hash all files recorded in the manifest at `path` and pass each of them with its status
+/// to `on_entry`. If a file was recorded more than once, e.g. because it was overwritten, only
+/// its last entry is checked.
+pub fn verify<F: FnMut(&Path, &EntryStatus)>(path: &Path, mut on_entry: F) -> Result<Verification, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("failed to open manifest \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), e))?;
+    let mut entries: Vec<(PathBuf, String)> = Vec::new();
+    let mut positions = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| format!("failed to read manifest \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), e))?;
+        if line.is_empty() {
+            continue;
+        }
+        let (hash, target) = match line.split_once("  ") {
+            Some((h, t)) if h.len() == 64 => (h.to_lowercase(), PathBuf::from(t)),
+            _ => return Err(format!("invalid manifest entry: \"{}\"", line))
+        };
+        match positions.get(&target) {
+            Some(&i) => entries[i] = (target, hash),
+            None => {
+                positions.insert(target.clone(), entries.len());
+                entries.push((target, hash));
+            }
+        }
+    }
+
+    let mut verification = Verification::default();
+    for (target, hash) in entries {
+        let status = match FileComparer::hash::<Sha256>(target.as_path()) {
+            Ok(h) if h.iter().map(|b| format!("{:02x}", b)).collect::<String>() == hash => EntryStatus::Ok,
+            Ok(_) => EntryStatus::Corrupted,
+            Err(_) if !target.exists() => EntryStatus::Missing,
+            Err(e) => EntryStatus::Unreadable(e.into_parts().1)
+        };
+        match &status {
+            EntryStatus::Ok => verification.count_ok += 1,
+            EntryStatus::Missing => verification.count_missing += 1,
+            EntryStatus::Corrupted => verification.count_corrupted += 1,
+            EntryStatus::Unreadable(_) => verification.count_unreadable += 1
+        }
+        on_entry(target.as_path(), &status);
+    }
+    Ok(verification)
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod manifest {
+        use crate::manifest::{EntryStatus, Manifest, verify};
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn detects_missing_and_corrupted_files() {
+            let dir = TempDir::new("manifest");
+            for name in ["a.jpg", "b.jpg", "c.jpg"] {
+                std::fs::write(dir.join(name), name.as_bytes()).unwrap();
+            }
+            let path = dir.join("manifest.sha256");
+            {
+                let manifest = Manifest::open(path.as_path()).unwrap();
+                for name in ["a.jpg", "b.jpg", "c.jpg"] {
+                    manifest.clone().record(dir.join(name).as_path());
+                }
+            }
+            std::fs::remove_file(dir.join("b.jpg")).unwrap();
+            std::fs::write(dir.join("c.jpg"), b"bit rot").unwrap();
+
+            let mut problems = Vec::new();
+            let verification = verify(path.as_path(), |p, status| if *status != EntryStatus::Ok {
+                problems.push((p.file_name().unwrap().to_os_string(), status.clone()));
+            }).unwrap();
+            assert_eq!(verification.count_ok, 1);
+            assert!(!verification.is_intact());
+            assert_eq!(problems, vec![("b.jpg".into(), EntryStatus::Missing), ("c.jpg".into(), EntryStatus::Corrupted)]);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+
+use chrono::{Duration, Local};
+
+use crate::media::{ImgInfo, ImgMeta};
+
+/// minimum count of files with both timestamps required to infer the drift of a device
+pub const DEFAULT_MIN_SAMPLES: usize = 3;
+/// maximum deviation of a single offset from the median to count as consistent
+pub const DEFAULT_TOLERANCE_SECS: i64 = 120;
+/// offsets below this are considered accurate clocks and not corrected
+pub const DEFAULT_MIN_OFFSET_SECS: i64 = 60;
+
+/// a device as identified by the make and model in its metadata
+pub type Device = (String, String);
+
+/// Collects the offsets between the camera clock (Exif timestamp) and the GPS timestamp of files
+/// to infer the clock drift of each device.
+pub struct DriftEstimator {
+    samples: BTreeMap<Device, Vec<i64>>
+}
+
+impl DriftEstimator {
+    pub fn new() -> DriftEstimator {
+        DriftEstimator {
+            samples: BTreeMap::new()
+        }
+    }
+
+    /// add the offset of a file if it has both a camera and a GPS timestamp
+    pub fn add(&mut self, meta: &ImgMeta) {
+        if let (Some(camera), Some(gps)) = (meta.created_at(), meta.gps_time()) {
+            let offset = camera.signed_duration_since(gps.with_timezone(&Local)).num_seconds();
+            self.samples.entry(device_of(meta)).or_default().push(offset);
+        }
+    }
+
+    /// infer the clock drift of every device with at least `min_samples` offsets. A drift is
+    /// only detected if at least 80% of the offsets are within `tolerance_secs` of their median
+    /// and the median is at least `min_offset_secs`.
+    pub fn estimate(&self, min_samples: usize, tolerance_secs: i64, min_offset_secs: i64) -> ClockDrift {
+        let mut offsets = BTreeMap::new();
+        for (device, samples) in &self.samples {
+            if samples.len() < min_samples.max(1) {
+                continue;
+            }
+            let mut sorted = samples.clone();
+            sorted.sort_unstable();
+            let median = sorted[sorted.len() / 2];
+            let consistent = sorted.iter().filter(|o| (**o - median).abs() <= tolerance_secs).count();
+            if consistent * 5 >= sorted.len() * 4 && median.abs() >= min_offset_secs {
+                offsets.insert(device.clone(), Duration::seconds(median));
+            }
+        }
+        ClockDrift { offsets }
+    }
+}
+
+impl Default for DriftEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The inferred clock drift per device, see [DriftEstimator].
+#[derive(Clone)]
+pub struct ClockDrift {
+    offsets: BTreeMap<Device, Duration>
+}
+
+impl ClockDrift {
+    /// get the drift of the device that created a file (camera clock minus true time)
+    pub fn offset(&self, meta: &ImgMeta) -> Option<&Duration> {
+        self.offsets.get(&device_of(meta))
+    }
+
+    pub fn offsets(&self) -> &BTreeMap<Device, Duration> {
+        &self.offsets
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// correct the camera timestamp of `file` by the drift of its device.
+    ///
+    /// Returns `true` if the timestamp has been corrected.
+    pub fn apply(&self, file: &mut ImgInfo) -> bool {
+        let corrected = match (file.metadata().created_at(), self.offset(file.metadata())) {
+            (Some(ts), Some(offset)) => *ts - *offset,
+            _ => return false
+        };
+        let mut meta = file.metadata().clone();
+        meta.set_created_at(Some(corrected));
+        file.set_metadata(meta);
+        true
+    }
+}
+
+fn device_of(meta: &ImgMeta) -> Device {
+    (String::from(meta.make().trim()), String::from(meta.model().trim()))
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod estimate {
+        use chrono::{Duration, Local, Utc};
+
+        use crate::media::clock_drift::DriftEstimator;
+        use crate::media::ImgMeta;
+
+        fn meta_with_offset(secs: i64) -> ImgMeta {
+            let gps = Utc::now();
+            let mut meta = ImgMeta::new();
+            meta.make = String::from("Canon");
+            meta.model = String::from("EOS 80D");
+            meta.gps_time = Some(gps);
+            meta.created_at = Some(gps.with_timezone(&Local) + Duration::seconds(secs));
+            meta
+        }
+
+        #[test]
+        fn detects_consistent_offset() {
+            let mut estimator = DriftEstimator::new();
+            for secs in &[3600, 3610, 3595, 3602] {
+                estimator.add(&meta_with_offset(*secs));
+            }
+            let drift = estimator.estimate(3, 120, 60);
+            let offset = drift.offset(&meta_with_offset(0)).map(|d| d.num_seconds());
+            assert_eq!(offset, Some(3602));
+        }
+
+        #[test]
+        fn ignores_inconsistent_offsets() {
+            let mut estimator = DriftEstimator::new();
+            for secs in &[3600, -7200, 60000, 0] {
+                estimator.add(&meta_with_offset(*secs));
+            }
+            assert!(estimator.estimate(3, 120, 60).is_empty());
+        }
+    }
+}
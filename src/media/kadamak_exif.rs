@@ -2,15 +2,27 @@ use std::fs;
 use std::io::BufReader;
 use std::path::Path;
 
-use chrono::{DateTime, Local, TimeZone};
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
 use exif::Value;
 
-use crate::media::{FileMetaProcessor, FileType, ImgMeta, MetaType, TagParseError};
+use crate::media::{FileMetaProcessor, FileType, ImgMeta, MetaType, parse_gps_time, TagParseError};
 
 pub struct KadamakExifProcessor {
 
 }
 
+/// The Exif fields identifying a shot independently of its encoding, e.g. to recognize a
+/// recompressed copy of a photo, see [KadamakExifProcessor::read_signature]
+#[derive(Debug, PartialEq)]
+pub struct ShotSignature {
+    /// DateTimeOriginal as written by the camera
+    pub taken_at: String,
+    pub make: String,
+    pub model: String,
+    pub width: u32,
+    pub height: u32
+}
+
 impl FileMetaProcessor for KadamakExifProcessor {
     fn supports(&self, mt: &MetaType, ft: &FileType) -> bool {
         match ft {
@@ -66,20 +78,52 @@ impl KadamakExifProcessor {
                 };
                 let software = Self::extract_as_string(&exif, exif::Tag::Software).unwrap_or(String::new());
                 let is_screenshot = user_comment == "Screenshot" || software.starts_with("Android ");
+                let gps_time = Self::extract_as_string(&exif, exif::Tag::GPSDateStamp)
+                    .and_then(|date| {
+                        let field = exif.get_field(exif::Tag::GPSTimeStamp, exif::In::PRIMARY)?;
+                        match &field.value {
+                            Value::Rational(v) if v.len() >= 3 => parse_gps_time(&date, (v[0].to_f64(), v[1].to_f64(), v[2].to_f64())),
+                            _ => None
+                        }
+                    });
 
                 Some(ImgMeta {
                     created_at: timestamp,
                     make,
                     model,
                     user_comment,
-                    is_screenshot
+                    is_screenshot,
+                    gps_time
                 })
             }
         }
     }
 
+    /// read the [ShotSignature] of a file, `None` if it has no Exif data or lacks any of its
+    /// fields
+    pub fn read_signature(path: &Path) -> Option<ShotSignature> {
+        let file = fs::File::open(path).ok()?;
+        let exif = exif::Reader::new().read_from_container(&mut BufReader::new(file)).ok()?;
+        let dimension = |tags: [exif::Tag; 2]| tags.iter()
+            .filter_map(|t| exif.get_field(*t, exif::In::PRIMARY))
+            .find_map(|f| f.value.get_uint(0));
+        Some(ShotSignature {
+            taken_at: Self::extract_as_string(&exif, exif::Tag::DateTimeOriginal)?,
+            make: Self::extract_as_string(&exif, exif::Tag::Make)?.trim().to_string(),
+            model: Self::extract_as_string(&exif, exif::Tag::Model)?.trim().to_string(),
+            width: dimension([exif::Tag::PixelXDimension, exif::Tag::ImageWidth])?,
+            height: dimension([exif::Tag::PixelYDimension, exif::Tag::ImageLength])?
+        })
+    }
+
     fn read_exif_data(path: &Path) -> Option<exif::Exif> {
-        let file = fs::File::open(path).expect("Failed to open path as file!");
+        let file = match fs::File::open(path) {
+            Ok(f) => f,
+            Err(err) => {
+                println!("Failed to open file for reading Exif data: {}", err);
+                return None;
+            }
+        };
         let mut bufreader = BufReader::new(file);
         let exifreader = exif::Reader::new();
         match exifreader.read_from_container(&mut bufreader) {
@@ -96,15 +140,11 @@ impl KadamakExifProcessor {
             Value::Ascii(values ) => {
                 if let Some(bytes) = values.first() {
                     if let Ok(dt) = exif::DateTime::from_ascii(bytes.as_slice()) {
-                        Some(Local.ymd(
-                            dt.year as i32,
-                            dt.month as u32,
-                            dt.day as u32
-                        ).and_hms(
-                            dt.hour as u32,
-                            dt.minute as u32,
-                            dt.second as u32
-                        ))
+                        // invalid dates (e.g. "0000:00:00") and local times skipped by DST
+                        // transitions are treated as missing
+                        NaiveDate::from_ymd_opt(dt.year as i32, dt.month as u32, dt.day as u32)
+                            .and_then(|d| d.and_hms_opt(dt.hour as u32, dt.minute as u32, dt.second as u32))
+                            .and_then(|ndt| Local.from_local_datetime(&ndt).earliest())
                     } else {
                         None
                     }
@@ -164,6 +204,50 @@ impl KadamakExifProcessor {
     }
 }
 
+#[cfg(test)]
+mod tests {
 
+    mod kadamak_exif_processor {
+        use crate::media::kadamak_exif::KadamakExifProcessor;
+        use crate::test_util::TempDir;
 
+        fn isobmff_box(boxtype: &[u8], body: &[u8]) -> Vec<u8> {
+            [&(8 + body.len() as u32).to_be_bytes()[..], boxtype, body].concat()
+        }
+
+        /// a HEIF file with only an Exif item containing Make and DateTime, like iPhones write them
+        fn heic_file() -> Vec<u8> {
+            let mut tiff = [&b"MM\0*"[..], &8u32.to_be_bytes(), &2u16.to_be_bytes()].concat();
+            let values: [(u16, &[u8]); 2] = [(0x010f, b"Apple\0"), (0x0132, b"2023:08:14 10:11:12\0")];
+            let mut offset = 8 + 2 + 2 * 12 + 4;
+            for (tag, value) in values.iter() {
+                tiff.extend([&tag.to_be_bytes()[..], &2u16.to_be_bytes(), &(value.len() as u32).to_be_bytes(), &(offset as u32).to_be_bytes()].concat());
+                offset += value.len();
+            }
+            tiff.extend(0u32.to_be_bytes());
+            values.iter().for_each(|(_, v)| tiff.extend(*v));
+            let exif = [&6u32.to_be_bytes()[..], b"Exif\0\0", &tiff].concat();
+
+            let ftyp = isobmff_box(b"ftyp", b"heic\0\0\0\0mif1heic");
+            let infe = isobmff_box(b"infe", &[&[2u8, 0, 0, 0, 0, 1, 0, 0][..], b"Exif\0"].concat());
+            let iinf = isobmff_box(b"iinf", &[&[0u8, 0, 0, 0, 0, 1][..], &infe].concat());
+            let meta = |exif_offset: u32| {
+                let iloc = isobmff_box(b"iloc", &[&[0u8, 0, 0, 0, 0x44, 0, 0, 1, 0, 1, 0, 0, 0, 1][..],
+                    &exif_offset.to_be_bytes(), &(exif.len() as u32).to_be_bytes()].concat());
+                isobmff_box(b"meta", &[&[0u8; 4][..], &iinf, &iloc].concat())
+            };
+            let exif_offset = (ftyp.len() + meta(0).len() + 8) as u32;
+            [ftyp, meta(exif_offset), isobmff_box(b"mdat", &exif)].concat()
+        }
 
+        #[test]
+        fn reads_heic() {
+            let dir = TempDir::new("heic");
+            std::fs::write(dir.join("IMG_0001.HEIC"), heic_file()).unwrap();
+            let meta = KadamakExifProcessor::new().read_metadata(dir.join("IMG_0001.HEIC").as_path());
+            let meta = meta.unwrap();
+            assert_eq!(meta.make(), "Apple");
+            assert_eq!(meta.created_at().unwrap().format("%F %T").to_string(), "2023-08-14 10:11:12");
+        }
+    }
+}
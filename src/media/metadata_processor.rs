@@ -9,6 +9,7 @@ pub struct MetaProcessorBuilder {
     proc_p_low: Vec<Box<dyn FileMetaProcessor + Send>>,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Priority {
     Highest,
     Lowest,
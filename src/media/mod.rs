@@ -1,11 +1,14 @@
+use std::ffi::{OsStr, OsString};
 use std::io::{Error, ErrorKind};
 use std::path::{Path, PathBuf};
 
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
 
 //mod image;
+pub mod clock_drift;
 pub mod kadamak_exif;
 pub mod metadata_processor;
+#[cfg(feature = "rexiv2")]
 pub mod rexiv_proc;
 
 #[derive(Debug)]
@@ -33,15 +36,28 @@ pub trait FileMetaProcessor {
     fn clone_boxed(&self) -> Box<dyn FileMetaProcessor + Send>;
 }
 
+/// create the preferred metadata backend available in this build, for reading single files
+/// without a configured [metadata_processor::MetaProcessor]
+pub fn default_processor() -> Box<dyn FileMetaProcessor + Send> {
+    #[cfg(feature = "rexiv2")]
+    return rexiv_proc::Rexiv2Processor::new();
+    #[cfg(not(feature = "rexiv2"))]
+    return kadamak_exif::KadamakExifProcessor::new();
+}
+
 
 
 #[derive(Debug)]
 pub struct ImgInfo {
     fp: PathBuf,
+    /// the real filename if the file is stored under an internal name (e.g. in backups)
+    original_name: Option<OsString>,
     size: usize,
     file_type: FileType,
     meta: ImgMeta,
-    changed_at: DateTime<Local>
+    changed_at: DateTime<Local>,
+    /// the file in the preferred format if this is the secondary file of a HEIC/JPEG pair
+    pair_primary: Option<PathBuf>
 }
 
 #[derive(Debug,Clone,PartialEq)]
@@ -50,7 +66,8 @@ pub struct ImgMeta {
     make: String,
     model: String,
     user_comment: String,
-    is_screenshot: bool
+    is_screenshot: bool,
+    gps_time: Option<DateTime<Utc>>
 }
 #[derive(Debug, Clone)]
 struct TagParseError {
@@ -58,12 +75,13 @@ struct TagParseError {
 }
 
 impl FileType {
-    fn from(extension: &str) -> FileType {
+    pub fn from(extension: &str) -> FileType {
         match extension.to_lowercase().as_str() {
             "jpeg" => FileType::JPEG,
             "jpg" => FileType::JPEG,
             "png" => FileType::PNG,
-            "heic" => FileType::HEIC,
+            // HEIF files with other codecs than HEVC are read the same way
+            "heic" | "heif" | "hif" => FileType::HEIC,
             "dng" => FileType::DNG,
             "arw" => FileType::ARW,
             _ => FileType::Other
@@ -71,6 +89,18 @@ impl FileType {
     }
 }
 
+/// combine the GPS date stamp (`YYYY:MM:DD`) and the GPS time stamp (hour, minute, second) into
+/// a UTC timestamp
+fn parse_gps_time(date: &str, hms: (f64, f64, f64)) -> Option<DateTime<Utc>> {
+    let date = NaiveDate::parse_from_str(date.trim_end_matches('\0').trim(), "%Y:%m:%d").ok()?;
+    let (h, m, s) = hms;
+    if !(h >= 0.0 && m >= 0.0 && s >= 0.0) {
+        return None;
+    }
+    let ndt = date.and_hms_opt(h as u32, m as u32, s as u32)?;
+    Utc.from_local_datetime(&ndt).single()
+}
+
 impl MetaType {
     pub fn from_filetype(e: &FileType) -> Vec<MetaType> {
         match e {
@@ -108,22 +138,50 @@ impl ImgInfo {
         let metadata : std::fs::Metadata = file.metadata()?;
         let file_type = match file.extension() {
             None => FileType::Other,
-            Some(s) => FileType::from(s.to_str().expect("Could not convert extension to str!"))
+            // a non UTF-8 extension cannot match any known type
+            Some(s) => s.to_str().map_or(FileType::Other, FileType::from)
         };
 
         Ok(ImgInfo {
             fp: file,
-            size: 0,
+            original_name: None,
+            size: metadata.len() as usize,
             file_type,
             meta: ImgMeta::new(),
-            changed_at: DateTime::from(metadata.modified()?)
+            changed_at: DateTime::from(metadata.modified()?),
+            pair_primary: None
         })
     }
 
+    /// create the info of a file stored under an internal name, e.g. a file inside a device
+    /// backup. The file type is derived from `original_name` and the file system timestamp is
+    /// replaced by `changed_at` if given.
+    pub fn with_original_name(file: PathBuf, original_name: &OsStr, changed_at: Option<DateTime<Local>>) -> Result<ImgInfo, std::io::Error> {
+        let mut info = Self::new(file)?;
+        info.file_type = match Path::new(original_name).extension() {
+            None => FileType::Other,
+            Some(s) => s.to_str().map_or(FileType::Other, FileType::from)
+        };
+        info.original_name = Some(original_name.to_os_string());
+        if let Some(ts) = changed_at {
+            info.changed_at = ts;
+        }
+        Ok(info)
+    }
+
     pub fn path(&self) -> &Path {
         self.fp.as_path()
     }
 
+    /// get the real filename of the file, which differs from the name in [Self::path] for files
+    /// created by [Self::with_original_name]
+    pub fn file_name(&self) -> Option<&OsStr> {
+        match &self.original_name {
+            Some(name) => Some(name.as_os_str()),
+            None => self.fp.file_name()
+        }
+    }
+
     pub fn size(&self) -> &usize {
         &self.size
     }
@@ -143,6 +201,16 @@ impl ImgInfo {
     pub fn set_metadata(&mut self, m: ImgMeta) {
         self.meta = m;
     }
+
+    /// get the file in the preferred format if this is the secondary file of a HEIC/JPEG pair,
+    /// as detected while scanning, see [crate::sorting::routing::PairPolicy]
+    pub fn pair_primary(&self) -> Option<&Path> {
+        self.pair_primary.as_deref()
+    }
+
+    pub fn set_pair_primary(&mut self, primary: Option<PathBuf>) {
+        self.pair_primary = primary;
+    }
 }
 
 impl ImgMeta {
@@ -152,7 +220,8 @@ impl ImgMeta {
             make: String::new(),
             model: String::new(),
             user_comment: String::new(),
-            is_screenshot: false
+            is_screenshot: false,
+            gps_time: None
         }
     }
 
@@ -165,6 +234,10 @@ impl ImgMeta {
         }
     }
 
+    pub fn set_created_at(&mut self, ts: Option<DateTime<Local>>) {
+        self.created_at = ts;
+    }
+
     pub fn make(&self) -> &str {
         &self.make
     }
@@ -173,6 +246,14 @@ impl ImgMeta {
         &self.model
     }
 
+    pub fn set_make(&mut self, make: &str) {
+        self.make = String::from(make);
+    }
+
+    pub fn set_model(&mut self, model: &str) {
+        self.model = String::from(model);
+    }
+
     pub fn user_comment(&self) -> &str {
         &self.user_comment
     }
@@ -181,6 +262,11 @@ impl ImgMeta {
         self.is_screenshot
     }
 
+    /// the (UTC) timestamp from the GPS receiver, independent of the camera clock
+    pub fn gps_time(&self) -> Option<&DateTime<Utc>> {
+        self.gps_time.as_ref()
+    }
+
     pub fn merge_in(&mut self, other: &ImgMeta) {
         if self.created_at != other.created_at {
             match self.created_at {
@@ -205,6 +291,10 @@ impl ImgMeta {
         if self.user_comment.is_empty() && !other.user_comment.is_empty() {
             self.user_comment = other.user_comment.clone();
         }
+
+        if self.gps_time.is_none() {
+            self.gps_time = other.gps_time;
+        }
     }
 
     pub fn merge(m1: &ImgMeta, m2: &ImgMeta) -> ImgMeta {
@@ -218,7 +308,7 @@ impl ImgMeta {
 mod tests {
 
     mod img_meta_tests {
-        use chrono::Local;
+        use chrono::{Local, Utc};
 
         use crate::media::ImgMeta;
 
@@ -231,7 +321,8 @@ mod tests {
                 make: String::from("SomeMake"),
                 model: String::from("SomeModel"),
                 user_comment: String::from("A comment!"),
-                is_screenshot: true
+                is_screenshot: true,
+                gps_time: Some(Utc::now())
             };
             empty.merge_in(&not_empty);
             assert_eq!(not_empty, empty);
@@ -1,9 +1,9 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
 use rexiv2::Metadata;
 
-use crate::media::{FileMetaProcessor, FileType, ImgMeta, MetaType};
+use crate::media::{FileMetaProcessor, FileType, ImgMeta, MetaType, parse_gps_time};
 
 const EXIF_DATETIME_RX: &str = "^\\d{4}:\\d{2}:\\d{2} \\d{2}:\\d{2}:\\d{2}$";
 const EXIF_DATETIME_FMT: &str = "%Y:%m:%d %T";
@@ -15,10 +15,23 @@ const EXIF_T_MAKE: (u64,&str) = (0x010f, "Exif.Image.Make");
 const EXIF_T_MODEL: (u64,&str) = (0x0110, "Exif.Image.Model");
 const EXIF_T_USER_COMMENT: (u64,&str) = (0x9286, "Exif.Photo.UserComment");
 const EXIF_T_SOFTWARE: (u64, &str) = (0x0131, "Exif.Image.Software");
+const EXIF_T_GPS_DATESTAMP: (u64, &str) = (0x001d, "Exif.GPSInfo.GPSDateStamp");
+const EXIF_T_GPS_TIMESTAMP: (u64, &str) = (0x0007, "Exif.GPSInfo.GPSTimeStamp");
 
 const XMP_T_CREATE_DATE: &str = "Xmp.photoshop.DateCreated";
 const XMP_T_USER_COMMENT: &str = "Xmp.exif.UserComment";
 
+const XMP_NS_DCIMSORT: (&str, &str) = ("https://github.com/mclayan/dcim-sort/ns/1.0/", "dcimsort");
+const XMP_T_SESSION_ID: &str = "Xmp.dcimsort.SessionId";
+const XMP_T_SOURCE_PATH: &str = "Xmp.dcimsort.SourcePath";
+/// packet without properties, written to create a sidecar that exiv2 can add tags to
+const XMP_EMPTY_PACKET: &str = "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">
+ <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\"/>
+</x:xmpmeta>
+<?xpacket end=\"w\"?>
+";
+
 pub struct Rexiv2Processor { }
 
 impl FileMetaProcessor for Rexiv2Processor {
@@ -68,6 +81,39 @@ impl Rexiv2Processor {
         Box::new(Rexiv2Processor{})
     }
 
+    /// write the import session and original source path of a sorted file into custom XMP tags
+    /// (`Xmp.dcimsort.SessionId`, `Xmp.dcimsort.SourcePath`) of its sidecar (see
+    /// [Self::sidecar_path]), creating the sidecar if needed. The file itself is left unchanged,
+    /// so it still matches its source when comparing them.
+    pub fn write_provenance(file: &Path, session_id: &str, source: &Path) -> Result<(), String> {
+        if let Err(e) = rexiv2::register_xmp_namespace(XMP_NS_DCIMSORT.0, XMP_NS_DCIMSORT.1) {
+            return Err(format!("failed to register XMP namespace: {}", e));
+        }
+        let sidecar = Self::sidecar_path(file);
+        if !sidecar.exists() {
+            if let Err(e) = std::fs::write(&sidecar, XMP_EMPTY_PACKET) {
+                return Err(format!("failed to create sidecar: {}", e));
+            }
+        }
+        let rmeta = match Metadata::new_from_path(&sidecar) {
+            Ok(m) => m,
+            Err(e) => return Err(format!("failed to read sidecar: {}", e))
+        };
+        let source = source.to_string_lossy();
+        rmeta.set_tag_string(XMP_T_SESSION_ID, session_id)
+            .and_then(|_| rmeta.set_tag_string(XMP_T_SOURCE_PATH, &source))
+            .and_then(|_| rmeta.save_to_file(&sidecar))
+            .map_err(|e| format!("failed to write XMP tags: {}", e))
+    }
+
+    /// the XMP sidecar of `file` holding its provenance, named `<file>.xmp` so files differing
+    /// in their extension only (e.g. JPEG and raw) have distinct sidecars
+    pub fn sidecar_path(file: &Path) -> PathBuf {
+        let mut name = file.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+        name.push(".xmp");
+        file.with_file_name(name)
+    }
+
     fn read_exif(rmeta: &Metadata) -> ImgMeta {
         let created_at = Self::exif_read_datetime(rmeta);
         let make = rmeta.get_tag_string(EXIF_T_MAKE.1).unwrap_or(String::new());
@@ -86,10 +132,26 @@ impl Rexiv2Processor {
             make,
             model,
             user_comment,
-            is_screenshot
+            is_screenshot,
+            gps_time: Self::exif_read_gps_time(rmeta)
         }
     }
 
+    /// read the GPS timestamp, which is stored as rationals like "12/1 34/1 5600/100"
+    fn exif_read_gps_time(rmeta: &Metadata) -> Option<DateTime<Utc>> {
+        let date = rmeta.get_tag_string(EXIF_T_GPS_DATESTAMP.1).ok()?;
+        let time = rmeta.get_tag_string(EXIF_T_GPS_TIMESTAMP.1).ok()?;
+        let mut parts = time.split_whitespace().map(|r| {
+            let (num, denom) = r.split_once('/').unwrap_or((r, "1"));
+            match (num.parse::<f64>(), denom.parse::<f64>()) {
+                (Ok(n), Ok(d)) if d != 0.0 => Some(n / d),
+                _ => None
+            }
+        });
+        let hms = (parts.next()??, parts.next()??, parts.next()??);
+        parse_gps_time(&date, hms)
+    }
+
     fn exif_read_datetime(rmeta: &Metadata) -> Option<DateTime<Local>> {
         if let Ok(tag) = rmeta.get_tag_string(EXIF_T_DATETIME_ORIGINAL_TIFF.1) {
             Self::exif_parse_datetime(&tag)
@@ -107,7 +169,7 @@ impl Rexiv2Processor {
 
     fn exif_parse_datetime(inp: &str) -> Option<DateTime<Local>> {
         if let Ok(result) = NaiveDateTime::parse_from_str(inp, EXIF_DATETIME_FMT) {
-            Some(Local.from_local_datetime(&result).unwrap())
+            Local.from_local_datetime(&result).earliest()
         }
         else {
             None
@@ -124,14 +186,15 @@ impl Rexiv2Processor {
             make: String::new(),
             model: String::new(),
             user_comment,
-            is_screenshot
+            is_screenshot,
+            gps_time: None
         }
     }
 
     fn xmp_read_datetime(rmeta: &Metadata) -> Option<DateTime<Local>> {
         if let Ok(ts) = rmeta.get_tag_string(XMP_T_CREATE_DATE) {
             if let Ok(dt) = NaiveDateTime::parse_from_str(&ts, "%FT%T") {
-                Some(Local.from_local_datetime(&dt).unwrap())
+                Local.from_local_datetime(&dt).earliest()
             }
             else {
                 None
@@ -156,4 +219,27 @@ mod tests {
             assert!(!flag);
         }
     }
+
+    mod provenance {
+        use std::path::Path;
+
+        use rexiv2::Metadata;
+
+        use crate::media::rexiv_proc::Rexiv2Processor;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn is_written_to_sidecar() {
+            let dir = TempDir::new("provenance");
+            let file = dir.join("IMG_0001.jpg");
+            std::fs::write(&file, b"unchanged").unwrap();
+            Rexiv2Processor::write_provenance(file.as_path(), "session-1", Path::new("/card/IMG_0001.jpg")).unwrap();
+            Rexiv2Processor::write_provenance(file.as_path(), "session-2", Path::new("/card/IMG_0001.jpg")).unwrap();
+
+            assert_eq!(std::fs::read(&file).unwrap(), b"unchanged");
+            let sidecar = Metadata::new_from_path(dir.join("IMG_0001.jpg.xmp")).unwrap();
+            assert_eq!(sidecar.get_tag_string("Xmp.dcimsort.SessionId").unwrap(), "session-2");
+            assert_eq!(sidecar.get_tag_string("Xmp.dcimsort.SourcePath").unwrap(), "/card/IMG_0001.jpg");
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,156 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::pipeline::Report;
+use crate::sorting::PATHSTR_FB;
+
+/// default port used for SMTP servers given without a port
+pub const DEFAULT_SMTP_PORT: u16 = 25;
+
+/// The outcome of a finished run which is sent by a [Notifier]. `errors` holds errors that
+/// occurred outside of the pipelines (e.g. while scanning) in addition to the ones recorded in
+/// the report.
+pub struct RunSummary<'a> {
+    pub report: &'a Report,
+    pub errors: &'a [(PathBuf, String)],
+    pub elapsed: Duration
+}
+
+impl<'a> RunSummary<'a> {
+    pub fn error_count(&self) -> u64 {
+        self.report.count_error + self.errors.len() as u64
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    pub fn subject(&self) -> String {
+        match self.has_errors() {
+            true => format!("dcim-sort finished with {} error(s)", self.error_count()),
+            false => String::from("dcim-sort finished successfully")
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let errors: Vec<serde_json::Value> = self.errors.iter()
+            .map(|(path, msg)| json!({
+                "path": path.to_str().unwrap_or(PATHSTR_FB),
+                "error": msg
+            }))
+            .collect();
+        json!({
+            "subject": self.subject(),
+            "elapsed_seconds": self.elapsed.as_secs_f64(),
+            "report": serde_json::to_value(self.report).unwrap_or(serde_json::Value::Null),
+            "errors": errors
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n\nfinished in {:.3} seconds\n{}",
+                               self.subject(),
+                               self.elapsed.as_secs_f64(),
+                               self.report
+        );
+        if !self.errors.is_empty() {
+            text.push_str("\nother errors:\n");
+            for (path, msg) in self.errors {
+                text.push_str(format!("  \"{}\": {}\n", path.to_str().unwrap_or(PATHSTR_FB), msg).as_str());
+            }
+        }
+        text
+    }
+}
+
+/// Sends a [RunSummary] after a run has finished, e.g. for unattended imports.
+///
+/// # Variants
+/// - [Notifier::Webhook] POST the summary as JSON to the given URL
+/// - [Notifier::Email] send the summary as plain text mail via an SMTP relay. Only unencrypted
+///   and unauthenticated relays (e.g. a local MTA) are supported.
+pub enum Notifier {
+    Webhook(String),
+    Email {
+        server: String,
+        from: String,
+        to: String
+    }
+}
+
+impl Notifier {
+    pub fn notify(&self, summary: &RunSummary) -> Result<(), String> {
+        match self {
+            Notifier::Webhook(url) => Self::post_webhook(url, summary),
+            Notifier::Email { server, from, to } => Self::send_mail(server, from, to, summary)
+                .map_err(|e| format!("failed to send mail via \"{}\": {}", server, e))
+        }
+    }
+
+    fn post_webhook(url: &str, summary: &RunSummary) -> Result<(), String> {
+        match ureq::post(url)
+            .set("Content-Type", "application/json")
+            .send_string(summary.to_json().to_string().as_str()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("failed to post webhook to \"{}\": {}", url, e))
+        }
+    }
+
+    fn send_mail(server: &str, from: &str, to: &str, summary: &RunSummary) -> std::io::Result<()> {
+        let address = match server.contains(':') {
+            true => server.to_string(),
+            false => format!("{}:{}", server, DEFAULT_SMTP_PORT)
+        };
+        let stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        Self::smtp_expect(&mut reader, "220")?;
+        Self::smtp_cmd(&mut writer, &mut reader, "HELO dcim-sort", "250")?;
+        Self::smtp_cmd(&mut writer, &mut reader, format!("MAIL FROM:<{}>", from).as_str(), "250")?;
+        Self::smtp_cmd(&mut writer, &mut reader, format!("RCPT TO:<{}>", to).as_str(), "250")?;
+        Self::smtp_cmd(&mut writer, &mut reader, "DATA", "354")?;
+
+        let mut data = format!("From: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n",
+                               from, to, summary.subject());
+        for line in summary.to_text().lines() {
+            // lines starting with a dot must be escaped (RFC 5321, 4.5.2)
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+            data.push_str("\r\n");
+        }
+        data.push('.');
+        Self::smtp_cmd(&mut writer, &mut reader, data.as_str(), "250")?;
+        Self::smtp_cmd(&mut writer, &mut reader, "QUIT", "221")
+    }
+
+    fn smtp_cmd(writer: &mut TcpStream, reader: &mut BufReader<TcpStream>, cmd: &str, expected: &str) -> std::io::Result<()> {
+        write!(writer, "{}\r\n", cmd)?;
+        writer.flush()?;
+        Self::smtp_expect(reader, expected)
+    }
+
+    /// read a (possibly multi-line) reply and check its status code
+    fn smtp_expect(reader: &mut BufReader<TcpStream>, expected: &str) -> std::io::Result<()> {
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "connection closed by server"));
+            }
+            if !line.starts_with(expected) {
+                return Err(std::io::Error::other(format!("unexpected reply: {}", line.trim_end())));
+            }
+            // a dash after the code marks a continued reply
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+}
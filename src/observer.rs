@@ -0,0 +1,104 @@
+use std::path::Path;
+
+use crate::error::SortError;
+use crate::media::ImgInfo;
+use crate::pipeline::Report;
+use crate::sorting::ActionResult;
+use crate::sorting::preview::TargetPreview;
+
+/// Callbacks for applications embedding the library, e.g. to drive a progress bar or a UI
+/// without parsing the messages sent to a [crate::logging::Logger]. Set with
+/// [crate::runner::Runner::set_observer] or [crate::pipeline::Pipeline::set_observer].
+///
+/// All pipeline threads of a run share the same observer, so calls for different files arrive
+/// concurrently from different threads. Callbacks block the pipeline calling them and should
+/// return quickly. All methods do nothing by default.
+pub trait SortObserver: Send + Sync {
+    /// processing of `source` started
+    fn on_file_started(&self, _source: &Path) {}
+
+    /// the metadata of `file` has been read and corrected and `preview` is the target it is
+    /// about to be sorted to, before any filter or the duplicate policy applies. Only called if
+    /// previews are enabled, see [crate::pipeline::Pipeline::set_preview].
+    fn on_target_previewed(&self, _file: &ImgInfo, _preview: &TargetPreview) {}
+
+    /// `source` has been processed with `result`. `target` is the file actually written for
+    /// moved and copied files.
+    fn on_action_executed(&self, _source: &Path, _target: Option<&Path>, _result: &ActionResult) {}
+
+    /// processing of `source` failed with `error`, including sources vanished since the scan
+    fn on_error(&self, _source: &Path, _error: &SortError) {}
+
+    /// all files of a run have been processed, called by the [crate::runner::Runner] with the
+    /// merged report of all pipelines
+    fn on_finished(&self, _report: &Report) {}
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod sort_observer {
+        use std::path::Path;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use crate::error::SortError;
+        use crate::media::ImgInfo;
+        use crate::observer::SortObserver;
+        use crate::pipeline::Pipeline;
+        use crate::sorting::{ActionResult, DuplicateResolution, Operation, Sorter};
+        use crate::sorting::preview::TargetPreview;
+        use crate::test_util::TempDir;
+
+        #[derive(Default)]
+        struct Counter {
+            started: AtomicUsize,
+            previewed: AtomicUsize,
+            copied: AtomicUsize,
+            errors: AtomicUsize
+        }
+
+        impl SortObserver for Counter {
+            fn on_file_started(&self, _source: &Path) {
+                self.started.fetch_add(1, Ordering::Relaxed);
+            }
+
+            fn on_target_previewed(&self, file: &ImgInfo, preview: &TargetPreview) {
+                assert_eq!(preview.target().and_then(|t| t.file_name()), file.path().file_name());
+                self.previewed.fetch_add(1, Ordering::Relaxed);
+            }
+
+            fn on_action_executed(&self, _source: &Path, target: Option<&Path>, result: &ActionResult) {
+                if let (ActionResult::Copied, Some(t)) = (result, target) {
+                    assert!(t.is_file());
+                    self.copied.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+
+            fn on_error(&self, _source: &Path, _error: &SortError) {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        #[test]
+        fn reports_executed_actions_and_errors() {
+            let dir = TempDir::new("observer");
+            std::fs::write(dir.join("a.jpg"), b"a").unwrap();
+            std::fs::write(dir.join("b.jpg"), b"b").unwrap();
+            let (a, b) = (ImgInfo::new(dir.join("a.jpg")).unwrap(), ImgInfo::new(dir.join("b.jpg")).unwrap());
+            std::fs::remove_file(dir.join("b.jpg")).unwrap();
+
+            let target = dir.join("sorted");
+            let counter = Arc::new(Counter::default());
+            let mut pipeline = Pipeline::sorting_only(Sorter::builder().build_sync(), Operation::Copy, target.as_path(), DuplicateResolution::Ignore);
+            pipeline.set_observer(counter.clone());
+            pipeline.set_preview(true);
+            pipeline.process(a).unwrap();
+            assert!(pipeline.process(b).is_err());
+            assert_eq!(counter.started.load(Ordering::Relaxed), 2);
+            assert_eq!(counter.previewed.load(Ordering::Relaxed), 2);
+            assert_eq!(counter.copied.load(Ordering::Relaxed), 1);
+            assert_eq!(counter.errors.load(Ordering::Relaxed), 1);
+        }
+    }
+}
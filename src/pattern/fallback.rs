@@ -1,7 +1,9 @@
-use crate::media::ImgInfo;
+use crate::media::{FileType, ImgInfo};
 use crate::pattern::PatternElement;
 
-enum GeneralFileType {
+/// General class of a file, e.g. to sort or route all videos alike regardless of their format.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GeneralFileType {
     Video,
     Picture,
     Audio,
@@ -11,6 +13,39 @@ enum GeneralFileType {
 }
 
 impl GeneralFileType {
+    pub fn parse(s: &str) -> Option<GeneralFileType> {
+        match s.to_lowercase().as_str() {
+            "video" => Some(GeneralFileType::Video),
+            "picture" => Some(GeneralFileType::Picture),
+            "audio" => Some(GeneralFileType::Audio),
+            "text" => Some(GeneralFileType::Text),
+            "document" => Some(GeneralFileType::Document),
+            "other" => Some(GeneralFileType::Other),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GeneralFileType::Video => "video",
+            GeneralFileType::Picture => "picture",
+            GeneralFileType::Audio => "audio",
+            GeneralFileType::Text => "text",
+            GeneralFileType::Document => "document",
+            GeneralFileType::Other => "other"
+        }
+    }
+
+    /// the class of `file` by its extension, files of a supported image format are pictures
+    pub fn of(file: &ImgInfo) -> GeneralFileType {
+        let by_extension = GeneralFileType::from(file.path().extension().and_then(|e| e.to_str()).unwrap_or(""));
+        match (by_extension, file.file_type()) {
+            (GeneralFileType::Other, FileType::Other) => GeneralFileType::Other,
+            (GeneralFileType::Other, _) => GeneralFileType::Picture,
+            (t, _) => t
+        }
+    }
+
     pub fn from(extension: &str) -> GeneralFileType {
         match extension.to_lowercase().as_str() {
             "mov" | "mp4" | "mpeg" | "mpg" | "ts"  | "mkv" |"avi" => GeneralFileType::Video,
@@ -79,7 +79,7 @@ impl PatternElement for ScreenshotPattern {
     fn translate(&self, info: &ImgInfo) -> Option<String> {
         let name_matches = match &self.filename_pattern {
             None => false,
-            Some(regex) => match info.path().file_name() {
+            Some(regex) => match info.file_name() {
                 Some(name) => match name.to_str() {
                     Some(n) => regex.is_match(n),
                     None => false
@@ -1,6 +1,9 @@
 use std::fmt::Formatter;
 
 use crate::media::ImgInfo;
+use crate::pattern::device::MakeModelPattern;
+use crate::pattern::fallback::SimpleFileTypePattern;
+use crate::pattern::general::{DateTimePattern, ScreenshotPattern};
 
 pub mod general;
 pub mod device;
@@ -14,6 +17,49 @@ pub trait PatternElement {
     fn clone_boxed(&self) -> Box<dyn PatternElement + Send>;
 }
 
+/// The names of all folders generated for files without a matching value (e.g. an unknown
+/// device) or for fixed categories, so they can be localized in one place. Segments configured
+/// with an explicit value take precedence.
+#[derive(Clone)]
+pub struct FolderDefaults {
+    pub unknown_make: String,
+    pub unknown_model: String,
+    pub unknown_date: String,
+    pub screenshots: String,
+    pub video: String,
+    pub picture: String,
+    pub audio: String,
+    pub text: String,
+    pub document: String,
+    pub other: String,
+    /// folder for files with implausible dates, relative to the output directory
+    pub quarantine: String
+}
+
+impl FolderDefaults {
+    pub fn new() -> FolderDefaults {
+        FolderDefaults {
+            unknown_make: MakeModelPattern::def_default_make(),
+            unknown_model: MakeModelPattern::def_default_model(),
+            unknown_date: DateTimePattern::def_default(),
+            screenshots: ScreenshotPattern::def_value(),
+            video: SimpleFileTypePattern::def_video(),
+            picture: SimpleFileTypePattern::def_picture(),
+            audio: SimpleFileTypePattern::def_audio(),
+            text: SimpleFileTypePattern::def_text(),
+            document: SimpleFileTypePattern::def_document(),
+            other: SimpleFileTypePattern::def_other(),
+            quarantine: String::from("quarantine")
+        }
+    }
+}
+
+impl Default for FolderDefaults {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PatternInitError {
     msg: String
@@ -1,28 +1,86 @@
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
 use std::path::{Path, PathBuf};
-use std::sync::mpsc;
+use std::sync::{Arc, mpsc};
 use std::sync::mpsc::Sender;
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-use crate::media::ImgInfo;
+use serde::{Serialize, Serializer};
+use serde::ser::SerializeSeq;
+
+#[cfg(feature = "catalog")]
+use crate::catalog::{Catalog, CatalogMatch};
+use crate::checkpoint::Checkpoint;
+use crate::hook::PostActionHook;
+use crate::manifest::Manifest;
+use crate::observer::SortObserver;
+use crate::error::SortError;
+use crate::index::DateRange;
+use crate::logging::{LogLevel, LogMsg, LogReq};
+use crate::logging::console::{self, Event};
+use crate::media::{ImgInfo, ImgMeta};
 use crate::media::metadata_processor::{MetaProcessor, MetaProcessorBuilder};
-use crate::sorting::{Operation, SorterBuilder, Sorter, DuplicateResolution, ActionResult};
-use crate::sorting::fs_support::{DirCreationRequest, DirManager};
+use crate::sorting::{Operation, SorterBuilder, Sorter, DuplicateResolution, ActionResult, PATHSTR_FB};
+use crate::sorting::fs_support::{DirCreationRequest, DirManager, TargetReservations};
+use crate::sorting::simulation;
 
 pub struct Pipeline {
-    processor: MetaProcessor,
+    /// `None` if metadata has already been processed by a [MetaWorker]
+    processor: Option<MetaProcessor>,
     sorter: Sorter,
     sorting_operation: Operation,
     target_root: PathBuf,
     dup_handling: DuplicateResolution,
-    report: Report
+    report: Report,
+    checkpoint: Option<Checkpoint>,
+    manifest: Option<Manifest>,
+    post_action: Option<PostActionHook>,
+    observer: Option<Arc<dyn SortObserver>>,
+    /// pass a [crate::sorting::preview::TargetPreview] of every file to the observer
+    preview: bool,
+    date_range: Option<DateRange>,
+    #[cfg(feature = "catalog")]
+    catalog: Option<Catalog>,
+    /// channel to a [crate::logging::Logger] receiving errors and skipped files
+    log: Option<Sender<LogReq>>
 }
 
+/// Control messages exchanged between the [PipelineController] and its threads.
+///
+/// # Variants
+/// - [ControlMsg::Shutdown] finish all pending requests and stop, replying on the given channel
+/// - [ControlMsg::Ack] / [ControlMsg::AckReport] replies to [ControlMsg::Shutdown]
+/// - [ControlMsg::Pause] suspend processing after the current file until
+///   [ControlMsg::Resume] is received. Pending requests are kept in the queue. Both are sent on
+///   a separate control channel, so they take effect without waiting for queued requests.
 pub enum ControlMsg {
     Shutdown(mpsc::Sender<ControlMsg>),
     Ack,
-    AckReport(Report)
+    AckReport(Box<Report>),
+    Pause,
+    Resume
+}
+
+/// check the control channel of a thread and block while it is paused. Returns once the thread
+/// has been resumed or the controller has hung up.
+fn wait_if_paused(control: &mpsc::Receiver<ControlMsg>) {
+    let mut paused = false;
+    while let Ok(msg) = control.try_recv() {
+        match msg {
+            ControlMsg::Pause => paused = true,
+            ControlMsg::Resume => paused = false,
+            _ => console::warn("unexpected ControlMsg on control channel")
+        }
+    }
+    while paused {
+        match control.recv() {
+            Ok(ControlMsg::Resume) | Err(_) => paused = false,
+            Ok(ControlMsg::Pause) => (),
+            Ok(_) => console::warn("unexpected ControlMsg on control channel")
+        }
+    }
 }
 
 pub enum Request<T> {
@@ -30,50 +88,532 @@ pub enum Request<T> {
     Cmd(ControlMsg)
 }
 
-#[derive(Copy, Clone)]
+/// maximum count of individual errors kept in a [Report], further errors are only counted
+pub const MAX_REPORTED_ERRORS: usize = 100;
+/// default count of the largest files and slowest operations kept in a [Report]
+pub const DEFAULT_TOP_N: usize = 5;
+/// length of the longest bar in text histograms
+const HISTOGRAM_WIDTH: usize = 50;
+/// key for files without a metadata timestamp or device in histograms
+const HISTOGRAM_UNKNOWN: &str = "unknown";
+
+/// Output format of [Report::histogram].
+///
+/// # Variants
+/// - [HistogramFormat::Text] a table with a bar per entry
+/// - [HistogramFormat::Csv] rows of `category,key,count`
+#[derive(Clone, Copy)]
+pub enum HistogramFormat {
+    Text,
+    Csv
+}
+
+#[derive(Clone, Serialize)]
 pub struct Report {
     pub count_success: u64,
     pub count_skipped: u64,
-    pub count_duplicate: u64
+    pub count_duplicate: u64,
+    pub count_error: u64,
+    /// count of files deleted after they have been scanned, which are not counted as errors
+    pub count_vanished: u64,
+    /// count of files with an implausible metadata timestamp
+    pub count_implausible_date: u64,
+    /// total size of all moved or copied files
+    pub bytes_processed: u64,
+    /// count of moved or copied files by file type
+    pub by_file_type: BTreeMap<String, u64>,
+    /// count of moved or copied files by the first folder below the target root
+    pub by_segment: BTreeMap<String, u64>,
+    /// count of files processed without error (including skipped and simulated ones) by the
+    /// month of their metadata timestamp (`YYYY-mm`)
+    pub by_month: BTreeMap<String, u64>,
+    /// count of files processed without error by device (make and model)
+    pub by_device: BTreeMap<String, u64>,
+    #[serde(serialize_with = "serialize_errors")]
+    errors: Vec<(PathBuf, String)>,
+    #[serde(serialize_with = "serialize_paths")]
+    implausible_dates: Vec<PathBuf>,
+    /// the largest moved or copied files with their size, largest first
+    #[serde(serialize_with = "serialize_sizes")]
+    largest_files: Vec<(PathBuf, u64)>,
+    /// the moves or copies that took longest with their duration, slowest first
+    #[serde(serialize_with = "serialize_durations")]
+    slowest_operations: Vec<(PathBuf, Duration)>,
+    /// count of entries kept in [Self::largest_files] and [Self::slowest_operations]
+    #[serde(skip)]
+    top_n: usize
+}
+
+/// serialize paths as strings, replacing invalid UTF-8 with [PATHSTR_FB]
+fn serialize_paths<S: Serializer>(paths: &Vec<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut seq = serializer.serialize_seq(Some(paths.len()))?;
+    for path in paths {
+        seq.serialize_element(path.to_str().unwrap_or(PATHSTR_FB))?;
+    }
+    seq.end()
+}
+
+/// serialize recorded errors as a list of `{"path": .., "error": ..}` objects
+fn serialize_errors<S: Serializer>(errors: &Vec<(PathBuf, String)>, serializer: S) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Entry<'a> {
+        path: &'a str,
+        error: &'a str
+    }
+
+    let mut seq = serializer.serialize_seq(Some(errors.len()))?;
+    for (path, error) in errors {
+        seq.serialize_element(&Entry { path: path.to_str().unwrap_or(PATHSTR_FB), error: error.as_str() })?;
+    }
+    seq.end()
+}
+/// serialize the largest files as a list of `{"path": .., "bytes": ..}` objects
+fn serialize_sizes<S: Serializer>(files: &Vec<(PathBuf, u64)>, serializer: S) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Entry<'a> {
+        path: &'a str,
+        bytes: u64
+    }
+
+    let mut seq = serializer.serialize_seq(Some(files.len()))?;
+    for (path, bytes) in files {
+        seq.serialize_element(&Entry { path: path.to_str().unwrap_or(PATHSTR_FB), bytes: *bytes })?;
+    }
+    seq.end()
+}
+
+/// serialize the slowest operations as a list of `{"path": .., "millis": ..}` objects
+fn serialize_durations<S: Serializer>(ops: &Vec<(PathBuf, Duration)>, serializer: S) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Entry<'a> {
+        path: &'a str,
+        millis: u64
+    }
+
+    let mut seq = serializer.serialize_seq(Some(ops.len()))?;
+    for (path, duration) in ops {
+        seq.serialize_element(&Entry { path: path.to_str().unwrap_or(PATHSTR_FB), millis: duration.as_millis() as u64 })?;
+    }
+    seq.end()
+}
+
+/// insert `entry` into `list` ordered by descending value, keeping at most `n` entries
+fn insert_top<T: PartialOrd>(list: &mut Vec<(PathBuf, T)>, entry: (PathBuf, T), n: usize) {
+    if list.len() >= n && list.last().is_none_or(|(_, v)| *v >= entry.1) {
+        return;
+    }
+    let pos = list.iter().position(|(_, v)| *v < entry.1).unwrap_or(list.len());
+    list.insert(pos, entry);
+    list.truncate(n);
 }
+
 impl Report {
     pub fn new() -> Report {
-        Report{ count_success: 0, count_skipped: 0, count_duplicate: 0 }
+        Report{
+            count_success: 0,
+            count_skipped: 0,
+            count_duplicate: 0,
+            count_error: 0,
+            count_vanished: 0,
+            count_implausible_date: 0,
+            bytes_processed: 0,
+            by_file_type: BTreeMap::new(),
+            by_segment: BTreeMap::new(),
+            by_month: BTreeMap::new(),
+            by_device: BTreeMap::new(),
+            errors: Vec::new(),
+            implausible_dates: Vec::new(),
+            largest_files: Vec::new(),
+            slowest_operations: Vec::new(),
+            top_n: DEFAULT_TOP_N
+        }
+    }
+
+    /// keep the `n` largest files and slowest operations, 0 disables both lists
+    pub fn set_top_n(&mut self, n: usize) {
+        self.top_n = n;
+        self.largest_files.truncate(n);
+        self.slowest_operations.truncate(n);
+    }
+
+    /// record the size of a moved or copied file and the duration of the operation
+    pub fn record_transfer(&mut self, path: &Path, size: u64, duration: Duration) {
+        insert_top(&mut self.largest_files, (path.to_path_buf(), size), self.top_n);
+        insert_top(&mut self.slowest_operations, (path.to_path_buf(), duration), self.top_n);
+    }
+
+    pub fn largest_files(&self) -> &[(PathBuf, u64)] {
+        self.largest_files.as_slice()
+    }
+
+    pub fn slowest_operations(&self) -> &[(PathBuf, Duration)] {
+        self.slowest_operations.as_slice()
+    }
+
+    /// count a successfully moved or copied file
+    pub fn record_success(&mut self, size: u64, file_type: &str, segment: &str) {
+        self.count_success += 1;
+        self.bytes_processed += size;
+        *self.by_file_type.entry(String::from(file_type)).or_insert(0) += 1;
+        *self.by_segment.entry(String::from(segment)).or_insert(0) += 1;
+    }
+
+    /// count a file with the metadata `meta` processed without error in the histograms by the
+    /// month of its timestamp and its make and model, see [Report::histogram]
+    pub fn record_histogram(&mut self, meta: &ImgMeta) {
+        let month = meta.created_at()
+            .map_or(String::from(HISTOGRAM_UNKNOWN), |ts| ts.format("%Y-%m").to_string());
+        let device = format!("{} {}", meta.make().trim(), meta.model().trim());
+        let device = match device.trim() {
+            "" => HISTOGRAM_UNKNOWN,
+            d => d
+        };
+        *self.by_month.entry(month).or_insert(0) += 1;
+        *self.by_device.entry(String::from(device)).or_insert(0) += 1;
+    }
+
+    /// render the files per month and per device, e.g. to validate that an import covered the
+    /// expected date range
+    pub fn histogram(&self, format: HistogramFormat) -> String {
+        let sections = [("month", &self.by_month), ("device", &self.by_device)];
+        let mut out = String::new();
+        match format {
+            HistogramFormat::Text => {
+                for (name, counts) in sections.iter() {
+                    let max = counts.values().copied().max().unwrap_or(0);
+                    let width = counts.keys().map(|k| k.chars().count()).max().unwrap_or(0);
+                    out.push_str(format!("files per {}:\n", name).as_str());
+                    for (key, count) in counts.iter() {
+                        let bar = (count * HISTOGRAM_WIDTH as u64 + max - 1) / max.max(1);
+                        out.push_str(format!("  {:<width$} {:>8} {}\n", key, count, "#".repeat(bar as usize), width = width).as_str());
+                    }
+                }
+            },
+            HistogramFormat::Csv => {
+                out.push_str("category,key,count\n");
+                for (name, counts) in sections.iter() {
+                    for (key, count) in counts.iter() {
+                        out.push_str(format!("{},\"{}\",{}\n", name, key.replace('"', "\"\""), count).as_str());
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// count an error and keep its details as long as there are less than [MAX_REPORTED_ERRORS]
+    pub fn record_error(&mut self, path: PathBuf, error: String) {
+        self.count_error += 1;
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push((path, error));
+        }
+    }
+
+    /// count a file with an implausible timestamp and keep its path as long as there are less
+    /// than [MAX_REPORTED_ERRORS]
+    pub fn record_implausible_date(&mut self, path: PathBuf) {
+        self.count_implausible_date += 1;
+        if self.implausible_dates.len() < MAX_REPORTED_ERRORS {
+            self.implausible_dates.push(path);
+        }
+    }
+
+    /// the recorded files with implausible timestamps
+    pub fn implausible_dates(&self) -> &[PathBuf] {
+        self.implausible_dates.as_slice()
+    }
+
+    /// serialize the report into a pretty-printed JSON document
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// the recorded errors, which may be less than [Report::count_error]
+    pub fn errors(&self) -> &[(PathBuf, String)] {
+        self.errors.as_slice()
     }
 
     pub fn add(&mut self, other: Report) {
         self.count_duplicate += other.count_duplicate;
         self.count_skipped += other.count_skipped;
         self.count_success += other.count_success;
+        self.count_error += other.count_error;
+        self.count_vanished += other.count_vanished;
+        self.bytes_processed += other.bytes_processed;
+        for (k, v) in other.by_file_type {
+            *self.by_file_type.entry(k).or_insert(0) += v;
+        }
+        for (k, v) in other.by_segment {
+            *self.by_segment.entry(k).or_insert(0) += v;
+        }
+        for (k, v) in other.by_month {
+            *self.by_month.entry(k).or_insert(0) += v;
+        }
+        for (k, v) in other.by_device {
+            *self.by_device.entry(k).or_insert(0) += v;
+        }
+        self.count_implausible_date += other.count_implausible_date;
+        let free = MAX_REPORTED_ERRORS.saturating_sub(self.errors.len());
+        self.errors.extend(other.errors.into_iter().take(free));
+        let free = MAX_REPORTED_ERRORS.saturating_sub(self.implausible_dates.len());
+        self.implausible_dates.extend(other.implausible_dates.into_iter().take(free));
+        for entry in other.largest_files {
+            insert_top(&mut self.largest_files, entry, self.top_n);
+        }
+        for entry in other.slowest_operations {
+            insert_top(&mut self.slowest_operations, entry, self.top_n);
+        }
     }
 }
 impl Display for Report {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "  success  : {}\n  skipped  : {}\n  duplicate: {}\n", self.count_success, self.count_skipped, self.count_duplicate)
+        writeln!(f, "  success  : {}\n  skipped  : {}\n  duplicate: {}\n  error    : {}\n  bytes    : {}",
+                 self.count_success, self.count_skipped, self.count_duplicate, self.count_error,
+                 format_byte_size(self.bytes_processed))?;
+        if !self.by_file_type.is_empty() {
+            writeln!(f, "  by file type:")?;
+            for (ft, count) in &self.by_file_type {
+                writeln!(f, "    {:<20} {:>8}", ft, count)?;
+            }
+        }
+        if !self.by_segment.is_empty() {
+            writeln!(f, "  by target folder:")?;
+            for (seg, count) in &self.by_segment {
+                writeln!(f, "    {:<20} {:>8}", seg, count)?;
+            }
+        }
+        if !self.largest_files.is_empty() {
+            writeln!(f, "  largest files:")?;
+            for (path, size) in &self.largest_files {
+                writeln!(f, "    {:>12} \"{}\"", format_byte_size(*size), path.to_str().unwrap_or(PATHSTR_FB))?;
+            }
+        }
+        if !self.slowest_operations.is_empty() {
+            writeln!(f, "  slowest operations:")?;
+            for (path, duration) in &self.slowest_operations {
+                writeln!(f, "    {:>11.3}s \"{}\"", duration.as_secs_f64(), path.to_str().unwrap_or(PATHSTR_FB))?;
+            }
+        }
+        if self.count_vanished > 0 {
+            writeln!(f, "  vanished : {}", self.count_vanished)?;
+        }
+        if self.count_implausible_date > 0 {
+            writeln!(f, "  implausible dates: {}", self.count_implausible_date)?;
+            for path in &self.implausible_dates {
+                writeln!(f, "    \"{}\"", path.to_str().unwrap_or(PATHSTR_FB))?;
+            }
+        }
+        if self.count_error > 0 {
+            writeln!(f, "  errors:")?;
+        }
+        for (path, error) in &self.errors {
+            writeln!(f, "    \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), error)?;
+        }
+        if self.count_error > self.errors.len() as u64 {
+            writeln!(f, "    ... and {} more", self.count_error - self.errors.len() as u64)?;
+        }
+        Ok(())
     }
 }
 
+/// format a size in bytes with the largest fitting binary unit, e.g. `1.50 GiB`
+pub fn format_byte_size(bytes: u64) -> String {
+    let units = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for u in units.iter() {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = u;
+    }
+    format!("{:.2} {}", size, unit)
+}
+
 impl Pipeline {
 
     pub fn new(processor: MetaProcessor, sorter: Sorter, sorting_operation: Operation, target_root: &Path, dup_handling: DuplicateResolution) -> Pipeline {
+        let mut pipeline = Self::sorting_only(sorter, sorting_operation, target_root, dup_handling);
+        pipeline.processor = Some(processor);
+        pipeline
+    }
+
+    /// create a pipeline for files whose metadata has already been processed, e.g. the IO stage
+    /// behind [MetaWorker]s
+    pub fn sorting_only(sorter: Sorter, sorting_operation: Operation, target_root: &Path, dup_handling: DuplicateResolution) -> Pipeline {
         Pipeline {
-            processor,
+            processor: None,
             sorter,
             sorting_operation,
             target_root: target_root.to_path_buf(),
             dup_handling,
-            report: Report::new()
+            report: Report::new(),
+            checkpoint: None,
+            manifest: None,
+            post_action: None,
+            observer: None,
+            preview: false,
+            date_range: None,
+            #[cfg(feature = "catalog")]
+            catalog: None,
+            log: None
         }
     }
 
-    pub fn run(&mut self, rx: mpsc::Receiver<Request<ImgInfo>>) {
+    /// keep the `n` largest files and slowest operations in the report, see [Report::set_top_n]
+    pub fn set_top_n(&mut self, n: usize) {
+        self.report.set_top_n(n);
+    }
+
+    /// record the source paths of processed files in `checkpoint`, see [Checkpoint]
+    pub fn set_checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.checkpoint = Some(checkpoint);
+    }
+
+    /// record the hashes of moved and copied files in `manifest`, see [Manifest]
+    pub fn set_manifest(&mut self, manifest: Manifest) {
+        self.manifest = Some(manifest);
+    }
+
+    /// run `hook` after every moved or copied file, see [PostActionHook]
+    pub fn set_post_action(&mut self, hook: PostActionHook) {
+        self.post_action = Some(hook);
+    }
+
+    /// pass the progress of every file to `observer`, see [SortObserver]
+    pub fn set_observer(&mut self, observer: Arc<dyn SortObserver>) {
+        self.observer = Some(observer);
+    }
+
+    /// calculate the target of every file once its metadata has been processed and pass it to
+    /// [SortObserver::on_target_previewed], e.g. to show where a file goes before it is sorted
+    pub fn set_preview(&mut self, b: bool) {
+        self.preview = b;
+    }
+
+    /// skip files with a metadata timestamp (or modification time if there is none) outside of
+    /// `range`
+    pub fn set_date_range(&mut self, range: DateRange) {
+        self.date_range = Some(range);
+    }
+
+    /// skip files recorded in `catalog` by a previous run and record sorted files in it, see
+    /// [Catalog]
+    #[cfg(feature = "catalog")]
+    pub fn set_catalog(&mut self, catalog: Catalog) {
+        self.catalog = Some(catalog);
+    }
+
+    /// send errors and skipped files to the logger connected to `log`
+    pub fn set_log(&mut self, log: Sender<LogReq>) {
+        self.log = Some(log);
+    }
+
+    pub fn report(&self) -> Report {
+        self.report.clone()
+    }
+
+    fn send_log(&self, msg: LogMsg) {
+        #[cfg(feature = "tracing")]
+        msg.trace();
+        if let Some(log) = &self.log {
+            // the logger only exits after all pipelines, a failed send has nowhere to be reported
+            let _ = log.send(LogReq::Msg(msg));
+        }
+    }
+
+    /// log a message about the file at `path` with the outcome `result` of processing it
+    fn log(&self, level: LogLevel, path: &Path, result: &'static str, msg: String) {
+        self.send_log(LogMsg::with_level(Self::sender_id(), level, msg)
+            .source(path)
+            .action(self.sorting_operation.to_str())
+            .result(result));
+    }
+
+    /// log that the file at `path` has been skipped, `target` is the existing file if there is one
+    fn log_skipped(&self, path: &Path, target: Option<&Path>, reason: &str) {
+        let mut msg = LogMsg::skipped(Self::sender_id(), format!("\"{}\" skipped, {}", path.to_str().unwrap_or(PATHSTR_FB), reason))
+            .source(path)
+            .action(self.sorting_operation.to_str())
+            .result("skipped");
+        if let Some(t) = target {
+            msg = msg.target(t);
+        }
+        self.send_log(msg);
+    }
+
+    /// log the file `req` moved or copied by the last action of the sorter, with the time and
+    /// the bytes it took
+    fn log_transfer(&self, req: &ImgInfo, result: &ActionResult, duration: Duration) {
+        let verb = match result {
+            ActionResult::Moved => "moved",
+            _ => "copied"
+        };
+        let target = self.sorter.last_target().unwrap_or_else(|| Path::new(PATHSTR_FB));
+        let msg = LogMsg::new(Self::sender_id(), format!("\"{}\" {} to \"{}\" ({} bytes in {} ms)",
+                                                          req.path().to_str().unwrap_or(PATHSTR_FB),
+                                                          verb,
+                                                          target.to_str().unwrap_or(PATHSTR_FB),
+                                                          req.size(),
+                                                          duration.as_millis()))
+            .source(req.path())
+            .target(target)
+            .action(self.sorting_operation.to_str())
+            .result(verb)
+            .duration(duration)
+            .bytes(*req.size() as u64);
+        self.send_log(msg);
+    }
+
+    /// emit the outcome of a file for [console::OutputFormat::Json], simulated files are emitted
+    /// by the [crate::sorting::simulation::SimulationPrinter] of the sorter
+    fn emit_file(&self, path: &Path, result: &Result<ActionResult, SortError>) {
+        let (result, error) = match result {
+            Ok(ActionResult::Moved) => ("moved", None),
+            Ok(ActionResult::Copied) => ("copied", None),
+            Ok(ActionResult::Skipped) => ("skipped", None),
+            Err(SortError::SourceNotFound(_)) => ("vanished", None),
+            Err(e) => ("error", Some(e.to_string()))
+        };
+        let target = match result {
+            "moved" | "copied" => self.sorter.last_target(),
+            _ => None
+        };
+        console::emit(&Event::File {
+            source: path,
+            target,
+            result,
+            error
+        });
+    }
+
+    /// check if files are only simulated, by a simulating pipeline or in a dry run (see
+    /// [simulation::set_dry_run])
+    fn is_simulated(&self) -> bool {
+        simulation::is_dry_run() || matches!(self.sorting_operation, Operation::Print)
+    }
+
+    fn sender_id() -> String {
+        String::from(thread::current().name().unwrap_or("pipeline"))
+    }
+
+    pub fn sorter(&self) -> &Sorter {
+        &self.sorter
+    }
+
+    /// process requests from `rx` until [ControlMsg::Shutdown] is received. `control` is checked
+    /// for [ControlMsg::Pause] before each file.
+    pub fn run(&mut self, rx: mpsc::Receiver<Request<ImgInfo>>, control: mpsc::Receiver<ControlMsg>) {
         let mut callback: Option<Sender<ControlMsg>> = None;
         for request in &rx {
             match request {
-                Request::Input(req) => match self.process(req).unwrap() {
-                    ActionResult::Moved => {}
-                    ActionResult::Copied => {}
-                    ActionResult::Skipped => {}
+                Request::Input(req) => {
+                    wait_if_paused(&control);
+                    self.process_logged(req);
                 },
                 Request::Cmd(cmd) => {
                     match cmd {
@@ -82,7 +622,10 @@ impl Pipeline {
                             break;
                         }
                         ControlMsg::Ack | ControlMsg::AckReport(_) => {
-                            println!("[WARN] unexpected ControlMsg: Ack");
+                            console::warn("unexpected ControlMsg: Ack");
+                        }
+                        ControlMsg::Pause | ControlMsg::Resume => {
+                            console::warn("unexpected ControlMsg on request channel");
                         }
                     }
                 }
@@ -91,47 +634,340 @@ impl Pipeline {
 
         while let Ok(req) = rx.try_recv() {
             match req {
-                Request::Input(r) => self.process(r).unwrap(),
+                Request::Input(r) => {
+                    wait_if_paused(&control);
+                    self.process_logged(r);
+                },
                 Request::Cmd(_) => continue
             };
         }
         if let Some(cb) = callback {
-            cb.send(ControlMsg::AckReport(self.report.clone()));
+            let _ = cb.send(ControlMsg::AckReport(Box::new(self.report.clone())));
         }
     }
 
-    pub fn process(&mut self, mut req: ImgInfo) -> Result<ActionResult, String> {
+    /// process a request, printing errors instead of returning them so a single failing file
+    /// does not abort the whole pipeline. Returns the result if the file has been processed.
+    pub(crate) fn process_logged(&mut self, req: ImgInfo) -> Option<ActionResult> {
+        match self.process(req) {
+            Err(SortError::SourceNotFound(p)) => {
+                console::warn(format!("source vanished before it could be sorted: \"{}\"", p.to_str().unwrap_or(PATHSTR_FB)).as_str());
+                None
+            },
+            Err(e) => {
+                eprintln!("[{}] error processing file: {}", thread::current().name().unwrap_or("pipeline"), e);
+                None
+            },
+            Ok(r) => Some(r)
+        }
+    }
+
+    /// process a single file. Errors are recorded in the report of this pipeline before they
+    /// are returned.
+    pub fn process(&mut self, req: ImgInfo) -> Result<ActionResult, SortError> {
+        let path = req.path().to_path_buf();
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("sort_file",
+                                        path = path.to_str().unwrap_or(PATHSTR_FB),
+                                        pipeline = thread::current().name().unwrap_or("pipeline"),
+                                        operation = self.sorting_operation.to_str()).entered();
+        if let Some(observer) = &self.observer {
+            observer.on_file_started(path.as_path());
+        }
+        let result = self.process_file(req);
+        if let Some(observer) = &self.observer {
+            match &result {
+                Ok(r @ (ActionResult::Moved | ActionResult::Copied)) => observer.on_action_executed(path.as_path(), self.sorter.last_target(), r),
+                Ok(r) => observer.on_action_executed(path.as_path(), None, r),
+                Err(e) => observer.on_error(path.as_path(), e)
+            }
+        }
+        match &result {
+            Err(SortError::SourceNotFound(_)) => self.log(LogLevel::Warn, path.as_path(), "vanished", format!("source vanished before it could be sorted: \"{}\"", path.to_str().unwrap_or(PATHSTR_FB))),
+            Err(e) => self.log(LogLevel::Error, path.as_path(), "error", format!("error processing file \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), e)),
+            Ok(_) => ()
+        }
+        if !self.is_simulated() {
+            self.emit_file(path.as_path(), &result);
+        }
+        match (&result, &self.checkpoint) {
+            // the file has been deleted since it was scanned, there is nothing left to sort
+            (Err(SortError::SourceNotFound(_)), _) => self.report.count_vanished += 1,
+            (Err(e), _) => self.report.record_error(path, e.to_string()),
+            // simulated files have not actually been processed
+            (Ok(_), Some(checkpoint)) if !self.is_simulated() => {
+                checkpoint.record(path.as_path());
+            },
+            _ => ()
+        }
+        result
+    }
+
+    fn process_file(&mut self, mut req: ImgInfo) -> Result<ActionResult, SortError> {
         // process metadata
-        self.processor.process(&mut req);
+        if let Some(processor) = &self.processor {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!("metadata").entered();
+            processor.process(&mut req);
+        }
+        self.sorter.apply_clock_drift(&mut req);
+        if !self.sorter.check_date(&mut req) {
+            self.report.record_implausible_date(req.path().to_path_buf());
+        }
+        if let (true, Some(observer)) = (self.preview, &self.observer) {
+            observer.on_target_previewed(&req, &self.sorter.preview(&req, self.target_root.as_path()));
+        }
+        if let Some(range) = &self.date_range {
+            if !range.contains(req.metadata().created_at().unwrap_or(req.changed_at())) {
+                self.log_skipped(req.path(), None, "outside of the date range");
+                self.report.count_skipped += 1;
+                return Ok(ActionResult::Skipped);
+            }
+        }
+        if self.sorter.skip_pair(&req) {
+            if let Operation::Print = self.sorting_operation {
+                self.sorter.simulation().print_skip(req.path(), "secondary file of a HEIC/JPEG pair");
+            }
+            self.log_skipped(req.path(), None, "secondary file of a HEIC/JPEG pair");
+            self.report.count_skipped += 1;
+            return Ok(ActionResult::Skipped);
+        }
+        #[cfg(feature = "catalog")]
+        let mut content_hash = None;
+        #[cfg(feature = "catalog")]
+        if let Some(catalog) = &self.catalog {
+            match catalog.find(&req) {
+                Ok(CatalogMatch::Imported(target)) => {
+                    if let Operation::Print = self.sorting_operation {
+                        self.sorter.simulation().print_skip(req.path(), format!("already imported as \"{}\"", target.to_str().unwrap_or(PATHSTR_FB)).as_str());
+                    }
+                    self.log_skipped(req.path(), Some(target.as_path()), format!("already imported as \"{}\"", target.to_str().unwrap_or(PATHSTR_FB)).as_str());
+                    self.report.count_skipped += 1;
+                    return Ok(ActionResult::Skipped);
+                },
+                Ok(CatalogMatch::New(hash)) => content_hash = Some(hash),
+                // the file is sorted anyway, at worst it ends up as a duplicate
+                Err(e) => console::warn(e.to_string().as_str())
+            }
+        }
 
         // translate into action
+        #[cfg(feature = "tracing")]
+        let translate_span = tracing::debug_span!("translate").entered();
         let action = match &self.sorting_operation {
             Operation::Copy => self.sorter.calc_copy(&req, self.target_root.as_path()),
             Operation::Move => self.sorter.calc_move(&req, self.target_root.as_path()),
             Operation::Print => self.sorter.calc_simulation(&req, self.target_root.as_path())
+        }?;
+        let skip_reason = match action.target_exists() {
+            true => format!("target \"{}\" already exists", action.get_target().to_str().unwrap_or(PATHSTR_FB)),
+            false => String::from("quota of the target branch exceeded")
+        };
+        let skip_target = match action.target_exists() {
+            true => Some(action.get_target().to_path_buf()),
+            false => None
         };
         if action.target_exists() {
             self.report.count_duplicate += 1;
         }
+        let segment = match action.get_target().strip_prefix(action.get_root()) {
+            Ok(rel) if rel.components().count() > 1 => rel.components().next()
+                .map_or(String::from("."), |c| c.as_os_str().to_string_lossy().to_string()),
+            _ => String::from(".")
+        };
+        #[cfg(feature = "catalog")]
+        let target = action.get_target().to_path_buf();
+        #[cfg(feature = "tracing")]
+        translate_span.exit();
         // execute action with policy check
+        let started = Instant::now();
+        #[cfg(feature = "tracing")]
+        let execute_span = tracing::debug_span!("execute", target = action.get_target().to_str().unwrap_or(PATHSTR_FB)).entered();
         let result = self.sorter.execute_checked(action, &self.dup_handling)?;
+        #[cfg(feature = "tracing")]
+        execute_span.exit();
+        let duration = started.elapsed();
+        self.report.record_histogram(req.metadata());
         match result {
-            ActionResult::Moved | ActionResult::Copied => { self.report.count_success += 1; }
-            ActionResult::Skipped                      => { self.report.count_skipped += 1; }
+            ActionResult::Moved | ActionResult::Copied => {
+                let file_type = format!("{:?}", req.file_type());
+                self.report.record_success(*req.size() as u64, file_type.as_str(), segment.as_str());
+                self.report.record_transfer(req.path(), *req.size() as u64, duration);
+                self.log_transfer(&req, &result, duration);
+                // the target actually written, which differs from the planned one if it was renamed
+                if let (Some(manifest), Some(written)) = (&self.manifest, self.sorter.last_target()) {
+                    manifest.record(written);
+                }
+                if let (Some(hook), Some(written)) = (&self.post_action, self.sorter.last_target()) {
+                    let action = match result {
+                        ActionResult::Moved => "moved",
+                        _ => "copied"
+                    };
+                    hook.run(action, req.path(), written);
+                }
+                #[cfg(feature = "catalog")]
+                if let (Some(catalog), Some(hash)) = (&self.catalog, &content_hash) {
+                    catalog.record(&req, hash.as_str(), target.as_path());
+                }
+            }
+            ActionResult::Skipped                      => {
+                self.log_skipped(req.path(), skip_target.as_deref(), skip_reason.as_str());
+                self.report.count_skipped += 1;
+            }
         }
         Ok(result)
     }
 }
 
+/// The first stage of a two-stage pipeline: processes the metadata of files (CPU-bound) and
+/// forwards them round-robin to the sorting pipelines (IO-bound).
+pub struct MetaWorker {
+    processor: MetaProcessor,
+    outputs: Vec<mpsc::Sender<Request<ImgInfo>>>,
+    current_output: usize
+}
+
+impl MetaWorker {
+    pub fn new(processor: MetaProcessor, outputs: Vec<mpsc::Sender<Request<ImgInfo>>>) -> MetaWorker {
+        MetaWorker {
+            processor,
+            outputs,
+            current_output: 0
+        }
+    }
+
+    /// see [Pipeline::run]
+    pub fn run(&mut self, rx: mpsc::Receiver<Request<ImgInfo>>, control: mpsc::Receiver<ControlMsg>) {
+        let mut callback: Option<Sender<ControlMsg>> = None;
+        for request in &rx {
+            match request {
+                Request::Input(req) => {
+                    wait_if_paused(&control);
+                    self.forward(req)
+                },
+                Request::Cmd(ControlMsg::Shutdown(cb)) => {
+                    callback = Some(cb);
+                    break;
+                },
+                Request::Cmd(_) => console::warn("unexpected ControlMsg on request channel")
+            }
+        }
+
+        while let Ok(req) = rx.try_recv() {
+            if let Request::Input(r) = req {
+                wait_if_paused(&control);
+                self.forward(r);
+            }
+        }
+        if let Some(cb) = callback {
+            let _ = cb.send(ControlMsg::Ack);
+        }
+    }
+
+    fn forward(&mut self, mut req: ImgInfo) {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("metadata", path = req.path().to_str().unwrap_or(PATHSTR_FB)).entered();
+        self.processor.process(&mut req);
+        #[cfg(feature = "tracing")]
+        span.exit();
+        if self.outputs.is_empty() {
+            return;
+        }
+        if let Err(e) = self.outputs[self.current_output].send(Request::Input(req)) {
+            eprintln!("[{}] error forwarding request to pipeline[{}]: {}",
+                      thread::current().name().unwrap_or("metaworker"),
+                      self.current_output,
+                      e
+            );
+        }
+        self.current_output = (self.current_output + 1) % self.outputs.len();
+    }
+}
+
+/// Settings applied to every [Pipeline] created by a [PipelineController]
+#[derive(Clone, Default)]
+pub struct PipelineOptions {
+    pub checkpoint: Option<Checkpoint>,
+    pub manifest: Option<Manifest>,
+    pub post_action: Option<PostActionHook>,
+    pub observer: Option<Arc<dyn SortObserver>>,
+    /// see [Pipeline::set_preview]
+    pub preview: bool,
+    pub date_range: Option<DateRange>,
+    #[cfg(feature = "catalog")]
+    pub catalog: Option<Catalog>,
+    /// see [Pipeline::set_top_n]
+    pub top_n: Option<usize>,
+    /// see [Pipeline::set_log]
+    pub log: Option<Sender<LogReq>>
+}
+
+impl PipelineOptions {
+    pub fn apply(&self, pipeline: &mut Pipeline) {
+        if let Some(cp) = &self.checkpoint {
+            pipeline.set_checkpoint(cp.clone());
+        }
+        if let Some(manifest) = &self.manifest {
+            pipeline.set_manifest(manifest.clone());
+        }
+        if let Some(hook) = &self.post_action {
+            pipeline.set_post_action(hook.clone());
+        }
+        if let Some(observer) = &self.observer {
+            pipeline.set_observer(observer.clone());
+        }
+        pipeline.set_preview(self.preview);
+        if let Some(range) = self.date_range {
+            pipeline.set_date_range(range);
+        }
+        if let Some(n) = self.top_n {
+            pipeline.set_top_n(n);
+        }
+        if let Some(log) = &self.log {
+            pipeline.set_log(log.clone());
+        }
+        #[cfg(feature = "catalog")]
+        if let Some(catalog) = &self.catalog {
+            pipeline.set_catalog(catalog.clone());
+        }
+    }
+}
+
+/// the channels and handle of a thread started by the [PipelineController]
+struct WorkerHandle {
+    tx: mpsc::Sender<Request<ImgInfo>>,
+    control: mpsc::Sender<ControlMsg>,
+    handle: JoinHandle<()>
+}
+
 pub struct PipelineController {
-    threads: Vec<(mpsc::Sender<Request<ImgInfo>>, JoinHandle<()>)>,
+    /// threads receiving requests, either full pipelines or [MetaWorker]s
+    threads: Vec<WorkerHandle>,
+    /// sorting pipelines behind the [MetaWorker]s in two-stage mode
+    io_threads: Vec<WorkerHandle>,
     current_thread: usize,
     dir_manager_handle: Option<JoinHandle<()>>,
-    is_debug: bool
+    is_debug: bool,
+    /// see [Report::set_top_n], applied to the combined report
+    top_n: usize
 }
 
 impl PipelineController {
-    pub fn new(thread_count: usize, proc_cfg: MetaProcessorBuilder, mut sorter_cfg: SorterBuilder, sorting_operation: Operation, target_root: &Path, dup_handling: DuplicateResolution) -> PipelineController {
+    /// create `thread_count` pipelines, each processing metadata and sorting its files
+    pub fn new(thread_count: usize, proc_cfg: &MetaProcessorBuilder, sorter_cfg: &mut SorterBuilder, sorting_operation: Operation, target_root: &Path, dup_handling: DuplicateResolution, options: &PipelineOptions) -> PipelineController {
+        Self::create(0, thread_count, proc_cfg, sorter_cfg, sorting_operation, target_root, dup_handling, options)
+    }
+
+    /// create a two-stage pipeline with `meta_threads` workers processing metadata and feeding
+    /// `io_threads` pipelines which only sort files, so CPU and disk can be saturated
+    /// independently
+    #[allow(clippy::too_many_arguments)]
+    pub fn two_stage(meta_threads: usize, io_threads: usize, proc_cfg: &MetaProcessorBuilder, sorter_cfg: &mut SorterBuilder, sorting_operation: Operation, target_root: &Path, dup_handling: DuplicateResolution, options: &PipelineOptions) -> PipelineController {
+        Self::create(meta_threads.max(1), io_threads.max(1), proc_cfg, sorter_cfg, sorting_operation, target_root, dup_handling, options)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create(meta_threads: usize, thread_count: usize, proc_cfg: &MetaProcessorBuilder, sorter_cfg: &mut SorterBuilder, sorting_operation: Operation, target_root: &Path, dup_handling: DuplicateResolution, options: &PipelineOptions) -> PipelineController {
         let mut threads = Vec::with_capacity(thread_count);
 
         let (tx_dm, rx_dm) = mpsc::channel::<DirCreationRequest>();
@@ -142,27 +978,54 @@ impl PipelineController {
                 dm.run(rx_dm);
             }).unwrap();
 
+        // targets claimed by any pipeline thread during this run
+        let reservations = TargetReservations::new();
+
         for i in 0..thread_count {
             let (tx, rx) = mpsc::channel::<Request<ImgInfo>>();
-            let processor = proc_cfg.build_clone();
-            let sorter = sorter_cfg.build_async(tx_dm.clone());
-            let mut pipeline = Pipeline::new(processor, sorter, sorting_operation.clone(), target_root, dup_handling);
+            let (tx_ctl, rx_ctl) = mpsc::channel::<ControlMsg>();
+            let sorter = sorter_cfg.build_async(tx_dm.clone(), reservations.clone());
+            let mut pipeline = match meta_threads {
+                0 => Pipeline::new(proc_cfg.build_clone(), sorter, sorting_operation, target_root, dup_handling),
+                _ => Pipeline::sorting_only(sorter, sorting_operation, target_root, dup_handling)
+            };
+            options.apply(&mut pipeline);
             let t = thread::Builder::new()
                 .name(format!("pipeline{:03}", i))
                 .spawn(move || {
-                    pipeline.run(rx);
+                    pipeline.run(rx, rx_ctl);
                 }).unwrap();
-            threads.push((tx, t));
+            threads.push(WorkerHandle { tx, control: tx_ctl, handle: t });
         }
 
         //drop tx_dm so if sorters are dropped the DM thread exits the rec loop
         drop(tx_dm);
 
+        let mut io_threads = Vec::new();
+        if meta_threads > 0 {
+            io_threads = threads;
+            threads = Vec::with_capacity(meta_threads);
+            for i in 0..meta_threads {
+                let (tx, rx) = mpsc::channel::<Request<ImgInfo>>();
+                let (tx_ctl, rx_ctl) = mpsc::channel::<ControlMsg>();
+                let outputs = io_threads.iter().map(|w| w.tx.clone()).collect();
+                let mut worker = MetaWorker::new(proc_cfg.build_clone(), outputs);
+                let t = thread::Builder::new()
+                    .name(format!("metaworker{:03}", i))
+                    .spawn(move || {
+                        worker.run(rx, rx_ctl);
+                    }).unwrap();
+                threads.push(WorkerHandle { tx, control: tx_ctl, handle: t });
+            }
+        }
+
         PipelineController{
             threads: threads,
+            io_threads,
             current_thread: 0,
             dir_manager_handle: Some(dm_handle),
-            is_debug: false
+            is_debug: false,
+            top_n: options.top_n.unwrap_or(DEFAULT_TOP_N)
         }
     }
 
@@ -171,15 +1034,18 @@ impl PipelineController {
     }
 
     pub fn process(&mut self, request: ImgInfo) {
-        assert!(self.current_thread < self.threads.len());
-        let (tx, _) = self.threads.get(self.current_thread).unwrap();
-        match tx.send(Request::Input(request)) {
-            Ok(_) => (),
-            Err(e) => {
-                eprintln!("[PipelineControl] error sending request to pipeline[{}]: {}", self.current_thread, e);
-                panic!();
+        let tx = match self.threads.get(self.current_thread) {
+            Some(w) => &w.tx,
+            None => {
+                eprintln!("[PipelineControl] no pipeline available for processing: {}",
+                          request.path().to_str().unwrap_or(PATHSTR_FB));
+                return;
             }
         };
+        // a failed send means the pipeline thread is gone, the request is dropped
+        if let Err(e) = tx.send(Request::Input(request)) {
+            eprintln!("[PipelineControl] error sending request to pipeline[{}]: {}", self.current_thread, e);
+        }
 
         if self.current_thread >= self.threads.len() - 1 {
             self.current_thread = 0;
@@ -189,25 +1055,59 @@ impl PipelineController {
         }
     }
 
+    /// suspend all threads once they have finished their current file. Pending requests are kept
+    /// and processed after [Self::resume].
+    pub fn pause(&self) {
+        self.send_control(|| ControlMsg::Pause);
+    }
+
+    /// continue processing after [Self::pause]
+    pub fn resume(&self) {
+        self.send_control(|| ControlMsg::Resume);
+    }
+
+    fn send_control<F: Fn() -> ControlMsg>(&self, msg: F) {
+        for (p, w) in self.threads.iter().chain(self.io_threads.iter()).enumerate() {
+            if let Err(e) = w.control.send(msg()) {
+                eprintln!("[PipelineControl] error sending control message to thread[{}]: {}", p, e);
+            }
+        }
+    }
+
     pub fn shutdown(mut self) -> Report {
-        let mut p = 0;
+        // paused threads could never finish their pending requests
+        self.resume();
         let mut report = Report::new();
-        for (tx, handle) in self.threads {
+        report.set_top_n(self.top_n);
+        // the first stage has to be finished before the second stage can be shut down, as it
+        // may still forward pending requests
+        Self::shutdown_stage(self.threads, self.is_debug, &mut report);
+        Self::shutdown_stage(self.io_threads, self.is_debug, &mut report);
+        if let Some(handle) = self.dir_manager_handle.take() {
+            let _ = handle.join();
+        }
+        report
+    }
+
+    /// shut down all threads of a stage, waiting for them to finish processing pending requests
+    /// and adding their reports to `report`
+    fn shutdown_stage(threads: Vec<WorkerHandle>, is_debug: bool, report: &mut Report) {
+        for (p, WorkerHandle { tx, handle, .. }) in threads.into_iter().enumerate() {
             let (cb_tx, cb_rx) = mpsc::channel::<ControlMsg>();
             // send shutdown cmd to allow processing pending requests
-            tx.send(Request::Cmd(ControlMsg::Shutdown(cb_tx)));
+            let _ = tx.send(Request::Cmd(ControlMsg::Shutdown(cb_tx)));
 
             // try 5 times to receive ACK
-            for i in 0..5 {
+            for _ in 0..5 {
                 // maybe a timeout rec should be done here
                 match cb_rx.recv() {
                     Ok(response) => match response {
                         ControlMsg::Ack => break,
                         ControlMsg::AckReport(rep) => {
-                            if self.is_debug {
+                            if is_debug {
                                 println!("=== pipeline[{:02}]===\n{}", p, &rep);
                             }
-                            report.add(rep);
+                            report.add(*rep);
                             break;
                         },
                         _ => ()
@@ -215,10 +1115,57 @@ impl PipelineController {
                     Err(e) => eprintln!("Failed to receive callback for pipeline[{}]: {}", p, e)
                 }
             }
-            handle.join();
-            p += 1;
+            let _ = handle.join();
         }
-        self.dir_manager_handle.take().expect("[PipelineController] failed to join DirManager: is None").join();
-        report
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod report {
+        use std::path::{Path, PathBuf};
+        use std::time::Duration;
+
+        use chrono::{Local, TimeZone};
+
+        use crate::media::ImgMeta;
+        use crate::pipeline::{HistogramFormat, Report};
+
+        #[test]
+        fn keeps_top_n_when_merged() {
+            let mut report = Report::new();
+            report.set_top_n(2);
+            let mut other = report.clone();
+            report.record_transfer(Path::new("a"), 10, Duration::from_millis(30));
+            report.record_transfer(Path::new("b"), 30, Duration::from_millis(10));
+            other.record_transfer(Path::new("c"), 20, Duration::from_millis(20));
+            other.record_transfer(Path::new("d"), 5, Duration::from_millis(5));
+            report.add(other);
+            assert_eq!(report.largest_files(), &[(PathBuf::from("b"), 30), (PathBuf::from("c"), 20)]);
+            assert_eq!(report.slowest_operations()[0].0, PathBuf::from("a"));
+            assert_eq!(report.slowest_operations().len(), 2);
+        }
+
+        #[test]
+        fn buckets_by_month_and_device() {
+            let mut dated = ImgMeta::new();
+            dated.set_created_at(Some(Local.with_ymd_and_hms(2023, 8, 14, 10, 0, 0).unwrap()));
+            dated.set_make("Apple ");
+            dated.set_model("iPhone 12");
+            let mut model_only = ImgMeta::new();
+            model_only.set_model("EOS 80D");
+
+            let mut report = Report::new();
+            report.record_histogram(&dated);
+            report.record_histogram(&dated);
+            report.record_histogram(&model_only);
+            report.record_histogram(&ImgMeta::new());
+            assert_eq!(report.by_month.iter().collect::<Vec<_>>(), vec![(&String::from("2023-08"), &2), (&String::from("unknown"), &2)]);
+            assert_eq!(report.by_device.get("Apple iPhone 12"), Some(&2));
+            assert_eq!(report.by_device.get("EOS 80D"), Some(&1));
+            assert_eq!(report.by_device.get("unknown"), Some(&1));
+            assert!(report.histogram(HistogramFormat::Csv).contains("month,\"2023-08\",2\n"));
+        }
+    }
+}
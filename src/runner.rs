@@ -0,0 +1,254 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+
+#[cfg(feature = "catalog")]
+use crate::catalog::Catalog;
+use crate::checkpoint::Checkpoint;
+use crate::hook::PostActionHook;
+use crate::manifest::Manifest;
+use crate::observer::SortObserver;
+use crate::media::ImgInfo;
+use crate::media::metadata_processor::MetaProcessorBuilder;
+use crate::index::{DateRange, Scanner, ScannerBuilder};
+use crate::logging::LogReq;
+use crate::logging::console::{self, Verbosity};
+use crate::pipeline::{Pipeline, PipelineController, PipelineOptions, Report};
+use crate::sorting::{ActionResult, DuplicateResolution, Operation, PATHSTR_FB, SorterBuilder};
+#[cfg(feature = "watch")]
+use crate::watch::DropWatcher;
+
+/// Runs a complete sort: scans the input and feeds all files through the pipeline. This is the
+/// entry point for the CLI as well as library users.
+///
+/// Files are processed on the calling thread if the thread count is 0, otherwise a
+/// [PipelineController] is used, optionally with separate metadata workers. Both cases share
+/// the same flow and produce the same [Report].
+pub struct Runner {
+    scanner: Scanner,
+    proc_builder: MetaProcessorBuilder,
+    sorter_builder: SorterBuilder,
+    output_dir: PathBuf,
+    operation: Operation,
+    dup_policy: DuplicateResolution,
+    thread_count: usize,
+    meta_thread_count: usize,
+    options: PipelineOptions,
+    interrupt: Arc<AtomicBool>,
+    debug: bool
+}
+
+impl Runner {
+    pub fn new(scanner: Scanner, proc_builder: MetaProcessorBuilder, sorter_builder: SorterBuilder, output_dir: &Path, operation: Operation, dup_policy: DuplicateResolution) -> Runner {
+        Runner {
+            scanner,
+            proc_builder,
+            sorter_builder,
+            output_dir: output_dir.to_path_buf(),
+            operation,
+            dup_policy,
+            thread_count: 0,
+            meta_thread_count: 0,
+            options: PipelineOptions::default(),
+            interrupt: Arc::new(AtomicBool::new(false)),
+            debug: false
+        }
+    }
+
+    /// create the scanner of the input for sorting its files with `sorter_builder` and get the
+    /// operation to use instead of `operation`: HEIC/JPEG pairs are detected if the sorter has a
+    /// pair policy, and files are copied instead of moved out of a device backup, as moving them
+    /// would corrupt it
+    pub fn prepare_input(mut builder: ScannerBuilder, sorter_builder: &SorterBuilder, operation: Operation) -> Result<(Scanner, Operation), String> {
+        builder.set_pair_detection(sorter_builder.get_pair_policy().map(|p| p.preferred()));
+        let scanner = builder.build()?;
+        let operation = match operation {
+            Operation::Move if scanner.is_backup() => {
+                console::warn("the input is a device backup, its files are copied instead of moved");
+                Operation::Copy
+            },
+            op => op
+        };
+        Ok((scanner, operation))
+    }
+
+    /// process files on `thread_count` pipeline threads (0 processes them on the calling thread)
+    /// and read metadata on `meta_thread_count` separate threads (0 reads it in the pipelines)
+    pub fn set_threads(&mut self, thread_count: usize, meta_thread_count: usize) {
+        self.thread_count = thread_count;
+        self.meta_thread_count = meta_thread_count;
+    }
+
+    /// record processed files in `checkpoint`
+    pub fn set_checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.options.checkpoint = Some(checkpoint);
+    }
+
+    /// record the hashes of sorted files in `manifest`
+    pub fn set_manifest(&mut self, manifest: Manifest) {
+        self.options.manifest = Some(manifest);
+    }
+
+    /// run `hook` after every sorted file
+    pub fn set_post_action(&mut self, hook: PostActionHook) {
+        self.options.post_action = Some(hook);
+    }
+
+    /// pass the progress of every file and the final report to `observer`
+    pub fn set_observer(&mut self, observer: Arc<dyn SortObserver>) {
+        self.options.observer = Some(observer);
+    }
+
+    /// pass the target of every file to the observer before it is sorted, see
+    /// [Pipeline::set_preview]
+    pub fn set_preview(&mut self, b: bool) {
+        self.options.preview = b;
+    }
+
+    /// skip already imported files and record sorted ones in `catalog`, see [Catalog]
+    #[cfg(feature = "catalog")]
+    pub fn set_catalog(&mut self, catalog: Catalog) {
+        self.options.catalog = Some(catalog);
+    }
+
+    /// skip files with a metadata timestamp outside of `range`, see [Pipeline::set_date_range]
+    pub fn set_date_range(&mut self, range: DateRange) {
+        self.options.date_range = Some(range);
+    }
+
+    /// keep the `n` largest files and slowest operations in the report, see [Report::set_top_n]
+    pub fn set_top_n(&mut self, n: usize) {
+        self.options.top_n = Some(n);
+    }
+
+    /// send errors and skipped files to the logger connected to `log`, see [Pipeline::set_log]
+    pub fn set_log(&mut self, log: Sender<LogReq>) {
+        self.options.log = Some(log);
+    }
+
+    /// stop scanning (and watching) once `flag` is set, e.g. by a signal handler. Files already
+    /// passed to the pipelines are still processed and included in the report.
+    pub fn set_interrupt(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = flag;
+    }
+
+    /// check if the last run stopped early because the interrupt flag was set
+    pub fn interrupted(&self) -> bool {
+        self.interrupt.load(Ordering::Relaxed)
+    }
+
+    /// print the outcome of every file and the report of every pipeline, which is also done for
+    /// [Verbosity::Verbose]
+    pub fn debug(&mut self, b: bool) {
+        self.debug = b;
+    }
+
+    fn is_verbose(&self) -> bool {
+        self.debug || console::shows(Verbosity::Verbose)
+    }
+
+    /// the scanner of the input, e.g. to get the errors of the last run
+    pub fn scanner(&self) -> &Scanner {
+        &self.scanner
+    }
+
+    /// scan the input and process all files, returning the merged report of all pipelines
+    pub fn run(&mut self) -> Report {
+        let mut executor = self.create_executor();
+        self.scanner.scan_until(&self.interrupt, |file| executor.process(file));
+        self.finish(executor)
+    }
+
+    /// process all files in the input and then all files arriving in it as reported by
+    /// `watcher`, which has to watch the input, until no file arrived for `idle_timeout` (or
+    /// forever). Returns the merged report of all pipelines.
+    #[cfg(feature = "watch")]
+    pub fn watch(&mut self, watcher: &DropWatcher, idle_timeout: Option<std::time::Duration>) -> Result<Report, String> {
+        let mut executor = self.create_executor();
+        self.scanner.scan_until(&self.interrupt, |file| executor.process(file));
+        let scanner = &mut self.scanner;
+        let interrupt = &self.interrupt;
+        let result = watcher.run(idle_timeout, interrupt, |files| scanner.scan_paths_with(files, |file| executor.process(file)));
+        let report = self.finish(executor);
+        result.map(|_| report)
+    }
+
+    fn finish(&self, executor: Executor) -> Report {
+        let report = executor.finish();
+        if let Some(observer) = &self.options.observer {
+            observer.on_finished(&report);
+        }
+        report
+    }
+
+    fn create_executor(&mut self) -> Executor {
+        if self.thread_count == 0 {
+            let mut pipeline = Pipeline::new(
+                self.proc_builder.build_clone(),
+                self.sorter_builder.build_sync(),
+                self.operation,
+                self.output_dir.as_path(),
+                self.dup_policy
+            );
+            self.options.apply(&mut pipeline);
+            return Executor::Inline(Box::new(pipeline), self.is_verbose());
+        }
+
+        let mut controller = match self.meta_thread_count {
+            0 => PipelineController::new(
+                self.thread_count,
+                &self.proc_builder,
+                &mut self.sorter_builder,
+                self.operation,
+                self.output_dir.as_path(),
+                self.dup_policy,
+                &self.options
+            ),
+            n => PipelineController::two_stage(
+                n,
+                self.thread_count,
+                &self.proc_builder,
+                &mut self.sorter_builder,
+                self.operation,
+                self.output_dir.as_path(),
+                self.dup_policy,
+                &self.options
+            )
+        };
+        if self.is_verbose() {
+            controller.debug();
+        }
+        Executor::Threaded(controller)
+    }
+}
+
+/// processes the files of a [Runner] either on the calling thread or via a [PipelineController]
+enum Executor {
+    Inline(Box<Pipeline>, bool),
+    Threaded(PipelineController)
+}
+
+impl Executor {
+    fn process(&mut self, file: ImgInfo) {
+        match self {
+            Executor::Threaded(controller) => controller.process(file),
+            Executor::Inline(pipeline, debug) => {
+                let fpath = String::from(file.path().to_str().unwrap_or(PATHSTR_FB));
+                match pipeline.process_logged(file) {
+                    Some(ActionResult::Moved) if *debug => println!("moved \"{}\"", fpath),
+                    Some(ActionResult::Copied) if *debug => println!("copied \"{}\"", fpath),
+                    Some(ActionResult::Skipped) if *debug => println!("skipped \"{}\"", fpath),
+                    _ => ()
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> Report {
+        match self {
+            Executor::Inline(pipeline, _) => pipeline.report(),
+            Executor::Threaded(controller) => controller.shutdown()
+        }
+    }
+}
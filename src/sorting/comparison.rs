@@ -1,17 +1,43 @@
 use std::fs::{File, Metadata};
-use std::io::{BufReader, Read};
+use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path};
+use std::time::{Duration, SystemTime};
 
 use md5::{Digest, Md5};
 use md5::digest::generic_array::{GenericArray};
 use sha2::Sha256;
+use xxhash_rust::xxh3::Xxh3;
 
-pub static HASH_ALGO_NAMES: [(&str, HashAlgorithm); 3] = [("md5", HashAlgorithm::MD5), ("sha256", HashAlgorithm::SHA256), ("none", HashAlgorithm::None)];
+use crate::media::kadamak_exif::KadamakExifProcessor;
+use crate::sorting::hash_cache::HashCache;
 
+pub static HASH_ALGO_NAMES: [(&str, HashAlgorithm); 8] = [("md5", HashAlgorithm::MD5), ("sha256", HashAlgorithm::SHA256), ("blake3", HashAlgorithm::Blake3), ("xxh3", HashAlgorithm::Xxh3), ("bytes", HashAlgorithm::Bytes), ("metadata", HashAlgorithm::Metadata), ("size-mtime", HashAlgorithm::SizeMtime), ("none", HashAlgorithm::None)];
+/// default tolerance for modification times, the granularity of FAT32/exFAT timestamps
+pub const DEFAULT_MTIME_TOLERANCE_SECS: u64 = 2;
+/// offset of modification times shifted by daylight saving time, see [MtimeTolerance::dst_shift]
+pub const DST_SHIFT_SECS: u64 = 3600;
+/// default size of the blocks read from files when hashing or comparing them
+pub const DEFAULT_BUFFER_SIZE: usize = 1 << 20;
+
+/// Algorithms for comparing files by their content. [HashAlgorithm::Blake3] is considerably
+/// faster than the others on large files, e.g. videos. [HashAlgorithm::Xxh3] (128 bit XXH3) is
+/// not cryptographic, but faster still and sufficient to tell files apart when resolving
+/// duplicates. [HashAlgorithm::Bytes] does not hash at all, but compares both files block by
+/// block and stops at the first difference, which is cheaper than hashing both files on local
+/// disks and exact. [HashAlgorithm::Metadata] does not read the content either, but treats files
+/// as the same shot if their [crate::media::kadamak_exif::ShotSignature] matches, e.g. a photo
+/// and its recompressed copy from a phone. [HashAlgorithm::SizeMtime] only compares the size and
+/// modification time (see [MtimeTolerance]) and is the cheapest check that can still tell files
+/// apart.
 #[derive(Copy, Clone)]
 pub enum HashAlgorithm {
     MD5,
     SHA256,
+    Blake3,
+    Xxh3,
+    Bytes,
+    Metadata,
+    SizeMtime,
     None
 }
 impl HashAlgorithm {
@@ -27,6 +53,27 @@ impl HashAlgorithm {
         result
     }
 
+    /// the algorithm named `s` (case-insensitive) or `None` if there is none, unlike [Self::parse]
+    /// which falls back to [HashAlgorithm::None]
+    pub fn from_name(s: &str) -> Option<HashAlgorithm> {
+        let inp = s.to_lowercase();
+        HASH_ALGO_NAMES.iter().find(|o| o.0 == inp.as_str()).map(|o| o.1)
+    }
+
+    /// the name of the algorithm as listed in [HASH_ALGO_NAMES]
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::MD5 => "md5",
+            HashAlgorithm::SHA256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Xxh3 => "xxh3",
+            HashAlgorithm::Bytes => "bytes",
+            HashAlgorithm::Metadata => "metadata",
+            HashAlgorithm::SizeMtime => "size-mtime",
+            HashAlgorithm::None => "none"
+        }
+    }
+
     pub fn names() -> Vec<&'static str> {
         let mut names = Vec::new();
         for i in 0..HASH_ALGO_NAMES.len() {
@@ -36,6 +83,45 @@ impl HashAlgorithm {
     }
 }
 
+/// How far modification times of identical files may differ, e.g. after copying them between
+/// file systems. FAT32/exFAT cards store them with a granularity of 2 seconds and some tools
+/// shift them by an hour when daylight saving time changes.
+#[derive(Copy, Clone, Debug)]
+pub struct MtimeTolerance {
+    pub window: Duration,
+    /// also accept times that differ by one hour (plus or minus the window)
+    pub dst_shift: bool
+}
+
+impl MtimeTolerance {
+    pub fn new(window: Duration, dst_shift: bool) -> MtimeTolerance {
+        MtimeTolerance {
+            window,
+            dst_shift
+        }
+    }
+
+    /// check if `a` and `b` are the same time within the tolerance
+    pub fn matches(&self, a: SystemTime, b: SystemTime) -> bool {
+        let diff = match a.duration_since(b) {
+            Ok(d) => d,
+            Err(e) => e.duration()
+        };
+        let shift = Duration::from_secs(DST_SHIFT_SECS);
+        let shifted = match diff > shift {
+            true => diff - shift,
+            false => shift - diff
+        };
+        diff <= self.window || (self.dst_shift && shifted <= self.window)
+    }
+}
+
+impl Default for MtimeTolerance {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(DEFAULT_MTIME_TOLERANCE_SECS), false)
+    }
+}
+
 /// Different kinds of error that may happen when trying to compare files.
 ///
 /// # Variants:
@@ -69,6 +155,16 @@ impl ComparisonErr {
     pub fn access_denied<T>(c: Cause) -> Result<T, ComparisonErr> {
         Err(ComparisonErr::AccessDenied(c))
     }
+
+    /// split the error into the file that caused it and a human-readable reason
+    pub fn into_parts(self) -> (Cause, String) {
+        match self {
+            ComparisonErr::AccessDenied(c) => (c, String::from("access is denied")),
+            ComparisonErr::InvalidFile(c) => (c, String::from("file not found")),
+            ComparisonErr::Metadata(c) => (c, String::from("file metadata could not be read")),
+            ComparisonErr::Other(c, m) => (c, m.unwrap_or_else(|| String::from("unspecified error")))
+        }
+    }
 }
 
 /// Helper to indicate which of two files caused an error of [ComparisonErr], assuming a context in
@@ -106,7 +202,13 @@ pub enum Cause {
 
 pub struct FileComparer {
     ignore_zero_target: bool,
-    hash_algo: HashAlgorithm
+    hash_algo: HashAlgorithm,
+    /// only hash this many bytes at the start and end of each file, see [Self::partial_hash]
+    partial_size: Option<u64>,
+    /// size of the blocks read from files, see [Self::buffer_size]
+    buffer_size: usize,
+    cache: Option<HashCache>,
+    mtime_tolerance: MtimeTolerance
 }
 
 /// Type to wrap file comparison methods with different strategies (e.g. calculating a file hash).
@@ -123,10 +225,59 @@ impl FileComparer {
     pub fn new(ignore_zero_target: bool, hash_algo: HashAlgorithm) -> FileComparer {
         FileComparer{
             ignore_zero_target,
-            hash_algo
+            hash_algo,
+            partial_size: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            cache: None,
+            mtime_tolerance: MtimeTolerance::default()
         }
     }
 
+    /// set how far modification times may differ for [HashAlgorithm::SizeMtime]
+    pub fn mtime_tolerance(mut self, tolerance: MtimeTolerance) -> FileComparer {
+        self.mtime_tolerance = tolerance;
+        self
+    }
+
+    /// look up digests of unchanged files in `cache` instead of hashing them again and store
+    /// new digests in it, see [HashCache]
+    pub fn hash_cache(mut self, cache: Option<HashCache>) -> FileComparer {
+        self.cache = cache;
+        self
+    }
+
+    /// identifies the settings digests are calculated with, so cached digests are only used
+    /// with the same settings
+    pub fn cache_tag(&self) -> String {
+        match self.partial_size {
+            Some(size) => format!("{}/partial={}", self.hash_algo.name(), size),
+            None => String::from(self.hash_algo.name())
+        }
+    }
+
+    /// read files in blocks of `size` bytes (at least 1) when hashing or comparing them, see
+    /// [DEFAULT_BUFFER_SIZE]. Small blocks make hashing large files slow.
+    pub fn buffer_size(mut self, size: usize) -> FileComparer {
+        self.buffer_size = size.max(1);
+        self
+    }
+
+    /// only hash the first and last `size` bytes of files plus their size, trading certainty
+    /// for speed on huge files like videos. Files with identical start and end are considered
+    /// equal, so this should only be used if their content is not expected to differ in the
+    /// middle. Does not apply to [HashAlgorithm::Bytes] and [HashAlgorithm::Metadata].
+    pub fn partial_hash(mut self, size: Option<u64>) -> FileComparer {
+        self.partial_size = size;
+        self
+    }
+
+    /// get a comparer checking files byte by byte with the block size of this one, e.g. to
+    /// confirm that files matching by a partial hash, metadata or modification time are
+    /// identical before replacing one of them
+    pub fn byte_comparer(&self) -> FileComparer {
+        FileComparer::new(self.ignore_zero_target, HashAlgorithm::Bytes).buffer_size(self.buffer_size)
+    }
+
     /// check if two files match.
     ///
     /// **NOTE:** returns always `false` if `hash_algo` is `None` and both file sizes are equal.
@@ -141,6 +292,11 @@ impl FileComparer {
             return Err(ComparisonErr::InvalidFile(Cause::Target));
         }
 
+        // recompressed copies differ in size, so only their Exif data is compared
+        if let HashAlgorithm::Metadata = self.hash_algo {
+            return Ok(Self::compare_signatures(src, target));
+        }
+
         // read metadata
         let meta_src = match Self::read_metadata(src) {
             Some(m) => m,
@@ -156,25 +312,142 @@ impl FileComparer {
             return Ok(false);
         }
 
+        if let HashAlgorithm::SizeMtime = self.hash_algo {
+            let mtime_src = meta_src.modified().or_else(|_| ComparisonErr::metadata(Cause::Source))?;
+            let mtime_tgt = meta_tgt.modified().or_else(|_| ComparisonErr::metadata(Cause::Target))?;
+            return Ok(self.mtime_tolerance.matches(mtime_src, mtime_tgt));
+        }
+
+        if let HashAlgorithm::Bytes = self.hash_algo {
+            return self.compare_bytes(src, target);
+        }
+
         // file sizes match, calculate hashes
-        let result= match self.hash_algo {
-            HashAlgorithm::MD5 => Self::hash::<Md5>(src)? == Self::hash::<Md5>(target)?,
-            HashAlgorithm::SHA256 => Self::hash::<Sha256>(src)? == Self::hash::<Sha256>(target)?,
-            HashAlgorithm::None => false
+        let result = match (self.hash_file(src)?, self.hash_file(target)?) {
+            (Some(h_src), Some(h_tgt)) => h_src == h_tgt,
+            _ => false
         };
 
         Ok(result)
     }
 
+    /// calculate the hash of a file with the algorithm of this comparer, `None` if hashing is
+    /// turned off or files are compared by their content directly
+    pub fn hash_file(&self, path: &Path) -> Result<Option<Vec<u8>>, ComparisonErr> {
+        let cache = match &self.cache {
+            Some(c) => c,
+            None => return self.calc_hash(path)
+        };
+        let meta = match path.metadata() {
+            Ok(m) => m,
+            Err(_) => return ComparisonErr::metadata(Cause::NA)
+        };
+        let tag = self.cache_tag();
+        if let Some(digest) = cache.get(path, &meta, tag.as_str()) {
+            return Ok(Some(digest));
+        }
+        let hash = self.calc_hash(path)?;
+        if let Some(digest) = &hash {
+            cache.insert(path, &meta, tag.as_str(), digest.as_slice());
+        }
+        Ok(hash)
+    }
+
+    /// hash a file without using the cache, see [Self::hash_file]
+    fn calc_hash(&self, path: &Path) -> Result<Option<Vec<u8>>, ComparisonErr> {
+        let hash = match self.hash_algo {
+            HashAlgorithm::MD5 => self.digest::<Md5>(path)?.to_vec(),
+            HashAlgorithm::SHA256 => self.digest::<Sha256>(path)?.to_vec(),
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                self.read_chunks(path, |chunk| { hasher.update(chunk); })?;
+                hasher.finalize().as_bytes().to_vec()
+            },
+            HashAlgorithm::Xxh3 => {
+                let mut hasher = Xxh3::new();
+                self.read_chunks(path, |chunk| hasher.update(chunk))?;
+                hasher.digest128().to_be_bytes().to_vec()
+            },
+            HashAlgorithm::Bytes | HashAlgorithm::Metadata | HashAlgorithm::SizeMtime | HashAlgorithm::None => return Ok(None)
+        };
+        Ok(Some(hash))
+    }
+
+    /// check if two files are the same shot by their Exif data, see
+    /// [crate::media::kadamak_exif::ShotSignature]. Files without a complete signature never
+    /// match.
+    pub fn compare_signatures(src: &Path, target: &Path) -> bool {
+        match (KadamakExifProcessor::read_signature(src), KadamakExifProcessor::read_signature(target)) {
+            (Some(sig_src), Some(sig_tgt)) => sig_src == sig_tgt,
+            _ => false
+        }
+    }
+
+    /// compare two files of the same size block by block, stopping at the first difference
+    pub fn compare_bytes(&self, src: &Path, target: &Path) -> Result<bool, ComparisonErr> {
+        let open = |path: &Path, cause: Cause| match File::open(path) {
+            Ok(f) => Ok(f),
+            Err(e) => ComparisonErr::other_msg(cause, format!("error opening file: {}", e))
+        };
+        let mut file_src = open(src, Cause::Source)?;
+        let mut file_tgt = open(target, Cause::Target)?;
+        let mut block_src = vec![0u8; self.buffer_size];
+        let mut block_tgt = vec![0u8; self.buffer_size];
+        loop {
+            let n_src = match Self::fill_block(&mut file_src, &mut block_src) {
+                Ok(n) => n,
+                Err(e) => return ComparisonErr::other_msg(Cause::Source, format!("error while reading file: {}", e))
+            };
+            let n_tgt = match Self::fill_block(&mut file_tgt, &mut block_tgt) {
+                Ok(n) => n,
+                Err(e) => return ComparisonErr::other_msg(Cause::Target, format!("error while reading file: {}", e))
+            };
+            if n_src != n_tgt || block_src[..n_src] != block_tgt[..n_tgt] {
+                return Ok(false);
+            }
+            if n_src == 0 {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// read until `block` is full or the end of the file is reached, returning the bytes read
+    fn fill_block<R: Read>(reader: &mut R, block: &mut [u8]) -> std::io::Result<usize> {
+        let mut filled = 0;
+        while filled < block.len() {
+            match reader.read(&mut block[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(filled)
+    }
+
     /// calculate a file hash with algorithm `T`
     pub fn hash<T: Digest>(path: &Path) -> Result<GenericArray<u8, T::OutputSize>, ComparisonErr> {
+        Self::new(false, HashAlgorithm::None).digest::<T>(path)
+    }
+
+    /// calculate a file hash with algorithm `T` and the settings of this comparer
+    fn digest<T: Digest>(&self, path: &Path) -> Result<GenericArray<u8, T::OutputSize>, ComparisonErr> {
+        let mut hasher = T::new();
+        self.read_chunks(path, |chunk| hasher.update(chunk))?;
+        let result: GenericArray<u8, _> = hasher.finalize();
+        Ok(result)
+    }
+
+    /// read a file, passing its content to `consume` in chunks of the buffer size. If a partial
+    /// size is set (see [Self::partial_hash]) and the file is larger than twice of it, only its
+    /// size and first and last bytes are passed.
+    fn read_chunks<F: FnMut(&[u8])>(&self, path: &Path, mut consume: F) -> Result<(), ComparisonErr> {
         if !path.is_file() {
             return Err(ComparisonErr::InvalidFile(Cause::NA));
         }
 
-        let mut hasher = T::new();
-        let mut buffer: [u8; 64] = [0; 64];
-        let file = match File::open(path) {
+        let mut buffer = vec![0u8; self.buffer_size];
+        let mut file = match File::open(path) {
             Ok(f) => f,
             Err(e) => return ComparisonErr::other_msg(
                 Cause::NA,
@@ -182,26 +455,42 @@ impl FileComparer {
             )
         };
 
-        let mut reader = BufReader::new(file);
-        loop {
-            match reader.read(&mut buffer) {
-                Ok(n) => {
-                    if n > 0 {
-                        hasher.update(&buffer[0..n]);
-                    }
-                    else {
-                        break;
-                    }
-                },
-                Err(e) => return ComparisonErr::other_msg(
-                    Cause::NA,
-                    format!("error while reading file: {}", e)
-                )
+        let len = match file.metadata() {
+            Ok(m) => m.len(),
+            Err(_) => return ComparisonErr::metadata(Cause::NA)
+        };
+        let ranges = match self.partial_size {
+            Some(size) if len > size * 2 => {
+                consume(&len.to_le_bytes());
+                vec![(0, size), (len - size, size)]
+            },
+            // the whole file, even if it grows while being read
+            _ => vec![(0, u64::MAX)]
+        };
+        for (start, count) in ranges {
+            if let Err(e) = file.seek(SeekFrom::Start(start)) {
+                return ComparisonErr::other_msg(Cause::NA, format!("error while reading file: {}", e));
+            }
+            let mut range = (&mut file).take(count);
+            loop {
+                match range.read(&mut buffer) {
+                    Ok(n) => {
+                        if n > 0 {
+                            consume(&buffer[0..n]);
+                        }
+                        else {
+                            break;
+                        }
+                    },
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => return ComparisonErr::other_msg(
+                        Cause::NA,
+                        format!("error while reading file: {}", e)
+                    )
+                }
             }
         }
-
-        let result: GenericArray<u8, _> = hasher.finalize();
-        Ok(result)
+        Ok(())
     }
 
     fn read_metadata(f: &Path) -> Option<Metadata> {
@@ -220,4 +509,24 @@ impl FileComparer {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod mtime_tolerance {
+        use std::time::{Duration, SystemTime};
+
+        use crate::sorting::comparison::MtimeTolerance;
+
+        #[test]
+        fn accepts_granularity_and_dst_shift() {
+            let t = SystemTime::now();
+            let tolerance = MtimeTolerance::new(Duration::from_secs(2), true);
+            assert!(tolerance.matches(t, t + Duration::from_secs(2)));
+            assert!(tolerance.matches(t + Duration::from_secs(3601), t));
+            assert!(!tolerance.matches(t, t + Duration::from_secs(3)));
+            assert!(!MtimeTolerance::new(Duration::from_secs(2), false).matches(t, t + Duration::from_secs(3600)));
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::media::ImgInfo;
+use crate::sorting::comparison::FileComparer;
+
+/// What to do with the duplicates of a [DuplicateGroup].
+///
+/// # Variants
+/// - [DedupeAction::Report] only list them
+/// - [DedupeAction::Hardlink] replace each duplicate by a hard link to the kept file, so all
+///   paths stay valid while the content is stored once
+/// - [DedupeAction::Remove] delete the duplicates
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DedupeAction {
+    Report,
+    Hardlink,
+    Remove
+}
+
+/// Files with identical content, see [find_duplicates].
+pub struct DuplicateGroup {
+    pub size: u64,
+    /// the file that is kept, the first one in path order
+    pub kept: PathBuf,
+    pub duplicates: Vec<PathBuf>
+}
+
+/// All duplicates found by [find_duplicates] and the files that could not be hashed.
+pub struct DedupeResult {
+    pub groups: Vec<DuplicateGroup>,
+    pub errors: Vec<(PathBuf, String)>
+}
+
+impl DuplicateGroup {
+    /// bytes freed by removing or hard linking all duplicates
+    pub fn reclaimable(&self) -> u64 {
+        self.size * self.duplicates.len() as u64
+    }
+
+    /// apply `action` to all duplicates, returning the ones it failed for with the reason. Each
+    /// duplicate is compared to the kept file byte by byte first (see
+    /// [FileComparer::byte_comparer]), so files changed since they were hashed or only matching
+    /// by partial hash, metadata or modification time are never replaced.
+    pub fn apply(&self, action: DedupeAction, comparer: &FileComparer) -> Vec<(PathBuf, String)> {
+        let mut errors = Vec::new();
+        if action == DedupeAction::Report {
+            return errors;
+        }
+        let comparer = comparer.byte_comparer();
+        for dup in &self.duplicates {
+            match comparer.check_files_matching(self.kept.as_path(), dup.as_path()) {
+                Ok(true) => (),
+                Ok(false) => {
+                    errors.push((dup.clone(), String::from("content differs from the kept file")));
+                    continue;
+                },
+                Err(e) => {
+                    errors.push((dup.clone(), e.into_parts().1));
+                    continue;
+                }
+            }
+            let result = match action {
+                DedupeAction::Hardlink => hardlink(self.kept.as_path(), dup.as_path()),
+                DedupeAction::Remove => std::fs::remove_file(dup),
+                DedupeAction::Report => Ok(())
+            };
+            if let Err(e) = result {
+                errors.push((dup.clone(), e.to_string()));
+            }
+        }
+        errors
+    }
+}
+
+/// find files with identical content in `files`, e.g. all files of a sorted archive, which
+/// often contains the same photo in several folders after multiple device migrations. Only
+/// files sharing their size with another file are hashed with `comparer`, hard links of the
+/// kept file are not reported as duplicates. Groups are ordered by the path of the kept file.
+///
+/// If `comparer` does not hash files (see [FileComparer::hash_file]), each file is compared to
+/// one file of every group of the same size found so far instead.
+pub fn find_duplicates(files: Vec<ImgInfo>, comparer: &FileComparer) -> DedupeResult {
+    let mut by_size: HashMap<usize, Vec<PathBuf>> = HashMap::new();
+    for f in files {
+        by_size.entry(*f.size()).or_default().push(f.path().to_path_buf());
+    }
+
+    let mut result = DedupeResult {
+        groups: Vec::new(),
+        errors: Vec::new()
+    };
+    for (size, paths) in by_size {
+        if paths.len() < 2 {
+            continue;
+        }
+        let mut by_hash: HashMap<Vec<u8>, Vec<PathBuf>> = HashMap::new();
+        let mut unhashed: Vec<Vec<PathBuf>> = Vec::new();
+        for path in paths {
+            match comparer.hash_file(path.as_path()) {
+                Ok(Some(hash)) => by_hash.entry(hash).or_default().push(path),
+                Ok(None) => match find_matching(&unhashed, path.as_path(), comparer) {
+                    Ok(Some(i)) => unhashed[i].push(path),
+                    Ok(None) => unhashed.push(vec![path]),
+                    Err(e) => result.errors.push((path, e))
+                },
+                Err(e) => result.errors.push((path, e.into_parts().1))
+            }
+        }
+        for mut paths in by_hash.into_values().chain(unhashed) {
+            paths.sort();
+            let kept = paths.remove(0);
+            let duplicates: Vec<PathBuf> = paths.into_iter()
+                .filter(|p| !is_same_file(kept.as_path(), p.as_path()))
+                .collect();
+            if !duplicates.is_empty() {
+                result.groups.push(DuplicateGroup {
+                    size: size as u64,
+                    kept,
+                    duplicates
+                });
+            }
+        }
+    }
+    result.groups.sort_by(|a, b| a.kept.cmp(&b.kept));
+    result
+}
+
+/// find the index of the group in `groups` whose files match `path`
+fn find_matching(groups: &[Vec<PathBuf>], path: &Path, comparer: &FileComparer) -> Result<Option<usize>, String> {
+    for (i, group) in groups.iter().enumerate() {
+        match comparer.check_files_matching(group[0].as_path(), path) {
+            Ok(true) => return Ok(Some(i)),
+            Ok(false) => (),
+            Err(e) => return Err(e.into_parts().1)
+        }
+    }
+    Ok(None)
+}
+
+/// replace `dup` by a hard link to `kept`. The link is created next to `dup` and renamed over
+/// it, so `dup` is never missing.
+fn hardlink(kept: &Path, dup: &Path) -> io::Result<()> {
+    let mut name = dup.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(".dcim-sort-link");
+    let link = dup.with_file_name(name);
+    std::fs::hard_link(kept, &link)?;
+    std::fs::rename(&link, dup).inspect_err(|_| {
+        let _ = std::fs::remove_file(&link);
+    })
+}
+
+/// check if both paths are links to the same file
+#[cfg(unix)]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (a.metadata(), b.metadata()) {
+        (Ok(m_a), Ok(m_b)) => m_a.dev() == m_b.dev() && m_a.ino() == m_b.ino(),
+        _ => false
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod dedupe {
+        use crate::media::ImgInfo;
+        use crate::sorting::comparison::FileComparer;
+        use crate::sorting::dedupe::{DedupeAction, find_duplicates};
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn hardlinks_duplicates_across_folders() {
+            let dir = TempDir::new("dedupe");
+            std::fs::create_dir_all(dir.join("2019")).unwrap();
+            std::fs::create_dir_all(dir.join("2021")).unwrap();
+            std::fs::write(dir.join("2019/a.jpg"), b"content").unwrap();
+            std::fs::write(dir.join("2021/a.jpg"), b"content").unwrap();
+            std::fs::write(dir.join("2021/b.jpg"), b"differs").unwrap();
+            let files = ["2019/a.jpg", "2021/a.jpg", "2021/b.jpg"].iter()
+                .map(|f| ImgInfo::new(dir.join(f)).unwrap())
+                .collect();
+
+            let comparer = FileComparer::default();
+            let result = find_duplicates(files, &comparer);
+            assert_eq!(result.groups.len(), 1);
+            let group = &result.groups[0];
+            assert_eq!(group.kept, dir.join("2019/a.jpg"));
+            assert_eq!(group.duplicates, vec![dir.join("2021/a.jpg")]);
+            assert!(group.apply(DedupeAction::Hardlink, &comparer).is_empty());
+
+            // linked files are no duplicates anymore
+            let files = ["2019/a.jpg", "2021/a.jpg"].iter()
+                .map(|f| ImgInfo::new(dir.join(f)).unwrap())
+                .collect();
+            let linked = find_duplicates(files, &comparer);
+            if cfg!(unix) {
+                assert!(linked.groups.is_empty());
+            }
+        }
+
+        #[test]
+        fn removes_only_identical_content() {
+            let dir = TempDir::new("dedupe-partial");
+            std::fs::write(dir.join("a.mp4"), b"head-0000-tail").unwrap();
+            std::fs::write(dir.join("b.mp4"), b"head-1111-tail").unwrap();
+            let files = ["a.mp4", "b.mp4"].iter()
+                .map(|f| ImgInfo::new(dir.join(f)).unwrap())
+                .collect();
+
+            // only start and end are hashed, so both files are reported as duplicates
+            let comparer = FileComparer::default().partial_hash(Some(4));
+            let result = find_duplicates(files, &comparer);
+            assert_eq!(result.groups.len(), 1);
+            let failed = result.groups[0].apply(DedupeAction::Remove, &comparer);
+            assert_eq!(failed.len(), 1);
+            assert!(dir.join("b.mp4").is_file());
+        }
+    }
+}
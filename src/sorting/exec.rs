@@ -0,0 +1,144 @@
+use std::io;
+use std::path::Path;
+
+use crate::error::SortError;
+use crate::logging::console;
+#[cfg(feature = "rexiv2")]
+use crate::media::rexiv_proc::Rexiv2Processor;
+use crate::sorting::{ActionResult, Operation, PATHSTR_FB, SortAction};
+use crate::sorting::retry::RetryPolicy;
+use crate::sorting::throttle::Throttle;
+use crate::sorting::workspace::Workspace;
+
+/// `EXDEV` on Linux and macOS, which has no stable [io::ErrorKind]
+#[cfg(unix)]
+const EXDEV: i32 = 18;
+
+/// The final step of sorting a file: performing the operation of a [SortAction] after its target
+/// has been calculated, checked against the duplicate policy and quotas and reserved (see
+/// [crate::sorting::Sorter::execute_checked]). Replacing the executor of a sorter (see
+/// [crate::sorting::SorterBuilder::executor]) allows e.g. uploading files instead of copying them,
+/// while reusing everything before that step. Wrapping a [LocalExecutor] keeps the default
+/// behavior and allows adding to it.
+pub trait ActionExecutor {
+    /// perform `action`, returning what has actually been done. Only called for existing sources.
+    /// Actions with [Operation::Print] belong to simulated runs and must not change any file, the
+    /// [LocalExecutor] returns [ActionResult::Skipped] for them.
+    fn execute(&mut self, action: &SortAction) -> Result<ActionResult, SortError>;
+
+    /// whether the parent directory of the target has to exist before executing an action. If
+    /// true, the sorter creates missing directories first.
+    fn needs_target_dir(&self) -> bool {
+        true
+    }
+
+    fn clone_boxed(&self) -> Box<dyn ActionExecutor + Send>;
+}
+
+/// The default [ActionExecutor] moving or copying files on the local file system and printing
+/// simulated actions. Copies are made in the [Workspace] first if there is one and limited by the
+/// [Throttle] if there is one.
+#[derive(Clone)]
+pub struct LocalExecutor {
+    retry: RetryPolicy,
+    /// session ID written to the sidecars of copied files, see
+    /// `Rexiv2Processor::write_provenance`. Ignored without the `rexiv2` feature.
+    #[cfg_attr(not(feature = "rexiv2"), allow(dead_code))]
+    provenance: Option<String>,
+    workspace: Option<Workspace>,
+    throttle: Option<Throttle>
+}
+
+impl LocalExecutor {
+    pub fn new(retry: RetryPolicy, provenance: Option<String>, workspace: Option<Workspace>, throttle: Option<Throttle>) -> LocalExecutor {
+        LocalExecutor {
+            retry,
+            provenance,
+            workspace,
+            throttle
+        }
+    }
+
+    fn copy_file(&self, source: &Path, target: &Path) -> io::Result<u64> {
+        match &self.throttle {
+            Some(throttle) => throttle.copy(source, target),
+            None => std::fs::copy(source, target)
+        }
+    }
+
+    /// copy `source` to `target`, through a partial copy in the workspace if there is one
+    fn copy(&self, source: &Path, target: &Path) -> io::Result<u64> {
+        let workspace = match &self.workspace {
+            Some(ws) => ws,
+            None => return self.retry.run(|| self.copy_file(source, target))
+        };
+        std::fs::create_dir_all(workspace.dir())?;
+        if !workspace.same_file_system(target) {
+            return self.retry.run(|| self.copy_file(source, target));
+        }
+        let partial = workspace.partial_path();
+        let result = self.retry.run(|| self.copy_file(source, &partial))
+            .and_then(|bytes| self.retry.run(|| std::fs::rename(&partial, target)).map(|_| bytes));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&partial);
+        }
+        result
+    }
+}
+
+impl ActionExecutor for LocalExecutor {
+    fn execute(&mut self, action: &SortAction) -> Result<ActionResult, SortError> {
+        let (source, target) = (action.get_source(), action.get_target());
+        let result = match &action.operation {
+            // targets on another file system, e.g. routed to another volume, cannot be renamed to
+            Operation::Move => match self.retry.run(|| std::fs::rename(source, target)) {
+                Err(e) if crosses_devices(&e) => self.copy(source, target).and_then(|_| std::fs::remove_file(source)),
+                result => result
+            },
+            Operation::Copy => match self.copy(source, target) {
+                    Ok(bytes) => {
+                        if bytes == 0 {
+                            console::warn(format!("copied {} bytes for src=\"{}\"",
+                                                  bytes,
+                                                  source.to_str().unwrap_or(PATHSTR_FB)
+                            ).as_str());
+                        }
+                        Ok(())
+                    },
+                    Err(e) => Err(e)
+            },
+            // simulated actions are printed by the sorter
+            Operation::Print => Ok(())
+        };
+
+        #[cfg(feature = "rexiv2")]
+        if let (Ok(_), Operation::Copy, Some(session_id)) = (&result, &action.operation, &self.provenance) {
+            // the copy itself succeeded, so a failed sidecar is not an error of the action
+            if let Err(e) = Rexiv2Processor::write_provenance(target, session_id, source) {
+                console::warn(format!("could not write provenance of \"{}\": {}", target.to_str().unwrap_or(PATHSTR_FB), e).as_str());
+            }
+        }
+
+        match result {
+            Ok(_) => Ok(match &action.operation {
+                Operation::Print => ActionResult::Skipped,
+                Operation::Move => ActionResult::Moved,
+                Operation::Copy => ActionResult::Copied
+            }),
+            Err(e) => Err(SortError::io(action.operation.to_str(), source.to_path_buf(), e))
+        }
+    }
+
+    fn clone_boxed(&self) -> Box<dyn ActionExecutor + Send> {
+        Box::new(self.clone())
+    }
+}
+
+/// check if a rename failed because source and target are on different file systems
+fn crosses_devices(e: &io::Error) -> bool {
+    match e.raw_os_error() {
+        #[cfg(unix)]
+        Some(EXDEV) => true,
+        _ => false
+    }
+}
@@ -1,17 +1,19 @@
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use std::collections::{HashSet, VecDeque};
 use std::path::{Path, PathBuf};
-use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, mpsc};
 use std::sync::mpsc::Sender;
-use crate::sorting::PATHSTR_FB;
+use crate::error::{DirErrorKind, SortError};
+use crate::sorting::simulation;
 
+/// A request to the [DirManager] to create the directory `target`. The result, including why the
+/// directory could not be created, is sent to `callback`.
 pub struct DirCreationRequest {
     target: PathBuf,
-    callback: mpsc::Sender<bool>,
+    callback: mpsc::Sender<Result<(), SortError>>,
     cache_only: bool
 }
 impl DirCreationRequest {
-    pub fn new(path: &Path, callback: mpsc::Sender<bool>) -> DirCreationRequest {
+    pub fn new(path: &Path, callback: mpsc::Sender<Result<(), SortError>>) -> DirCreationRequest {
         DirCreationRequest {
             target: path.to_path_buf(),
             callback: callback,
@@ -19,7 +21,7 @@ impl DirCreationRequest {
         }
     }
 
-    pub fn new_simulating(path: &Path, callback: mpsc::Sender<bool>) -> DirCreationRequest {
+    pub fn new_simulating(path: &Path, callback: mpsc::Sender<Result<(), SortError>>) -> DirCreationRequest {
         DirCreationRequest {
             target: path.to_path_buf(),
             callback: callback,
@@ -28,67 +30,391 @@ impl DirCreationRequest {
     }
 }
 
-struct CachedPath {
-    hash: u64,
+/// A table of target paths being written during a run, shared between all pipeline threads. A
+/// sorter holds the [Reservation] of its target from checking the duplicate handling until the
+/// action has been executed, so sources translating to the same target path are handled one
+/// after another like in a single thread instead of racing on it and overwriting each other.
+#[derive(Clone)]
+pub struct TargetReservations {
+    reserved: Arc<(Mutex<HashSet<PathBuf>>, Condvar)>
+}
+impl TargetReservations {
+    pub fn new() -> TargetReservations {
+        TargetReservations {
+            reserved: Arc::new((Mutex::new(HashSet::new()), Condvar::new()))
+        }
+    }
+
+    /// claim `path`, waiting until a sorter holding it has finished
+    pub fn reserve(&self, path: &Path) -> Reservation {
+        let mut reserved = self.lock();
+        while reserved.contains(path) {
+            reserved = match self.reserved.1.wait(reserved) {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner()
+            };
+        }
+        reserved.insert(path.to_path_buf());
+        Reservation { table: self.clone(), path: path.to_path_buf() }
+    }
+
+    /// claim `path` if no other sorter holds it
+    pub fn try_reserve(&self, path: &Path) -> Option<Reservation> {
+        match self.lock().insert(path.to_path_buf()) {
+            true => Some(Reservation { table: self.clone(), path: path.to_path_buf() }),
+            false => None
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashSet<PathBuf>> {
+        match self.reserved.0.lock() {
+            Ok(guard) => guard,
+            // a panicking thread cannot leave the set in an inconsistent state
+            Err(poisoned) => poisoned.into_inner()
+        }
+    }
+}
+
+impl Default for TargetReservations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A target claimed in [TargetReservations], released when dropped.
+pub struct Reservation {
+    table: TargetReservations,
     path: PathBuf
 }
 
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.table.lock().remove(&self.path);
+        self.table.reserved.1.notify_all();
+    }
+}
+
+/// create the target root if it does not exist and check that files can be created in it, so an
+/// unusable target fails before processing any file instead of for every single file
+pub fn preflight(root: &Path) -> Result<(), SortError> {
+    if root.is_file() {
+        return Err(SortError::DirCreation {
+            path: root.to_path_buf(),
+            kind: DirErrorKind::NameConflict,
+            reason: String::from("a normal file with that name already exists!")
+        });
+    }
+    std::fs::create_dir_all(root).map_err(|e| SortError::dir_creation(root, &e))?;
+    let probe = root.join(format!(".dcim-sort-preflight-{}", std::process::id()));
+    let result = std::fs::OpenOptions::new().write(true).create_new(true).open(&probe)
+        .and_then(|mut f| std::io::Write::write_all(&mut f, b"preflight"));
+    let _ = std::fs::remove_file(&probe);
+    result.map_err(|e| SortError::DirCreation {
+        path: root.to_path_buf(),
+        kind: DirErrorKind::of(&e),
+        reason: format!("target is not writable: {}", e)
+    })
+}
+
+/// get `path` or its nearest ancestor which exists, e.g. to determine the file system a target
+/// directory will be created on. Relative paths without an existing ancestor resolve to `.`.
+pub fn nearest_existing(path: &Path) -> &Path {
+    path.ancestors()
+        .find(|p| p.exists())
+        .unwrap_or_else(|| Path::new("."))
+}
 
+/// get the space available to unprivileged users on the file system of `path`, or of its nearest
+/// existing ancestor if it does not exist yet
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> std::io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let existing = nearest_existing(path);
+    let c_path = CString::new(existing.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid NUL-terminated string and `stat` is a valid out pointer
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> std::io::Result<u64> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "free space can only be determined on unix"))
+}
+
+/// get the ID of the device containing `path`, `None` if it cannot be determined
+#[cfg(unix)]
+pub fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    path.metadata().ok().map(|m| m.dev())
+}
+
+#[cfg(not(unix))]
+pub fn device_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// An exclusive advisory lock on a lock file (`flock` on unix, `LockFileEx` on Windows), which
+/// guards state shared by runs, such as a checkpoint, against concurrent use. The lock is held
+/// until the value is dropped and released by the OS if the process ends, so a crashed run does
+/// not block later ones. On unix, the lock file is removed when the lock is released.
+pub struct FileLock {
+    path: PathBuf,
+    _file: std::fs::File
+}
+
+impl FileLock {
+    /// lock the file at `path`, creating it if needed. Returns `None` if another run (or another
+    /// handle of this run) holds the lock.
+    pub fn try_acquire(path: &Path) -> std::io::Result<Option<FileLock>> {
+        loop {
+            let mut file = std::fs::OpenOptions::new().write(true).create(true).truncate(false).open(path)?;
+            if !try_lock(&file)? {
+                return Ok(None);
+            }
+            // the previous holder may have removed the file between opening and locking it, in
+            // which case the lock does not guard the current file at `path`
+            if !is_same_file(&file, path) {
+                continue;
+            }
+            // the pid helps to find the run holding the lock
+            let _ = file.set_len(0).and_then(|_| std::io::Write::write_all(&mut file, format!("{}\n", std::process::id()).as_bytes()));
+            return Ok(Some(FileLock { path: path.to_path_buf(), _file: file }));
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // removed while still locked, the file is released when it is closed afterwards
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn try_lock(file: &std::fs::File) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+    // SAFETY: the descriptor is owned by `file` and stays open for the duration of the call
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let e = std::io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(e)
+    }
+}
+
+#[cfg(not(unix))]
+fn try_lock(file: &std::fs::File) -> std::io::Result<bool> {
+    match file.try_lock() {
+        Ok(_) => Ok(true),
+        Err(std::fs::TryLockError::WouldBlock) => Ok(false),
+        Err(std::fs::TryLockError::Error(e)) => Err(e)
+    }
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &std::fs::File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), path.metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_file: &std::fs::File, _path: &Path) -> bool {
+    // the lock file is never removed
+    true
+}
+
+/// Creates the target directories of all pipeline threads, remembering created directories so
+/// that each one is only created once. The number of remembered directories is limited, the
+/// oldest ones are forgotten first and created again (which is a no-op) if needed later.
 pub struct DirManager {
-    cache: Vec<u64>,
+    /// directories created (or simulated) before
+    cache: HashSet<PathBuf>,
+    /// cached directories in the order they were added
+    order: VecDeque<PathBuf>,
+    capacity: usize
 }
 
 impl DirManager {
+    /// number of directories remembered unless set by [DirManager::with_capacity]
+    pub const DEFAULT_CAPACITY: usize = 4096;
 
     pub fn new() -> DirManager {
+        DirManager::with_capacity(DirManager::DEFAULT_CAPACITY)
+    }
+
+    /// create a manager remembering at most `capacity` (at least 1) directories
+    pub fn with_capacity(capacity: usize) -> DirManager {
         DirManager {
-            cache: Vec::new(),
+            cache: HashSet::new(),
+            order: VecDeque::new(),
+            capacity: capacity.max(1)
         }
     }
 
     pub fn run(&mut self, rx_input: mpsc::Receiver<DirCreationRequest>) {
         for request in rx_input {
-            let tgt = request.target;
-            match self.create_path(tgt.as_path(), request.cache_only) {
-                Ok(_) => request.callback.send(true).unwrap(),
-                Err(e) => {
-                    eprintln!("[{}] failed to create path=\"{}\": {}",
-                        std::thread::current().name().unwrap_or("logmgr"),
-                        tgt.to_str().unwrap_or(PATHSTR_FB),
-                        e
-                    );
-                    request.callback.send(false).unwrap();
-                }
-            }
+            // errors are reported by the requesting sorter, a closed callback means it is gone
+            // and there is nothing to report to
+            let _ = request.callback.send(self.create_path(request.target.as_path(), request.cache_only));
         }
     }
 
-    pub fn create_path(&mut self, path: &Path, cache_only: bool) -> Result<(), String> {
-        let hash = Self::hash_path(path);
-        let mut is_cached = false;
-        for pp in &self.cache {
-            if *pp == hash {
-                return Ok(());
-            }
+    /// create `path` including all parents unless it has been created before. A cached path that
+    /// has been removed in the meantime (e.g. by another process) is created again.
+    pub fn create_path(&mut self, path: &Path, cache_only: bool) -> Result<(), SortError> {
+        let cache_only = cache_only || simulation::is_dry_run();
+        let is_cached = self.cache.contains(path);
+        if is_cached && (cache_only || path.is_dir()) {
+            return Ok(());
+        }
+        if !cache_only {
+            std::fs::create_dir_all(path).map_err(|e| SortError::dir_creation(path, &e))?;
+        }
+        if !is_cached {
+            self.remember(path);
         }
-        match cache_only {
-            false => match std::fs::create_dir_all(path) {
-                Err(e) => Err(format!("Failed to create destination directory: {}", e)),
-                Ok(_) => {
-                    self.cache.push(hash);
-                    Ok(())
-                }
-            },
-            true => {
-                self.cache.push(hash);
-                Ok(())
+        Ok(())
+    }
+
+    fn remember(&mut self, path: &Path) {
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.cache.remove(&oldest);
             }
         }
+        self.cache.insert(path.to_path_buf());
+        self.order.push_back(path.to_path_buf());
+    }
+
+    /// get the number of remembered directories
+    pub fn cached(&self) -> usize {
+        self.cache.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    #[cfg(unix)]
+    mod available_space {
+        use std::path::Path;
+
+        use crate::sorting::fs_support::{available_space, nearest_existing};
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn uses_existing_ancestor() {
+            let dir = TempDir::new("space");
+            assert!(available_space(&dir).unwrap() > 0);
+            let missing = dir.join("a/b");
+            assert_eq!(nearest_existing(missing.as_path()), dir.as_path());
+            assert!(available_space(missing.as_path()).unwrap() > 0);
+            assert_eq!(nearest_existing(Path::new("relative/missing")), Path::new("."));
+            assert!(available_space(Path::new("relative/missing")).unwrap() > 0);
+        }
     }
 
-    fn hash_path(path: &Path) -> u64 {
-        let mut hasher = DefaultHasher::new();
-        path.hash(&mut hasher);
-        hasher.finish()
+    mod file_lock {
+        use crate::sorting::fs_support::FileLock;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn is_exclusive_until_dropped() {
+            let dir = TempDir::new("file-lock");
+            let path = dir.join("state.lock");
+            let first = FileLock::try_acquire(path.as_path()).unwrap().unwrap();
+            assert!(FileLock::try_acquire(path.as_path()).unwrap().is_none());
+            drop(first);
+            #[cfg(unix)]
+            assert!(!path.exists());
+            assert!(FileLock::try_acquire(path.as_path()).unwrap().is_some());
+        }
+
+        #[test]
+        fn stale_lock_file_does_not_block() {
+            let dir = TempDir::new("file-lock-stale");
+            let path = dir.join("state.lock");
+            // left behind by a crashed run
+            std::fs::write(&path, b"12345\n").unwrap();
+            assert!(FileLock::try_acquire(path.as_path()).unwrap().is_some());
+        }
     }
-}
\ No newline at end of file
+
+    mod target_reservations {
+        use std::path::Path;
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        use crate::sorting::fs_support::TargetReservations;
+
+        #[test]
+        fn waits_for_release() {
+            let table = TargetReservations::new();
+            let target = Path::new("/target/2023/IMG_0001.JPG");
+            let first = table.reserve(target);
+            assert!(table.try_reserve(target).is_none());
+            assert!(table.try_reserve(Path::new("/target/2023/IMG_0002.JPG")).is_some());
+
+            let (tx, rx) = mpsc::channel();
+            let waiting = table.clone();
+            let handle = thread::spawn(move || {
+                let _second = waiting.reserve(target);
+                tx.send(()).unwrap();
+            });
+            assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+            drop(first);
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            handle.join().unwrap();
+            assert!(table.try_reserve(target).is_some());
+        }
+    }
+
+    mod dir_manager {
+        use crate::error::{DirErrorKind, SortError};
+        use crate::sorting::fs_support::DirManager;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn removed_dir_is_created_again() {
+            let root = TempDir::new("dirmgr");
+            let dir = root.join("2023");
+            let mut dm = DirManager::new();
+            dm.create_path(dir.as_path(), false).unwrap();
+            std::fs::remove_dir(&dir).unwrap();
+            dm.create_path(dir.as_path(), false).unwrap();
+            assert!(dir.is_dir());
+        }
+
+        #[test]
+        fn forgets_oldest_paths() {
+            let root = TempDir::new("dirmgr-cap");
+            let mut dm = DirManager::with_capacity(2);
+            for name in ["a", "b", "c", "a"] {
+                dm.create_path(root.join(name).as_path(), true).unwrap();
+            }
+            assert_eq!(dm.cached(), 2);
+            assert_eq!(std::fs::read_dir(&root).unwrap().count(), 0);
+        }
+
+        #[test]
+        fn reports_name_conflicts() {
+            let dir = TempDir::new("dirmgr-conflict");
+            let file = dir.join("a.jpg");
+            std::fs::write(&file, b"a").unwrap();
+            let result = DirManager::new().create_path(file.join("2023").as_path(), false);
+            assert!(matches!(result, Err(SortError::DirCreation { kind: DirErrorKind::NameConflict, .. })));
+        }
+    }
+}
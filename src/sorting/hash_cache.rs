@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::fs::{File, Metadata};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::UNIX_EPOCH;
+
+use crate::sorting::fs_support::FileLock;
+use crate::sorting::{simulation, PATHSTR_FB};
+
+/// Digests of files calculated by a [crate::sorting::comparison::FileComparer], persisted on disk
+/// so repeated imports against a large target library do not read all of it again. An entry is
+/// only used while the size and modification time of its file are unchanged and it has been
+/// calculated with the same settings (see [crate::sorting::comparison::FileComparer::cache_tag]).
+///
+/// The cache is stored as one line per file (`<tag>\t<size>\t<mtime>\t<digest>\t<path>`) and
+/// rewritten by [Self::save] and when the last handle is dropped, leaving out files that no
+/// longer exist. Handles are cheap to clone and share the same entries, so all pipeline threads
+/// can use one cache. As each run rewrites the whole cache, it is locked like a
+/// [crate::checkpoint::Checkpoint] and concurrent runs need distinct caches.
+#[derive(Clone)]
+pub struct HashCache {
+    inner: Arc<Mutex<CacheState>>
+}
+
+struct CacheState {
+    path: PathBuf,
+    entries: HashMap<PathBuf, CacheEntry>,
+    dirty: bool,
+    /// released after the final save, `None` in a dry run, which never saves
+    _lock: Option<FileLock>
+}
+
+struct CacheEntry {
+    tag: String,
+    size: u64,
+    mtime: u128,
+    digest: Vec<u8>
+}
+
+impl HashCache {
+    /// load the cache at `path`. A missing file is treated as an empty cache, which is created
+    /// when saving.
+    pub fn open(path: &Path) -> Result<HashCache, String> {
+        let lock = match simulation::is_dry_run() {
+            true => None,
+            false => {
+                let mut name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+                name.push(".lock");
+                match FileLock::try_acquire(path.with_file_name(name).as_path()) {
+                    Ok(Some(l)) => Some(l),
+                    Ok(None) => return Err(format!("hash cache \"{}\" is in use by another run", path.to_str().unwrap_or(PATHSTR_FB))),
+                    Err(e) => return Err(format!("failed to lock hash cache \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), e))
+                }
+            }
+        };
+        let mut entries = HashMap::new();
+        match File::open(path) {
+            Ok(f) => {
+                for line in BufReader::new(f).lines() {
+                    match line {
+                        Ok(l) => if let Some((p, e)) = parse_line(l.as_str()) {
+                            entries.insert(p, e);
+                        },
+                        // a truncated last line is not valid UTF-8 in the worst case
+                        Err(_) => break
+                    }
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(format!("failed to open hash cache \"{}\": {}", path.to_str().unwrap_or(PATHSTR_FB), e))
+        }
+        Ok(HashCache {
+            inner: Arc::new(Mutex::new(CacheState {
+                path: path.to_path_buf(),
+                entries,
+                dirty: false,
+                _lock: lock
+            }))
+        })
+    }
+
+    /// get the cached digest of `path` calculated with settings `tag` if the file is unchanged
+    pub fn get(&self, path: &Path, meta: &Metadata, tag: &str) -> Option<Vec<u8>> {
+        let mtime = mtime(meta)?;
+        let state = self.lock();
+        let entry = state.entries.get(path)?;
+        match entry.tag == tag && entry.size == meta.len() && entry.mtime == mtime {
+            true => Some(entry.digest.clone()),
+            false => None
+        }
+    }
+
+    /// store the digest of `path` calculated with settings `tag`. Paths that are not valid UTF-8
+    /// or contain a tab or line break are not cached.
+    pub fn insert(&self, path: &Path, meta: &Metadata, tag: &str, digest: &[u8]) {
+        let valid = path.to_str().is_some_and(|p| !p.contains(['\t', '\n']));
+        let mtime = match mtime(meta) {
+            Some(m) if valid => m,
+            _ => return
+        };
+        let mut state = self.lock();
+        state.entries.insert(path.to_path_buf(), CacheEntry {
+            tag: String::from(tag),
+            size: meta.len(),
+            mtime,
+            digest: digest.to_vec()
+        });
+        state.dirty = true;
+    }
+
+    /// write the cache to disk if it has changed, a dry run (see
+    /// [crate::sorting::simulation::set_dry_run]) never writes it
+    pub fn save(&self) -> Result<(), String> {
+        self.lock().save()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        match self.inner.lock() {
+            Ok(guard) => guard,
+            // entries are inserted in one step, so the state can still be used
+            Err(poisoned) => poisoned.into_inner()
+        }
+    }
+}
+
+impl CacheState {
+    fn save(&mut self) -> Result<(), String> {
+        if !self.dirty || simulation::is_dry_run() {
+            return Ok(());
+        }
+        // write to a temporary file first, so an interrupted save keeps the previous cache
+        let mut name = self.path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+        name.push(".tmp");
+        let tmp = self.path.with_file_name(name);
+        let result = File::create(&tmp)
+            .and_then(|f| {
+                let mut writer = BufWriter::new(f);
+                for (path, e) in &self.entries {
+                    if !path.exists() {
+                        continue;
+                    }
+                    let digest: String = e.digest.iter().map(|b| format!("{:02x}", b)).collect();
+                    writeln!(writer, "{}\t{}\t{}\t{}\t{}", e.tag, e.size, e.mtime, digest, path.to_str().unwrap_or(PATHSTR_FB))?;
+                }
+                writer.flush()
+            })
+            .and_then(|_| std::fs::rename(&tmp, &self.path));
+        match result {
+            Ok(_) => {
+                self.dirty = false;
+                Ok(())
+            },
+            Err(e) => {
+                let _ = std::fs::remove_file(&tmp);
+                Err(format!("failed to save hash cache \"{}\": {}", self.path.to_str().unwrap_or(PATHSTR_FB), e))
+            }
+        }
+    }
+}
+
+impl Drop for CacheState {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            eprintln!("[ERROR] {}", e);
+        }
+    }
+}
+
+/// the modification time of a file in nanoseconds since the Unix epoch
+fn mtime(meta: &Metadata) -> Option<u128> {
+    meta.modified().ok()?.duration_since(UNIX_EPOCH).ok().map(|d| d.as_nanos())
+}
+
+fn parse_line(line: &str) -> Option<(PathBuf, CacheEntry)> {
+    let mut fields = line.splitn(5, '\t');
+    let tag = fields.next()?;
+    let size = fields.next()?.parse().ok()?;
+    let mtime = fields.next()?.parse().ok()?;
+    let hex = fields.next()?;
+    let path = fields.next()?;
+    if hex.len() % 2 != 0 || path.is_empty() {
+        return None;
+    }
+    let digest = (0..hex.len()).step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect::<Option<Vec<u8>>>()?;
+    Some((PathBuf::from(path), CacheEntry {
+        tag: String::from(tag),
+        size,
+        mtime,
+        digest
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod hash_cache {
+        use crate::sorting::hash_cache::HashCache;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn entries_survive_reopening() {
+            let dir = TempDir::new("hash-cache");
+            let file = dir.join("a.jpg");
+            std::fs::write(&file, b"content").unwrap();
+            let meta = file.metadata().unwrap();
+            let cache_path = dir.join("hashes");
+            {
+                let cache = HashCache::open(cache_path.as_path()).unwrap();
+                cache.clone().insert(file.as_path(), &meta, "sha256", &[0xab, 0x01]);
+            }
+
+            let cache = HashCache::open(cache_path.as_path()).unwrap();
+            let cached = cache.get(file.as_path(), &meta, "sha256");
+            let other_tag = cache.get(file.as_path(), &meta, "md5");
+            std::fs::write(&file, b"changed content").unwrap();
+            let changed = cache.get(file.as_path(), &file.metadata().unwrap(), "sha256");
+            assert_eq!(cached, Some(vec![0xab, 0x01]));
+            assert_eq!(other_tag, None);
+            assert_eq!(changed, None);
+        }
+
+        #[test]
+        fn concurrent_use_is_rejected() {
+            let dir = TempDir::new("hash-cache-lock");
+            let cache_path = dir.join("hashes");
+            let first = HashCache::open(cache_path.as_path()).unwrap();
+            assert!(HashCache::open(cache_path.as_path()).is_err());
+            drop(first);
+            assert!(HashCache::open(cache_path.as_path()).is_ok());
+        }
+    }
+}
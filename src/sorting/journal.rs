@@ -0,0 +1,93 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::SortError;
+use crate::sorting::PATHSTR_FB;
+
+/// A write-ahead log of moves inside the target tree. Every move is recorded as a line
+/// `<from>\t<to>` before it is executed, so a migration can be reverted with [undo] even if it
+/// was interrupted.
+pub struct Journal {
+    path: PathBuf,
+    file: File
+}
+
+impl Journal {
+    /// create a new journal at `path`, appending to it if it already exists
+    pub fn create(path: &Path) -> Result<Journal, SortError> {
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => Ok(Journal { path: path.to_path_buf(), file }),
+            Err(e) => Err(SortError::io("open journal", path.to_path_buf(), e))
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// record a move from `from` to `to`. The entry is synced to disk before returning.
+    pub fn record(&mut self, from: &Path, to: &Path) -> Result<(), SortError> {
+        let line = format!("{}\t{}\n", Self::encode(from)?, Self::encode(to)?);
+        self.file.write_all(line.as_bytes())
+            .and_then(|_| self.file.sync_data())
+            .map_err(|e| SortError::io("write journal", self.path.clone(), e))
+    }
+
+    fn encode(path: &Path) -> Result<&str, SortError> {
+        match path.to_str() {
+            Some(s) if !s.contains('\t') && !s.contains('\n') => Ok(s),
+            _ => Err(SortError::Policy(format!("path can not be recorded in the journal: \"{}\"",
+                                               path.to_str().unwrap_or(PATHSTR_FB))))
+        }
+    }
+}
+
+/// revert all moves recorded in the journal at `path`, latest first. Entries that can not be
+/// reverted (e.g. because the file has been removed since) are skipped and returned as errors.
+///
+/// Returns the count of restored files and the errors of skipped entries.
+pub fn undo(path: &Path) -> Result<(usize, Vec<SortError>), SortError> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => return Err(SortError::io("open journal", path.to_path_buf(), e))
+    };
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => return Err(SortError::io("read journal", path.to_path_buf(), e))
+        };
+        match line.split_once('\t') {
+            Some((from, to)) => entries.push((PathBuf::from(from), PathBuf::from(to))),
+            None if line.is_empty() => continue,
+            None => return Err(SortError::Policy(format!("invalid journal entry: \"{}\"", line)))
+        }
+    }
+
+    let mut restored = 0;
+    let mut errors = Vec::new();
+    for (from, to) in entries.into_iter().rev() {
+        if !to.is_file() {
+            errors.push(SortError::SourceNotFound(to));
+            continue;
+        }
+        if from.exists() {
+            errors.push(SortError::Policy(format!("original location is occupied: \"{}\"",
+                                                  from.to_str().unwrap_or(PATHSTR_FB))));
+            continue;
+        }
+        if let Some(parent) = from.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                errors.push(SortError::dir_creation(parent, &e));
+                continue;
+            }
+        }
+        match fs::rename(&to, &from) {
+            Ok(_) => restored += 1,
+            Err(e) => errors.push(SortError::io("restore", to, e))
+        }
+    }
+    Ok((restored, errors))
+}
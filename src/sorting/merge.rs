@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::media::ImgInfo;
+use crate::sorting::comparison::FileComparer;
+
+/// Result of removing files with identical content from the files of several sorted archives
+/// before merging them, see [dedupe].
+pub struct MergeSet {
+    /// files to merge, in the order given
+    pub unique: Vec<ImgInfo>,
+    /// files that are skipped as their content is identical to an already kept file, as
+    /// `(skipped, kept)`
+    pub duplicates: Vec<(PathBuf, PathBuf)>
+}
+
+/// a kept file with its hash, see [dedupe]
+type KeptFile = (Option<Vec<u8>>, PathBuf);
+
+/// treat files with identical content as one file, keeping the first occurrence. Archives
+/// merged earlier should therefore be listed first. Only files sharing their size with another
+/// file are compared with `comparer`, by hash or directly if it does not hash files (see
+/// [FileComparer::hash_file]); files that can not be read are kept.
+pub fn dedupe(files: Vec<ImgInfo>, comparer: &FileComparer) -> MergeSet {
+    let mut size_count: HashMap<usize, usize> = HashMap::new();
+    for f in &files {
+        *size_count.entry(*f.size()).or_insert(0) += 1;
+    }
+
+    // kept files of each size with their hash, `None` if the comparer does not hash
+    let mut kept: HashMap<usize, Vec<KeptFile>> = HashMap::new();
+    let mut result = MergeSet {
+        unique: Vec::with_capacity(files.len()),
+        duplicates: Vec::new()
+    };
+    for f in files {
+        if size_count[f.size()] < 2 {
+            result.unique.push(f);
+            continue;
+        }
+        let hash = match comparer.hash_file(f.path()) {
+            Ok(h) => h,
+            Err(_) => {
+                result.unique.push(f);
+                continue;
+            }
+        };
+        let same_size = kept.entry(*f.size()).or_default();
+        let original = same_size.iter()
+            .find(|(h, p)| match (&hash, h) {
+                (Some(hash), Some(h)) => hash == h,
+                _ => matches!(comparer.check_files_matching(p.as_path(), f.path()), Ok(true))
+            })
+            .map(|(_, p)| p.clone());
+        match original {
+            Some(original) => result.duplicates.push((f.path().to_path_buf(), original)),
+            None => {
+                same_size.push((hash, f.path().to_path_buf()));
+                result.unique.push(f);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod dedupe {
+        use crate::media::ImgInfo;
+        use crate::sorting::comparison::{FileComparer, HashAlgorithm};
+        use crate::sorting::merge::dedupe;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn skips_identical_content_only() {
+            let dir = TempDir::new("merge");
+            std::fs::write(dir.join("a.jpg"), b"content").unwrap();
+            std::fs::write(dir.join("renamed.jpg"), b"content").unwrap();
+            std::fs::write(dir.join("b.jpg"), b"differs").unwrap();
+
+            for algo in [HashAlgorithm::SHA256, HashAlgorithm::Bytes] {
+                let files = ["a.jpg", "renamed.jpg", "b.jpg"].iter()
+                    .map(|f| ImgInfo::new(dir.join(f)).unwrap())
+                    .collect();
+                let set = dedupe(files, &FileComparer::new(false, algo));
+                assert_eq!(set.duplicates, vec![(dir.join("renamed.jpg"), dir.join("a.jpg"))]);
+                assert_eq!(set.unique.iter().map(|f| f.path().to_path_buf()).collect::<Vec<_>>(), vec![dir.join("a.jpg"), dir.join("b.jpg")]);
+            }
+        }
+    }
+}
@@ -1,16 +1,45 @@
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 
+use crate::error::{DirErrorKind, SortError};
 use crate::media::ImgInfo;
+use crate::media::clock_drift::ClockDrift;
 use crate::logging::LogReq;
 use crate::pattern::PatternElement;
-use crate::sorting::comparison::{HashAlgorithm, Cause, ComparisonErr, FileComparer};
-use crate::sorting::fs_support::{DirCreationRequest, DirManager};
+use crate::sorting::comparison::{HashAlgorithm, Cause, ComparisonErr, DEFAULT_BUFFER_SIZE, FileComparer, MtimeTolerance};
+use crate::sorting::exec::{ActionExecutor, LocalExecutor};
+use crate::sorting::fs_support::{DirCreationRequest, DirManager, Reservation, TargetReservations};
+use crate::sorting::hash_cache::HashCache;
+use crate::sorting::journal::Journal;
+use crate::sorting::naming::{NamingScheme, RolloverRule};
+use crate::sorting::preview::TargetPreview;
+use crate::sorting::quota::{BranchQuota, QuotaTable};
+use crate::sorting::retry::RetryPolicy;
+use crate::sorting::routing::{AgeRoute, DatePlausibility, PairPolicy, SourceRule, TargetRoute};
+use crate::sorting::simulation::{SimOutcome, SimulationPrinter};
+use crate::sorting::split::FolderSplit;
+use crate::sorting::throttle::Throttle;
 use crate::sorting::translation::Translator;
+use crate::sorting::workspace::Workspace;
 
 pub mod fs_support;
 pub mod comparison;
+pub mod dedupe;
+pub mod exec;
+pub mod hash_cache;
+pub mod journal;
+pub mod merge;
+pub mod naming;
+pub mod preview;
+pub mod quota;
+pub mod retry;
+pub mod routing;
+pub mod simulation;
+pub mod split;
+pub mod throttle;
 pub mod translation;
+pub mod workspace;
 
 /// a fallback string in case an OsStr could not be transformed to a [std::String]
 pub static PATHSTR_FB: &str = "<INVALID_UTF-8>";
@@ -18,16 +47,18 @@ pub static PATHSTR_FB: &str = "<INVALID_UTF-8>";
 
 struct AsyncDirChannel {
     tx_dirm: mpsc::Sender<DirCreationRequest>,
-    rx_callback: mpsc::Receiver<bool>,
-    tx_callback: mpsc::Sender<bool>
+    rx_callback: mpsc::Receiver<Result<(), SortError>>,
+    tx_callback: mpsc::Sender<Result<(), SortError>>,
+    reservations: TargetReservations
 }
 impl AsyncDirChannel {
-    pub fn new(chan_dirmgr: mpsc::Sender<DirCreationRequest>) -> AsyncDirChannel {
-        let (tx_cb, rx_cb) = mpsc::channel::<bool>();
+    pub fn new(chan_dirmgr: mpsc::Sender<DirCreationRequest>, reservations: TargetReservations) -> AsyncDirChannel {
+        let (tx_cb, rx_cb) = mpsc::channel::<Result<(), SortError>>();
         AsyncDirChannel{
             tx_dirm: chan_dirmgr,
             rx_callback: rx_cb,
-            tx_callback: tx_cb
+            tx_callback: tx_cb,
+            reservations
         }
     }
 }
@@ -75,6 +106,33 @@ pub enum DuplicateResolution {
     Compare(Comparison),
 }
 
+impl DuplicateResolution {
+    /// parse a policy given as a single name, e.g. in an environment variable: `ignore`,
+    /// `overwrite` or the comparison `rename`, `favor_target`, `favor_source` (also written with
+    /// '-' instead of '_')
+    pub fn parse(s: &str) -> Option<DuplicateResolution> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "ignore" => Some(DuplicateResolution::Ignore),
+            "overwrite" => Some(DuplicateResolution::Overwrite),
+            "rename" => Some(DuplicateResolution::Compare(Comparison::Rename)),
+            "favor_target" => Some(DuplicateResolution::Compare(Comparison::FavorTarget)),
+            "favor_source" => Some(DuplicateResolution::Compare(Comparison::FavorSource)),
+            _ => None
+        }
+    }
+
+    /// the name of the policy as accepted by [Self::parse]
+    pub fn name(&self) -> &'static str {
+        match self {
+            DuplicateResolution::Ignore => "ignore",
+            DuplicateResolution::Overwrite => "overwrite",
+            DuplicateResolution::Compare(Comparison::Rename) => "rename",
+            DuplicateResolution::Compare(Comparison::FavorTarget) => "favor_target",
+            DuplicateResolution::Compare(Comparison::FavorSource) => "favor_source"
+        }
+    }
+}
+
 /// The result of a pre-check performed on a SortAction to detect possible existing target files
 /// and evaluation of a policy that tells what to do in that case.
 ///
@@ -87,7 +145,7 @@ pub enum PreCheckResult {
     Execute,
     Skip,
     RenameTarget,
-    Error(String)
+    Error(SortError)
 }
 impl PreCheckResult {
     pub fn to_str(&self) -> &'static str {
@@ -105,7 +163,11 @@ impl PreCheckResult {
 pub struct SortAction {
     operation: Operation,
     source: PathBuf,
-    target: PathBuf
+    target: PathBuf,
+    root: PathBuf,
+    /// the folder the sequence number of the target filename is drawn for once the action is
+    /// executed, see [naming::SequencePattern]
+    sequence_folder: Option<PathBuf>
 }
 impl SortAction {
     pub fn target_exists(&self) -> bool {
@@ -119,6 +181,11 @@ impl SortAction {
     pub fn get_target(&self) -> &Path {
         self.target.as_path()
     }
+
+    /// the target root the target has been calculated for
+    pub fn get_root(&self) -> &Path {
+        self.root.as_path()
+    }
 }
 
 /// An indicator of what has been performed when executing a [SortAction].
@@ -169,13 +236,27 @@ enum SorterMode {
 ///     .build_sync();
 ///
 /// // create a new SortAction (here the ImgInfo has no metadata due to missing Pre-Processing)
-/// let action = sorter.calc_copy(&input_file, target_root.as_path());
+/// let action = sorter.calc_copy(&input_file, target_root.as_path()).unwrap();
 /// let result = sorter.execute_checked(action, &DuplicateResolution::Ignore);
 /// ```
 pub struct Sorter {
     translator: Translator,
     comparer: FileComparer,
-    mode: SorterMode
+    mode: SorterMode,
+    quotas: Option<QuotaTable>,
+    age_route: Option<AgeRoute>,
+    source_rules: Vec<SourceRule>,
+    target_routes: Vec<TargetRoute>,
+    rollover_rules: Vec<RolloverRule>,
+    naming: NamingScheme,
+    date_check: Option<DatePlausibility>,
+    clock_drift: Option<ClockDrift>,
+    pairs: Option<PairPolicy>,
+    folder_split: Option<FolderSplit>,
+    executor: Box<dyn ActionExecutor + Send>,
+    /// the target of the last action passed to the executor, see [Sorter::last_target]
+    last_target: Option<PathBuf>,
+    simulation: SimulationPrinter
 }
 impl Sorter {
     pub fn builder() -> SorterBuilder {
@@ -185,7 +266,29 @@ impl Sorter {
             fallback_segments: Vec::new(),
             dup_handling: DuplicateResolution::Compare(Comparison::Rename),
             log: None,
-            hash_algo: HashAlgorithm::None
+            hash_algo: HashAlgorithm::None,
+            partial_hash: None,
+            hash_buffer_size: DEFAULT_BUFFER_SIZE,
+            hash_cache: None,
+            mtime_tolerance: MtimeTolerance::default(),
+            quotas: Vec::new(),
+            quota_table: None,
+            age_route: None,
+            source_rules: Vec::new(),
+            target_routes: Vec::new(),
+            provenance: None,
+            rollover_rules: Vec::new(),
+            naming: NamingScheme::Original,
+            date_check: None,
+            clock_drift: None,
+            pairs: None,
+            folder_split: None,
+            retry: RetryPolicy::none(),
+            executor: None,
+            workspace: None,
+            throttle: None,
+            fallback_root: None,
+            simulation: SimulationPrinter::new()
         }
     }
 
@@ -193,40 +296,155 @@ impl Sorter {
         Sorter {
             translator: translator,
             comparer: comparer,
-            mode: SorterMode::Sync(DirManager::new())
+            mode: SorterMode::Sync(DirManager::new()),
+            quotas: None,
+            age_route: None,
+            source_rules: Vec::new(),
+            target_routes: Vec::new(),
+            rollover_rules: Vec::new(),
+            naming: NamingScheme::Original,
+            date_check: None,
+            clock_drift: None,
+            pairs: None,
+            folder_split: None,
+            executor: Box::new(LocalExecutor::new(RetryPolicy::none(), None, None, None)),
+            last_target: None,
+            simulation: SimulationPrinter::new()
         }
     }
 
-    pub fn new_async(translator: Translator, comparer: FileComparer, dir_chan: mpsc::Sender<DirCreationRequest>) -> Sorter {
+    pub fn new_async(translator: Translator, comparer: FileComparer, dir_chan: mpsc::Sender<DirCreationRequest>, reservations: TargetReservations) -> Sorter {
         Sorter {
             translator: translator,
             comparer: comparer,
             mode: SorterMode::Async(
-                AsyncDirChannel::new(dir_chan)
-            )
+                AsyncDirChannel::new(dir_chan, reservations)
+            ),
+            quotas: None,
+            age_route: None,
+            source_rules: Vec::new(),
+            target_routes: Vec::new(),
+            rollover_rules: Vec::new(),
+            naming: NamingScheme::Original,
+            date_check: None,
+            clock_drift: None,
+            pairs: None,
+            folder_split: None,
+            executor: Box::new(LocalExecutor::new(RetryPolicy::none(), None, None, None)),
+            last_target: None,
+            simulation: SimulationPrinter::new()
         }
     }
 
+    /// the target of the last action passed to [Sorter::execute], which differs from the
+    /// calculated target if it has been renamed to avoid a conflict
+    pub fn last_target(&self) -> Option<&Path> {
+        self.last_target.as_deref()
+    }
+
+    /// the printer of simulated files, e.g. to print files skipped before they reach the sorter
+    pub fn simulation(&self) -> &SimulationPrinter {
+        &self.simulation
+    }
+
     /// get the number of segments in a tuple of (<supported>, <fallback>)
     pub fn get_seg_count(&self) -> (usize, usize) {
         self.translator.get_seg_count()
     }
 
     /// create a new [SortAction] with operation=copy
-    pub fn calc_copy(&self, file: &ImgInfo, target_root: &Path) -> SortAction {
+    pub fn calc_copy(&self, file: &ImgInfo, target_root: &Path) -> Result<SortAction, SortError> {
         self.calc_action(file, target_root, Operation::Copy)
     }
 
     /// create a new [SortAction] with operation=move
-    pub fn calc_move(&self, file: &ImgInfo, target_root: &Path) -> SortAction {
+    pub fn calc_move(&self, file: &ImgInfo, target_root: &Path) -> Result<SortAction, SortError> {
         self.calc_action(file, target_root, Operation::Move)
     }
 
     /// create a new [SortAction] with operation=simulate (print)
-    pub fn calc_simulation(&self, file: &ImgInfo, target_root: &Path) -> SortAction {
+    pub fn calc_simulation(&self, file: &ImgInfo, target_root: &Path) -> Result<SortAction, SortError> {
         self.calc_action(file, target_root, Operation::Print)
     }
 
+    /// get the target `file` would be sorted to below `target_root` with diagnostics about the
+    /// segments used and potential problems of the path, see [TargetPreview]. Unlike
+    /// [Self::calc_simulation] this has no side effects.
+    pub fn preview(&self, file: &ImgInfo, target_root: &Path) -> TargetPreview {
+        match self.calc_target(file, target_root, true) {
+            Ok((_, target, quarantined, _)) => {
+                let segments = match quarantined {
+                    true => Vec::new(),
+                    false => self.translator_for(file).explain(file)
+                };
+                TargetPreview::new(Ok(target), segments)
+            },
+            Err(e) => TargetPreview::new(Err(e), self.translator_for(file).explain(file))
+        }
+    }
+
+    /// correct the metadata timestamp of `file` by the [ClockDrift] of its camera, if one is set.
+    /// Applied before [Sorter::check_date], so the corrected timestamp is checked.
+    pub fn apply_clock_drift(&self, file: &mut ImgInfo) {
+        if let Some(drift) = &self.clock_drift {
+            drift.apply(file);
+        }
+    }
+
+    /// check the metadata timestamp of `file` against the plausibility window (if configured),
+    /// replacing it if configured so. Returns `false` if the timestamp is implausible.
+    pub fn check_date(&self, file: &mut ImgInfo) -> bool {
+        match &self.date_check {
+            Some(check) => check.check(file),
+            None => true
+        }
+    }
+
+    /// check if `file` is the secondary file of a HEIC/JPEG pair which should not be sorted, see
+    /// [PairPolicy]
+    pub fn skip_pair(&self, file: &ImgInfo) -> bool {
+        self.pairs.as_ref().is_some_and(|p| p.skip(file))
+    }
+
+    /// re-evaluate an already sorted file below `target_root` against the current configuration.
+    /// Returns the path the file would be sorted to now if it differs from its current directory,
+    /// or `None` if the file is where it belongs.
+    pub fn recheck(&self, file: &ImgInfo, target_root: &Path) -> Result<Option<PathBuf>, SortError> {
+        let action = self.calc_action(file, target_root, Operation::Print)?;
+        match action.target.parent() == file.path().parent() {
+            true => Ok(None),
+            false => Ok(Some(action.target))
+        }
+    }
+
+    /// move an already sorted file below `target_root` to the location computed by the current
+    /// configuration, recording the move in `journal` first. Conflicting filenames at the new
+    /// location are resolved like [Self::mutate_target_filename].
+    ///
+    /// Returns the new path or `None` if the file is already where it belongs.
+    pub fn migrate(&mut self, file: &ImgInfo, target_root: &Path, journal: &mut Journal) -> Result<Option<PathBuf>, SortError> {
+        let mut action = self.calc_action(file, target_root, Operation::Move)?;
+        if action.target.parent() == file.path().parent() {
+            return Ok(None);
+        }
+        if action.target.exists() {
+            action = match Self::mutate_target_filename(action) {
+                Ok(a) => a,
+                Err(_) => return Err(SortError::policy("a non-conflicting filename could not be created"))
+            };
+        }
+        let target = action.target.clone();
+        journal.record(action.source.as_path(), target.as_path())?;
+        self.execute(action)?;
+
+        // remove the directory the file has been moved out of if it is empty now
+        if let Some(parent) = file.path().parent() {
+            if parent != target_root {
+                let _ = std::fs::remove_dir(parent);
+            }
+        }
+        Ok(Some(target))
+    }
 
     /// perform a pre-check on the operation to determine if it should be executed according to the
     /// policy of handling duplicates (if the target exists).
@@ -235,11 +453,7 @@ impl Sorter {
         let target = action.target.as_path();
 
         if !src.is_file() {
-            return PreCheckResult::Error(
-                format!("source file does not exist: {}",
-                        src.to_str().unwrap_or(PATHSTR_FB)
-                )
-            );
+            return PreCheckResult::Error(SortError::SourceNotFound(src.to_path_buf()));
         }
         if !target.exists() {
             return PreCheckResult::Execute;
@@ -267,7 +481,7 @@ impl Sorter {
                             Comparison::FavorSource => PreCheckResult::Execute
                         }
                     },
-                    Err(e) => PreCheckResult::Error(Self::create_cmp_err_msg(e, src, target))
+                    Err(e) => PreCheckResult::Error(Self::create_cmp_err(e, src, target))
                 }
             }
         }
@@ -306,88 +520,147 @@ impl Sorter {
         Ok(action)
     }
 
-    /// execute an action with the given operation, consuming the input action.
+    /// claim the target of `action` in the run-wide reservation table in asynchronous mode,
+    /// waiting for another thread writing the same target to finish first, so the duplicate
+    /// handling sees its result. Returns `None` in synchronous mode.
+    fn reserve_target(&self, action: &SortAction) -> Option<Reservation> {
+        match &self.mode {
+            SorterMode::Async(chan) => Some(chan.reservations.reserve(action.target.as_path())),
+            SorterMode::Sync(_) => None
+        }
+    }
+
+    /// mutate the target filename of `action` like [Self::mutate_target_filename], skipping
+    /// names reserved by other threads in asynchronous mode, and reserve the new target
+    fn rename_target(&self, mut action: SortAction) -> Result<(SortAction, Option<Reservation>), MutationErr> {
+        let reservations = match &self.mode {
+            SorterMode::Async(chan) => &chan.reservations,
+            SorterMode::Sync(_) => return Self::mutate_target_filename(action).map(|a| (a, None))
+        };
+        if !action.target.exists() {
+            return Err(MutationErr::InvalidTarget);
+        }
+        let filename = match action.target.file_name().and_then(|n| n.to_str()) {
+            Some(s) => String::from(s),
+            None => return Err(MutationErr::InvalidTarget)
+        };
+
+        let mut target = action.target.clone();
+        for counter in 1..1000u16 {
+            target.set_file_name(format!("{}.{:03}", filename, counter));
+            if target.exists() {
+                continue;
+            }
+            if let Some(reservation) = reservations.try_reserve(target.as_path()) {
+                // the thread holding the name before may have written it in the meantime
+                if target.exists() {
+                    continue;
+                }
+                action.target = target;
+                return Ok((action, Some(reservation)));
+            }
+        }
+        Err(MutationErr::Failed)
+    }
+
+    /// execute an action with the given operation, consuming the input action. The file
+    /// operation itself is performed by the configured [ActionExecutor].
     ///
-    /// **WARNING:** does not perform any policy checks and will overwrite existing files.
-    pub fn execute(&mut self, action: SortAction) -> Result<ActionResult, String> {
+    /// **WARNING:** does not perform any policy checks and will overwrite existing files. In
+    /// asynchronous mode, targets are only reserved against other pipeline threads by
+    /// [Self::execute_checked].
+    pub fn execute(&mut self, mut action: SortAction) -> Result<ActionResult, SortError> {
+        self.draw_sequence(&mut action);
+        self.last_target = Some(action.target.clone());
         let (source, target) = (action.source.as_path(), action.target.as_path());
 
         // pre-checks to assure operation can be completed
         if !source.is_file() {
-            return Err(format!("Invalid operation, source file does not exist: \"{}\"",
-                &action.source.to_str().unwrap_or(PATHSTR_FB)
-            ));
+            return Err(SortError::SourceNotFound(action.source.clone()));
         }
 
-        // check if any parent directories have to be created
-        match target.parent() {
-            // no parent dir that may have to be created
-            None => (),
-            // parent dir, check if exists
-            Some(parent) => {
-                if !parent.is_dir() {
-                    if parent.is_file() {
-                        return Err(
-                            format!("failed to create parent directory \"{}\": a normal file with that name already exists!",
-                                parent.to_str().unwrap_or(PATHSTR_FB)
-                            )
-                        );
-                    }
-                    match &mut self.mode {
-                        // synchronous mode, directly create path
-                        SorterMode::Sync(dm) => dm.create_path(parent,
-                                                               matches!(&action.operation, Operation::Print)
-                        )?,
-                        // asynchronous mode, request creation via channel
-                        SorterMode::Async(chan) => {
-                            let req = DirCreationRequest::new(parent, chan.tx_callback.clone());
-                            chan.tx_dirm.send(req).expect("Failed to send dir creation request: channel is closed");
-                            let result = chan.rx_callback.recv().expect("Error receiving callback: channel is closed or hung up");
-                            if !result {
-                                return Err(format!("Could not create target directory \"{}\": DirMgr returned false",
-                                    parent.to_str().unwrap_or(PATHSTR_FB)
-                                ));
-                            }
-                        }
-                    }
+        // a failure may be caused by another thread or process racing on the target directory,
+        // so creating it is retried once
+        if self.executor.needs_target_dir() {
+            if let Err(e) = self.ensure_target_dir(&action) {
+                if !Self::is_dir_race(&e) {
+                    return Err(e);
                 }
+                self.ensure_target_dir(&action)?;
             }
         }
 
-        let result = match &action.operation {
-            Operation::Move => std::fs::rename(source, target),
-            Operation::Copy => match std::fs::copy(source, target) {
-                    Ok(bytes) => {
-                        if bytes <= 0 {
-                            println!("[WARN]: copied {} bytes for src=\"{}\"",
-                                     bytes,
-                                     &action.source.to_str().unwrap_or(PATHSTR_FB)
-                            );
-                        }
-                        Ok(())
-                    },
-                    Err(e) => Err(e)
+        match self.executor.execute(&action) {
+            // the target directory has been removed after it has been confirmed, e.g. by a
+            // cleanup of another process, so it is requested again and the action re-queued once
+            Err(SortError::Io { source: e, .. }) if e.kind() == std::io::ErrorKind::NotFound
+                && source.is_file()
+                && self.executor.needs_target_dir()
+                && target.parent().is_some_and(|p| !p.is_dir()) => {
+                self.ensure_target_dir(&action)?;
+                self.executor.execute(&action)
             },
-            Operation::Print => {
-                println!("\"{}\" -> \"{}\"",
-                    source.to_str().unwrap_or(PATHSTR_FB),
-                    target.to_str().unwrap_or(PATHSTR_FB),
-                );
-                Ok(())
-            }
-        };
+            result => result
+        }
+    }
+
+    /// check if a failure to create a directory may have been caused by a concurrent change of
+    /// the file system, so retrying may succeed
+    fn is_dir_race(e: &SortError) -> bool {
+        match e {
+            SortError::DirCreation { path, kind, .. } => kind.is_retryable() && !path.is_file(),
+            _ => false
+        }
+    }
 
-        match result {
-            Ok(_) => Ok(match &action.operation {
-                Operation::Print => ActionResult::Skipped,
-                Operation::Move => ActionResult::Moved,
-                Operation::Copy => ActionResult::Copied
-            }),
-            Err(e) => Err(format!("failed to execute operation=\"{}\": {}",
-                &action.operation.to_str(),
-                e
-            ))
+    /// create the parent directory of the target of `action` if it does not exist
+    fn ensure_target_dir(&mut self, action: &SortAction) -> Result<(), SortError> {
+        let target = action.target.as_path();
+        // check if any parent directories have to be created
+        match target.parent() {
+            // parent dir which does not exist yet
+            Some(parent) if !parent.is_dir() => {
+                if parent.is_file() {
+                    return Err(SortError::DirCreation {
+                        path: parent.to_path_buf(),
+                        kind: DirErrorKind::NameConflict,
+                        reason: String::from("a normal file with that name already exists!")
+                    });
+                }
+                match &mut self.mode {
+                    // synchronous mode, directly create path
+                    SorterMode::Sync(dm) => dm.create_path(parent,
+                                                           matches!(&action.operation, Operation::Print)
+                    )?,
+                    // asynchronous mode, request creation via channel
+                    SorterMode::Async(chan) => {
+                        let req = match &action.operation {
+                            Operation::Print => DirCreationRequest::new_simulating(parent, chan.tx_callback.clone()),
+                            _ => DirCreationRequest::new(parent, chan.tx_callback.clone())
+                        };
+                        if chan.tx_dirm.send(req).is_err() {
+                            return Err(SortError::DirCreation {
+                                path: parent.to_path_buf(),
+                                kind: DirErrorKind::Stopped,
+                                reason: String::from("DirMgr channel is closed")
+                            });
+                        }
+                        match chan.rx_callback.recv() {
+                            Ok(result) => result?,
+                            Err(_) => return Err(SortError::DirCreation {
+                                path: parent.to_path_buf(),
+                                kind: DirErrorKind::Stopped,
+                                reason: String::from("DirMgr hung up before answering")
+                            })
+                        }
+                    }
+                }
+            },
+            // no parent dir or it exists already
+            _ => ()
         }
+
+        Ok(())
     }
 
     /// consume an action and execute an operation following a policy pre-check (see
@@ -396,88 +669,230 @@ impl Sorter {
     /// to resolve conflicting filenames in the target directory.
     ///
     /// # Errors
-    /// This functions returns an [Err(String)] in case any errors were received while
-    /// executing the action with an error message that can be printed.
-    pub fn execute_checked(&mut self, mut action: SortAction, policy: &DuplicateResolution) -> Result<ActionResult, String> {
+    /// This functions returns a [SortError] in case any errors were received while
+    /// executing the action. If the source has been deleted in the meantime, this is always
+    /// [SortError::SourceNotFound].
+    pub fn execute_checked(&mut self, action: SortAction, policy: &DuplicateResolution) -> Result<ActionResult, SortError> {
+        let source = action.source.clone();
+        self.execute_checked_inner(action, policy)
+            .map_err(|e| Self::classify_vanished(e, source.as_path()))
+    }
+
+    fn execute_checked_inner(&mut self, mut action: SortAction, policy: &DuplicateResolution) -> Result<ActionResult, SortError> {
+        if simulation::is_dry_run() {
+            action.operation = Operation::Print;
+        }
+        // a sequence name is drawn on execution and never exists yet, so there is no duplicate
+        // to check and no other thread can write the same target
+        if action.sequence_folder.is_some() {
+            return self.execute_within_quota(action, SimOutcome::New);
+        }
+        // held until the action has been executed, also if it fails
+        let _reservation = self.reserve_target(&action);
         let precheck_result = self.evaluate_execution(&action, policy);
 
         match precheck_result {
-            PreCheckResult::Execute => self.execute(action),
-            PreCheckResult::Skip => match &action.operation {
-                Operation::Print => self.execute(action),
-                _                => Ok(ActionResult::Skipped)
+            PreCheckResult::Execute => {
+                let outcome = match action.target_exists() {
+                    true => SimOutcome::Overwrite,
+                    false => SimOutcome::New
+                };
+                self.execute_within_quota(action, outcome)
+            },
+            PreCheckResult::Skip => {
+                if let Operation::Print = &action.operation {
+                    self.simulation.print_target(SimOutcome::Duplicate, &action.source, &action.target, &action.root);
+                }
+                Ok(ActionResult::Skipped)
             },
             PreCheckResult::RenameTarget => {
-                action = match Self::mutate_target_filename(action) {
+                let (action, _renamed) = match self.rename_target(action) {
                     Ok(a) => a,
                     Err(e) => {
-                        return Err(format!("error renaming target: {}", match e {
+                        return Err(SortError::Policy(format!("error renaming target: {}", match e {
                             MutationErr::InvalidTarget => "the target does not exist",
                             MutationErr::Failed => "a non-conflicting filename could not be created"
-                        }
-                        ));
+                        })));
                     }
                 };
-                self.execute(action)
+                self.execute_within_quota(action, SimOutcome::Rename)
             }
             PreCheckResult::Error(e) => Err(e)
         }
     }
 
-    fn calc_action(&self, file: &ImgInfo, target_root: &Path, op: Operation) -> SortAction {
-        let mut target_folder = self.translator.translate(file, target_root);
-        let fname = file.path().file_name().expect("source filename is invalid!");
-        target_folder.push(fname);
-        SortAction{
-            operation: op,
-            source: file.path().to_path_buf(),
-            target: target_folder,
+    /// execute `action`, printing it as `outcome` if it is simulated
+    fn execute_as(&mut self, action: SortAction, outcome: SimOutcome) -> Result<ActionResult, SortError> {
+        if !matches!(action.operation, Operation::Print) {
+            return self.execute(action);
         }
+        let (source, root) = (action.source.clone(), action.root.clone());
+        let result = self.execute(action)?;
+        if let Some(target) = &self.last_target {
+            self.simulation.print_target(outcome, source.as_path(), target.as_path(), root.as_path());
+        }
+        Ok(result)
     }
 
-    /// process a [ComparisonErr] into a readable error message
-    fn create_cmp_err_msg(e: ComparisonErr, f1: &Path, f2: &Path) -> String {
-        let mut cause: Option<&Path> = None;
-        let mut msg: Option<String> = None;
-        match e {
-            ComparisonErr::AccessDenied(c) => {
-                cause = match c {
-                    Cause::Source => Some(f1),
-                    Cause::Target => Some(f2),
-                    Cause::NA => None
-                };
-                msg = Some(String::from("access is denied"));
-            }
-            ComparisonErr::InvalidFile(c) => {
-                cause = match c {
-                    Cause::Source => Some(f1),
-                    Cause::Target => Some(f2),
-                    Cause::NA => None
-                };
-                msg = Some(String::from("file not found"))
-            }
-            ComparisonErr::Metadata(c) => {
-                cause = match c {
-                    Cause::Source => Some(f1),
-                    Cause::Target => Some(f2),
-                    Cause::NA => None
-                };
-                msg = Some(String::from("file metadata could not be read"))
-            }
-            ComparisonErr::Other(c, m) => {
-                cause = match c {
-                    Cause::Source => Some(f1),
-                    Cause::Target => Some(f2),
-                    Cause::NA => None
-                };
-                msg = m;
+    /// turn errors caused by `source` not existing (anymore) into [SortError::SourceNotFound], as
+    /// files may be deleted between scanning and executing, e.g. by a sync client
+    fn classify_vanished(e: SortError, source: &Path) -> SortError {
+        let missing = match &e {
+            SortError::Io { source: io_err, .. } => io_err.kind() == std::io::ErrorKind::NotFound,
+            SortError::Comparison { path, .. } => path == source,
+            _ => false
+        };
+        match missing && !source.exists() {
+            true => SortError::SourceNotFound(source.to_path_buf()),
+            false => e
+        }
+    }
+
+    /// check the target of `action` against the configured branch quotas and execute it as
+    /// [Self::execute_as] if admitted. The action is skipped if the quota of its branch is
+    /// exhausted, the usage it reserved is given back if it fails.
+    fn execute_within_quota(&mut self, action: SortAction, outcome: SimOutcome) -> Result<ActionResult, SortError> {
+        let quotas = match &self.quotas {
+            Some(q) => q.clone(),
+            None => return self.execute_as(action, outcome)
+        };
+        let size = match action.source.metadata() {
+            Ok(m) => m.len(),
+            Err(e) => return Err(SortError::io("read metadata of", action.source.clone(), e))
+        };
+        // an overwritten target frees its space
+        let replaced = match outcome {
+            SimOutcome::Overwrite => action.target.metadata().ok().map(|m| m.len()),
+            _ => None
+        };
+        let simulate = matches!(action.operation, Operation::Print);
+        let (target, root) = (action.target.clone(), action.root.clone());
+        if !quotas.admit(target.as_path(), root.as_path(), size, replaced, simulate)? {
+            if simulate {
+                self.simulation.print_skip(action.source.as_path(), "quota of the target branch exceeded");
             }
+            return Ok(ActionResult::Skipped);
+        }
+        let result = self.execute_as(action, outcome);
+        if !matches!(result, Ok(ActionResult::Moved | ActionResult::Copied)) && !simulate {
+            quotas.release(target.as_path(), root.as_path(), size, replaced);
         }
+        result
+    }
+
+    /// the first source rule matching `file`, see [SourceRule]
+    fn source_rule_for(&self, file: &ImgInfo) -> Option<&SourceRule> {
+        self.source_rules.iter().find(|r| r.matches(file))
+    }
 
-        format!("error accessing file=\"{}\": {}",
-                cause.unwrap().to_str().unwrap_or("<INVALID_UTF-8>"),
-                msg.unwrap()
-        )
+    /// the translator of the source rule matching `file` or the default one
+    fn translator_for(&self, file: &ImgInfo) -> &Translator {
+        self.source_rule_for(file)
+            .and_then(|r| r.get_translator())
+            .unwrap_or(&self.translator)
+    }
+
+    fn calc_action(&self, file: &ImgInfo, target_root: &Path, op: Operation) -> Result<SortAction, SortError> {
+        let (root, target, _, sequence_folder) = self.calc_target(file, target_root, false)?;
+        Ok(SortAction{
+            operation: op,
+            source: file.path().to_path_buf(),
+            target,
+            root,
+            sequence_folder
+        })
+    }
+
+    /// calculate the target of `file`, returning the effective target root, the target, whether
+    /// the file is quarantined and the folder a sequence number must be drawn for. The target
+    /// carries the next sequence number without drawing it, which is left to the execution
+    /// (see [Self::draw_sequence]). With `peek` set, no slot in a split folder is taken either.
+    fn calc_target(&self, file: &ImgInfo, target_root: &Path, peek: bool) -> Result<(PathBuf, PathBuf, bool, Option<PathBuf>), SortError> {
+        // a source rule selecting a target root takes precedence over the target routes, both
+        // over the cold storage
+        let rule = self.source_rule_for(file);
+        let rule_root = rule.and_then(|r| r.route(target_root))
+            .or_else(|| self.target_routes.iter().find(|r| r.matches(file)).map(|r| r.route(target_root)));
+        let translator = rule.and_then(|r| r.get_translator()).unwrap_or(&self.translator);
+        // files exceeding the maximum age are routed to the cold storage root instead
+        let target_root = match (&rule_root, &self.age_route) {
+            (Some(root), _) => root.as_path(),
+            (None, Some(route)) => route.route(file).unwrap_or(target_root),
+            (None, None) => target_root
+        };
+        // files with implausible timestamps may be quarantined instead of being translated
+        let quarantine = self.date_check.as_ref().and_then(|c| c.quarantine(file, target_root));
+        let quarantined = quarantine.is_some();
+        // the secondary file of a HEIC/JPEG pair may be sorted into a separate branch
+        let paired = match quarantined {
+            true => None,
+            false => self.pairs.as_ref().and_then(|p| p.route(file, target_root))
+        };
+        let mut target_folder = match (quarantine, paired) {
+            (Some(dir), _) => dir,
+            (None, Some(branch)) => translator.translate(file, branch.as_path()),
+            (None, None) => translator.translate(file, target_root)
+        };
+        let fname = match file.file_name() {
+            Some(name) => name,
+            None => return Err(SortError::Translation {
+                path: file.path().to_path_buf(),
+                reason: String::from("source filename is invalid")
+            })
+        };
+        // content-addressed and sequence names are unique already, rollover suffixes are not needed
+        let target_name = match (&self.naming, self.rollover_rules.iter().find(|r| r.matches(file.metadata()))) {
+            (NamingScheme::Original, Some(rule)) => rule.apply(file, fname)?,
+            (scheme, _) => scheme.peek_filename(file, fname, target_folder.as_path())?
+        };
+        let sequence_folder = match &self.naming {
+            NamingScheme::Sequence(_) => Some(target_folder.clone()),
+            _ => None
+        };
+        // the bucket of a sequence name is taken with its number
+        let peek = peek || sequence_folder.is_some();
+        target_folder = self.split_folder(target_folder, target_name.as_os_str(), peek);
+        target_folder.push(target_name);
+        Ok((target_root.to_path_buf(), target_folder, quarantined, sequence_folder))
+    }
+
+    /// get the bucket of `folder` a file named `fname` is placed in if folders are split, see
+    /// [FolderSplit::place]
+    fn split_folder(&self, folder: PathBuf, fname: &OsStr, peek: bool) -> PathBuf {
+        // names are calculated for the folder itself, so sequence numbers continue across its buckets
+        match &self.folder_split {
+            Some(split) => split.place(folder.as_path(), fname, peek),
+            None => folder
+        }
+    }
+
+    /// draw the sequence number of the target filename of `action` if it has one, replacing the
+    /// number calculated without drawing it. Only done right before executing, so files which
+    /// are skipped do not use up a number.
+    fn draw_sequence(&self, action: &mut SortAction) {
+        let (pattern, folder) = match (&self.naming, action.sequence_folder.take()) {
+            (NamingScheme::Sequence(pattern), Some(folder)) => (pattern, folder),
+            _ => return
+        };
+        let fname = action.target.file_name().map(OsStr::to_os_string).unwrap_or_default();
+        let target_name = pattern.filename(fname.as_os_str(), folder.as_path());
+        let mut target = self.split_folder(folder, target_name.as_os_str(), false);
+        target.push(target_name);
+        action.target = target;
+    }
+
+    /// process a [ComparisonErr] into a [SortError] naming the file that caused it
+    fn create_cmp_err(e: ComparisonErr, f1: &Path, f2: &Path) -> SortError {
+        let (cause, reason) = e.into_parts();
+        let path = match cause {
+            Cause::Source | Cause::NA => f1,
+            Cause::Target => f2
+        };
+
+        SortError::Comparison {
+            path: path.to_path_buf(),
+            reason
+        }
     }
 }
 
@@ -487,7 +902,29 @@ pub struct SorterBuilder {
     fallback_segments: Vec<Box<dyn PatternElement + Send>>,
     dup_handling: DuplicateResolution,
     log: Option<mpsc::Sender<LogReq>>,
-    hash_algo: HashAlgorithm
+    hash_algo: HashAlgorithm,
+    partial_hash: Option<u64>,
+    hash_buffer_size: usize,
+    hash_cache: Option<HashCache>,
+    mtime_tolerance: MtimeTolerance,
+    quotas: Vec<BranchQuota>,
+    quota_table: Option<QuotaTable>,
+    age_route: Option<AgeRoute>,
+    source_rules: Vec<SourceRule>,
+    target_routes: Vec<TargetRoute>,
+    provenance: Option<String>,
+    rollover_rules: Vec<RolloverRule>,
+    naming: NamingScheme,
+    date_check: Option<DatePlausibility>,
+    clock_drift: Option<ClockDrift>,
+    pairs: Option<PairPolicy>,
+    folder_split: Option<FolderSplit>,
+    retry: RetryPolicy,
+    executor: Option<Box<dyn ActionExecutor + Send>>,
+    workspace: Option<Workspace>,
+    throttle: Option<Throttle>,
+    fallback_root: Option<PathBuf>,
+    simulation: SimulationPrinter
 }
 impl SorterBuilder {
 
@@ -509,12 +946,72 @@ impl SorterBuilder {
         self
     }
 
+    /// the hash algorithm used by dcim-sort if neither the config nor the command line set one
+    pub fn default_hash_algorithm() -> HashAlgorithm {
+        HashAlgorithm::MD5
+    }
+
     /// set the hash algorithm for comparing
     pub fn hash_algorithm(mut self, algo: HashAlgorithm) -> SorterBuilder {
+        self.set_hash_algorithm(algo);
+        self
+    }
+
+    pub fn set_hash_algorithm(&mut self, algo: HashAlgorithm) {
         self.hash_algo = algo;
+    }
+
+    /// only hash the first and last `size` bytes of files when comparing them, see
+    /// [FileComparer::partial_hash]
+    pub fn partial_hash(mut self, size: u64) -> SorterBuilder {
+        self.set_partial_hash(size);
+        self
+    }
+
+    pub fn set_partial_hash(&mut self, size: u64) {
+        self.partial_hash = Some(size);
+    }
+
+    /// read files in blocks of `size` bytes when comparing them, see [FileComparer::buffer_size]
+    pub fn hash_buffer_size(mut self, size: usize) -> SorterBuilder {
+        self.set_hash_buffer_size(size);
+        self
+    }
+
+    pub fn set_hash_buffer_size(&mut self, size: usize) {
+        self.hash_buffer_size = size;
+    }
+
+    /// reuse digests of unchanged files from `cache` when comparing them, see [HashCache]
+    pub fn hash_cache(mut self, cache: HashCache) -> SorterBuilder {
+        self.set_hash_cache(cache);
+        self
+    }
+
+    pub fn set_hash_cache(&mut self, cache: HashCache) {
+        self.hash_cache = Some(cache);
+    }
+
+    /// set how far modification times of identical files may differ, see [MtimeTolerance]
+    pub fn mtime_tolerance(mut self, tolerance: MtimeTolerance) -> SorterBuilder {
+        self.set_mtime_tolerance(tolerance);
         self
     }
 
+    pub fn set_mtime_tolerance(&mut self, tolerance: MtimeTolerance) {
+        self.mtime_tolerance = tolerance;
+    }
+
+    /// the [FileComparer] detecting duplicates in sorters built by this builder, e.g. to compare
+    /// files the same way outside of a sorter
+    pub fn comparer(&self) -> FileComparer {
+        FileComparer::new(false, self.hash_algo)
+            .partial_hash(self.partial_hash)
+            .buffer_size(self.hash_buffer_size)
+            .hash_cache(self.hash_cache.clone())
+            .mtime_tolerance(self.mtime_tolerance)
+    }
+
     /// Add a segment pattern to the internal vec of segments for sorting
     /// files without supported metadata.
     pub fn fallback(mut self, s: Box<dyn PatternElement + Send>) -> SorterBuilder {
@@ -529,6 +1026,211 @@ impl SorterBuilder {
         self
     }
 
+    /// add a quota limiting a branch of the target tree
+    pub fn quota(mut self, q: BranchQuota) -> SorterBuilder {
+        self.push_quota(q);
+        self
+    }
+
+    /// add a quota limiting a branch of the target tree
+    pub fn push_quota(&mut self, q: BranchQuota) {
+        self.quotas.push(q);
+        self.quota_table = None;
+    }
+
+    /// route files older than the maximum age of `route` to its alternative target root
+    pub fn age_route(mut self, route: AgeRoute) -> SorterBuilder {
+        self.set_age_route(route);
+        self
+    }
+
+    /// route files older than the maximum age of `route` to its alternative target root
+    pub fn set_age_route(&mut self, route: AgeRoute) {
+        self.age_route = Some(route);
+    }
+
+    /// sort files matching `rule` by its segments and/or target root, see [SourceRule]. Rules are
+    /// checked in the order they are added.
+    pub fn source_rule(mut self, rule: SourceRule) -> SorterBuilder {
+        self.push_source_rule(rule);
+        self
+    }
+
+    pub fn push_source_rule(&mut self, rule: SourceRule) {
+        self.source_rules.push(rule);
+    }
+
+    /// record the import session `session_id` and the original source path in XMP sidecars of
+    /// copied files
+    pub fn provenance(mut self, session_id: &str) -> SorterBuilder {
+        self.set_provenance(session_id);
+        self
+    }
+
+    /// record the import session `session_id` and the original source path in XMP sidecars of
+    /// copied files (`<file>.xmp`), which requires the `rexiv2` feature. The copies themselves
+    /// are left unchanged.
+    pub fn set_provenance(&mut self, session_id: &str) {
+        self.provenance = Some(String::from(session_id));
+    }
+
+    /// add a rule appending a suffix to filenames of matching cameras. The first matching rule
+    /// is applied.
+    pub fn rollover_rule(mut self, rule: RolloverRule) -> SorterBuilder {
+        self.push_rollover_rule(rule);
+        self
+    }
+
+    /// add a rule appending a suffix to filenames of matching cameras. The first matching rule
+    /// is applied.
+    pub fn push_rollover_rule(&mut self, rule: RolloverRule) {
+        self.rollover_rules.push(rule);
+    }
+
+    /// set the scheme for generating target filenames
+    pub fn naming_scheme(mut self, scheme: NamingScheme) -> SorterBuilder {
+        self.set_naming_scheme(scheme);
+        self
+    }
+
+    /// set the scheme for generating target filenames
+    pub fn set_naming_scheme(&mut self, scheme: NamingScheme) {
+        self.naming = scheme;
+    }
+
+    /// check metadata timestamps against a window of plausible dates
+    pub fn date_plausibility(mut self, check: DatePlausibility) -> SorterBuilder {
+        self.set_date_plausibility(check);
+        self
+    }
+
+    /// check metadata timestamps against a window of plausible dates
+    pub fn set_date_plausibility(&mut self, check: DatePlausibility) {
+        self.date_check = Some(check);
+    }
+
+    /// skip or separately route the secondary files of HEIC/JPEG pairs
+    pub fn pair_policy(mut self, policy: PairPolicy) -> SorterBuilder {
+        self.set_pair_policy(policy);
+        self
+    }
+
+    /// skip or separately route the secondary files of HEIC/JPEG pairs
+    pub fn set_pair_policy(&mut self, policy: PairPolicy) {
+        self.pairs = Some(policy);
+    }
+
+    /// get the policy for HEIC/JPEG pairs, whose pairs must be detected by the
+    /// [crate::index::Scanner] of the input, see [crate::index::ScannerBuilder::pair_detection]
+    pub fn get_pair_policy(&self) -> Option<&PairPolicy> {
+        self.pairs.as_ref()
+    }
+
+    /// correct metadata timestamps by the inferred clock drift of their device
+    pub fn clock_drift(mut self, drift: ClockDrift) -> SorterBuilder {
+        self.set_clock_drift(drift);
+        self
+    }
+
+    /// correct metadata timestamps by the inferred clock drift of their device
+    pub fn set_clock_drift(&mut self, drift: ClockDrift) {
+        self.clock_drift = Some(drift);
+    }
+
+    /// split target folders exceeding a maximum number of files into numbered sub-buckets
+    pub fn folder_split(mut self, split: FolderSplit) -> SorterBuilder {
+        self.set_folder_split(split);
+        self
+    }
+
+    pub fn set_folder_split(&mut self, split: FolderSplit) {
+        self.folder_split = Some(split);
+    }
+
+    /// retry moving or copying files failing with transient IO errors
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> SorterBuilder {
+        self.set_retry_policy(policy);
+        self
+    }
+
+    /// retry moving or copying files failing with transient IO errors
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+    }
+
+    /// send files matching `route` to its target root, see [TargetRoute]
+    pub fn target_route(mut self, route: TargetRoute) -> SorterBuilder {
+        self.push_target_route(route);
+        self
+    }
+
+    /// add a target route after all existing ones, the first matching route applies
+    pub fn push_target_route(&mut self, route: TargetRoute) {
+        self.target_routes.push(route);
+    }
+
+    /// place all files sorted by the fallback segments in `folder` below the target root, e.g.
+    /// `unsorted`, instead of next to the tree of supported files
+    pub fn fallback_root(mut self, folder: PathBuf) -> SorterBuilder {
+        self.set_fallback_root(folder);
+        self
+    }
+
+    pub fn set_fallback_root(&mut self, folder: PathBuf) {
+        self.fallback_root = Some(folder);
+    }
+
+    /// copy files into `workspace` first and rename them to their target once complete, see
+    /// [Workspace]. Only applies to the default executor.
+    pub fn workspace(mut self, workspace: Workspace) -> SorterBuilder {
+        self.set_workspace(workspace);
+        self
+    }
+
+    pub fn set_workspace(&mut self, workspace: Workspace) {
+        self.workspace = Some(workspace);
+    }
+
+    /// limit the rate of copying files, shared by all sorters built by this builder. Only
+    /// applies to the default executor.
+    pub fn throttle(mut self, throttle: Throttle) -> SorterBuilder {
+        self.set_throttle(throttle);
+        self
+    }
+
+    pub fn set_throttle(&mut self, throttle: Throttle) {
+        self.throttle = Some(throttle);
+    }
+
+    /// print simulated files with `printer`, e.g. with relative paths
+    pub fn simulation_printer(mut self, printer: SimulationPrinter) -> SorterBuilder {
+        self.set_simulation_printer(printer);
+        self
+    }
+
+    pub fn set_simulation_printer(&mut self, printer: SimulationPrinter) {
+        self.simulation = printer;
+    }
+
+    /// replace the default [LocalExecutor] performing sorted actions. The retry policy and
+    /// provenance only apply to the default executor. The executor also receives the actions of
+    /// simulated runs with [Operation::Print], which must not change any file.
+    pub fn executor(mut self, executor: Box<dyn ActionExecutor + Send>) -> SorterBuilder {
+        self.set_executor(executor);
+        self
+    }
+
+    pub fn set_executor(&mut self, executor: Box<dyn ActionExecutor + Send>) {
+        self.executor = Some(executor);
+    }
+
+    fn build_executor(&self) -> Box<dyn ActionExecutor + Send> {
+        match &self.executor {
+            Some(e) => e.clone_boxed(),
+            None => Box::new(LocalExecutor::new(self.retry, self.provenance.clone(), self.workspace.clone(), self.throttle.clone()))
+        }
+    }
+
     /// add a supported path segment to the end of the list
     pub fn push_segment_supported(&mut self, s: Box<dyn PatternElement + Send>) {
         self.segments.push(s);
@@ -556,21 +1258,111 @@ impl SorterBuilder {
 
     fn build_clone_translator(&mut self) -> Translator {
         let segs = self.clone_segs();
-        Translator::new(segs.0, segs.1)
+        let mut translator = Translator::new(segs.0, segs.1);
+        if let Some(root) = &self.fallback_root {
+            translator.set_fallback_root(root.clone());
+        }
+        translator
+    }
+
+    /// get the quota table shared by all sorters built from this builder
+    fn shared_quotas(&mut self) -> Option<QuotaTable> {
+        if self.quotas.is_empty() {
+            return None;
+        }
+        if self.quota_table.is_none() {
+            self.quota_table = Some(QuotaTable::new(self.quotas.clone()));
+        }
+        self.quota_table.clone()
     }
 
     /// build a new synchronous builder
     pub fn build_sync(&mut self) -> Sorter {
         let translator = self.build_clone_translator();
-        let comparer = FileComparer::new(false, self.hash_algo);
-        Sorter::new(translator, comparer)
+        let comparer = self.comparer();
+        let mut sorter = Sorter::new(translator, comparer);
+        sorter.quotas = self.shared_quotas();
+        sorter.age_route = self.age_route.clone();
+        sorter.source_rules = self.source_rules.clone();
+        sorter.target_routes = self.target_routes.clone();
+        sorter.rollover_rules = self.rollover_rules.clone();
+        sorter.naming = self.naming.clone();
+        sorter.date_check = self.date_check.clone();
+        sorter.clock_drift = self.clock_drift.clone();
+        sorter.pairs = self.pairs.clone();
+        sorter.folder_split = self.folder_split.clone();
+        sorter.executor = self.build_executor();
+        sorter.simulation = self.simulation.clone();
+        sorter
     }
 
-    /// build a new asynchronous sorter
-    pub fn build_async(&mut self, chan_dir_mgr: mpsc::Sender<DirCreationRequest>) -> Sorter {
+    /// build a new asynchronous sorter. All sorters of a run must share the same
+    /// `reservations` to detect target collisions between threads.
+    pub fn build_async(&mut self, chan_dir_mgr: mpsc::Sender<DirCreationRequest>, reservations: TargetReservations) -> Sorter {
         let translator = self.build_clone_translator();
-        let comparer = FileComparer::new(false, self.hash_algo);
+        let comparer = self.comparer();
+
+        let mut sorter = Sorter::new_async(translator, comparer, chan_dir_mgr, reservations);
+        sorter.quotas = self.shared_quotas();
+        sorter.age_route = self.age_route.clone();
+        sorter.source_rules = self.source_rules.clone();
+        sorter.target_routes = self.target_routes.clone();
+        sorter.rollover_rules = self.rollover_rules.clone();
+        sorter.naming = self.naming.clone();
+        sorter.date_check = self.date_check.clone();
+        sorter.clock_drift = self.clock_drift.clone();
+        sorter.pairs = self.pairs.clone();
+        sorter.folder_split = self.folder_split.clone();
+        sorter.executor = self.build_executor();
+        sorter.simulation = self.simulation.clone();
+        sorter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod sorter {
+        use std::io;
+        use std::path::{Path, PathBuf};
 
-        Sorter::new_async(translator, comparer, chan_dir_mgr)
+        use crate::error::{DirErrorKind, SortError};
+        use crate::media::ImgInfo;
+        use crate::sorting::{DuplicateResolution, Sorter};
+        use crate::sorting::naming::{NamingScheme, SequencePattern, SequenceScope};
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn vanished_source_is_classified() {
+            let source = Path::new("/nonexistent/dcim-sort/IMG_0001.JPG");
+            let err = SortError::io("copy", source.to_path_buf(), io::Error::from(io::ErrorKind::NotFound));
+            assert!(matches!(Sorter::classify_vanished(err, source), SortError::SourceNotFound(_)));
+            let err = SortError::io("copy", PathBuf::from("/"), io::Error::from(io::ErrorKind::NotFound));
+            assert!(matches!(Sorter::classify_vanished(err, Path::new("/")), SortError::Io { .. }));
+        }
+
+        #[test]
+        fn stopped_dir_manager_is_no_race() {
+            let error = |kind| SortError::DirCreation { path: PathBuf::from("/nonexistent/2023"), kind, reason: String::new() };
+            assert!(Sorter::is_dir_race(&error(DirErrorKind::Transient)));
+            assert!(!Sorter::is_dir_race(&error(DirErrorKind::Stopped)));
+        }
+
+        #[test]
+        fn sequence_number_is_drawn_on_execution() {
+            let dir = TempDir::new("sorter-sequence");
+            std::fs::write(dir.join("a.jpg"), b"a").unwrap();
+            let pattern = SequencePattern::new("IMG_", 3, SequenceScope::Run);
+            let mut sorter = Sorter::builder().naming_scheme(NamingScheme::Sequence(pattern.clone())).build_sync();
+            let file = ImgInfo::new(dir.join("a.jpg")).unwrap();
+
+            let action = sorter.calc_simulation(&file, dir.join("out").as_path()).unwrap();
+            let folder = action.get_target().parent().unwrap().to_path_buf();
+            assert!(action.get_target().ends_with("IMG_001.jpg"));
+            assert_eq!(pattern.peek(folder.as_path()), 1);
+            sorter.execute_checked(action, &DuplicateResolution::Ignore).unwrap();
+            assert_eq!(sorter.last_target(), Some(folder.join("IMG_001.jpg").as_path()));
+            assert_eq!(pattern.peek(folder.as_path()), 2);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,342 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use sha2::Sha256;
+
+use crate::error::SortError;
+use crate::media::{ImgInfo, ImgMeta};
+use crate::sorting::comparison::FileComparer;
+
+/// length of the hex-encoded content hash used in filenames
+const SHORT_HASH_LEN: usize = 8;
+const TIMESTAMP_SUFFIX_FMT: &str = "%Y%m%d-%H%M%S";
+const CONTENT_ADDRESSED_DATE_FMT: &str = "%Y%m%d";
+/// default count of digits sequence numbers are padded to
+pub const DEFAULT_SEQUENCE_DIGITS: usize = 5;
+
+/// How target filenames are generated.
+///
+/// # Variants
+/// - [NamingScheme::Original] keep the original filename (default)
+/// - [NamingScheme::ContentAddressed] derive the filename from the date and content hash of the
+///   file as `<YYYYmmdd>_<shorthash>.<ext>`. Identical files always get the same name, so
+///   duplicates are detected by their target already existing.
+/// - [NamingScheme::Sequence] number files consecutively, see [SequencePattern]
+#[derive(Clone)]
+pub enum NamingScheme {
+    Original,
+    ContentAddressed,
+    Sequence(SequencePattern)
+}
+
+impl NamingScheme {
+    /// get the target filename for `file` with the original filename `fname` in the target
+    /// directory `folder`
+    pub fn filename(&self, file: &ImgInfo, fname: &OsStr, folder: &Path) -> Result<OsString, SortError> {
+        self.generate(file, fname, folder, false)
+    }
+
+    /// like [Self::filename], but without drawing a sequence number
+    pub fn peek_filename(&self, file: &ImgInfo, fname: &OsStr, folder: &Path) -> Result<OsString, SortError> {
+        self.generate(file, fname, folder, true)
+    }
+
+    fn generate(&self, file: &ImgInfo, fname: &OsStr, folder: &Path, peek: bool) -> Result<OsString, SortError> {
+        match self {
+            NamingScheme::Original => Ok(fname.to_os_string()),
+            NamingScheme::Sequence(pattern) if peek => Ok(pattern.format(fname, pattern.peek(folder))),
+            NamingScheme::Sequence(pattern) => Ok(pattern.filename(fname, folder)),
+            NamingScheme::ContentAddressed => {
+                let date = file.metadata().created_at().unwrap_or(file.changed_at());
+                let mut result = OsString::from(format!("{}_{}",
+                                                        date.format(CONTENT_ADDRESSED_DATE_FMT),
+                                                        short_hash(file)?
+                ));
+                if let Some(ext) = Path::new(fname).extension() {
+                    result.push(".");
+                    result.push(ext.to_string_lossy().to_lowercase());
+                }
+                Ok(result)
+            }
+        }
+    }
+}
+
+/// The scope sequence numbers of a [SequencePattern] are counted in.
+///
+/// # Variants
+/// - [SequenceScope::Run] one counter for all files of a run
+/// - [SequenceScope::Folder] a separate counter for each target folder
+#[derive(Clone, Copy, PartialEq)]
+pub enum SequenceScope {
+    Run,
+    Folder
+}
+
+/// Names files `<prefix><number>.<ext>` with a number that increases by one for every file in
+/// its [SequenceScope]. Counters are shared by all clones of a pattern, so sorters in different
+/// pipeline threads draw from the same sequence and never assign a number twice.
+///
+/// A counter continues after the highest number already used by files with the same prefix in
+/// every target folder it is used for, so repeated runs do not collide with previously sorted
+/// files. Sorters draw numbers right before executing an action, a file skipped before leaves no
+/// gap, a file failing afterwards does.
+#[derive(Clone)]
+pub struct SequencePattern {
+    prefix: String,
+    digits: usize,
+    scope: SequenceScope,
+    counters: Arc<Mutex<Counters>>
+}
+
+/// the last numbers drawn for each key of a [SequencePattern] and the folders it has scanned for
+/// numbers already in use
+#[derive(Default)]
+struct Counters {
+    last: HashMap<PathBuf, u64>,
+    scanned: HashSet<PathBuf>
+}
+
+impl SequencePattern {
+    pub fn new(prefix: &str, digits: usize, scope: SequenceScope) -> SequencePattern {
+        SequencePattern {
+            prefix: String::from(prefix),
+            digits,
+            scope,
+            counters: Arc::new(Mutex::new(Counters::default()))
+        }
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    pub fn digits(&self) -> usize {
+        self.digits
+    }
+
+    pub fn scope(&self) -> SequenceScope {
+        self.scope
+    }
+
+    /// draw the next number for a file in the target directory `folder`
+    pub fn next(&self, folder: &Path) -> u64 {
+        let key = match self.scope {
+            SequenceScope::Run => PathBuf::new(),
+            SequenceScope::Folder => folder.to_path_buf()
+        };
+        let mut counters = self.lock();
+        let highest = match counters.scanned.insert(folder.to_path_buf()) {
+            true => self.highest_in(folder),
+            false => 0
+        };
+        let counter = counters.last.entry(key).or_insert(0);
+        *counter = (*counter).max(highest) + 1;
+        *counter
+    }
+
+    /// get the number [Self::next] would draw for `folder` without drawing it
+    pub fn peek(&self, folder: &Path) -> u64 {
+        let key = match self.scope {
+            SequenceScope::Run => PathBuf::new(),
+            SequenceScope::Folder => folder.to_path_buf()
+        };
+        let counters = self.lock();
+        let current = counters.last.get(&key).copied().unwrap_or(0);
+        match counters.scanned.contains(folder) {
+            true => current + 1,
+            false => current.max(self.highest_in(folder)) + 1
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Counters> {
+        match self.counters.lock() {
+            Ok(guard) => guard,
+            // the maps are only modified by single inserts, a panicking thread cannot corrupt them
+            Err(poisoned) => poisoned.into_inner()
+        }
+    }
+
+    /// get the target filename for a file with the original filename `fname`
+    pub fn filename(&self, fname: &OsStr, folder: &Path) -> OsString {
+        self.format(fname, self.next(folder))
+    }
+
+    fn format(&self, fname: &OsStr, number: u64) -> OsString {
+        let mut result = OsString::from(format!("{}{:0width$}", self.prefix, number, width = self.digits));
+        if let Some(ext) = Path::new(fname).extension() {
+            result.push(".");
+            result.push(ext);
+        }
+        result
+    }
+
+    /// find the highest sequence number of files in `folder` named by this pattern
+    fn highest_in(&self, folder: &Path) -> u64 {
+        let entries = match fs::read_dir(folder) {
+            Ok(e) => e,
+            Err(_) => return 0
+        };
+        entries.filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let path = e.path();
+                let stem = path.file_stem()?.to_str()?;
+                stem.strip_prefix(self.prefix.as_str())?.parse::<u64>().ok()
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// get the first 8 hex digits of the SHA-256 hash of the contents of `file`
+fn short_hash(file: &ImgInfo) -> Result<String, SortError> {
+    match FileComparer::hash::<Sha256>(file.path()) {
+        Ok(hash) => {
+            let hex: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+            Ok(String::from(&hex[..SHORT_HASH_LEN]))
+        },
+        Err(_) => Err(SortError::Translation {
+            path: file.path().to_path_buf(),
+            reason: String::from("failed to hash file contents for the target filename")
+        })
+    }
+}
+
+/// The kind of suffix appended to target filenames by a [RolloverRule].
+///
+/// # Variants
+/// - [SuffixKind::ContentHash] the first 8 hex digits of the SHA-256 hash of the file contents
+/// - [SuffixKind::Timestamp] the metadata timestamp of the file or the file system timestamp if
+///   there is none, formatted as `YYYYmmdd-HHMMSS`
+#[derive(Clone, Copy)]
+pub enum SuffixKind {
+    ContentHash,
+    Timestamp
+}
+
+/// Appends a suffix to the filenames of all files taken by a specific camera, so files of
+/// cameras that reset their file counters (e.g. `IMG_0001.JPG` reappearing every year) get
+/// stable and distinct names instead of renamed duplicates. Make and model are compared
+/// case-insensitively, a missing value matches any camera.
+#[derive(Clone)]
+pub struct RolloverRule {
+    make: Option<String>,
+    model: Option<String>,
+    kind: SuffixKind
+}
+
+impl RolloverRule {
+    pub fn new(make: Option<&str>, model: Option<&str>, kind: SuffixKind) -> RolloverRule {
+        RolloverRule {
+            make: make.map(|s| s.trim().to_lowercase()),
+            model: model.map(|s| s.trim().to_lowercase()),
+            kind
+        }
+    }
+
+    /// the make to match in lowercase, `None` matches any make
+    pub fn make(&self) -> Option<&str> {
+        self.make.as_deref()
+    }
+
+    /// the model to match in lowercase, `None` matches any model
+    pub fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    pub fn kind(&self) -> SuffixKind {
+        self.kind
+    }
+
+    pub fn matches(&self, meta: &ImgMeta) -> bool {
+        let matches = |expected: &Option<String>, actual: &str| match expected {
+            Some(e) => e.as_str() == actual.trim().to_lowercase().as_str(),
+            None => true
+        };
+        matches(&self.make, meta.make()) && matches(&self.model, meta.model())
+    }
+
+    /// calculate the suffix for `file`
+    pub fn suffix(&self, file: &ImgInfo) -> Result<String, SortError> {
+        match self.kind {
+            SuffixKind::ContentHash => short_hash(file),
+            SuffixKind::Timestamp => {
+                let ts = file.metadata().created_at().unwrap_or(file.changed_at());
+                Ok(ts.format(TIMESTAMP_SUFFIX_FMT).to_string())
+            }
+        }
+    }
+
+    /// get the filename `fname` with the suffix of this rule inserted before the extension
+    pub fn apply(&self, file: &ImgInfo, fname: &OsStr) -> Result<OsString, SortError> {
+        let suffix = self.suffix(file)?;
+        let fpath = Path::new(fname);
+        let mut result = OsString::from(fpath.file_stem().unwrap_or(fname));
+        result.push("_");
+        result.push(suffix);
+        if let Some(ext) = fpath.extension() {
+            result.push(".");
+            result.push(ext);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod sequence {
+        use std::ffi::OsStr;
+        use std::path::Path;
+        use std::thread;
+
+        use crate::sorting::naming::{SequencePattern, SequenceScope};
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn unique_across_threads() {
+            let pattern = SequencePattern::new("IMG_", 5, SequenceScope::Run);
+            let handles: Vec<_> = (0..4).map(|_| {
+                let p = pattern.clone();
+                thread::spawn(move || (0..25).map(|_| p.next(Path::new("/nonexistent"))).collect::<Vec<u64>>())
+            }).collect();
+            let mut numbers: Vec<u64> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+            numbers.sort_unstable();
+            assert_eq!(numbers, (1..=100).collect::<Vec<u64>>());
+        }
+
+        #[test]
+        fn peek_does_not_draw() {
+            let pattern = SequencePattern::new("IMG_", 3, SequenceScope::Run);
+            let folder = Path::new("/nonexistent/dcim-sort");
+            assert_eq!(pattern.peek(folder), 1);
+            assert_eq!(pattern.next(folder), 1);
+            assert_eq!(pattern.peek(folder), 2);
+        }
+
+        #[test]
+        fn counts_per_folder() {
+            let pattern = SequencePattern::new("IMG_", 3, SequenceScope::Folder);
+            assert_eq!(pattern.filename(OsStr::new("a.jpg"), Path::new("/nonexistent/a")), "IMG_001.jpg");
+            assert_eq!(pattern.filename(OsStr::new("b.jpg"), Path::new("/nonexistent/b")), "IMG_001.jpg");
+            assert_eq!(pattern.filename(OsStr::new("c.CR2"), Path::new("/nonexistent/a")), "IMG_002.CR2");
+        }
+
+        #[test]
+        fn run_continues_after_every_folder() {
+            let root = TempDir::new("sequence-run");
+            for (folder, name) in [("a", "IMG_003.jpg"), ("b", "IMG_010.jpg")] {
+                std::fs::create_dir_all(root.join(folder)).unwrap();
+                std::fs::write(root.join(folder).join(name), b"").unwrap();
+            }
+            let pattern = SequencePattern::new("IMG_", 3, SequenceScope::Run);
+            assert_eq!(pattern.next(root.join("a").as_path()), 4);
+            assert_eq!(pattern.peek(root.join("b").as_path()), 11);
+            assert_eq!(pattern.next(root.join("b").as_path()), 11);
+            assert_eq!(pattern.next(root.join("a").as_path()), 12);
+        }
+    }
+}
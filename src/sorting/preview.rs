@@ -0,0 +1,121 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::SortError;
+
+/// maximum length of a path in bytes on common file systems (Linux `PATH_MAX`)
+pub const MAX_PATH_LEN: usize = 4096;
+/// maximum length of a single path component in bytes on common file systems
+pub const MAX_NAME_LEN: usize = 255;
+/// characters that are not allowed in filenames on Windows or have a special meaning in a segment
+pub const UNPORTABLE_CHARS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// The target a file would be sorted to with diagnostics about how it has been calculated, see
+/// [crate::sorting::Sorter::preview]. Calculating a preview has no side effects, e.g. no sequence
+/// number is drawn.
+pub struct TargetPreview {
+    /// the calculated target or the error preventing it
+    pub target: Result<PathBuf, SortError>,
+    /// name and value of every segment pattern applying to the file, `None` for segments that
+    /// are skipped. Empty if the file is quarantined.
+    pub segments: Vec<(String, Option<String>)>,
+    /// characters in segment values or the target filename that are not portable across file
+    /// systems, see [UNPORTABLE_CHARS]. A `/` in a segment value results in additional directories.
+    pub unportable_chars: Vec<char>,
+    /// length of the target path in bytes
+    pub path_len: usize,
+    /// length of the longest component of the target path in bytes
+    pub max_name_len: usize,
+    /// true if the target already exists, so the duplicate policy would be applied
+    pub would_conflict: bool
+}
+
+impl TargetPreview {
+    pub fn new(target: Result<PathBuf, SortError>, segments: Vec<(String, Option<String>)>) -> TargetPreview {
+        let mut unportable_chars: Vec<char> = segments.iter()
+            .filter_map(|(_, v)| v.as_deref())
+            .chain(target.as_ref().ok().and_then(|t| t.file_name()).and_then(|n| n.to_str()))
+            .flat_map(|v| v.chars())
+            .filter(|c| UNPORTABLE_CHARS.contains(c) || c.is_control())
+            .collect();
+        unportable_chars.sort_unstable();
+        unportable_chars.dedup();
+        let (path_len, max_name_len, would_conflict) = match &target {
+            Ok(t) => (
+                t.as_os_str().len(),
+                t.components()
+                    .filter_map(|c| match c {
+                        Component::Normal(n) => Some(n.len()),
+                        _ => None
+                    })
+                    .max()
+                    .unwrap_or(0),
+                t.exists()
+            ),
+            Err(_) => (0, 0, false)
+        };
+        TargetPreview {
+            target,
+            segments,
+            unportable_chars,
+            path_len,
+            max_name_len,
+            would_conflict
+        }
+    }
+
+    pub fn target(&self) -> Option<&Path> {
+        self.target.as_ref().ok().map(|t| t.as_path())
+    }
+
+    /// check if the target exceeds [MAX_PATH_LEN] or [MAX_NAME_LEN]
+    pub fn exceeds_limits(&self) -> bool {
+        self.path_len > MAX_PATH_LEN || self.max_name_len > MAX_NAME_LEN
+    }
+
+    /// get a human-readable description of every problem found
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if let Err(e) = &self.target {
+            warnings.push(e.to_string());
+        }
+        if !self.unportable_chars.is_empty() {
+            warnings.push(format!("unportable characters: {}",
+                                  self.unportable_chars.iter().map(|c| format!("{:?}", c)).collect::<Vec<String>>().join(" ")));
+        }
+        if self.path_len > MAX_PATH_LEN {
+            warnings.push(format!("path is {} bytes long, exceeding the limit of {}", self.path_len, MAX_PATH_LEN));
+        }
+        if self.max_name_len > MAX_NAME_LEN {
+            warnings.push(format!("a path component is {} bytes long, exceeding the limit of {}", self.max_name_len, MAX_NAME_LEN));
+        }
+        if self.would_conflict {
+            warnings.push(String::from("target already exists"));
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod preview {
+        use std::path::PathBuf;
+
+        use crate::sorting::preview::{MAX_NAME_LEN, TargetPreview};
+
+        #[test]
+        fn diagnostics() {
+            let long_name = "a".repeat(MAX_NAME_LEN + 1);
+            let target = PathBuf::from("/nonexistent/sorted").join("canon:eos").join(&long_name);
+            let preview = TargetPreview::new(Ok(target), vec![
+                (String::from("MakeModelPattern"), Some(String::from("canon:eos"))),
+                (String::from("ScreenshotPattern"), None)
+            ]);
+            assert_eq!(preview.unportable_chars, vec![':']);
+            assert_eq!(preview.max_name_len, MAX_NAME_LEN + 1);
+            assert!(preview.exceeds_limits());
+            assert!(!preview.would_conflict);
+            assert_eq!(preview.warnings().len(), 2);
+        }
+    }
+}
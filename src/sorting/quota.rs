@@ -0,0 +1,559 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use crate::error::SortError;
+use crate::logging::console::{self, Verbosity};
+use crate::sorting::PATHSTR_FB;
+
+/// Handling of files that would exceed the quota of their target branch.
+///
+/// # Variants
+/// - [QuotaOverflow::Skip] the file is not sorted and remains in the source directory
+/// - [QuotaOverflow::Spillover] the oldest files of the branch are moved into the given folder
+///   (relative to the target root) until the new file fits
+#[derive(Clone)]
+pub enum QuotaOverflow {
+    Skip,
+    Spillover(PathBuf)
+}
+
+/// A limit on the total size and/or number of files below a branch of the target tree. The
+/// branch is a relative path (e.g. `screenshots`) which matches every target directory that
+/// contains its components in sequence, so all matching directories share one quota.
+#[derive(Clone)]
+pub struct BranchQuota {
+    branch: PathBuf,
+    max_bytes: Option<u64>,
+    max_count: Option<u64>,
+    overflow: QuotaOverflow
+}
+
+impl BranchQuota {
+    pub fn new(branch: &Path, max_bytes: Option<u64>, max_count: Option<u64>, overflow: QuotaOverflow) -> BranchQuota {
+        BranchQuota {
+            branch: branch.to_path_buf(),
+            max_bytes,
+            max_count,
+            overflow
+        }
+    }
+
+    pub fn branch(&self) -> &Path {
+        self.branch.as_path()
+    }
+
+    pub fn max_bytes(&self) -> Option<u64> {
+        self.max_bytes
+    }
+
+    pub fn max_count(&self) -> Option<u64> {
+        self.max_count
+    }
+
+    pub fn overflow(&self) -> &QuotaOverflow {
+        &self.overflow
+    }
+
+    /// get the directory in which the branch begins for a target directory below `target_root`
+    /// or `None` if the target is not part of this branch.
+    fn branch_root(&self, target_dir: &Path, target_root: &Path) -> Option<PathBuf> {
+        let rel = target_dir.strip_prefix(target_root).ok()?;
+        let rel: Vec<Component> = rel.components().collect();
+        let branch: Vec<Component> = self.branch.components().collect();
+        if branch.is_empty() || branch.len() > rel.len() {
+            return None;
+        }
+
+        for start in 0..=(rel.len() - branch.len()) {
+            if rel[start..start + branch.len()] == branch[..] {
+                let mut root = target_root.to_path_buf();
+                for c in &rel[..start + branch.len()] {
+                    root.push(c.as_os_str());
+                }
+                return Some(root);
+            }
+        }
+        None
+    }
+
+    /// the directory files are evicted to, `None` if files exceeding the quota are skipped
+    fn spillover(&self, target_root: &Path) -> Option<PathBuf> {
+        match &self.overflow {
+            QuotaOverflow::Skip => None,
+            QuotaOverflow::Spillover(dir) => Some(target_root.join(dir))
+        }
+    }
+
+    fn fits(&self, bytes: u64, count: u64) -> bool {
+        self.max_bytes.is_none_or(|max| bytes <= max) && self.max_count.is_none_or(|max| count <= max)
+    }
+}
+
+/// accumulated usage of a single branch. Existing content is added once for every branch root
+/// that is encountered during a run.
+struct BranchUsage {
+    roots: HashSet<PathBuf>,
+    bytes: u64,
+    count: u64,
+    /// files evicted in a simulation with their size, which are still counted in `bytes` and
+    /// `count` as they have not been moved, but are not evicted again
+    simulated: HashMap<PathBuf, u64>
+}
+
+impl BranchUsage {
+    fn empty() -> BranchUsage {
+        BranchUsage { roots: HashSet::new(), bytes: 0, count: 0, simulated: HashMap::new() }
+    }
+
+    /// the usage without files evicted in a simulation
+    fn effective(&self) -> (u64, u64) {
+        let bytes: u64 = self.simulated.values().sum();
+        (self.bytes.saturating_sub(bytes), self.count.saturating_sub(self.simulated.len() as u64))
+    }
+
+    /// check if `file` is counted in this usage, i.e. below one of its roots but not in the
+    /// spillover folder
+    fn contains(&self, file: &Path, spillover: Option<&Path>) -> bool {
+        self.roots.iter().any(|r| file.starts_with(r)) && spillover.is_none_or(|s| !file.starts_with(s))
+    }
+}
+
+/// Run-wide bookkeeping of [BranchQuota]s, shared between all sorters of a run.
+#[derive(Clone)]
+pub struct QuotaTable {
+    quotas: Arc<Vec<BranchQuota>>,
+    usage: Arc<Mutex<Vec<BranchUsage>>>,
+    /// held while planning and moving evictions, which is done without locking `usage`
+    evicting: Arc<Mutex<()>>
+}
+
+/// a quota of a target which requires evictions, with a snapshot of its usage
+struct Overflow {
+    index: usize,
+    used: (u64, u64),
+    roots: Vec<PathBuf>,
+    simulated: HashSet<PathBuf>
+}
+
+/// a file to evict from a branch into its spillover folder
+struct Eviction {
+    file: PathBuf,
+    size: u64,
+    destination: PathBuf
+}
+
+impl QuotaTable {
+    pub fn new(quotas: Vec<BranchQuota>) -> QuotaTable {
+        let mut usage = Vec::with_capacity(quotas.len());
+        for _ in &quotas {
+            usage.push(BranchUsage::empty());
+        }
+        QuotaTable {
+            quotas: Arc::new(quotas),
+            usage: Arc::new(Mutex::new(usage)),
+            evicting: Arc::new(Mutex::new(()))
+        }
+    }
+
+    /// check if a file of `size` bytes may be placed at `target` and reserve its usage in all
+    /// quotas of the target, which are checked before any of them is changed. `replaced` is the
+    /// size of an existing target the file overwrites, which is freed by it. Depending on the
+    /// overflow handling of a quota, older files of its branch are evicted to make room. The
+    /// evictions of all quotas are planned before any file is moved, so nothing is evicted for
+    /// a file that is skipped anyway. If `simulate` is set, evictions are only printed. The
+    /// reserved usage must be given back with [QuotaTable::release] if the file is not written
+    /// after all.
+    ///
+    /// Returns `Ok(false)` if the file must be skipped.
+    pub fn admit(&self, target: &Path, target_root: &Path, size: u64, replaced: Option<u64>, simulate: bool) -> Result<bool, SortError> {
+        let target_dir = match target.parent() {
+            Some(p) => p,
+            None => return Ok(true)
+        };
+        let roots: Vec<Option<PathBuf>> = self.quotas.iter()
+            .map(|q| q.branch_root(target_dir, target_root))
+            .collect();
+        self.count_existing(&roots, target_root);
+        let added = Added::new(size, replaced);
+
+        let evicting = match self.check(&roots, &added, size, target_root) {
+            Some(e) if e.is_empty() => return Ok(true),
+            Some(_) => self.evicting.lock().unwrap_or_else(|poisoned| poisoned.into_inner()),
+            None => return Ok(false)
+        };
+        // another thread may have evicted or reserved in the meantime
+        let overflowing = match self.check(&roots, &added, size, target_root) {
+            Some(e) if e.is_empty() => return Ok(true),
+            Some(e) => e,
+            None => return Ok(false)
+        };
+        let evictions = match self.plan_evictions(&overflowing, &added, target, target_root) {
+            Some(e) => e,
+            None => return Ok(false)
+        };
+        let mut evicted = Vec::with_capacity(evictions.len());
+        let mut result = Ok(());
+        for eviction in evictions {
+            if simulate {
+                if console::shows(Verbosity::Normal) {
+                    println!("[QUOTA] would evict \"{}\" -> \"{}\"",
+                             eviction.file.to_str().unwrap_or(PATHSTR_FB),
+                             eviction.destination.to_str().unwrap_or(PATHSTR_FB)
+                    );
+                }
+            }
+            else if let Err(e) = Self::evict(&eviction) {
+                result = Err(e);
+                break;
+            }
+            evicted.push(eviction);
+        }
+        drop(evicting);
+
+        let mut usage = self.lock();
+        for eviction in &evicted {
+            for (quota, used) in self.quotas.iter().zip(usage.iter_mut()) {
+                if !used.contains(eviction.file.as_path(), quota.spillover(target_root).as_deref()) {
+                    continue;
+                }
+                if simulate {
+                    used.simulated.insert(eviction.file.clone(), eviction.size);
+                }
+                else {
+                    used.bytes = used.bytes.saturating_sub(eviction.size);
+                    used.count = used.count.saturating_sub(1);
+                }
+            }
+        }
+        result?;
+        // the room made may have been taken by another file of the branch in the meantime
+        for ((quota, used), root) in self.quotas.iter().zip(usage.iter()).zip(&roots) {
+            if root.is_some() && !added.fits(quota, used.effective()) {
+                return Ok(false);
+            }
+        }
+        for (used, root) in usage.iter_mut().zip(&roots) {
+            if root.is_some() {
+                added.add_to(used);
+            }
+        }
+        Ok(true)
+    }
+
+    /// give back the usage reserved by [QuotaTable::admit] for a file which has not been written
+    pub fn release(&self, target: &Path, target_root: &Path, size: u64, replaced: Option<u64>) {
+        let target_dir = match target.parent() {
+            Some(p) => p,
+            None => return
+        };
+        let mut usage = self.lock();
+        let added = Added::new(size, replaced);
+        for (quota, used) in self.quotas.iter().zip(usage.iter_mut()) {
+            if quota.branch_root(target_dir, target_root).is_some() {
+                added.remove_from(used);
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<BranchUsage>> {
+        match self.usage.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner()
+        }
+    }
+
+    /// add the existing content of branch roots seen for the first time, which is read without
+    /// holding the lock
+    fn count_existing(&self, roots: &[Option<PathBuf>], target_root: &Path) {
+        let missing: Vec<usize> = {
+            let usage = self.lock();
+            roots.iter().zip(usage.iter()).enumerate()
+                .filter(|(_, (root, used))| root.as_ref().is_some_and(|r| !used.roots.contains(r)))
+                .map(|(i, _)| i)
+                .collect()
+        };
+        for i in missing {
+            let root = match &roots[i] {
+                Some(r) => r,
+                None => continue
+            };
+            let (bytes, count) = dir_usage(root.as_path(), self.quotas[i].spillover(target_root).as_deref());
+            let mut usage = self.lock();
+            // another thread may have counted the root in the meantime
+            if usage[i].roots.insert(root.clone()) {
+                usage[i].bytes += bytes;
+                usage[i].count += count;
+            }
+        }
+    }
+
+    /// check all quotas of a target against their usage and reserve the file if it fits all of
+    /// them. Returns the quotas requiring evictions, which is empty if the file has been
+    /// reserved, or `None` if a quota cannot make room for it.
+    fn check(&self, roots: &[Option<PathBuf>], added: &Added, size: u64, target_root: &Path) -> Option<Vec<Overflow>> {
+        let mut usage = self.lock();
+        let mut overflowing = Vec::new();
+        for (i, ((quota, used), root)) in self.quotas.iter().zip(usage.iter()).zip(roots).enumerate() {
+            if root.is_none() || added.fits(quota, used.effective()) {
+                continue;
+            }
+            // the branch can at most be emptied down to the new file
+            if quota.spillover(target_root).is_none() || !quota.fits(size, 1) {
+                return None;
+            }
+            overflowing.push(Overflow {
+                index: i,
+                used: used.effective(),
+                roots: used.roots.iter().cloned().collect(),
+                simulated: used.simulated.keys().cloned().collect()
+            });
+        }
+        if overflowing.is_empty() {
+            for (used, root) in usage.iter_mut().zip(roots) {
+                if root.is_some() {
+                    added.add_to(used);
+                }
+            }
+        }
+        Some(overflowing)
+    }
+
+    /// select the oldest files of each overflowing quota, except `target`, to move into its
+    /// spillover folder until the new file fits. Files selected for one quota also make room in
+    /// the others they count in. Returns `None` if a quota cannot make enough room.
+    fn plan_evictions(&self, overflowing: &[Overflow], added: &Added, target: &Path, target_root: &Path) -> Option<Vec<Eviction>> {
+        let mut planned: Vec<Eviction> = Vec::new();
+        let mut destinations = HashSet::new();
+        for overflow in overflowing {
+            let quota = &self.quotas[overflow.index];
+            let spillover = quota.spillover(target_root)?;
+            let in_branch = |file: &Path| overflow.roots.iter().any(|r| file.starts_with(r)) && !file.starts_with(&spillover);
+            let (mut bytes, mut count) = overflow.used;
+            for eviction in planned.iter().filter(|e| in_branch(e.file.as_path())) {
+                bytes = bytes.saturating_sub(eviction.size);
+                count = count.saturating_sub(1);
+            }
+
+            let mut files: Vec<(SystemTime, u64, PathBuf)> = Vec::new();
+            for root in &overflow.roots {
+                collect_files(root.as_path(), spillover.as_path(), &mut files);
+            }
+            files.retain(|f| f.2 != target && !overflow.simulated.contains(&f.2) && !planned.iter().any(|e| e.file == f.2));
+            files.sort_by_key(|f| f.0);
+            let mut files = files.into_iter();
+            while !added.fits(quota, (bytes, count)) {
+                let (_, size, file) = files.next()?;
+                let rel = file.strip_prefix(target_root).unwrap_or(file.as_path());
+                let destination = free_destination(spillover.join(rel), &destinations)?;
+                destinations.insert(destination.clone());
+                bytes = bytes.saturating_sub(size);
+                count = count.saturating_sub(1);
+                planned.push(Eviction { file, size, destination });
+            }
+        }
+        Some(planned)
+    }
+
+    fn evict(eviction: &Eviction) -> Result<(), SortError> {
+        if let Some(parent) = eviction.destination.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                return Err(SortError::dir_creation(parent, &e));
+            }
+        }
+        // the destination has been checked when planning, but may have been created since
+        if eviction.destination.exists() {
+            return Err(SortError::io("evict to spillover", eviction.file.clone(),
+                                     io::Error::new(io::ErrorKind::AlreadyExists, "spillover destination exists")));
+        }
+        fs::rename(&eviction.file, &eviction.destination)
+            .map_err(|e| SortError::io("evict to spillover", eviction.file.clone(), e))
+    }
+}
+
+/// get `path` or, if it already exists or is in `taken`, the first free name with a numbered
+/// suffix like [crate::sorting::Sorter::mutate_target_filename]
+fn free_destination(path: PathBuf, taken: &HashSet<PathBuf>) -> Option<PathBuf> {
+    let is_free = |p: &Path| !p.exists() && !taken.contains(p);
+    if is_free(path.as_path()) {
+        return Some(path);
+    }
+    let name = path.file_name()?.to_os_string();
+    (1..1000).map(|counter| {
+        let mut numbered = name.clone();
+        numbered.push(format!(".{:03}", counter));
+        path.with_file_name(numbered)
+    }).find(|p| is_free(p.as_path()))
+}
+
+/// the usage a file adds to a branch: its size minus the size of a target it replaces, which
+/// is already counted, and one file unless it replaces one
+struct Added {
+    size: u64,
+    replaced: u64,
+    count: u64
+}
+
+impl Added {
+    fn new(size: u64, replaced: Option<u64>) -> Added {
+        Added {
+            size,
+            replaced: replaced.unwrap_or(0),
+            count: if replaced.is_some() { 0 } else { 1 }
+        }
+    }
+
+    /// check if the file fits into `quota` with `used` bytes and files
+    fn fits(&self, quota: &BranchQuota, used: (u64, u64)) -> bool {
+        quota.fits((used.0 + self.size).saturating_sub(self.replaced), used.1 + self.count)
+    }
+
+    fn add_to(&self, used: &mut BranchUsage) {
+        used.bytes = (used.bytes + self.size).saturating_sub(self.replaced);
+        used.count += self.count;
+    }
+
+    fn remove_from(&self, used: &mut BranchUsage) {
+        used.bytes = (used.bytes + self.replaced).saturating_sub(self.size);
+        used.count = used.count.saturating_sub(self.count);
+    }
+}
+
+/// sum up size and number of all files below `dir`, excluding anything below `exclude`
+fn dir_usage(dir: &Path, exclude: Option<&Path>) -> (u64, u64) {
+    let mut files = Vec::new();
+    collect_files(dir, exclude.unwrap_or_else(|| Path::new("")), &mut files);
+    files.iter().fold((0, 0), |(bytes, count), f| (bytes + f.1, count + 1))
+}
+
+/// recursively collect all files below `dir` with their modification time and size, excluding
+/// anything below `exclude`.
+fn collect_files(dir: &Path, exclude: &Path, files: &mut Vec<(SystemTime, u64, PathBuf)>) {
+    let entries = match dir.read_dir() {
+        Ok(e) => e,
+        Err(_) => return
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !exclude.as_os_str().is_empty() && path.starts_with(exclude) {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(path.as_path(), exclude, files);
+        }
+        else if let Ok(meta) = entry.metadata() {
+            let mtime = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((mtime, meta.len(), path));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod branch_root {
+        use std::path::{Path, PathBuf};
+
+        use crate::sorting::quota::{BranchQuota, QuotaOverflow};
+
+        #[test]
+        fn matches_nested_branch() {
+            let quota = BranchQuota::new(Path::new("screenshots"), Some(1), None, QuotaOverflow::Skip);
+            let root = quota.branch_root(Path::new("out/apple_iphone/screenshots/2021-03"), Path::new("out"));
+            assert_eq!(root, Some(PathBuf::from("out/apple_iphone/screenshots")));
+        }
+
+        #[test]
+        fn ignores_partial_component() {
+            let quota = BranchQuota::new(Path::new("screenshots"), Some(1), None, QuotaOverflow::Skip);
+            let root = quota.branch_root(Path::new("out/old_screenshots/2021-03"), Path::new("out"));
+            assert_eq!(root, None);
+        }
+    }
+
+    mod admit {
+        use std::path::Path;
+        use std::time::{Duration, SystemTime};
+
+        use crate::sorting::quota::{BranchQuota, QuotaOverflow, QuotaTable};
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn rejecting_quota_leaves_others_unchanged() {
+            let root = TempDir::new("quota-multi");
+            let table = QuotaTable::new(vec![
+                BranchQuota::new(Path::new("pics"), None, Some(1), QuotaOverflow::Skip),
+                BranchQuota::new(Path::new("2021"), Some(5), None, QuotaOverflow::Skip)
+            ]);
+            assert!(!table.admit(root.join("pics/2021/a.jpg").as_path(), &root, 10, None, false).unwrap());
+            assert!(table.admit(root.join("pics/2020/b.jpg").as_path(), &root, 10, None, false).unwrap());
+            assert!(!table.admit(root.join("pics/2020/c.jpg").as_path(), &root, 1, None, false).unwrap());
+        }
+
+        #[test]
+        fn released_usage_is_available_again() {
+            let root = TempDir::new("quota-release");
+            let table = QuotaTable::new(vec![BranchQuota::new(Path::new("pics"), Some(10), Some(1), QuotaOverflow::Skip)]);
+            let target = root.join("pics/a.jpg");
+            assert!(table.admit(target.as_path(), &root, 10, None, false).unwrap());
+            table.release(target.as_path(), &root, 10, None);
+            assert!(table.admit(target.as_path(), &root, 10, None, false).unwrap());
+        }
+
+        #[test]
+        fn overwritten_target_frees_its_size() {
+            let root = TempDir::new("quota-overwrite");
+            std::fs::create_dir_all(root.join("pics")).unwrap();
+            std::fs::write(root.join("pics/a.jpg"), b"12345678").unwrap();
+            let table = QuotaTable::new(vec![BranchQuota::new(Path::new("pics"), Some(10), Some(1), QuotaOverflow::Skip)]);
+            let target = root.join("pics/a.jpg");
+            assert!(!table.admit(target.as_path(), &root, 9, None, false).unwrap());
+            assert!(table.admit(target.as_path(), &root, 9, Some(8), false).unwrap());
+            assert!(table.admit(target.as_path(), &root, 10, Some(9), false).unwrap());
+        }
+
+        #[test]
+        fn spillover_does_not_count() {
+            let root = TempDir::new("quota-spillover");
+            std::fs::create_dir_all(root.join("pics/old")).unwrap();
+            std::fs::write(root.join("pics/old/a.jpg"), b"12345").unwrap();
+            let table = QuotaTable::new(vec![BranchQuota::new(Path::new("pics"), Some(5), None, QuotaOverflow::Spillover("pics/old".into()))]);
+            assert!(table.admit(root.join("pics/b.jpg").as_path(), &root, 5, None, false).unwrap());
+            assert!(root.join("pics/old/a.jpg").is_file());
+        }
+
+        #[test]
+        fn evicted_files_do_not_replace_each_other() {
+            let root = TempDir::new("quota-evict-name");
+            std::fs::create_dir_all(root.join("pics")).unwrap();
+            std::fs::create_dir_all(root.join("old/pics")).unwrap();
+            std::fs::write(root.join("pics/a.jpg"), b"new").unwrap();
+            std::fs::write(root.join("old/pics/a.jpg"), b"evicted before").unwrap();
+            let table = QuotaTable::new(vec![BranchQuota::new(Path::new("pics"), None, Some(1), QuotaOverflow::Spillover("old".into()))]);
+            assert!(table.admit(root.join("pics/b.jpg").as_path(), &root, 1, None, false).unwrap());
+            assert_eq!(std::fs::read(root.join("old/pics/a.jpg")).unwrap(), b"evicted before");
+            assert_eq!(std::fs::read(root.join("old/pics/a.jpg.001")).unwrap(), b"new");
+        }
+
+        #[test]
+        fn simulated_evictions_are_not_repeated() {
+            let root = TempDir::new("quota-simulate");
+            std::fs::create_dir_all(root.join("pics")).unwrap();
+            for (name, age) in [("a.jpg", 20), ("b.jpg", 10)] {
+                let file = std::fs::File::create(root.join("pics").join(name)).unwrap();
+                file.set_modified(SystemTime::now() - Duration::from_secs(age)).unwrap();
+            }
+            let table = QuotaTable::new(vec![BranchQuota::new(Path::new("pics"), None, Some(2), QuotaOverflow::Spillover("old".into()))]);
+            // a.jpg and then b.jpg make room, after that no file is left to evict
+            assert!(table.admit(root.join("pics/c.jpg").as_path(), &root, 1, None, true).unwrap());
+            assert!(table.admit(root.join("pics/d.jpg").as_path(), &root, 1, None, true).unwrap());
+            assert!(!table.admit(root.join("pics/e.jpg").as_path(), &root, 1, None, true).unwrap());
+            assert!(root.join("pics/a.jpg").is_file());
+            assert!(root.join("pics/b.jpg").is_file());
+            assert!(!root.join("old").exists());
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use crate::logging::console;
+
+/// `EBUSY` on Linux and macOS, which has no stable [io::ErrorKind]
+#[cfg(unix)]
+const EBUSY: i32 = 16;
+
+/// largest number of attempts accepted from a config
+pub const MAX_ATTEMPTS: u32 = 100;
+
+/// the delay between two attempts never exceeds this, however many attempts failed
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Retries IO operations failing with transient errors (e.g. timeouts or busy resources on
+/// network shares) with an exponential backoff, so a single hiccup does not fail a file.
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    factor: u32
+}
+
+impl RetryPolicy {
+    /// create a policy performing at most `max_attempts` attempts, waiting `initial_backoff`
+    /// before the first retry and doubling the delay for each subsequent one, up to [MAX_BACKOFF]
+    pub fn new(max_attempts: u32, initial_backoff: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            initial_backoff: initial_backoff.min(MAX_BACKOFF),
+            factor: 2
+        }
+    }
+
+    /// a policy without any retries
+    pub fn none() -> RetryPolicy {
+        Self::new(1, Duration::from_millis(0))
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn initial_backoff(&self) -> Duration {
+        self.initial_backoff
+    }
+
+    /// run `op` until it succeeds, fails with a permanent error or all attempts are used up
+    pub fn run<T, F: FnMut() -> io::Result<T>>(&self, mut op: F) -> io::Result<T> {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Err(e) if attempt < self.max_attempts && Self::is_transient(&e) => {
+                    console::warn(format!("transient error (attempt {}/{}), retrying in {}ms: {}",
+                                          attempt, self.max_attempts, backoff.as_millis(), e).as_str());
+                    thread::sleep(backoff);
+                    backoff = self.next_backoff(backoff);
+                    attempt += 1;
+                },
+                result => return result
+            }
+        }
+    }
+
+    fn next_backoff(&self, backoff: Duration) -> Duration {
+        backoff.checked_mul(self.factor).map_or(MAX_BACKOFF, |b| b.min(MAX_BACKOFF))
+    }
+
+    /// check if an error is likely to disappear when retrying the operation
+    pub fn is_transient(e: &io::Error) -> bool {
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock => true,
+            #[cfg(unix)]
+            _ if e.raw_os_error() == Some(EBUSY) => true,
+            _ => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod run {
+        use std::io;
+        use std::time::Duration;
+
+        use crate::sorting::retry::{MAX_BACKOFF, RetryPolicy};
+
+        #[test]
+        fn retries_transient_errors() {
+            let mut calls = 0;
+            let result = RetryPolicy::new(3, Duration::from_millis(1)).run(|| {
+                calls += 1;
+                match calls {
+                    1 | 2 => Err(io::Error::new(io::ErrorKind::TimedOut, "timeout")),
+                    _ => Ok(calls)
+                }
+            });
+            assert_eq!(result.unwrap(), 3);
+        }
+
+        #[test]
+        fn fails_on_permanent_errors() {
+            let mut calls = 0;
+            let result: io::Result<()> = RetryPolicy::new(3, Duration::from_millis(1)).run(|| {
+                calls += 1;
+                Err(io::Error::new(io::ErrorKind::NotFound, "missing"))
+            });
+            assert!(result.is_err());
+            assert_eq!(calls, 1);
+        }
+
+        #[test]
+        fn backoff_is_capped() {
+            let policy = RetryPolicy::new(u32::MAX, Duration::from_millis(u64::MAX));
+            assert_eq!(policy.initial_backoff(), MAX_BACKOFF);
+            assert_eq!(policy.next_backoff(Duration::MAX), MAX_BACKOFF);
+            assert_eq!(RetryPolicy::new(3, Duration::from_millis(500)).next_backoff(Duration::from_millis(500)), Duration::from_secs(1));
+        }
+    }
+}
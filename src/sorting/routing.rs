@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Local, TimeZone};
+use regex::Regex;
+
+use crate::index::Glob;
+use crate::media::{self, ImgInfo};
+use crate::pattern::fallback::GeneralFileType;
+use crate::sorting::translation::Translator;
+
+/// Routes files older than a maximum age to an alternative target root (e.g. an archive on a
+/// different mount), allowing hot/cold tiering in a single pass. The age of a file is based on
+/// its metadata timestamp or the file system timestamp if no metadata is available.
+#[derive(Clone)]
+pub struct AgeRoute {
+    max_age: Duration,
+    root: PathBuf
+}
+
+impl AgeRoute {
+    pub fn new(max_age: Duration, root: &Path) -> AgeRoute {
+        AgeRoute {
+            max_age,
+            root: root.to_path_buf()
+        }
+    }
+
+    pub fn max_age(&self) -> &Duration {
+        &self.max_age
+    }
+
+    pub fn root(&self) -> &Path {
+        self.root.as_path()
+    }
+
+    /// get the alternative target root for `file` or `None` if it is not old enough
+    pub fn route(&self, file: &ImgInfo) -> Option<&Path> {
+        let timestamp = match file.metadata().created_at() {
+            Some(ts) => ts,
+            None => file.changed_at()
+        };
+        if Local::now().signed_duration_since(*timestamp) > self.max_age {
+            Some(self.root.as_path())
+        }
+        else {
+            None
+        }
+    }
+}
+
+/// How a [SourceRule] matches the path of a source file.
+///
+/// # Variants
+/// - [SourcePattern::Glob] a [Glob] matched against the end of the source path, e.g.
+///   `WhatsApp/**` or `*/DCIM/*`. Patterns starting with a `/` are matched against the whole
+///   path instead.
+/// - [SourcePattern::Regex] a regular expression searched for in the source path
+#[derive(Clone)]
+pub enum SourcePattern {
+    Glob(Glob),
+    Regex(Regex)
+}
+
+impl SourcePattern {
+    pub fn glob(pattern: &str) -> Result<SourcePattern, String> {
+        let glob = match pattern.starts_with('/') {
+            true => Glob::new(pattern)?,
+            false => Glob::new(format!("**/{}", pattern).as_str())?
+        };
+        Ok(SourcePattern::Glob(glob))
+    }
+
+    pub fn regex(pattern: &str) -> Result<SourcePattern, String> {
+        match Regex::new(pattern) {
+            Ok(re) => Ok(SourcePattern::Regex(re)),
+            Err(e) => Err(format!("invalid regular expression \"{}\": {}", pattern, e))
+        }
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let path = match path.to_str() {
+            Some(p) => p,
+            None => return false
+        };
+        match self {
+            // globs of absolute paths are matched without the leading "/"
+            SourcePattern::Glob(glob) => glob.matches(Path::new(path.strip_prefix('/').unwrap_or(path))),
+            SourcePattern::Regex(re) => re.is_match(path)
+        }
+    }
+}
+
+/// Sorts files whose source path matches a [SourcePattern] differently than all others, e.g.
+/// messenger images with another segment chain than camera pictures or into another target root.
+/// The first matching rule of a [crate::sorting::Sorter] applies, files matching none are sorted
+/// as usual.
+#[derive(Clone)]
+pub struct SourceRule {
+    pattern: SourcePattern,
+    root: Option<PathBuf>,
+    translator: Option<Translator>
+}
+
+impl SourceRule {
+    pub fn new(pattern: SourcePattern) -> SourceRule {
+        SourceRule {
+            pattern,
+            root: None,
+            translator: None
+        }
+    }
+
+    /// sort matching files below `root` instead of the target root. A relative `root` is placed
+    /// below the target root.
+    pub fn root(mut self, root: &Path) -> SourceRule {
+        self.root = Some(root.to_path_buf());
+        self
+    }
+
+    /// translate matching files with the segments of `translator` instead of the default ones
+    pub fn translator(mut self, translator: Translator) -> SourceRule {
+        self.translator = Some(translator);
+        self
+    }
+
+    pub fn matches(&self, file: &ImgInfo) -> bool {
+        self.pattern.matches(file.path())
+    }
+
+    /// the target root of matching files, `None` if it is not changed by this rule
+    pub fn route(&self, target_root: &Path) -> Option<PathBuf> {
+        self.root.as_ref().map(|r| target_root.join(r))
+    }
+
+    pub fn get_translator(&self) -> Option<&Translator> {
+        self.translator.as_ref()
+    }
+}
+
+/// Sends files of a class to another target root, e.g. videos to a large HDD volume while
+/// pictures stay on an SSD. A route applies if all of its conditions match, the first matching
+/// route of a [crate::sorting::Sorter] is used and files matching none stay in the target root.
+#[derive(Clone)]
+pub struct TargetRoute {
+    root: PathBuf,
+    types: Vec<GeneralFileType>,
+    make: Option<String>,
+    min_bytes: Option<u64>
+}
+
+impl TargetRoute {
+    /// route all files to `root`, a relative `root` is placed below the target root
+    pub fn new(root: &Path) -> TargetRoute {
+        TargetRoute {
+            root: root.to_path_buf(),
+            types: Vec::new(),
+            make: None,
+            min_bytes: None
+        }
+    }
+
+    /// only route files of one of `types`
+    pub fn types(mut self, types: Vec<GeneralFileType>) -> TargetRoute {
+        self.types = types;
+        self
+    }
+
+    /// only route files of devices by `make`, compared case-insensitively
+    pub fn make(mut self, make: &str) -> TargetRoute {
+        self.make = Some(String::from(make));
+        self
+    }
+
+    /// only route files of at least `min_bytes`
+    pub fn min_bytes(mut self, min_bytes: u64) -> TargetRoute {
+        self.min_bytes = Some(min_bytes);
+        self
+    }
+
+    pub fn get_root(&self) -> &Path {
+        self.root.as_path()
+    }
+
+    pub fn get_types(&self) -> &[GeneralFileType] {
+        self.types.as_slice()
+    }
+
+    pub fn get_make(&self) -> Option<&str> {
+        self.make.as_deref()
+    }
+
+    pub fn get_min_bytes(&self) -> Option<u64> {
+        self.min_bytes
+    }
+
+    pub fn matches(&self, file: &ImgInfo) -> bool {
+        (self.types.is_empty() || self.types.contains(&GeneralFileType::of(file)))
+            && self.make.as_ref().is_none_or(|m| file.metadata().make().trim().eq_ignore_ascii_case(m.trim()))
+            && self.min_bytes.is_none_or(|b| *file.size() as u64 >= b)
+    }
+
+    /// the target root of matching files
+    pub fn route(&self, target_root: &Path) -> PathBuf {
+        target_root.join(&self.root)
+    }
+}
+
+/// Handling of files whose metadata timestamp lies outside of the plausible window.
+///
+/// # Variants
+/// - [ImplausibleDate::Accept] sort the file by its timestamp anyway
+/// - [ImplausibleDate::FallbackMtime] use the file system timestamp instead
+/// - [ImplausibleDate::Quarantine] sort the file into the given folder (relative to the target
+///   root) without applying any segments
+#[derive(Clone)]
+pub enum ImplausibleDate {
+    Accept,
+    FallbackMtime,
+    Quarantine(PathBuf)
+}
+
+/// A window of plausible metadata timestamps. Timestamps in the future or before the minimum year
+/// (e.g. cameras with a dead clock battery reporting 1970) are considered implausible.
+#[derive(Clone)]
+pub struct DatePlausibility {
+    min_year: i32,
+    handling: ImplausibleDate
+}
+
+impl DatePlausibility {
+    pub fn new(min_year: i32, handling: ImplausibleDate) -> DatePlausibility {
+        DatePlausibility {
+            min_year,
+            handling
+        }
+    }
+
+    pub fn default_min_year() -> i32 {
+        1990
+    }
+
+    pub fn min_year(&self) -> i32 {
+        self.min_year
+    }
+
+    pub fn handling(&self) -> &ImplausibleDate {
+        &self.handling
+    }
+
+    pub fn is_plausible(&self, ts: &DateTime<Local>) -> bool {
+        let min = match Local.with_ymd_and_hms(self.min_year, 1, 1, 0, 0, 0).earliest() {
+            Some(m) => m,
+            None => return true
+        };
+        // allow a day of clock skew between camera and host
+        *ts >= min && *ts <= Local::now() + Duration::days(1)
+    }
+
+    /// check the metadata timestamp of `file`, files without one are always plausible. With
+    /// [ImplausibleDate::FallbackMtime] the timestamp is replaced by the file system timestamp.
+    ///
+    /// Returns `false` if the timestamp is implausible.
+    pub fn check(&self, file: &mut ImgInfo) -> bool {
+        let plausible = match file.metadata().created_at() {
+            Some(ts) => self.is_plausible(ts),
+            None => true
+        };
+        if !plausible {
+            if let ImplausibleDate::FallbackMtime = self.handling {
+                let mut meta = file.metadata().clone();
+                meta.set_created_at(Some(*file.changed_at()));
+                file.set_metadata(meta);
+            }
+        }
+        plausible
+    }
+
+    /// get the quarantine folder below `target_root` for `file` or `None` if it should be sorted
+    /// as usual
+    pub fn quarantine(&self, file: &ImgInfo, target_root: &Path) -> Option<PathBuf> {
+        match &self.handling {
+            ImplausibleDate::Quarantine(dir) => match file.metadata().created_at() {
+                Some(ts) if !self.is_plausible(ts) => Some(target_root.join(dir)),
+                _ => None
+            },
+            _ => None
+        }
+    }
+}
+
+/// maximum difference between the timestamps of two files of a HEIC/JPEG pair in seconds
+pub const PAIR_MAX_OFFSET_SECS: i64 = 2;
+
+/// The format kept of a HEIC/JPEG pair, see [PairPolicy].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PairFormat {
+    Heic,
+    Jpeg
+}
+
+impl PairFormat {
+    pub fn parse(s: &str) -> Option<PairFormat> {
+        match s.to_lowercase().as_str() {
+            "heic" => Some(PairFormat::Heic),
+            "jpeg" | "jpg" => Some(PairFormat::Jpeg),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PairFormat::Heic => "heic",
+            PairFormat::Jpeg => "jpeg"
+        }
+    }
+
+    /// the other format of a pair
+    pub fn other(&self) -> PairFormat {
+        match self {
+            PairFormat::Heic => PairFormat::Jpeg,
+            PairFormat::Jpeg => PairFormat::Heic
+        }
+    }
+
+    /// check if `path` has one of the [Self::extensions] of the format
+    fn has_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| self.extensions().iter().any(|ext| ext.eq_ignore_ascii_case(e)))
+    }
+
+    /// file extensions of the format as used by common devices
+    fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            PairFormat::Heic => &["HEIC", "heic"],
+            PairFormat::Jpeg => &["JPG", "jpg", "JPEG", "jpeg"]
+        }
+    }
+}
+
+/// Handling of the secondary file of a HEIC/JPEG pair.
+///
+/// # Variants
+/// - [PairHandling::Skip] do not sort the file, it is left at its source
+/// - [PairHandling::Route] sort the file below the given folder (relative to the target root)
+///   instead, applying all segments below it
+#[derive(Clone)]
+pub enum PairHandling {
+    Skip,
+    Route(PathBuf)
+}
+
+/// Handles shots existing as both HEIC and JPEG, e.g. iPhone "Most Compatible" exports next to
+/// the originals, so only the preferred format is sorted as usual. Pairs are detected by the
+/// [crate::index::Scanner] (see [find_pairs]) and recorded in the [ImgInfo] of the secondary
+/// file, as the primary file may have been sorted already when the secondary file is processed.
+#[derive(Clone)]
+pub struct PairPolicy {
+    preferred: PairFormat,
+    handling: PairHandling
+}
+
+impl PairPolicy {
+    pub fn new(preferred: PairFormat, handling: PairHandling) -> PairPolicy {
+        PairPolicy {
+            preferred,
+            handling
+        }
+    }
+
+    pub fn preferred(&self) -> PairFormat {
+        self.preferred
+    }
+
+    pub fn handling(&self) -> &PairHandling {
+        &self.handling
+    }
+
+    /// check if `file` is the secondary file of a pair which should not be sorted
+    pub fn skip(&self, file: &ImgInfo) -> bool {
+        matches!(self.handling, PairHandling::Skip) && file.pair_primary().is_some()
+    }
+
+    /// get the folder below `target_root` to sort `file` into or `None` if it should be sorted
+    /// as usual
+    pub fn route(&self, file: &ImgInfo, target_root: &Path) -> Option<PathBuf> {
+        match &self.handling {
+            PairHandling::Route(dir) if file.pair_primary().is_some() => Some(target_root.join(dir)),
+            _ => None
+        }
+    }
+}
+
+/// get the file in the `preferred` format if `path` is the secondary file of a pair: a file in
+/// the other format is the secondary file if a file with the same name in the preferred format
+/// exists in the same directory and both have the same Exif timestamp (within
+/// [PAIR_MAX_OFFSET_SECS]). If either has no Exif timestamp, the file system timestamps are
+/// compared instead.
+pub fn find_primary(path: &Path, preferred: PairFormat) -> Option<PathBuf> {
+    if !preferred.other().has_extension(path) {
+        return None;
+    }
+    let primary = preferred.extensions().iter()
+        .map(|ext| path.with_extension(ext))
+        .find(|p| p.is_file())?;
+    let exif_time = |p: &Path| media::default_processor().read_metadata(p)
+        .and_then(|m| m.created_at().copied());
+    let mtime = |p: &Path| p.metadata().and_then(|m| m.modified()).ok().map(DateTime::<Local>::from);
+    let (ts, primary_ts) = match (exif_time(path), exif_time(primary.as_path())) {
+        (Some(ts), Some(primary_ts)) => (ts, primary_ts),
+        _ => (mtime(path)?, mtime(primary.as_path())?)
+    };
+    match ts.signed_duration_since(primary_ts).num_seconds().abs() <= PAIR_MAX_OFFSET_SECS {
+        true => Some(primary),
+        false => None
+    }
+}
+
+/// find the secondary files of all pairs in `dir` with their primary file, see [find_primary].
+/// Called before any file of the directory is sorted, so moved primary files are still found.
+pub fn find_pairs(dir: &Path, preferred: PairFormat) -> HashMap<PathBuf, PathBuf> {
+    let entries = match dir.read_dir() {
+        Ok(e) => e,
+        Err(_) => return HashMap::new()
+    };
+    entries.filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| preferred.other().has_extension(p))
+        .filter_map(|p| find_primary(p.as_path(), preferred).map(|primary| (p, primary)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod pair_policy {
+        use std::path::PathBuf;
+
+        use crate::media::ImgInfo;
+        use crate::sorting::routing::{find_pairs, find_primary, PairFormat, PairHandling, PairPolicy};
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn detects_secondary_by_name_and_mtime() {
+            let dir = TempDir::new("pairs");
+            for name in ["IMG_0001.HEIC", "IMG_0001.JPG", "IMG_0002.JPG"] {
+                std::fs::write(dir.join(name), b"not an image").unwrap();
+            }
+            assert_eq!(find_primary(dir.join("IMG_0001.JPG").as_path(), PairFormat::Heic), Some(dir.join("IMG_0001.HEIC")));
+            assert_eq!(find_primary(dir.join("IMG_0001.HEIC").as_path(), PairFormat::Heic), None);
+            assert_eq!(find_primary(dir.join("IMG_0002.JPG").as_path(), PairFormat::Heic), None);
+            let pairs = find_pairs(&dir, PairFormat::Heic);
+            assert_eq!(pairs.len(), 1);
+            assert_eq!(pairs.get(&dir.join("IMG_0001.JPG")), Some(&dir.join("IMG_0001.HEIC")));
+
+            let mut secondary = ImgInfo::new(dir.join("IMG_0001.JPG")).unwrap();
+            secondary.set_pair_primary(pairs.get(secondary.path()).cloned());
+            let policy = PairPolicy::new(PairFormat::Heic, PairHandling::Route(PathBuf::from("derived")));
+            assert_eq!(policy.route(&secondary, &dir), Some(dir.join("derived")));
+            assert_eq!(policy.route(&ImgInfo::new(dir.join("IMG_0002.JPG")).unwrap(), &dir), None);
+            assert!(!policy.skip(&secondary));
+        }
+    }
+
+    mod target_route {
+        use std::path::Path;
+
+        use crate::media::ImgInfo;
+        use crate::pattern::fallback::GeneralFileType;
+        use crate::sorting::routing::TargetRoute;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn matches_all_conditions() {
+            let dir = TempDir::new("targets");
+            std::fs::write(dir.join("clip.mp4"), b"0123456789").unwrap();
+            std::fs::write(dir.join("IMG_0001.JPG"), b"0123456789").unwrap();
+            let info = |name: &str| ImgInfo::new(dir.join(name)).unwrap();
+            let videos = TargetRoute::new(Path::new("/mnt/hdd")).types(vec![GeneralFileType::Video]);
+            assert!(videos.matches(&info("clip.mp4")));
+            assert!(!videos.matches(&info("IMG_0001.JPG")));
+            let pictures = TargetRoute::new(Path::new("ssd")).types(vec![GeneralFileType::Picture]).min_bytes(10);
+            assert!(pictures.matches(&info("IMG_0001.JPG")));
+            assert!(!pictures.clone().min_bytes(11).matches(&info("IMG_0001.JPG")));
+            assert_eq!(pictures.route(Path::new("/out")), Path::new("/out/ssd"));
+            assert_eq!(videos.route(Path::new("/out")), Path::new("/mnt/hdd"));
+        }
+    }
+
+    mod source_pattern {
+        use std::path::Path;
+
+        use crate::sorting::routing::SourcePattern;
+
+        #[test]
+        fn matches_relative_and_absolute_globs() {
+            let path = Path::new("/home/user/WhatsApp/Media/IMG-01.jpg");
+            assert!(SourcePattern::glob("WhatsApp/**").unwrap().matches(path));
+            assert!(SourcePattern::glob("/home/*/WhatsApp/**").unwrap().matches(path));
+            assert!(!SourcePattern::glob("/WhatsApp/**").unwrap().matches(path));
+            assert!(SourcePattern::regex("/WhatsApp/").unwrap().matches(path));
+            assert!(!SourcePattern::regex("^/WhatsApp/").unwrap().matches(path));
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::HashSet;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::logging::console::{self, Event, Verbosity};
+use crate::sorting::{Operation, PATHSTR_FB};
+
+/// sources are padded to at most this width, so long paths do not push all targets to the right
+const MAX_SOURCE_WIDTH: usize = 60;
+
+/// characters ending a single quoted string in PowerShell, doubled to escape them
+const POWERSHELL_QUOTES: [char; 5] = ['\'', '\u{2018}', '\u{2019}', '\u{201a}', '\u{201b}'];
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// make the whole process a dry run, which leaves the file system untouched regardless of how
+/// components are configured: sorters only simulate their actions, the
+/// [crate::sorting::fs_support::DirManager] creates no directories, the
+/// [crate::logging::Logger] writes no log file, pipelines record nothing in checkpoints,
+/// manifests or catalogs and the [crate::sorting::hash_cache::HashCache] is not saved
+pub fn set_dry_run(b: bool) {
+    DRY_RUN.store(b, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// The outcome of a simulated file.
+///
+/// # Variants
+/// - [SimOutcome::New] the file would be sorted to a target which does not exist yet
+/// - [SimOutcome::Overwrite] the file would overwrite the existing target
+/// - [SimOutcome::Rename] the target exists, the file would be sorted to a renamed target
+/// - [SimOutcome::Duplicate] the target exists, the file would be skipped
+/// - [SimOutcome::Skip] the file would be skipped for another reason, e.g. an exceeded quota
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SimOutcome {
+    New,
+    Overwrite,
+    Rename,
+    Duplicate,
+    Skip
+}
+
+impl SimOutcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SimOutcome::New => "new",
+            SimOutcome::Overwrite => "overwrite",
+            SimOutcome::Rename => "rename",
+            SimOutcome::Duplicate => "duplicate",
+            SimOutcome::Skip => "skip"
+        }
+    }
+
+    /// the ANSI color code the outcome is printed in
+    fn color(&self) -> &'static str {
+        match self {
+            SimOutcome::New => "32",
+            SimOutcome::Overwrite => "31",
+            SimOutcome::Rename => "36",
+            SimOutcome::Duplicate => "33",
+            SimOutcome::Skip => "90"
+        }
+    }
+}
+
+/// The shell of a script printed instead of the outcome of simulated files, see
+/// [SimulationPrinter::script]
+///
+/// # Variants
+/// - [ScriptFormat::Sh] a POSIX shell script using `mkdir`, `mv` and `cp`
+/// - [ScriptFormat::PowerShell] a PowerShell script using `Move-Item` and `Copy-Item`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ScriptFormat {
+    Sh,
+    PowerShell
+}
+
+impl ScriptFormat {
+    pub fn parse(s: &str) -> Option<ScriptFormat> {
+        match s.to_lowercase().as_str() {
+            "sh" => Some(ScriptFormat::Sh),
+            "powershell" => Some(ScriptFormat::PowerShell),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScriptFormat::Sh => "sh",
+            ScriptFormat::PowerShell => "powershell"
+        }
+    }
+
+    /// the first lines of a script, which stop it at the first failing command
+    pub fn header(&self) -> &'static str {
+        match self {
+            ScriptFormat::Sh => "#!/bin/sh\nset -e",
+            ScriptFormat::PowerShell => "$ErrorActionPreference = 'Stop'"
+        }
+    }
+
+    /// `path` as a single quoted literal, `None` if it is not valid UTF-8
+    fn quote(&self, path: &Path) -> Option<String> {
+        let path = path.to_str()?;
+        Some(match self {
+            ScriptFormat::Sh => format!("'{}'", path.replace('\'', "'\\''")),
+            ScriptFormat::PowerShell => {
+                let mut quoted = String::from("'");
+                for c in path.chars() {
+                    // typographic single quotes end a single quoted string as well
+                    if POWERSHELL_QUOTES.contains(&c) {
+                        quoted.push(c);
+                    }
+                    quoted.push(c);
+                }
+                quoted.push('\'');
+                quoted
+            }
+        })
+    }
+
+    fn mkdir(&self, dir: &str) -> String {
+        match self {
+            ScriptFormat::Sh => format!("mkdir -p -- {}", dir),
+            ScriptFormat::PowerShell => format!("[void][System.IO.Directory]::CreateDirectory({})", dir)
+        }
+    }
+
+    fn command(&self, operation: Operation, source: &str, target: &str) -> String {
+        match (self, operation) {
+            (ScriptFormat::Sh, Operation::Move) => format!("mv -- {} {}", source, target),
+            (ScriptFormat::Sh, _) => format!("cp -p -- {} {}", source, target),
+            (ScriptFormat::PowerShell, Operation::Move) => format!("Move-Item -Force -LiteralPath {} -Destination {}", source, target),
+            (ScriptFormat::PowerShell, _) => format!("Copy-Item -Force -LiteralPath {} -Destination {}", source, target)
+        }
+    }
+}
+
+/// Prints the outcome of simulated files as aligned columns of outcome, source and target or
+/// the reason a file is skipped. Clones share the width of the source column, so the lines of
+/// all pipelines line up.
+#[derive(Clone)]
+pub struct SimulationPrinter {
+    color: bool,
+    /// print sources relative to this directory and targets relative to their root
+    relative_to: Option<PathBuf>,
+    source_width: Arc<AtomicUsize>,
+    /// print the commands performing the operation instead, see [SimulationPrinter::script]
+    script: Option<(ScriptFormat, Operation)>,
+    /// directories created by the commands printed so far
+    script_dirs: Arc<Mutex<HashSet<PathBuf>>>
+}
+
+impl Default for SimulationPrinter {
+    /// colored output if stdout is a terminal and `NO_COLOR` is not set, absolute paths
+    fn default() -> Self {
+        SimulationPrinter {
+            color: std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            relative_to: None,
+            source_width: Arc::new(AtomicUsize::new(0)),
+            script: None,
+            script_dirs: Arc::new(Mutex::new(HashSet::new()))
+        }
+    }
+}
+
+impl SimulationPrinter {
+    pub fn new() -> SimulationPrinter {
+        SimulationPrinter::default()
+    }
+
+    pub fn color(mut self, color: bool) -> SimulationPrinter {
+        self.set_color(color);
+        self
+    }
+
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+
+    /// print sources relative to `input` and targets relative to the output directory
+    pub fn relative_paths(mut self, input: PathBuf) -> SimulationPrinter {
+        self.set_relative_paths(input);
+        self
+    }
+
+    pub fn set_relative_paths(&mut self, input: PathBuf) {
+        self.relative_to = Some(input);
+    }
+
+    /// print a `format` script of the commands performing `operation` (move or copy) for every
+    /// file that would be sorted, so it can be reviewed and run by hand. Files that would not be
+    /// sorted are listed in comments. Paths are always absolute and the script has to be
+    /// preceded by [ScriptFormat::header]. The lines are printed regardless of the verbosity,
+    /// other output should be suppressed with [console::OutputFormat::Script].
+    pub fn script(mut self, format: ScriptFormat, operation: Operation) -> SimulationPrinter {
+        self.set_script(format, operation);
+        self
+    }
+
+    pub fn set_script(&mut self, format: ScriptFormat, operation: Operation) {
+        self.script = Some((format, operation));
+    }
+
+    /// print that `source` would be sorted to `target` below the output directory `root`
+    pub fn print_target(&self, outcome: SimOutcome, source: &Path, target: &Path, root: &Path) {
+        console::emit(&Event::Simulated {
+            source,
+            target: Some(target),
+            outcome: outcome.name(),
+            reason: None
+        });
+        if let Some((format, operation)) = self.script {
+            // held until the lines are printed, so no other thread prints a command for a new
+            // directory before the command creating it
+            let mut dirs = match self.script_dirs.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner()
+            };
+            println!("{}", Self::script_lines(&mut dirs, format, operation, outcome, source, target));
+        }
+        else if console::shows(Verbosity::Normal) {
+            println!("{}", self.line(outcome, source, format!("-> {}", self.display(target, root)).as_str()));
+        }
+    }
+
+    /// print that `source` would be skipped for `reason`
+    pub fn print_skip(&self, source: &Path, reason: &str) {
+        console::emit(&Event::Simulated {
+            source,
+            target: None,
+            outcome: SimOutcome::Skip.name(),
+            reason: Some(reason)
+        });
+        if self.script.is_some() {
+            println!("# skip: {} ({})", comment_path(source), reason);
+        }
+        else if console::shows(Verbosity::Normal) {
+            println!("{}", self.line(SimOutcome::Skip, source, format!("({})", reason).as_str()));
+        }
+    }
+
+    fn line(&self, outcome: SimOutcome, source: &Path, detail: &str) -> String {
+        let source = match &self.relative_to {
+            Some(input) => self.display(source, input.as_path()),
+            None => String::from(source.to_str().unwrap_or(PATHSTR_FB))
+        };
+        let len = source.chars().count();
+        let width = self.source_width.fetch_max(len.min(MAX_SOURCE_WIDTH), Ordering::Relaxed).max(len.min(MAX_SOURCE_WIDTH));
+        let label = format!("{:<9}", outcome.name());
+        let label = match self.color {
+            true => format!("\x1b[{}m{}\x1b[0m", outcome.color(), label),
+            false => label
+        };
+        format!("{} {:<width$}  {}", label, source, detail, width = width)
+    }
+
+    /// the commands sorting `source` to `target`, or a comment if it would not be sorted. The
+    /// target directory is created first unless it is in `dirs`, the directories created by the
+    /// commands printed so far.
+    fn script_lines(dirs: &mut HashSet<PathBuf>, format: ScriptFormat, operation: Operation, outcome: SimOutcome, source: &Path, target: &Path) -> String {
+        if let SimOutcome::Duplicate | SimOutcome::Skip = outcome {
+            return format!("# {}: {} -> {}", outcome.name(), comment_path(source), comment_path(target));
+        }
+        let (quoted_source, quoted_target) = match (format.quote(source), format.quote(target)) {
+            (Some(s), Some(t)) => (s, t),
+            _ => return format!("# not valid UTF-8, sort by hand: {} -> {}", comment_path(source), comment_path(target))
+        };
+        let mut lines = Vec::new();
+        if let Some(dir) = target.parent().filter(|d| !d.as_os_str().is_empty() && !d.is_dir()) {
+            if dirs.insert(dir.to_path_buf()) {
+                // the target is valid UTF-8, so is its parent
+                lines.push(format.mkdir(format.quote(dir).unwrap_or_default().as_str()));
+            }
+        }
+        lines.push(format.command(operation, quoted_source.as_str(), quoted_target.as_str()));
+        lines.join("\n")
+    }
+
+    /// `path` relative to `root` if relative paths are printed and it is below `root`
+    fn display(&self, path: &Path, root: &Path) -> String {
+        let path = match self.relative_to.is_some() {
+            true => path.strip_prefix(root).unwrap_or(path),
+            false => path
+        };
+        String::from(path.to_str().unwrap_or(PATHSTR_FB))
+    }
+}
+
+/// `path` for a comment of a script, which must not contain line breaks
+fn comment_path(path: &Path) -> String {
+    path.to_string_lossy().replace(['\n', '\r'], "?")
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod simulation_printer {
+        use std::collections::HashSet;
+        use std::path::{Path, PathBuf};
+
+        use crate::sorting::Operation;
+        use crate::sorting::simulation::{ScriptFormat, SimOutcome, SimulationPrinter};
+
+        #[test]
+        fn aligns_relative_paths() {
+            let printer = SimulationPrinter::new().color(false).relative_paths(PathBuf::from("/in"));
+            let root = Path::new("/out");
+            let long = printer.line(SimOutcome::Rename, Path::new("/in/dcim/IMG_0001.JPG"), "-> x");
+            assert_eq!(long, "rename    dcim/IMG_0001.JPG  -> x");
+            let short = printer.line(SimOutcome::New, Path::new("/in/a.jpg"), format!("-> {}", printer.display(Path::new("/out/2021/a.jpg"), root)).as_str());
+            assert_eq!(short, "new       a.jpg              -> 2021/a.jpg");
+
+            let colored = SimulationPrinter::new().color(true).line(SimOutcome::Skip, Path::new("a.jpg"), "(quota)");
+            assert!(colored.starts_with("\x1b[90mskip"));
+        }
+
+        #[test]
+        fn prints_quoted_commands() {
+            let mut dirs = HashSet::new();
+            let (source, target) = (Path::new("/in/it's.jpg"), Path::new("/nonexistent/2021/it's.jpg"));
+            assert_eq!(SimulationPrinter::script_lines(&mut dirs, ScriptFormat::Sh, Operation::Move, SimOutcome::New, source, target),
+                       "mkdir -p -- '/nonexistent/2021'\nmv -- '/in/it'\\''s.jpg' '/nonexistent/2021/it'\\''s.jpg'");
+            // the directory is only created once
+            assert_eq!(SimulationPrinter::script_lines(&mut dirs, ScriptFormat::PowerShell, Operation::Copy, SimOutcome::Rename, source, target),
+                       "Copy-Item -Force -LiteralPath '/in/it''s.jpg' -Destination '/nonexistent/2021/it''s.jpg'");
+            assert_eq!(SimulationPrinter::script_lines(&mut dirs, ScriptFormat::Sh, Operation::Move, SimOutcome::Duplicate, Path::new("/in/a\n.jpg"), target),
+                       "# duplicate: /in/a?.jpg -> /nonexistent/2021/it's.jpg");
+        }
+
+        #[test]
+        fn doubles_typographic_quotes_for_powershell() {
+            let source = Path::new("/in/Bob\u{2019}; Remove-Item -Recurse ~; \u{2019}.jpg");
+            assert_eq!(SimulationPrinter::script_lines(&mut HashSet::new(), ScriptFormat::PowerShell, Operation::Move, SimOutcome::Rename, source, Path::new("/nonexistent/a.jpg")),
+                       "[void][System.IO.Directory]::CreateDirectory('/nonexistent')\n\
+                        Move-Item -Force -LiteralPath '/in/Bob\u{2019}\u{2019}; Remove-Item -Recurse ~; \u{2019}\u{2019}.jpg' -Destination '/nonexistent/a.jpg'");
+            assert_eq!(ScriptFormat::PowerShell.quote(Path::new("\u{2018}\u{201a}\u{201b}'")).unwrap(),
+                       "'\u{2018}\u{2018}\u{201a}\u{201a}\u{201b}\u{201b}'''");
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Splits target folders exceeding a maximum number of files into numbered sub-buckets, e.g.
+/// `2023-08/part_02/` once `2023-08` is full, as some file systems and gallery apps handle huge
+/// directories badly. The folder itself is the first bucket, further buckets are created below
+/// it. Counts are shared by all clones of a split, so sorters in different pipeline threads fill
+/// the same buckets.
+///
+/// A bucket continues after the files already in the last existing bucket of a folder, so
+/// repeated runs do not overfill it. Like sequence numbers, a slot is taken when the target of a
+/// file is calculated, a file skipped afterwards leaves a bucket below its maximum. Files whose
+/// name exists in any bucket of their folder are placed next to it, so duplicates of previously
+/// sorted files are still detected.
+#[derive(Clone)]
+pub struct FolderSplit {
+    max_files: u64,
+    prefix: String,
+    digits: usize,
+    buckets: Arc<Mutex<HashMap<PathBuf, Bucket>>>
+}
+
+/// the last bucket of a folder and the number of files in it
+#[derive(Clone, Copy)]
+struct Bucket {
+    number: u64,
+    count: u64
+}
+
+impl FolderSplit {
+    /// create a split allowing at most `max_files` (at least 1) files per folder and naming
+    /// buckets `<prefix><number>` with at least `digits` digits
+    pub fn new(max_files: u64, prefix: &str, digits: usize) -> FolderSplit {
+        FolderSplit {
+            max_files: max_files.max(1),
+            prefix: String::from(prefix),
+            digits,
+            buckets: Arc::new(Mutex::new(HashMap::new()))
+        }
+    }
+
+    pub fn default_prefix() -> &'static str {
+        "part_"
+    }
+
+    pub fn default_digits() -> usize {
+        2
+    }
+
+    pub fn max_files(&self) -> u64 {
+        self.max_files
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    pub fn digits(&self) -> usize {
+        self.digits
+    }
+
+    /// get the bucket of `folder` a file named `fname` is placed in. With `peek` set, no slot is
+    /// taken.
+    pub fn place(&self, folder: &Path, fname: &OsStr, peek: bool) -> PathBuf {
+        let mut buckets = match self.buckets.lock() {
+            Ok(guard) => guard,
+            // the map is only modified by single inserts, a panicking thread cannot corrupt it
+            Err(poisoned) => poisoned.into_inner()
+        };
+        let last = *buckets.entry(folder.to_path_buf()).or_insert_with(|| self.scan(folder));
+        if let Some(dir) = (1..=last.number).map(|n| self.bucket_dir(folder, n)).find(|d| d.join(fname).exists()) {
+            return dir;
+        }
+        let mut next = match last.count >= self.max_files {
+            true => Bucket { number: last.number + 1, count: 0 },
+            false => last
+        };
+        if !peek {
+            next.count += 1;
+            buckets.insert(folder.to_path_buf(), next);
+        }
+        self.bucket_dir(folder, next.number)
+    }
+
+    fn bucket_dir(&self, folder: &Path, number: u64) -> PathBuf {
+        match number {
+            1 => folder.to_path_buf(),
+            n => folder.join(format!("{}{:0width$}", self.prefix, n, width = self.digits))
+        }
+    }
+
+    /// find the last bucket of `folder` existing on disk and count the files in it
+    fn scan(&self, folder: &Path) -> Bucket {
+        let number = match fs::read_dir(folder) {
+            Ok(entries) => entries.filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+                .filter_map(|e| e.file_name().to_str()?.strip_prefix(self.prefix.as_str())?.parse::<u64>().ok())
+                .filter(|n| *n > 1)
+                .max()
+                .unwrap_or(1),
+            Err(_) => return Bucket { number: 1, count: 0 }
+        };
+        let count = match fs::read_dir(self.bucket_dir(folder, number)) {
+            Ok(entries) => entries.filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_ok_and(|t| !t.is_dir()))
+                .count() as u64,
+            Err(_) => 0
+        };
+        Bucket { number, count }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod folder_split {
+        use std::ffi::OsStr;
+
+        use crate::sorting::split::FolderSplit;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn continues_existing_buckets() {
+            let dir = TempDir::new("split");
+            std::fs::create_dir_all(dir.join("part_02")).unwrap();
+            for name in ["a.jpg", "b.jpg"] {
+                std::fs::write(dir.join(name), b"a").unwrap();
+            }
+            std::fs::write(dir.join("part_02").join("c.jpg"), b"c").unwrap();
+
+            let split = FolderSplit::new(2, FolderSplit::default_prefix(), FolderSplit::default_digits());
+            assert_eq!(split.place(&dir, OsStr::new("d.jpg"), true), dir.join("part_02"));
+            assert_eq!(split.place(&dir, OsStr::new("d.jpg"), false), dir.join("part_02"));
+            assert_eq!(split.place(&dir, OsStr::new("e.jpg"), false), dir.join("part_03"));
+            // existing files stay in their bucket, so they are detected as duplicates
+            assert_eq!(split.place(&dir, OsStr::new("a.jpg"), false), *dir);
+        }
+    }
+}
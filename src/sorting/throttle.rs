@@ -0,0 +1,116 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// size of the chunks copied between two checks of the limit
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Limits the rate at which files are copied, e.g. so a background sort on a NAS does not starve
+/// other users of the disks or the network. Clones share the same budget, so the limit applies
+/// to all sorters of a run together and not to each pipeline on its own.
+///
+/// The budget refills continuously and is capped at one second of transfer, so an idle period
+/// allows a short burst but never more.
+#[derive(Clone)]
+pub struct Throttle {
+    bytes_per_sec: u64,
+    bucket: Arc<Mutex<Bucket>>
+}
+
+struct Bucket {
+    /// bytes which may be transferred without waiting, negative if transfers are ahead of the limit
+    available: f64,
+    refilled_at: Instant
+}
+
+impl Throttle {
+    /// create a limit of `bytes_per_sec`, which is at least 1
+    pub fn new(bytes_per_sec: u64) -> Throttle {
+        Throttle {
+            bytes_per_sec: bytes_per_sec.max(1),
+            bucket: Arc::new(Mutex::new(Bucket {
+                available: 0.0,
+                refilled_at: Instant::now()
+            }))
+        }
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    /// account for transferring `bytes`, waiting as long as transfers are ahead of the limit
+    pub fn consume(&self, bytes: u64) {
+        let rate = self.bytes_per_sec as f64;
+        let wait = {
+            let mut bucket = match self.bucket.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner()
+            };
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+            bucket.available = (bucket.available + elapsed * rate).min(rate) - bytes as f64;
+            bucket.refilled_at = now;
+            match bucket.available < 0.0 {
+                true => Duration::from_secs_f64(-bucket.available / rate),
+                false => Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+
+    /// copy the contents and permissions of `source` to `target` like [std::fs::copy], but in
+    /// chunks within the limit. Returns the number of bytes copied.
+    pub fn copy(&self, source: &Path, target: &Path) -> io::Result<u64> {
+        let mut reader = File::open(source)?;
+        let permissions = reader.metadata()?.permissions();
+        let mut writer = File::create(target)?;
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        let mut total = 0u64;
+        loop {
+            let n = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            };
+            self.consume(n as u64);
+            writer.write_all(&buffer[..n])?;
+            total += n as u64;
+        }
+        writer.set_permissions(permissions)?;
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod throttle {
+        use std::time::{Duration, Instant};
+
+        use crate::sorting::throttle::Throttle;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn copy_stays_within_limit() {
+            let dir = TempDir::new("throttle");
+            let data = vec![7u8; 3000];
+            std::fs::write(dir.join("a.jpg"), &data).unwrap();
+
+            let throttle = Throttle::new(10_000);
+            let start = Instant::now();
+            let copied = throttle.copy(dir.join("a.jpg").as_path(), dir.join("b.jpg").as_path()).unwrap();
+            let elapsed = start.elapsed();
+            let content = std::fs::read(dir.join("b.jpg")).unwrap();
+            assert_eq!(copied, 3000);
+            assert_eq!(content, data);
+            assert!(elapsed >= Duration::from_millis(250), "copied 3000 bytes at 10000 B/s in {:?}", elapsed);
+        }
+    }
+}
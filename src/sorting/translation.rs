@@ -4,29 +4,46 @@ use crate::pattern::PatternElement;
 
 pub struct Translator {
     segments_supported: Vec<Box<dyn PatternElement + Send>>,
-    segments_fallback: Vec<Box<dyn PatternElement + Send>>
+    segments_fallback: Vec<Box<dyn PatternElement + Send>>,
+    /// folder below the target root all files sorted by the fallback segments are placed in
+    fallback_root: Option<PathBuf>
+}
+
+impl Clone for Translator {
+    fn clone(&self) -> Self {
+        Translator {
+            segments_supported: self.segments_supported.iter().map(|s| s.clone_boxed()).collect(),
+            segments_fallback: self.segments_fallback.iter().map(|s| s.clone_boxed()).collect(),
+            fallback_root: self.fallback_root.clone()
+        }
+    }
 }
 
 impl Translator {
     pub fn new(segs_sup: Vec<Box<dyn PatternElement + Send>>, segs_fb: Vec<Box<dyn PatternElement + Send>>) -> Translator {
         Translator{
             segments_supported: segs_sup,
-            segments_fallback: segs_fb
+            segments_fallback: segs_fb,
+            fallback_root: None
         }
     }
 
+    /// place all files sorted by the fallback segments in `folder` (relative to the target root),
+    /// separating them from the tree of supported files
+    pub fn set_fallback_root(&mut self, folder: PathBuf) {
+        self.fallback_root = Some(folder);
+    }
+
     pub fn get_seg_count(&self) -> (usize, usize) {
         (self.segments_supported.len(), self.segments_fallback.len())
     }
 
     pub fn translate(&self, file: &ImgInfo, target_root: &Path) -> PathBuf {
         let mut destination = target_root.to_path_buf();
-        let segments = match file.file_type() {
-            FileType::Other => &self.segments_fallback,
-            _               => &self.segments_supported
-        };
-
-        for pattern in segments {
+        if let Some(root) = self.fallback_root_for(file) {
+            destination.push(root);
+        }
+        for pattern in self.segments_for(file) {
             if let Some(s) = pattern.translate(file) {
                 destination.push(s);
             }
@@ -34,4 +51,28 @@ impl Translator {
 
         destination
     }
+
+    /// get the name of every segment pattern applying to `file` with the value it translates to,
+    /// `None` for segments that are skipped
+    pub fn explain(&self, file: &ImgInfo) -> Vec<(String, Option<String>)> {
+        let root = self.fallback_root_for(file)
+            .map(|r| (String::from("fallbackRoot"), Some(r.to_string_lossy().to_string())));
+        root.into_iter()
+            .chain(self.segments_for(file).iter().map(|pattern| (String::from(pattern.name()), pattern.translate(file))))
+            .collect()
+    }
+
+    fn fallback_root_for(&self, file: &ImgInfo) -> Option<&Path> {
+        match file.file_type() {
+            FileType::Other => self.fallback_root.as_deref(),
+            _               => None
+        }
+    }
+
+    fn segments_for(&self, file: &ImgInfo) -> &Vec<Box<dyn PatternElement + Send>> {
+        match file.file_type() {
+            FileType::Other => &self.segments_fallback,
+            _               => &self.segments_supported
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,93 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::sorting::PATHSTR_FB;
+use crate::sorting::fs_support::device_id;
+
+/// name of the default workspace directory in the target root, see [Workspace::in_target]
+pub const WORKSPACE_DIR_NAME: &str = ".dcim-sort-tmp";
+
+/// distinguishes partial copies of concurrent sorters of this process
+static PARTIAL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory for temporary artifacts of a run, e.g. partial copies. Files are copied into the
+/// workspace first and renamed to their target once complete, so an interrupted copy never leaves
+/// a truncated file at the target. This requires the workspace to be on the same file system as
+/// the target, see [Workspace::validate].
+#[derive(Clone)]
+pub struct Workspace {
+    dir: PathBuf
+}
+
+impl Workspace {
+    pub fn new(dir: &Path) -> Workspace {
+        Workspace {
+            dir: dir.to_path_buf()
+        }
+    }
+
+    /// the default workspace: a hidden directory in the target root, which is on the target file
+    /// system in all but exotic setups
+    pub fn in_target(target_root: &Path) -> Workspace {
+        Self::new(target_root.join(WORKSPACE_DIR_NAME).as_path())
+    }
+
+    pub fn dir(&self) -> &Path {
+        self.dir.as_path()
+    }
+
+    /// create the workspace if it does not exist and check that files in it can be renamed into
+    /// the existing directory `target_root`
+    pub fn validate(&self, target_root: &Path) -> Result<(), String> {
+        let dir = self.dir.to_str().unwrap_or(PATHSTR_FB);
+        std::fs::create_dir_all(&self.dir).map_err(|e| format!("could not create \"{}\": {}", dir, e))?;
+        let name = format!(".dcim-sort-rename-{}", std::process::id());
+        let (probe, renamed) = (self.dir.join(&name), target_root.join(&name));
+        std::fs::write(&probe, b"rename").map_err(|e| format!("\"{}\" is not writable: {}", dir, e))?;
+        let result = std::fs::rename(&probe, &renamed);
+        let _ = std::fs::remove_file(&probe);
+        let _ = std::fs::remove_file(&renamed);
+        result.map_err(|e| format!("files in \"{}\" cannot be renamed to \"{}\", it must be on the same file system: {}",
+                                   dir, target_root.to_str().unwrap_or(PATHSTR_FB), e))
+    }
+
+    /// check if partial copies can be renamed to `target`, which is not the case if its
+    /// directory is on another file system, e.g. for targets routed to another volume
+    pub fn same_file_system(&self, target: &Path) -> bool {
+        match (device_id(self.dir.as_path()), target.parent().and_then(device_id)) {
+            (Some(ws), Some(t)) => ws == t,
+            _ => true
+        }
+    }
+
+    /// get an unused path for a partial copy
+    pub fn partial_path(&self) -> PathBuf {
+        let n = PARTIAL_COUNT.fetch_add(1, Ordering::Relaxed);
+        self.dir.join(format!("{}-{}.part", std::process::id(), n))
+    }
+
+    /// remove the workspace if it is empty, e.g. at the end of a run
+    pub fn cleanup(&self) {
+        let _ = std::fs::remove_dir(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod workspace {
+        use crate::sorting::workspace::Workspace;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn validate_and_cleanup() {
+            let root = TempDir::new("workspace");
+            let ws = Workspace::in_target(root.as_path());
+            ws.validate(root.as_path()).unwrap();
+            assert!(ws.dir().is_dir());
+            assert_ne!(ws.partial_path(), ws.partial_path());
+            ws.cleanup();
+            assert!(!ws.dir().exists());
+        }
+    }
+}
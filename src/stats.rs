@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::media::ImgInfo;
+use crate::pipeline::format_byte_size;
+
+/// key of files without a timestamp or device
+const UNKNOWN: &str = "unknown";
+
+/// Aggregate statistics of a library collected by reading the metadata of its files without
+/// sorting anything, e.g. to decide on a layout of the target before importing.
+#[derive(Clone, Default, Serialize)]
+pub struct LibraryStats {
+    pub count_files: u64,
+    pub total_bytes: u64,
+    /// count of files without a metadata timestamp, which are sorted by the fallback segments
+    /// or their file system timestamp
+    pub count_missing_date: u64,
+    /// count of files by device (make and model)
+    pub by_device: BTreeMap<String, u64>,
+    /// count of files by the year of their metadata timestamp
+    pub by_year: BTreeMap<String, u64>,
+    /// count of files by the month of their metadata timestamp (`YYYY-mm`)
+    pub by_month: BTreeMap<String, u64>,
+    pub by_file_type: BTreeMap<String, u64>
+}
+
+impl LibraryStats {
+    pub fn new() -> LibraryStats {
+        LibraryStats::default()
+    }
+
+    /// count `file`, whose metadata has to be read already
+    pub fn add(&mut self, file: &ImgInfo) {
+        let meta = file.metadata();
+        self.count_files += 1;
+        self.total_bytes += *file.size() as u64;
+        let (year, month) = match meta.created_at() {
+            Some(ts) => (ts.format("%Y").to_string(), ts.format("%Y-%m").to_string()),
+            None => {
+                self.count_missing_date += 1;
+                (String::from(UNKNOWN), String::from(UNKNOWN))
+            }
+        };
+        let device = format!("{} {}", meta.make().trim(), meta.model().trim());
+        let device = match device.trim() {
+            "" => UNKNOWN,
+            d => d
+        };
+        *self.by_device.entry(String::from(device)).or_insert(0) += 1;
+        *self.by_year.entry(year).or_insert(0) += 1;
+        *self.by_month.entry(month).or_insert(0) += 1;
+        *self.by_file_type.entry(format!("{:?}", file.file_type())).or_insert(0) += 1;
+    }
+
+    /// the statistics as a single line of JSON
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    fn write_section(&self, f: &mut fmt::Formatter<'_>, name: &str, counts: &BTreeMap<String, u64>) -> fmt::Result {
+        let width = counts.keys().map(|k| k.chars().count()).max().unwrap_or(0);
+        writeln!(f, "files per {}:", name)?;
+        for (key, count) in counts {
+            writeln!(f, "  {:<width$} {:>8} {:>5.1}%", key, count, *count as f64 * 100.0 / self.count_files.max(1) as f64, width = width)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for LibraryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "files: {} ({})", self.count_files, format_byte_size(self.total_bytes))?;
+        writeln!(f, "files without metadata timestamp: {}", self.count_missing_date)?;
+        self.write_section(f, "device", &self.by_device)?;
+        self.write_section(f, "year", &self.by_year)?;
+        self.write_section(f, "month", &self.by_month)?;
+        self.write_section(f, "type", &self.by_file_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod library_stats {
+        use chrono::{Local, TimeZone};
+
+        use crate::media::{ImgInfo, ImgMeta};
+        use crate::stats::LibraryStats;
+        use crate::test_util::TempDir;
+
+        #[test]
+        fn counts_files_without_date() {
+            let dir = TempDir::new("stats");
+            std::fs::write(dir.join("a.jpg"), b"1234").unwrap();
+            std::fs::write(dir.join("b.png"), b"12").unwrap();
+
+            let mut dated = ImgInfo::new(dir.join("a.jpg")).unwrap();
+            let mut meta = ImgMeta::new();
+            meta.set_created_at(Some(Local.with_ymd_and_hms(2021, 7, 14, 12, 0, 0).unwrap()));
+            dated.set_metadata(meta);
+            let undated = ImgInfo::new(dir.join("b.png")).unwrap();
+
+            let mut stats = LibraryStats::new();
+            stats.add(&dated);
+            stats.add(&undated);
+            assert_eq!(stats.count_files, 2);
+            assert_eq!(stats.total_bytes, 6);
+            assert_eq!(stats.count_missing_date, 1);
+            assert_eq!(stats.by_year.get("2021"), Some(&1));
+            assert_eq!(stats.by_month.get("2021-07"), Some(&1));
+            assert_eq!(stats.by_year.get("unknown"), Some(&1));
+            assert_eq!(stats.by_device.get("unknown"), Some(&2));
+            assert_eq!(stats.by_file_type.len(), 2);
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// An empty directory below the system temp dir for a test, removed with its contents when
+/// dropped, so a failing assertion does not leave it behind.
+pub(crate) struct TempDir {
+    path: PathBuf
+}
+
+impl TempDir {
+    /// create the directory `dcim-sort-test-<pid>-<name>`, removing leftovers of an earlier run
+    pub(crate) fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("dcim-sort-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+}
+
+impl Deref for TempDir {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        self.path.as_path()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::sorting::PATHSTR_FB;
+
+/// default time a file has to stay unchanged before it is processed, see [DropWatcher]
+pub const DEFAULT_SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Watches a drop folder for newly arrived files, e.g. for running as an ingest daemon on a NAS.
+/// Files are only passed on once they have settled, i.e. there was no event for them and their
+/// size did not change for the settle time, so files still being written are not processed
+/// half-way. Directories moved into the drop folder are passed on with all of their files.
+///
+/// Files already in the drop folder when watching starts are not reported, scan them separately
+/// (see [crate::runner::Runner::watch]).
+pub struct DropWatcher {
+    root: PathBuf,
+    settle: Duration,
+    // kept alive to receive events
+    _watcher: RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<Event>>
+}
+
+/// a file which has not settled yet, with the time of its last event and its size at that time
+struct PendingFile {
+    last_event: Instant,
+    size: u64
+}
+
+impl DropWatcher {
+    /// start watching `root` recursively. Events are queued until [Self::run] is called.
+    pub fn start(root: &Path, settle: Duration) -> Result<DropWatcher, String> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)
+            .map_err(|e| format!("could not create file system watcher: {}", e))?;
+        watcher.watch(root, RecursiveMode::Recursive)
+            .map_err(|e| format!("could not watch \"{}\": {}", root.to_str().unwrap_or(PATHSTR_FB), e))?;
+        Ok(DropWatcher {
+            root: root.to_path_buf(),
+            settle,
+            _watcher: watcher,
+            events: rx
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        self.root.as_path()
+    }
+
+    /// pass settled files to `on_ready` in batches until no file arrived for `idle_timeout`, or
+    /// forever if it is `None`, or until `stop` is set. Files which have not settled yet when
+    /// stopping are not passed on.
+    pub fn run<F: FnMut(Vec<PathBuf>)>(&self, idle_timeout: Option<Duration>, stop: &AtomicBool, mut on_ready: F) -> Result<(), String> {
+        let mut pending: HashMap<PathBuf, PendingFile> = HashMap::new();
+        let mut last_activity = Instant::now();
+        let tick = (self.settle / 2).max(Duration::from_millis(100));
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            match self.events.recv_timeout(tick) {
+                Ok(Ok(event)) => {
+                    if let EventKind::Create(_) | EventKind::Modify(_) = event.kind {
+                        for path in event.paths {
+                            Self::add_pending(&mut pending, path.as_path());
+                        }
+                        last_activity = Instant::now();
+                    }
+                },
+                Ok(Err(e)) => eprintln!("[WARN] error watching \"{}\": {}", self.root.to_str().unwrap_or(PATHSTR_FB), e),
+                Err(mpsc::RecvTimeoutError::Timeout) => (),
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err(String::from("file system watcher stopped unexpectedly"))
+            }
+
+            let ready = self.collect_settled(&mut pending);
+            if !ready.is_empty() {
+                on_ready(ready);
+                last_activity = Instant::now();
+            }
+            if let Some(timeout) = idle_timeout {
+                if pending.is_empty() && last_activity.elapsed() >= timeout {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// (re)start the settle time of `path` or all files below it if it is a directory
+    fn add_pending(pending: &mut HashMap<PathBuf, PendingFile>, path: &Path) {
+        if path.is_dir() {
+            if let Ok(children) = path.read_dir() {
+                for child in children.flatten() {
+                    Self::add_pending(pending, child.path().as_path());
+                }
+            }
+        }
+        else if let Ok(meta) = path.metadata() {
+            pending.insert(path.to_path_buf(), PendingFile {
+                last_event: Instant::now(),
+                size: meta.len()
+            });
+        }
+    }
+
+    /// remove all settled files from `pending`, dropping the ones that no longer exist
+    fn collect_settled(&self, pending: &mut HashMap<PathBuf, PendingFile>) -> Vec<PathBuf> {
+        let mut ready = Vec::new();
+        pending.retain(|path, file| {
+            if file.last_event.elapsed() < self.settle {
+                return true;
+            }
+            match path.metadata() {
+                // still growing without events, e.g. on network shares
+                Ok(meta) if meta.len() != file.size => {
+                    file.size = meta.len();
+                    file.last_event = Instant::now();
+                    true
+                },
+                Ok(meta) if meta.is_file() => {
+                    ready.push(path.clone());
+                    false
+                },
+                _ => false
+            }
+        });
+        ready.sort();
+        ready
+    }
+}